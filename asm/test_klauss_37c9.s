@@ -1,34 +1,47 @@
+; BRK frame check, after the BRK test in Klaus Dormann's functional suite.
+;
+; BRK skips its signature byte, pushes the return address and the status
+; with B and bit 5 set, and jumps through the IRQ/BRK vector at $FFFE. The
+; handler checks that frame and ends in a JMP-to-self trap: SUCCESS ($9040)
+; if it is right, FAIL ($9050) if not. Run from $0400 on the Generic system.
+
 .setcpu "6502"
 
 .segment "HEADER"
-.org $0400  
 _start:
+    LDX #$FF      ; Whole stack page, so the frame is not split across it
+    TXS
     LDA #$42      ; Load A with $42
     LDX #$52      ; Load X with $52
     LDY #$4B      ; Load Y with $4B
     BRK           ; Trigger BRK (software interrupt)
+    .byte $EA     ; Signature byte, skipped on return
+BRK_RETURN:
 
 .segment "HANDLER"
-.org $9000
 INTERRUPT_HANDLER:
     TSX
-    LDA $0102,X
-    CMP #$30           
+    LDA $0101,X   ; Pushed status
+    AND #$30
+    CMP #$30      ; B and bit 5 set
+    BNE FAIL
+    LDA $0102,X   ; Pushed return address, low
+    CMP #<BRK_RETURN
+    BNE FAIL
+    LDA $0103,X   ; Pushed return address, high
+    CMP #>BRK_RETURN
     BNE FAIL
-    JMP SUCCESS      
+    JMP SUCCESS
 
 .segment "SUCCESS"
-.org $9010
 SUCCESS:
-    NOP
-    RTI 
+    JMP SUCCESS
 
 .segment "FAILURE"
-.org $9020
 FAIL:
-    JMP FAIL       
+    JMP FAIL
 
 .segment "VECTORS"
-.org $FFFC
-.word _start
-.word INTERRUPT_HANDLER
\ No newline at end of file
+.word _start            ; NMI
+.word _start            ; RESET
+.word INTERRUPT_HANDLER ; IRQ/BRK
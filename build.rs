@@ -5,6 +5,8 @@ use std::process::Command;
 
 fn main() {
     let out_dir = env::var("OUT_DIR").unwrap();
+    assemble_interrupt_tests(Path::new(&out_dir));
+
     let asm_dir = Path::new("asm");
     let build_dir = Path::new("build/asm");
 
@@ -63,3 +65,38 @@ fn main() {
         println!("Built: {} -> {}", source_file, bin_file);
     }
 }
+
+// Klaus Dormann's interrupt test is AS65 source. Assemble an NMOS build and
+// a 65C02 build (D_clear = 1) into OUT_DIR for the cargo tests in
+// src/tests/klaus.rs; without as65 on the PATH those tests are ignored.
+fn assemble_interrupt_tests(out_dir: &Path) {
+    let source = Path::new("asm/6502_interrupt_test.a65");
+    println!("cargo:rerun-if-changed={}", source.display());
+    println!("cargo:rerun-if-env-changed=PATH");
+    println!("cargo:rustc-check-cfg=cfg(klaus_interrupt_images)");
+
+    let nmos = fs::read_to_string(source).expect("Failed to read the interrupt test source");
+    let cmos = nmos.replacen("D_clear     = 0", "D_clear     = 1", 1);
+    assert!(cmos != nmos, "D_clear setting not found in {}", source.display());
+
+    for (name, text) in [("6502_interrupt_test", nmos), ("65C02_interrupt_test", cmos)] {
+        fs::write(out_dir.join(format!("{}.a65", name)), text).expect("Failed to write interrupt test source");
+        // The switches the source asks for, with the Intel HEX and listing named explicitly
+        let status = Command::new("as65")
+            .args(["-m", "-s2", "-w", "-h0"])
+            .arg(format!("-l{}.lst", name))
+            .arg(format!("-o{}.hex", name))
+            .arg(format!("{}.a65", name))
+            .current_dir(out_dir)
+            .status();
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(_) => panic!("as65 assembly failed for {}", name),
+            Err(_) => {
+                println!("cargo:warning=as65 not found; the Klaus interrupt tests will be ignored");
+                return;
+            }
+        }
+    }
+    println!("cargo:rustc-cfg=klaus_interrupt_images");
+}
@@ -1,4 +1,3 @@
-use a2kit::img::DiskImage;
 use a2kit::img::Track;
 
 fn main() {
@@ -46,11 +45,11 @@ fn main() {
         
         // Decode address field
         let mut decoded = [0u8; 4];
-        for i in 0..4 {
+        for (i, byte) in decoded.iter_mut().enumerate() {
             let off = pos + 24 + i * 16;
             let hi = read_byte(&bits, off);
             let lo = read_byte(&bits, off + 8);
-            decoded[i] = (hi << 1 | 1) & lo;
+            *byte = (hi << 1 | 1) & lo;
         }
         let _vol = decoded[0];
         let _trk = decoded[1];
@@ -73,8 +72,8 @@ fn main() {
         
         if found {
             let mut nibbles = [0u8; 343];
-            for i in 0..343 {
-                nibbles[i] = read_byte(&bits, dpos + i * 8);
+            for (i, nibble) in nibbles.iter_mut().enumerate() {
+                *nibble = read_byte(&bits, dpos + i * 8);
             }
             let result = denibbilize(&nibbles, &denib);
             
@@ -105,7 +104,7 @@ fn main() {
     
     println!("\n=== DSK file sector 0 (boot sector) ===");
     print!("  ");
-    for i in 0..32 { print!("{:02X} ", dsk_data[i]); }
+    for byte in &dsk_data[..32] { print!("{:02X} ", byte); }
     println!();
 }
 
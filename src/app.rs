@@ -1,3 +1,4 @@
+use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -20,10 +21,15 @@ use crate::cpu::CPU;
 use crate::cpu_monitor::{CpuMonitor, CpuState};
 use crate::device::drive_audio::DriveAudioParams;
 use crate::monitor::Monitor;
+use crate::movie::{self, InputEvent, MoviePlayer, MovieRecorder};
+use crate::profiler::Profiler;
+use crate::recorder::{self, Recorder};
 use crate::render::{
     blit_direct, blit_nearest, CrtRenderer,
     DriveIcons, DriveStatusInfo, LcdRenderer, PostProcessor, ToolbarAction, ToolbarLabels, render_toolbar_ui,
 };
+use crate::savestate;
+use crate::screenshot;
 
 pub struct App {
     pub pixels: Option<Pixels<'static>>,
@@ -41,6 +47,7 @@ pub struct App {
     pub last_cursor_pos: Option<(f64, f64)>,
     pub show_toolbar: bool,
     pub is_fullscreen: bool,
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub start_fullscreen: bool,
     pub last_drive_click: Option<(usize, Instant)>,
     // egui state for shader parameter UI
@@ -55,6 +62,10 @@ pub struct App {
     pub drive_icons: Option<DriveIcons>,
     pub toolbar_labels: Option<ToolbarLabels>,
     pub paused: bool,
+    pub rewind_held: bool,
+    pub recorder: Option<Recorder>,
+    pub movie_recorder: Option<MovieRecorder>,
+    pub movie_player: Option<MoviePlayer>,
     pub window_aspect_ratio: f64,
     pub last_resize_time: Option<Instant>,
 }
@@ -93,6 +104,10 @@ impl App {
             drive_icons: None,
             toolbar_labels: None,
             paused: false,
+            rewind_held: false,
+            recorder: None,
+            movie_recorder: None,
+            movie_player: None,
             window_aspect_ratio: 1.0,
             last_resize_time: None,
         }
@@ -134,6 +149,87 @@ impl App {
         self.cpu.bus.iou.iwm.eject_disk(0);
         self.cpu.bus.iou.iwm.eject_disk(1);
         self.cpu.bus.iou.iwm.smartport.flush_all();
+        self.cpu.bus.flush_switch_trace();
+        self.cpu.stop_profiler();
+        self.cpu.stop_coverage();
+        self.cpu.stop_trace();
+    }
+
+    pub fn start_recording(&mut self, dir: &Path) {
+        match Recorder::start(dir, &mut self.cpu) {
+            Ok(rec) => {
+                println!("Recording to {}", dir.display());
+                self.recorder = Some(rec);
+            }
+            Err(e) => println!("Error starting recording: {}", e),
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(rec) = self.recorder.take() {
+            let dir = rec.dir().to_path_buf();
+            match rec.finish(&mut self.cpu) {
+                Ok(frames) => println!("Recording stopped: {} frames in {}", frames, dir.display()),
+                Err(e) => println!("Error finishing recording: {}", e),
+            }
+        }
+    }
+
+    // Append the frames emulated time has reached to the recording; called
+    // after every scanline
+    pub fn record_frames(&mut self) {
+        let Some(rec) = self.recorder.as_mut() else { return };
+        if let Err(e) = rec.capture_due(&mut self.cpu) {
+            println!("Error recording frame: {}", e);
+            self.stop_recording();
+        }
+    }
+
+    // Every host input that changes machine state goes through here so an
+    // input movie can capture it. Live input is ignored during playback.
+    pub fn send_input(&mut self, event: InputEvent) {
+        if self.movie_player.is_some() {
+            return;
+        }
+        if let Some(rec) = self.movie_recorder.as_mut() {
+            if let Err(e) = rec.record(self.cpu.cycles, &event) {
+                println!("Error recording movie: {}", e);
+                self.movie_recorder = None;
+            }
+        }
+        movie::apply(&mut self.cpu, &event);
+    }
+
+    // Poll the host gamepad and forward any change as movie input
+    pub fn poll_paddle(&mut self) {
+        if self.movie_player.is_some() {
+            return;
+        }
+        let paddle = &mut self.cpu.bus.iou.paddle;
+        let before = [paddle.position(0), paddle.position(1)];
+        let buttons_before = [paddle.button0.get(), paddle.button1.get()];
+        paddle.poll();
+        let after = [paddle.position(0), paddle.position(1)];
+        let buttons_after = [paddle.button0.get(), paddle.button1.get()];
+
+        for i in 0..2 {
+            if after[i] != before[i] {
+                self.send_input(InputEvent::Paddle { paddle: i as u8, position: after[i] });
+            }
+            if buttons_after[i] != buttons_before[i] {
+                self.send_input(InputEvent::PaddleButton { button: i as u8, pressed: buttons_after[i] });
+            }
+        }
+    }
+
+    // Machine state jumped (state load, rewind): the movie no longer describes it
+    pub fn stop_movie(&mut self, reason: &str) {
+        if let Some(rec) = self.movie_recorder.take() {
+            println!("Movie recording stopped ({}): {} events", reason, rec.events());
+        }
+        if let Some(player) = self.movie_player.take() {
+            println!("Movie playback stopped ({}): {} events left", reason, player.remaining());
+        }
     }
 
     /// Snap window to correct aspect ratio after user finishes resizing
@@ -454,38 +550,39 @@ impl winit::application::ApplicationHandler for App {
                     }
                 }
                 self.modifiers = ModifiersState::empty();
+                self.rewind_held = false;
             }
 
-            WindowEvent::Resized(size) => {
-                if size.width > 0 && size.height > 0 {
-                    if size.width != self.surface_width || size.height != self.surface_height {
-                        self.surface_width = size.width;
-                        self.surface_height = size.height;
-
-                        if let Some(pixels) = self.pixels.as_mut() {
-                            let _ = pixels.resize_surface(size.width, size.height);
-                            
-                            if self.shader_type != ShaderType::Crt {
-                                self.buffer_width = size.width;
-                                self.buffer_height = size.height;
-                                let _ = pixels.resize_buffer(size.width, size.height);
-                            }
-
-                            if let Some(pp) = self.post_processor.as_mut() {
-                                pp.resize(pixels.device(), pixels.queue(), size.width, size.height);
-                            }
-                        }
+            WindowEvent::Resized(size)
+                if size.width > 0
+                    && size.height > 0
+                    && (size.width != self.surface_width || size.height != self.surface_height) =>
+            {
+                self.surface_width = size.width;
+                self.surface_height = size.height;
 
-                        // Mark resize timestamp for deferred aspect-ratio snap
-                        if !self.is_fullscreen {
-                            self.last_resize_time = Some(Instant::now());
-                        }
+                if let Some(pixels) = self.pixels.as_mut() {
+                    let _ = pixels.resize_surface(size.width, size.height);
+                    
+                    if self.shader_type != ShaderType::Crt {
+                        self.buffer_width = size.width;
+                        self.buffer_height = size.height;
+                        let _ = pixels.resize_buffer(size.width, size.height);
+                    }
 
-                        if let Some(window) = &self.window {
-                            window.request_redraw();
-                        }
+                    if let Some(pp) = self.post_processor.as_mut() {
+                        pp.resize(pixels.device(), pixels.queue(), size.width, size.height);
                     }
                 }
+
+                // Mark resize timestamp for deferred aspect-ratio snap
+                if !self.is_fullscreen {
+                    self.last_resize_time = Some(Instant::now());
+                }
+
+                if let Some(window) = &self.window {
+                    window.request_redraw();
+                }
             }
 
             WindowEvent::ScaleFactorChanged { .. } => {
@@ -505,7 +602,7 @@ impl winit::application::ApplicationHandler for App {
                     if let Some((lx, ly)) = self.last_cursor_pos {
                         let dx = x - lx;
                         let dy = y - ly;
-                        self.cpu.bus.iou.mouse.add_delta(dx, dy);
+                        self.send_input(InputEvent::MouseMove { dx, dy });
                     }
                 }
                 self.last_cursor_pos = Some((x, y));
@@ -545,20 +642,16 @@ impl App {
                 
                 if let Some(path) = file {
                     println!("Loading disk into drive {}: {}", drive + 1, path.display());
-                    let result = match drive {
-                        0 => self.cpu.bus.iou.iwm.load_disk(&path),
-                        1 => self.cpu.bus.iou.iwm.load_disk2(&path),
-                        2 => self.cpu.bus.iou.iwm.load_disk35(&path),
-                        3 => self.cpu.bus.iou.iwm.load_disk35_drive(1, &path),
-                        _ => Ok(()),
-                    };
-                    if let Err(e) = result {
-                        println!("Error loading disk: {}", e);
-                    }
+                    let path = path.to_string_lossy().into_owned();
+                    self.send_input(InputEvent::InsertDisk { drive: drive as u8, path });
                 }
             }
         }
 
+        // Toolbar input is applied once the pixels borrow is released
+        let mut toolbar_input = Vec::new();
+        let mut state_loaded = false;
+
         if let Some(pixels) = self.pixels.as_mut() {
             if let Some(window) = &self.window {
                 let size = window.inner_size();
@@ -728,13 +821,13 @@ impl App {
                         }
                         
                         let mut memory_snapshot = [0u8; 512];
-                        for i in 0..256 {
-                            memory_snapshot[i] = self.cpu.bus.read_byte(0x0100 + i as u16);
+                        for (i, byte) in memory_snapshot[..256].iter_mut().enumerate() {
+                            *byte = self.cpu.bus.read_byte(0x0100 + i as u16);
                         }
                         let mem_page = self.cpu_monitor.memory_page;
                         let page_base = (mem_page as u16) << 8;
-                        for i in 0..256 {
-                            memory_snapshot[256 + i] = self.cpu.bus.read_byte(page_base + i as u16);
+                        for (i, byte) in memory_snapshot[256..].iter_mut().enumerate() {
+                            *byte = self.cpu.bus.read_byte(page_base + i as u16);
                         }
                         
                         let col80 = self.cpu.bus.iou.col80_switch;
@@ -763,6 +856,7 @@ impl App {
                         
                         let mut drive_audio_changed = false;
                         let mut toolbar_action = ToolbarAction::default();
+                        self.cpu_monitor.profiling = self.cpu.profiler.is_some();
                         let output = self.egui_ctx.run(raw_input, |ctx| {
                             if self.show_shader_ui {
                                 shader_ui::render_shader_ui(ctx, &mut self.shader_params, &mut self.show_shader_ui);
@@ -772,15 +866,15 @@ impl App {
                             }
                             if self.cpu_monitor.visible {
                                 let memory_reader = |addr: u16| -> u8 {
-                                    if addr >= 0x0100 && addr < 0x0200 {
+                                    if addr & 0xFF00 == 0x0100 {
                                         memory_snapshot[(addr - 0x0100) as usize]
-                                    } else if addr >= page_base && addr < page_base + 256 {
+                                    } else if addr & 0xFF00 == page_base {
                                         memory_snapshot[256 + (addr - page_base) as usize]
                                     } else {
                                         0x00
                                     }
                                 };
-                                self.cpu_monitor.render(ctx, &cpu_state, &memory_reader);
+                                self.cpu_monitor.render(ctx, &cpu_state, &memory_reader, &self.cpu.bus.symbols, self.cpu.profiler.as_deref());
                             }
                             if self.show_toolbar {
                                 if self.drive_icons.is_none() {
@@ -794,6 +888,19 @@ impl App {
                         });
                         egui_state.handle_platform_output(window.as_ref(), output.platform_output.clone());
                         
+                        if self.cpu_monitor.profiling != self.cpu.profiler.is_some() {
+                            if self.cpu_monitor.profiling {
+                                self.cpu.profiler = Some(Box::new(Profiler::new(None)));
+                            } else {
+                                self.cpu.stop_profiler();
+                            }
+                        }
+                        if std::mem::take(&mut self.cpu_monitor.reset_profile) {
+                            if let Some(profiler) = self.cpu.profiler.as_mut() {
+                                profiler.reset();
+                            }
+                        }
+
                         if drive_audio_changed {
                             self.cpu.bus.iou.iwm.drive_audio.params = self.drive_audio_params.clone();
                             self.cpu.bus.iou.iwm.drive_audio.apply_params();
@@ -803,36 +910,45 @@ impl App {
                             self.paused = !self.paused;
                         }
                         if toolbar_action.reset {
-                            self.cpu.reset();
+                            toolbar_input.push(InputEvent::Reset { hard: false });
                         }
                         if toolbar_action.power {
-                            self.cpu.power_cycle();
+                            toolbar_input.push(InputEvent::PowerCycle { ram_seed: fastrand::u64(..) });
                             self.power_on_time = Instant::now();
                         }
                         if toolbar_action.toggle_col80 {
-                            self.cpu.bus.iou.col80_switch = !self.cpu.bus.iou.col80_switch;
+                            toolbar_input.push(InputEvent::Col80Switch(!self.cpu.bus.iou.col80_switch));
+                        }
+                        if toolbar_action.save_state {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("Save State", &["iicstate"])
+                                .save_file()
+                            {
+                                save_state(&self.cpu, &path);
+                            }
+                        }
+                        if toolbar_action.load_state {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("Save State", &["iicstate"])
+                                .pick_file()
+                            {
+                                load_state(&mut self.cpu, &path);
+                                state_loaded = true;
+                            }
                         }
                         if let Some(drive) = toolbar_action.load_disk {
                             // Defer single-click so double-click (eject) can override it
                             self.last_drive_click = Some((drive, Instant::now()));
                         }
                         if let Some(drive) = toolbar_action.toggle_write_protect {
-                            if drive < 2 {
-                                self.cpu.bus.iou.iwm.toggle_write_protect(drive);
-                            } else {
-                                self.cpu.bus.iou.iwm.toggle_write_protect_35(drive - 2);
-                            }
+                            toolbar_input.push(InputEvent::WriteProtect { drive: drive as u8 });
                         }
                         if let Some(drive) = toolbar_action.eject_disk {
                             // Cancel any pending single-click for this drive
                             if let Some((d, _)) = self.last_drive_click {
                                 if d == drive { self.last_drive_click = None; }
                             }
-                            if drive < 2 {
-                                self.cpu.bus.iou.iwm.eject_disk(drive);
-                            } else {
-                                self.cpu.bus.iou.iwm.eject_disk_35(drive - 2);
-                            }
+                            toolbar_input.push(InputEvent::EjectDisk { drive: drive as u8 });
                         }
                         
                         let ppp = output.pixels_per_point;
@@ -919,6 +1035,13 @@ impl App {
                 }
             }
         }
+
+        if state_loaded {
+            self.stop_movie("state loaded");
+        }
+        for event in toolbar_input {
+            self.send_input(event);
+        }
     }
 
     fn handle_mouse_input(&mut self, state: ElementState, button: MouseButton) {
@@ -937,8 +1060,8 @@ impl App {
             }
         }
         match button {
-            MouseButton::Left => self.cpu.bus.iou.mouse.set_button(0, pressed),
-            MouseButton::Right => self.cpu.bus.iou.mouse.set_button(1, pressed),
+            MouseButton::Left => self.send_input(InputEvent::MouseButton { button: 0, pressed }),
+            MouseButton::Right => self.send_input(InputEvent::MouseButton { button: 1, pressed }),
             _ => (),
         }
     }
@@ -949,6 +1072,12 @@ impl App {
         event: &winit::event::KeyEvent,
         egui_consumed: bool,
     ) {
+        // Hold to rewind; the run loop restores snapshots while this is set
+        if event.logical_key == Key::Named(NamedKey::F2) {
+            self.rewind_held = event.state.is_pressed();
+            return;
+        }
+
         if event.logical_key == Key::Named(NamedKey::F7) && event.state.is_pressed() {
             if self.shader_type != ShaderType::None {
                 self.show_shader_ui = !self.show_shader_ui;
@@ -1028,7 +1157,7 @@ impl App {
                         if event.state.is_pressed() { "PRESS" } else { "RELEASE" }
                     );
                 }
-                self.cpu.bus.iou.mouse.set_button(0, event.state.is_pressed());
+                self.send_input(InputEvent::MouseButton { button: 0, pressed: event.state.is_pressed() });
             }
             PhysicalKey::Code(KeyCode::SuperRight) => {
                 if self.cpu.bus.iou.debug {
@@ -1037,7 +1166,7 @@ impl App {
                         if event.state.is_pressed() { "PRESS" } else { "RELEASE" }
                     );
                 }
-                self.cpu.bus.iou.mouse.set_button(1, event.state.is_pressed());
+                self.send_input(InputEvent::MouseButton { button: 1, pressed: event.state.is_pressed() });
             }
             _ => {}
         }
@@ -1115,25 +1244,25 @@ impl App {
         if event.state.is_pressed() {
             if event.logical_key == Key::Named(NamedKey::Backspace) && self.modifiers.control_key()
             {
-                if self.modifiers.super_key() {
+                let hard = self.modifiers.super_key();
+                if hard {
                     println!("Hard Reset Triggered (Control + Command + Backspace)");
-                    self.cpu.bus.write_byte(0x03F4, 0x00);
                 } else {
                     println!("Reset Triggered (Control + Backspace)");
                 }
-                self.cpu.reset();
+                self.send_input(InputEvent::Reset { hard });
                 return;
             }
 
             if self.modifiers.control_key() {
                 if let PhysicalKey::Code(KeyCode::KeyZ) = event.physical_key {
-                    self.cpu.bus.iou.zip.toggle();
+                    self.send_input(InputEvent::ZipToggle);
                     return;
                 }
             }
 
             if event.logical_key == Key::Named(NamedKey::Escape) {
-                self.cpu.bus.iou.zip.check_boot_escape();
+                self.send_input(InputEvent::ZipBootEscape);
             }
 
             if let (Some(phys), Some(code)) = (physical_key_id, key_code) {
@@ -1143,11 +1272,7 @@ impl App {
                         event.logical_key
                     );
                 }
-                self.cpu
-                    .bus
-                    .iou
-                    .keyboard
-                    .key_down(phys, code, self.cpu.bus.iou.cycles);
+                self.send_input(InputEvent::KeyDown { key: phys, code });
             }
         } else {
             if let Some(phys) = physical_key_id {
@@ -1157,11 +1282,7 @@ impl App {
                         event.logical_key
                     );
                 }
-                self.cpu
-                    .bus
-                    .iou
-                    .keyboard
-                    .key_up(phys, self.cpu.bus.iou.cycles);
+                self.send_input(InputEvent::KeyUp { key: phys });
             }
         }
 
@@ -1177,12 +1298,33 @@ impl App {
                     self.power_on_time = Instant::now();
                     self.cpu.bus.iou.iwm.drive_audio.trigger_channel_static();
                 }
+                Key::Named(NamedKey::F4) => {
+                    let path = screenshot::next_path();
+                    match screenshot::save_png(&self.cpu.bus.video, &path) {
+                        Ok(()) => println!("Saved screenshot: {}", path.display()),
+                        Err(e) => println!("Error saving screenshot: {}", e),
+                    }
+                }
+                Key::Named(NamedKey::F5) => {
+                    save_state(&self.cpu, Path::new(savestate::QUICKSAVE_PATH));
+                }
                 Key::Named(NamedKey::F6) => {
                     self.show_toolbar = !self.show_toolbar;
                     if let Some(window) = &self.window {
                         window.request_redraw();
                     }
                 }
+                Key::Named(NamedKey::F9) => {
+                    load_state(&mut self.cpu, Path::new(savestate::QUICKSAVE_PATH));
+                    self.stop_movie("state loaded");
+                }
+                Key::Named(NamedKey::F11) => {
+                    if self.recorder.is_some() {
+                        self.stop_recording();
+                    } else {
+                        self.start_recording(&recorder::next_dir());
+                    }
+                }
                 Key::Named(NamedKey::F10) => {
                     let new_debug_state = !self.cpu.debug;
                     self.cpu.debug = new_debug_state;
@@ -1204,3 +1346,21 @@ pub fn run_monitor_mode(cpu: &mut CPU) {
     let mut monitor = Monitor::new(cpu);
     monitor.repl();
 }
+
+fn save_state(cpu: &CPU, path: &Path) {
+    match savestate::save_to_file(cpu, path) {
+        Ok(()) => println!("Saved state: {}", path.display()),
+        Err(e) => println!("Error saving state: {}", e),
+    }
+}
+
+fn load_state(cpu: &mut CPU, path: &Path) {
+    match savestate::load_from_file(cpu, path) {
+        Ok(()) => {
+            // Host keys held at save time are not held now
+            cpu.bus.iou.keyboard.release_all();
+            println!("Loaded state: {}", path.display());
+        }
+        Err(e) => println!("Error loading state: {}", e),
+    }
+}
@@ -29,6 +29,33 @@ pub struct AudioProducers {
     pub drive_audio: AudioProducer,
 }
 
+/// Copy of the samples a source generates, taken in emulated time.
+///
+/// Sources push into their ring buffer for playback and, while a tap is
+/// enabled, into the tap as well so a recorder can mix the exact stream
+/// the emulator produced regardless of host playback timing.
+#[derive(Default)]
+pub struct SampleTap {
+    buf: Option<Vec<f32>>,
+}
+
+impl SampleTap {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.buf = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        if let Some(buf) = &mut self.buf {
+            buf.extend_from_slice(samples);
+        }
+    }
+
+    /// Take everything captured since the last call
+    pub fn take(&mut self) -> Vec<f32> {
+        self.buf.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+}
+
 // Maximum callback buffer size we expect (4096 samples is typical max)
 const MAX_CALLBACK_SIZE: usize = 4096;
 
@@ -198,12 +225,12 @@ impl DummyAudioMixer {
 
 /// Soft clipping to prevent harsh distortion when mixing
 #[inline(always)]
-fn soft_clip(x: f32) -> f32 {
+pub fn soft_clip(x: f32) -> f32 {
     // Fast soft clipper using polynomial approximation for small values
     // tanh for larger values
-    if x >= -0.5 && x <= 0.5 {
+    if (-0.5..=0.5).contains(&x) {
         x
-    } else if x >= -1.5 && x <= 1.5 {
+    } else if (-1.5..=1.5).contains(&x) {
         // Cubic approximation of tanh in the -1.5..1.5 range
         x - x * x * x / 3.0
     } else {
@@ -0,0 +1,141 @@
+use clap::Parser;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+// The trace format and opcode table are shared with the emulator
+#[allow(dead_code)]
+#[path = "../opcodes.rs"]
+mod opcodes;
+#[allow(dead_code)]
+#[path = "../trace.rs"]
+mod trace;
+
+use crate::trace::{TraceFilter, TraceReader, TraceRecord};
+
+#[derive(Parser)]
+#[command(name = "iictrace", about = "Decode binary instruction traces written by rust-iic --trace")]
+struct Cli {
+    /// Trace file
+    input: PathBuf,
+
+    /// Write the text to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Only instructions with PC in this range (hex), e.g. 0800-0FFF
+    #[arg(long, value_name = "LO-HI")]
+    range: Option<String>,
+
+    /// Only instructions from these banks: comma-separated main, aux, lcmain1, lcmain2,
+    /// lcaux1, lcaux2, rom1, rom2
+    #[arg(long)]
+    bank: Option<String>,
+
+    /// Only instructions starting in this cycle window; FROM- runs to the end
+    #[arg(long, value_name = "FROM-TO")]
+    cycles: Option<String>,
+
+    /// Skip this many matching instructions first
+    #[arg(long, default_value_t = 0)]
+    skip: u64,
+
+    /// Stop after this many instructions
+    #[arg(short = 'n', long)]
+    count: Option<u64>,
+
+    /// Print a summary of the matching instructions instead of listing them
+    #[arg(long)]
+    stats: bool,
+}
+
+#[derive(Default)]
+struct Stats {
+    instructions: u64,
+    first: Option<TraceRecord>,
+    last: Option<TraceRecord>,
+    // Cycles run between the first and last record. A reset or state load
+    // can put the clock back, so each stretch it runs forward is counted
+    // on its own.
+    cycles: u64,
+    clock_resets: u64,
+    banks: BTreeMap<&'static str, u64>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let mut filter = TraceFilter::default();
+    if let Some(range) = &cli.range {
+        filter.range = Some(TraceFilter::parse_range(range)?);
+    }
+    if let Some(banks) = &cli.bank {
+        filter.banks = TraceFilter::parse_banks(banks)?;
+    }
+    if let Some(cycles) = &cli.cycles {
+        filter.cycles = Some(TraceFilter::parse_cycles(cycles)?);
+    }
+
+    let reader = TraceReader::open(&cli.input)
+        .map_err(|e| anyhow::anyhow!("{}: {}", cli.input.display(), e))?;
+    let header = reader.header.clone();
+    let mut out: Box<dyn Write> = match &cli.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+
+    let mut stats = Stats::default();
+    let mut skip = cli.skip;
+    for record in reader {
+        let record = record?;
+        if !filter.matches(&record) {
+            continue;
+        }
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        if cli.count.is_some_and(|count| stats.instructions >= count) {
+            break;
+        }
+        stats.instructions += 1;
+        if cli.stats {
+            stats.first.get_or_insert(record);
+            if let Some(last) = stats.last {
+                match record.cycles.checked_sub(last.cycles) {
+                    Some(run) => stats.cycles += run,
+                    None => stats.clock_resets += 1,
+                }
+            }
+            stats.last = Some(record);
+            *stats.banks.entry(record.bank_name()).or_default() += 1;
+            continue;
+        }
+        match writeln!(out, "{}", record.text(header.set)) {
+            // Piped into head or less and closed early
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            result => result?,
+        }
+    }
+
+    if cli.stats {
+        writeln!(out, "CPU: {}", header.cpu)?;
+        writeln!(out, "Instructions: {}", stats.instructions)?;
+        if let (Some(first), Some(last)) = (stats.first, stats.last) {
+            write!(out, "Cycles: {}-{} ({} cycles run", first.cycles, last.cycles, stats.cycles)?;
+            match stats.clock_resets {
+                0 => writeln!(out, ")")?,
+                1 => writeln!(out, "; the clock went back once)")?,
+                n => writeln!(out, "; the clock went back {} times)", n)?,
+            }
+        }
+        for (bank, count) in &stats.banks {
+            writeln!(out, "  {:<8} {:>12}", bank, count)?;
+        }
+    }
+    match out.flush() {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
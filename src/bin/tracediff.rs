@@ -0,0 +1,347 @@
+// Compare an instruction trace from rust-iic against a reference log and
+// report the first instruction where they part: a different PC, register
+// or instruction timing.
+//
+// Either side may be a binary trace (--trace FILE) or a text log with one
+// instruction per line. Text lines are read loosely so logs from other
+// emulators work without conversion:
+//
+//   rust-iic   `  100007  rom1    $FCAC  D0 FC  BNE $FCAA  A=00 X=36 ... P=nv-bdIZC`
+//   MAME       `FF59: cld`, with registers from a tracelog prefix such as
+//              trace cpu.log,maincpu,noloop,{tracelog "A=%02X X=%02X Y=%02X P=%02X S=%02X ",a,x,y,p,s}
+//   AppleWin   `FF59:D8        CLD   A=00 X=00 Y=00 P=24 S=F7`
+//   nestest    `C000  4C F5 C5  JMP $C5F5  A:00 X:00 Y:00 P:24 SP:FD CYC:7`
+//
+// The PC is a `PC=` field, or the first word that is not a register field.
+// Registers are A, X, Y, P and S or SP, written `A=00` or `A:00`; P may be
+// hex or a flag string like `nv-BdIzc`. A `CYC`, `CYCLES` or `TC` field
+// gives the cycle count. Fields a line leaves out are not compared, and
+// lines without a PC are skipped. Cycle counts are compared from the first
+// instruction of each side, so the two need not count from the same point.
+//
+// Exits 0 when the traces agree to the end of both, 1 at a divergence or
+// when one ends before the other, and 2 on an error.
+
+use clap::Parser;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+
+// The trace format and opcode table are shared with the emulator
+#[allow(dead_code)]
+#[path = "../opcodes.rs"]
+mod opcodes;
+#[allow(dead_code)]
+#[path = "../trace.rs"]
+mod trace;
+
+use crate::trace::{TraceReader, BANK_NAMES, MAGIC};
+
+#[derive(Parser)]
+#[command(
+    name = "tracediff",
+    about = "Find the first instruction where a rust-iic trace and a reference log disagree"
+)]
+struct Cli {
+    /// Emulator trace: binary (--trace FILE) or text
+    emulator: PathBuf,
+
+    /// Reference trace or log, e.g. from MAME or AppleWin
+    reference: PathBuf,
+
+    /// Instructions to show before and after the divergence
+    #[arg(short = 'C', long, default_value_t = 5)]
+    context: usize,
+
+    /// Skip this many instructions of the emulator trace first
+    #[arg(long, default_value_t = 0)]
+    skip_emulator: u64,
+
+    /// Skip this many instructions of the reference first
+    #[arg(long, default_value_t = 0)]
+    skip_reference: u64,
+
+    /// Compare from the start of both, instead of first skipping the emulator
+    /// trace forward to the first instruction that matches the reference's first
+    #[arg(long)]
+    no_align: bool,
+
+    /// Bits of P to compare (hex); the default leaves out B and the unused bit
+    #[arg(long, default_value = "CF")]
+    p_mask: String,
+
+    /// Fields not to compare: comma-separated a, x, y, sp, p, cycles
+    #[arg(long, value_delimiter = ',')]
+    ignore: Vec<String>,
+}
+
+// One instruction, from either side
+#[derive(Clone, Debug, Default)]
+struct Step {
+    pc: u16,
+    a: Option<u8>,
+    x: Option<u8>,
+    y: Option<u8>,
+    sp: Option<u8>,
+    p: Option<u8>,
+    cycles: Option<u64>,
+    // How the instruction appears in its file, and where
+    text: String,
+    position: String,
+}
+
+enum Source {
+    Binary(TraceReader<BufReader<File>>, u64),
+    Text(Lines<BufReader<File>>, usize),
+}
+
+impl Source {
+    fn open(path: &Path) -> Result<Self> {
+        let fail = |e: std::io::Error| anyhow!("{}: {}", path.display(), e);
+        let mut magic = [0; 8];
+        let binary = File::open(path).map_err(fail)?.read_exact(&mut magic).is_ok() && &magic == MAGIC;
+        if binary {
+            Ok(Source::Binary(TraceReader::open(path).map_err(fail)?, 0))
+        } else {
+            Ok(Source::Text(BufReader::new(File::open(path).map_err(fail)?).lines(), 0))
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<Step>> {
+        match self {
+            Source::Binary(reader, count) => {
+                let Some(record) = reader.next().transpose()? else { return Ok(None) };
+                *count += 1;
+                Ok(Some(Step {
+                    pc: record.pc,
+                    a: Some(record.a),
+                    x: Some(record.x),
+                    y: Some(record.y),
+                    sp: Some(record.sp),
+                    p: Some(record.p),
+                    cycles: Some(record.cycles),
+                    text: record.text(reader.header.set),
+                    position: format!("record {}", count),
+                }))
+            }
+            Source::Text(lines, number) => loop {
+                let Some(line) = lines.next().transpose()? else { return Ok(None) };
+                *number += 1;
+                if let Some(mut step) = parse_line(&line).map_err(|e| anyhow!("line {}: {}", number, e))? {
+                    step.text = line.trim_end().to_string();
+                    step.position = format!("line {}", number);
+                    return Ok(Some(step));
+                }
+            },
+        }
+    }
+}
+
+fn hex_u16(text: &str) -> Option<u16> {
+    let text = text.strip_prefix('$').unwrap_or(text);
+    (text.len() == 4).then(|| u16::from_str_radix(text, 16).ok()).flatten()
+}
+
+// P as hex, or as a flag string with the set flags in upper case
+fn parse_p(text: &str) -> Option<u8> {
+    if text.len() == 8 && text.chars().all(|c| "NVBDIZC-".contains(c.to_ascii_uppercase())) {
+        return Some(text.chars().enumerate().fold(0x20, |p, (i, c)| if c.is_ascii_uppercase() { p | (0x80 >> i) } else { p }));
+    }
+    u8::from_str_radix(text.strip_prefix('$').unwrap_or(text), 16).ok()
+}
+
+fn parse_line(line: &str) -> Result<Option<Step>> {
+    if line.contains("loops for") {
+        bail!("the log folds repeated loops; trace with MAME's noloop option");
+    }
+    let mut step = Step::default();
+    let mut pc = None;
+    let mut words = Vec::new();
+    for token in line.split(|c: char| c.is_whitespace() || c == ',').filter(|t| !t.is_empty()) {
+        let field = token.split_once('=').or_else(|| token.split_once(':'));
+        let Some((key, value)) = field else {
+            words.push(token);
+            continue;
+        };
+        let byte = || u8::from_str_radix(value.strip_prefix('$').unwrap_or(value), 16).ok();
+        match key.to_ascii_uppercase().as_str() {
+            "A" => step.a = byte(),
+            "X" => step.x = byte(),
+            "Y" => step.y = byte(),
+            "S" | "SP" => step.sp = byte(),
+            "P" => step.p = parse_p(value),
+            "PC" => pc = hex_u16(value),
+            "CYC" | "CYCLES" | "TC" => step.cycles = value.parse().ok(),
+            _ => words.push(token),
+        }
+    }
+
+    // rust-iic text traces start with the cycle count and the bank
+    if let [cycles, bank, at, ..] = words[..] {
+        if cycles.bytes().all(|b| b.is_ascii_digit()) && (bank == "-" || BANK_NAMES.contains(&bank)) {
+            step.cycles = cycles.parse().ok();
+            pc = pc.or_else(|| hex_u16(at));
+        }
+    }
+    // Otherwise the PC leads the line, as `FF59:`, `FF59:D8`, `$FF59` or `FF59`
+    let pc = pc.or_else(|| words.first().and_then(|word| hex_u16(word.split(':').next().unwrap_or(word))));
+    Ok(pc.map(|pc| Step { pc, ..step }))
+}
+
+struct Compare {
+    p_mask: u8,
+    ignore: Vec<String>,
+}
+
+impl Compare {
+    // What differs between the two, given the cycle counts each side
+    // started from
+    fn differences(&self, emulator: &Step, reference: &Step, bases: (Option<u64>, Option<u64>)) -> Vec<String> {
+        let mut differences = Vec::new();
+        if emulator.pc != reference.pc {
+            differences.push(format!("PC: emulator ${:04X}, reference ${:04X}", emulator.pc, reference.pc));
+        }
+        let registers = [
+            ("a", "A", emulator.a, reference.a, 0xFF),
+            ("x", "X", emulator.x, reference.x, 0xFF),
+            ("y", "Y", emulator.y, reference.y, 0xFF),
+            ("sp", "SP", emulator.sp, reference.sp, 0xFF),
+            ("p", "P", emulator.p, reference.p, self.p_mask),
+        ];
+        for (field, name, ours, theirs, mask) in registers {
+            if let (Some(ours), Some(theirs)) = (ours, theirs) {
+                if !self.ignore.iter().any(|i| i == field) && ours & mask != theirs & mask {
+                    differences.push(format!("{}: emulator ${:02X}, reference ${:02X}", name, ours, theirs));
+                }
+            }
+        }
+        if let (Some(ours), Some(theirs), (Some(our_base), Some(their_base))) = (emulator.cycles, reference.cycles, bases) {
+            let (ours, theirs) = (ours.wrapping_sub(our_base), theirs.wrapping_sub(their_base));
+            if !self.ignore.iter().any(|i| i == "cycles") && ours != theirs {
+                // Earlier instructions agreed, so the one before this is out
+                let excess = ours as i64 - theirs as i64;
+                differences.push(format!(
+                    "cycles: emulator +{}, reference +{} from the first instruction; the one before took {} {} in the emulator",
+                    ours,
+                    theirs,
+                    excess.abs(),
+                    if excess > 0 { "cycles more" } else { "cycles fewer" },
+                ));
+            }
+        }
+        differences
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(true) => std::process::exit(0),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("tracediff: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+// Returns whether the traces agree
+fn run(cli: &Cli) -> Result<bool> {
+    let p_mask = u8::from_str_radix(&cli.p_mask, 16).map_err(|_| anyhow!("bad --p-mask '{}'", cli.p_mask))?;
+    for field in &cli.ignore {
+        if !["a", "x", "y", "sp", "p", "cycles"].contains(&field.as_str()) {
+            bail!("unknown field '{}' for --ignore", field);
+        }
+    }
+    let compare = Compare { p_mask, ignore: cli.ignore.clone() };
+
+    let mut emulator = Source::open(&cli.emulator)?;
+    let mut reference = Source::open(&cli.reference)?;
+    for _ in 0..cli.skip_emulator {
+        emulator.next()?;
+    }
+    for _ in 0..cli.skip_reference {
+        reference.next()?;
+    }
+
+    let Some(mut theirs) = reference.next()? else { bail!("no instructions in {}", cli.reference.display()) };
+    let mut ours = emulator.next()?;
+    if !cli.no_align {
+        let mut skipped = 0u64;
+        while ours.as_ref().is_some_and(|step| !compare.differences(step, &theirs, (None, None)).is_empty()) {
+            ours = emulator.next()?;
+            skipped += 1;
+        }
+        if ours.is_none() {
+            bail!("nothing in the emulator trace matches the reference's first instruction, at ${:04X}", theirs.pc);
+        }
+        if skipped > 0 {
+            println!("Skipped {} emulator instructions to reach ${:04X}", skipped, theirs.pc);
+        }
+    }
+    let Some(mut ours) = ours else { bail!("no instructions in {}", cli.emulator.display()) };
+
+    let bases = (ours.cycles, theirs.cycles);
+    let mut history: VecDeque<(Step, Step)> = VecDeque::with_capacity(cli.context + 1);
+    let mut compared = 0u64;
+    loop {
+        let differences = compare.differences(&ours, &theirs, bases);
+        if !differences.is_empty() {
+            println!("First divergence after {} matching instructions:", compared);
+            println!("  emulator {}, reference {}", ours.position, theirs.position);
+            for difference in &differences {
+                println!("  {}", difference);
+            }
+            println!();
+            for (before_ours, before_theirs) in &history {
+                print_pair("  ", before_ours, before_theirs);
+            }
+            print_pair("> ", &ours, &theirs);
+            for _ in 0..cli.context {
+                match (emulator.next()?, reference.next()?) {
+                    (Some(ours), Some(theirs)) => print_pair("  ", &ours, &theirs),
+                    _ => break,
+                }
+            }
+            return Ok(false);
+        }
+
+        compared += 1;
+        if history.len() == cli.context {
+            history.pop_front();
+        }
+        if cli.context > 0 {
+            history.push_back((ours, theirs));
+        }
+        match (emulator.next()?, reference.next()?) {
+            (Some(next_ours), Some(next_theirs)) => (ours, theirs) = (next_ours, next_theirs),
+            (None, None) => {
+                println!("Traces agree over all {} instructions", compared);
+                return Ok(true);
+            }
+            // Agreement as far as one side goes is not agreement
+            (None, Some(next_theirs)) => {
+                println!("The emulator trace ends after {} matching instructions; the reference goes on at {}:", compared, next_theirs.position);
+                println!("  ref {}", next_theirs.text);
+                return Ok(false);
+            }
+            (Some(next_ours), None) => {
+                println!("The reference ends after {} matching instructions; the emulator trace goes on at {}:", compared, next_ours.position);
+                println!("  emu {}", next_ours.text);
+                return Ok(false);
+            }
+        }
+    }
+}
+
+fn print_pair(marker: &str, ours: &Step, theirs: &Step) {
+    println!("{}emu {}", marker, ours.text);
+    println!("{}ref {}", marker, theirs.text);
+}
+
+#[cfg(test)]
+#[path = "../tests/tracediff.rs"]
+mod tests;
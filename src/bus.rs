@@ -1,10 +1,16 @@
+use crate::coverage::{Access, Coverage};
 use crate::cpu::{CpuType, SystemType};
 use crate::device::speaker::AudioProducer;
+use crate::disassembler::SymbolTable;
+use crate::hooks::{WatchHit, WatchKind, WatchList};
 use crate::interrupts::InterruptController;
 use crate::iou::IOU;
 use crate::memory::Memory;
-use crate::mmu::MMU;
+use crate::mmu::{Bank, MemoryView, MMU};
 use crate::rom::ROM;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+use crate::softswitch::{self, SwitchAccess, SwitchTrace};
+use crate::text_screen::{self, TextScreen};
 use crate::util::mem_state_to_string;
 use crate::video::{Video, VideoModeMask};
 use crate::timing;
@@ -12,6 +18,14 @@ use crate::timing;
 const MEMORY_SIZE: usize = 64 * 1024;
 const RAM_BANK_SIZE: usize = 48 * 1024;
 
+// One CPU bus access on the Generic system
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BusAccess {
+    pub addr: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
 pub struct Bus {
     system_type: SystemType,
     pub iou: IOU,
@@ -21,6 +35,22 @@ pub struct Bus {
 
     pub video: Video,
     pub i_port: u8, // Klauss IRQ/NMI Feedback Register
+    pub feedback_port: bool, // $BFFC writes go to i_port instead of RAM (Generic only)
+
+    // Generic-system accesses in order, when enabled (conformance tests)
+    pub access_log: Option<Vec<BusAccess>>,
+
+    // Memory watches, checked on every read and write
+    pub watches: WatchList,
+
+    // Soft-switch accesses ($C000-$C0FF on the //c), when tracing
+    pub switch_trace: Option<SwitchTrace>,
+
+    // Labels for the disassembler, the monitor and the CPU monitor window
+    pub symbols: SymbolTable,
+
+    // Bytes executed, read and written in each bank, when recording
+    pub coverage: Option<Box<Coverage>>,
 
     pub debug: bool,
 }
@@ -44,12 +74,22 @@ impl Bus {
 
             // #[cfg(feature = "klauss-interrupt-test")]
             i_port: 0,
+            feedback_port: true,
+            access_log: None,
+            watches: WatchList::default(),
+            switch_trace: None,
+            symbols: SymbolTable::new(),
+            coverage: None,
             debug: false,
         }
     }
 
-    pub fn randomize_ram(&mut self) {
-        self.mmu.randomize_ram();
+    pub fn randomize_ram(&mut self, seed: u64) {
+        self.mmu.randomize_ram(seed);
+    }
+
+    pub fn ram_seed(&self) -> u64 {
+        self.mmu.ram_seed()
     }
 
     pub fn mmu_mem_state_to_string(&self) -> String {
@@ -60,6 +100,22 @@ impl Bus {
         self.video.update(&self.iou, &self.mmu);
     }
 
+    // Text screen as currently displayed
+    pub fn text_screen(&self) -> TextScreen {
+        text_screen::read_text_screen(&self.iou, &self.mmu)
+    }
+
+    // Text page 1 or 2 in the current 40/80-column and character set mode
+    pub fn text_page(&self, page2: bool) -> TextScreen {
+        let video_mode = self.iou.video_mode.get();
+        text_screen::read_text_page(
+            &self.mmu,
+            page2,
+            check_bits_u8!(video_mode, VideoModeMask::COL80),
+            check_bits_u8!(video_mode, VideoModeMask::ALTCHAR),
+        )
+    }
+
     pub fn video_begin_frame(&mut self) {
         self.video.begin_frame();
     }
@@ -74,6 +130,9 @@ impl Bus {
 
     pub fn load_rom(&mut self, rom: ROM) {
         if self.system_type == SystemType::AppleIIc {
+            if let Some(revision) = rom.revision {
+                self.iou.set_rom_revision(revision);
+            }
             self.mmu.load_rom(rom);
         } else {
             self.bus_ram.load_bytes(0, &rom.data[0..MEMORY_SIZE]);
@@ -82,7 +141,7 @@ impl Bus {
 
     pub fn peek_byte(&mut self, addr: u16) -> u8 {
         if self.system_type == SystemType::AppleIIc {
-            if addr >= 0xC000 && addr <= 0xC0FF {
+            if (0xC000..=0xC0FF).contains(&addr) {
                 // TODO: for now, return 0 for soft switches to avoid side effects
                 0x00
             } else {
@@ -93,36 +152,87 @@ impl Bus {
         }
     }
 
+    // Byte in one bank of IIc memory, whatever the soft switches select
+    pub fn peek_view(&self, view: MemoryView, addr: u16) -> Option<u8> {
+        if self.system_type != SystemType::AppleIIc {
+            return None;
+        }
+        self.mmu.peek_view(view, addr)
+    }
+
+    // View a CPU read of `addr` comes from in the current memory state
+    pub fn read_view(&self, addr: u16) -> Option<MemoryView> {
+        if self.system_type != SystemType::AppleIIc {
+            return None;
+        }
+        self.mmu.read_view(&self.iou, addr)
+    }
+
+    pub fn poke_view(&mut self, view: MemoryView, addr: u16, value: u8) -> bool {
+        self.system_type == SystemType::AppleIIc && self.mmu.poke_view(view, addr, value)
+    }
+
     pub fn update_interrupts(&mut self) {
         if self.system_type == SystemType::AppleIIc {
             self.interrupts.irq = self.iou.check_interrupts();
             if self.interrupts.irq {
                 self.interrupts.waiting = false;
             }
+        } else if self.feedback_port {
+            // IRQ is level-triggered: held for as long as bit 0 is set
+            self.interrupts.irq = self.i_port & (1 << 0) != 0;
+            if self.interrupts.irq {
+                self.interrupts.waiting = false;
+            }
         }
     }
 
     pub fn read_byte(&mut self, addr: u16) -> u8 {
+        self.read_byte_as(addr, Access::READ)
+    }
+
+    // A read counted as the given kind of access: EXECUTE for an
+    // instruction byte read at PC
+    pub fn read_byte_as(&mut self, addr: u16, access: Access) -> u8 {
+        // Resolve the bank first: a soft-switch read may change it.
+        // Instruction bytes are left to execute watches.
+        let watched = if access != Access::EXECUTE && !self.watches.is_empty() && self.watches.covers(WatchKind::READ, addr) {
+            Some(self.read_bank(addr))
+        } else {
+            None
+        };
+        if self.coverage.is_some() {
+            self.mark_coverage(addr, access);
+        }
+        let value = self.read_byte_unwatched(addr);
+        if let Some(bank) = watched {
+            self.fire_watch(WatchKind::READ, addr, value, None, bank);
+        }
+        value
+    }
+
+    fn read_byte_unwatched(&mut self, addr: u16) -> u8 {
         if self.system_type == SystemType::AppleIIc {
-            if addr >= 0xC000 && addr <= 0xC0FF {
-                let result = self.handle_iic_read(addr);
-                if self.debug {
-                    println!("SoftSwitch Read: {:#06X} = {:#04X}", addr, result);
+            if (0xC000..=0xC0FF).contains(&addr) {
+                if !self.debug && self.switch_trace.is_none() {
+                    return self.handle_iic_read(addr);
                 }
+                let access = self.switch_access(addr, false);
+                let result = self.handle_iic_read(addr);
+                self.trace_switch(access, result);
                 result
             } else {
                 self.mmu.read_byte(&mut self.iou, addr)
             }
         } else {
-            // #[cfg(feature = "klauss-interrupt-test")]
-            // match addr {
-            //     0xBFFC => {
-            //         println!("Reading $BFFC: {:#04X}", self.i_port);
-            //         self.i_port
-            //     }
-            //     _ => self.testmem.read_byte(addr),
-            // }
-            self.bus_ram.read_byte(addr)
+            let value = match addr {
+                0xBFFC if self.feedback_port => self.i_port,
+                _ => self.bus_ram.read_byte(addr),
+            };
+            if let Some(log) = self.access_log.as_mut() {
+                log.push(BusAccess { addr, value, write: false });
+            }
+            value
         }
     }
 
@@ -133,43 +243,49 @@ impl Bus {
     }
 
     pub fn write_byte(&mut self, addr: u16, value: u8) -> u8 {
+        let watched = if !self.watches.is_empty() && self.watches.covers(WatchKind::WRITE, addr) {
+            Some(self.write_target(addr))
+        } else {
+            None
+        };
+        if self.coverage.is_some() {
+            self.mark_coverage(addr, Access::WRITE);
+        }
+        let result = self.write_byte_unwatched(addr, value);
+        if let Some((bank, old)) = watched {
+            self.fire_watch(WatchKind::WRITE, addr, value, old, bank);
+        }
+        result
+    }
+
+    fn write_byte_unwatched(&mut self, addr: u16, value: u8) -> u8 {
         if self.system_type == SystemType::AppleIIc {
-            if addr >= 0xC000 && addr <= 0xC0FF {
-                let result = self.handle_iic_write(addr, value);
-                if self.debug {
-                    println!("SoftSwitch Write: {:#06X} = {:#04X}", addr, value);
+            if (0xC000..=0xC0FF).contains(&addr) {
+                if !self.debug && self.switch_trace.is_none() {
+                    return self.handle_iic_write(addr, value);
                 }
+                let access = self.switch_access(addr, true);
+                let result = self.handle_iic_write(addr, value);
+                self.trace_switch(access, value);
                 result
             } else {
-                let video_mode = self.iou.video_mode.get();
-                let is_page2 = (video_mode & crate::video::VideoModeMask::PAGE2) != 0;
-                let is_hires = (video_mode & crate::video::VideoModeMask::HIRES) != 0;
-                let is_80store = self.iou.is_80store.get();
-                let mem_state = self.iou.mem_state.get();
-
-                self.mmu.write_byte(
-                    &mut self.iou,
-                    addr,
-                    value,
-                    mem_state,
-                    is_80store,
-                    is_page2,
-                    is_hires,
-                )
+                self.mmu.write_byte(&mut self.iou, addr, value)
             }
         } else {
+            if let Some(log) = self.access_log.as_mut() {
+                log.push(BusAccess { addr, value, write: true });
+            }
             match addr {
-                0xBFFC => {
+                0xBFFC if self.feedback_port => {
+                    // NMI is edge-triggered on bit 1 going high
+                    let nmi_edge = value & !self.i_port & (1 << 1) != 0;
                     self.i_port = value;
 
-                    let irq_triggered = value & (1 << 0) != 0;
-                    let nmi_triggered = value & (1 << 1) != 0;
-
-                    if irq_triggered {
+                    if value & (1 << 0) != 0 {
                         self.interrupts.request_irq();
                     }
 
-                    if nmi_triggered {
+                    if nmi_edge {
                         self.interrupts.request_nmi();
                     }
 
@@ -180,6 +296,91 @@ impl Bus {
         }
     }
 
+    // Fire execute watches for the opcode fetched at `addr`
+    pub fn watch_execute(&mut self, addr: u16, opcode: u8) {
+        if self.watches.covers(WatchKind::EXECUTE, addr) {
+            let bank = self.read_bank(addr);
+            self.fire_watch(WatchKind::EXECUTE, addr, opcode, None, bank);
+        }
+    }
+
+    // Bank a CPU read of `addr` resolves to in the current memory state
+    pub fn read_bank(&self, addr: u16) -> Bank {
+        match self.system_type {
+            SystemType::AppleIIc => self.mmu.read_bank(&self.iou, addr),
+            SystemType::Generic if addr == 0xBFFC && self.feedback_port => Bank::Io,
+            SystemType::Generic => Bank::Main,
+        }
+    }
+
+    // Count an access in the bank it reaches; the Generic system's memory
+    // is all main RAM
+    pub fn mark_coverage(&mut self, addr: u16, access: Access) {
+        let view = match self.system_type {
+            SystemType::AppleIIc if access == Access::WRITE => self.mmu.write_view(&self.iou, addr),
+            SystemType::AppleIIc => self.mmu.read_view(&self.iou, addr),
+            SystemType::Generic if addr == 0xBFFC && self.feedback_port => None,
+            SystemType::Generic => Some(MemoryView::Main),
+        };
+        if let (Some(view), Some(coverage)) = (view, self.coverage.as_mut()) {
+            coverage.mark(view, addr, access);
+        }
+    }
+
+    // Bank a CPU write of `addr` lands in, and the byte it replaces
+    fn write_target(&self, addr: u16) -> (Bank, Option<u8>) {
+        match self.system_type {
+            SystemType::AppleIIc => self.mmu.write_target(&self.iou, addr),
+            SystemType::Generic if addr == 0xBFFC && self.feedback_port => (Bank::Io, None),
+            SystemType::Generic => (Bank::Main, Some(self.bus_ram.read_byte(addr))),
+        }
+    }
+
+    fn fire_watch(&mut self, kind: WatchKind, addr: u16, value: u8, old: Option<u8>, bank: Bank) {
+        let pc = self.iou.current_pc.get();
+        self.watches.fire(&WatchHit { kind, addr, value, old, bank, pc });
+    }
+
+    // A soft-switch access about to be made, named from the state before it
+    fn switch_access(&self, addr: u16, write: bool) -> SwitchAccess {
+        let (device, name) = softswitch::lookup(addr, write, self.iou.ioudis.get());
+        let scc_reg = matches!(addr, 0xC038 | 0xC039).then(|| self.iou.scc.register_pointer(addr));
+        SwitchAccess {
+            cycle: self.iou.cycles,
+            pc: self.iou.current_pc.get(),
+            addr,
+            value: 0,
+            write,
+            device,
+            name,
+            scc_reg,
+            mem_state: 0,
+            video_mode: 0,
+        }
+    }
+
+    // Complete an access with its value and the state it left, then log it
+    fn trace_switch(&mut self, access: SwitchAccess, value: u8) {
+        let access = SwitchAccess {
+            value,
+            mem_state: self.iou.mem_state.get(),
+            video_mode: self.iou.video_mode.get(),
+            ..access
+        };
+        if self.debug {
+            println!("SoftSwitch {}", access);
+        }
+        if let Some(trace) = self.switch_trace.as_mut() {
+            trace.record(access);
+        }
+    }
+
+    pub fn flush_switch_trace(&mut self) {
+        if let Some(trace) = self.switch_trace.as_mut() {
+            trace.flush();
+        }
+    }
+
     pub fn write_bytes(&mut self, start: u16, bytes: &[u8]) {
         for (i, &byte) in bytes.iter().enumerate() {
             self.write_byte(start.wrapping_add(i as u16), byte);
@@ -202,23 +403,7 @@ impl Bus {
                 self.iou.zip.io_access();  // ZIP Chip: slow down for I/O
                 self.iou.ss_write(addr, value)
             },
-            _ => {
-                let video_mode = self.iou.video_mode.get();
-                let is_page2 = (video_mode & VideoModeMask::PAGE2) != 0;
-                let is_hires = (video_mode & VideoModeMask::HIRES) != 0;
-                let is_80store = self.iou.is_80store.get();
-                let mem_state = self.iou.mem_state.get();
-
-                self.mmu.write_byte(
-                    &mut self.iou,
-                    addr,
-                    value,
-                    mem_state,
-                    is_80store,
-                    is_page2,
-                    is_hires,
-                )
-            }
+            _ => self.mmu.write_byte(&mut self.iou, addr, value),
         }
     }
 
@@ -300,3 +485,25 @@ impl Bus {
         }
     }
 }
+
+impl SaveState for Bus {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"BUS ");
+        w.u8(self.i_port);
+        self.interrupts.save_state(w);
+        self.bus_ram.save_state(w);
+        self.mmu.save_state(w);
+        self.iou.save_state(w);
+        self.video.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"BUS ")?;
+        self.i_port = r.u8()?;
+        self.interrupts.load_state(r)?;
+        self.bus_ram.load_state(r)?;
+        self.mmu.load_state(r)?;
+        self.iou.load_state(r)?;
+        self.video.load_state(r)
+    }
+}
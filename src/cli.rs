@@ -14,6 +14,33 @@ pub enum ShaderType {
     Lcd,
 }
 
+/// Machine to emulate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SystemArg {
+    /// Apple //c
+    #[default]
+    Iic,
+    /// Bare CPU on 64K of flat RAM, loaded from --rom (binary or Intel HEX)
+    Generic,
+}
+
+/// CPU variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CpuArg {
+    /// NMOS 6502 with the undocumented opcodes (generic system only)
+    #[value(name = "6502")]
+    Nmos6502,
+    /// CMOS 65C02, as fitted to the //c
+    #[value(name = "65c02")]
+    Cmos65c02,
+    /// Rockwell R65C02, adds RMB, SMB, BBR and BBS
+    #[value(name = "r65c02")]
+    R65c02,
+    /// WDC 65C02S, the Rockwell instructions plus WAI and STP
+    #[value(name = "65c02s")]
+    Wdc65c02s,
+}
+
 /// Apple //c Emulator command-line arguments.
 #[derive(Parser)]
 #[command(version, about = "Apple //c Emulator")]
@@ -30,10 +57,27 @@ pub struct Args {
     #[arg(long)]
     pub monitor: bool,
 
-    /// ROM type selection (auto, 3, 4, etc.)
+    /// System: iic, or generic to run a 64K memory image on a bare CPU
+    #[arg(long, value_enum, default_value_t = SystemArg::Iic)]
+    pub system: SystemArg,
+
+    /// CPU: 6502, 65c02, r65c02 or 65c02s (default 65c02 for the //c, 6502 for generic)
+    #[arg(long, value_enum)]
+    pub cpu: Option<CpuArg>,
+
+    /// Start address for --system generic (hex), instead of the reset vector
+    #[arg(long)]
+    pub entry: Option<String>,
+
+    /// ROM revision: auto (detect by checksum), 255, 0, 3, 4 or 4x
     #[arg(long, default_value = "auto")]
     pub rom_type: String,
 
+    /// Load the //c firmware from this file instead of the built-in ROM 3;
+    /// with --system generic, the 64K memory image to run
+    #[arg(long)]
+    pub rom: Option<String>,
+
     /// Enable debug logging
     #[arg(long, short)]
     pub debug: bool,
@@ -133,4 +177,102 @@ pub struct Args {
     /// Enable paddle input via host gamepad
     #[arg(long)]
     pub paddle: bool,
+
+    /// Restore a machine save state (.iicstate) after startup
+    #[arg(long)]
+    pub load_state: Option<String>,
+
+    /// Seconds of emulated time kept for rewind (hold F2), 0 to disable
+    #[arg(long, default_value_t = 30)]
+    pub rewind_seconds: usize,
+
+    /// Run a headless test script and exit with its result (0 pass, 1 fail, 2 error)
+    #[arg(long)]
+    pub script: Option<String>,
+
+    /// Load a Lua script that registers PC hooks, timed hooks and memory watches
+    #[arg(long)]
+    pub lua: Option<String>,
+
+    /// Write every soft-switch access ($C000-$C0FF) to this file, with its name and the state it left
+    #[arg(long)]
+    pub trace_switches: Option<String>,
+
+    /// Devices to trace: comma-separated keyboard, memory, langcard, video, mouse,
+    /// paddle, speaker, scc, iwm, slot, system, or all
+    #[arg(long, default_value = "all")]
+    pub trace_devices: String,
+
+    /// Load labels from a symbol file (AppleWin .SYM, ca65 .lbl, VICE, Merlin or Ophis map);
+    /// FILE@BANK limits them to one bank: main, aux, lcmain1, lcmain2, lcaux1, lcaux2, rom1, rom2.
+    /// May be given more than once
+    #[arg(long, value_name = "FILE[@BANK]")]
+    pub symbols: Vec<String>,
+
+    /// Profile execution: cycles per address and bank, and the JSR call graph. On exit, write
+    /// the call stacks in collapsed form (for flamegraph.pl or inferno) to this file
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Record which bytes of each bank are executed, read and written. On exit, write a
+    /// coverage map to STEM.png and a code/data listing to STEM.lst
+    #[arg(long, value_name = "STEM")]
+    pub coverage: Option<String>,
+
+    /// Write every instruction run (PC, bank, bytes, registers and cycle) to this file: a compact
+    /// binary trace, or text if FILE ends in .txt. The iictrace tool decodes binary traces
+    #[arg(long)]
+    pub trace: Option<String>,
+
+    /// Only trace instructions with PC in this range (hex), e.g. 0800-0FFF
+    #[arg(long, value_name = "LO-HI")]
+    pub trace_range: Option<String>,
+
+    /// Only trace instructions from these banks: comma-separated main, aux, lcmain1, lcmain2,
+    /// lcaux1, lcaux2, rom1, rom2
+    #[arg(long)]
+    pub trace_bank: Option<String>,
+
+    /// Only trace instructions starting in this cycle window; FROM- runs to the end
+    #[arg(long, value_name = "FROM-TO")]
+    pub trace_cycles: Option<String>,
+
+    /// Start tracing when PC reaches this address (hex or symbol), and the
+    /// breakpoint condition holds if one is given, e.g. "C600 if A==FF"
+    #[arg(long, value_name = "ADDR[ if COND]")]
+    pub trace_start: Option<String>,
+
+    /// Pause tracing after the instruction at this address (hex or symbol),
+    /// if the breakpoint condition holds when one is given
+    #[arg(long, value_name = "ADDR[ if COND]")]
+    pub trace_stop: Option<String>,
+
+    /// Keep only the last N traced instructions, and write them on exit
+    #[arg(long, value_name = "N")]
+    pub trace_ring: Option<usize>,
+
+    /// Serve the GDB remote protocol on this local TCP port (the generic system waits for the debugger)
+    #[arg(long)]
+    pub gdb: Option<u16>,
+
+    /// Serve the Debug Adapter Protocol on this local TCP port, for VS Code's "debugServer"
+    /// (the generic system waits for the client)
+    #[arg(long)]
+    pub dap: Option<u16>,
+
+    /// Record video frames (PNG) and audio (WAV) into this directory from startup (F11 toggles)
+    #[arg(long)]
+    pub record: Option<String>,
+
+    /// Record all keyboard, mouse, paddle and disk input to an input movie file
+    #[arg(long)]
+    pub record_movie: Option<String>,
+
+    /// Replay an input movie recorded with --record-movie
+    #[arg(long)]
+    pub play_movie: Option<String>,
+
+    /// Seed for the power-on RAM pattern (random if omitted)
+    #[arg(long)]
+    pub ram_seed: Option<u64>,
 }
@@ -0,0 +1,221 @@
+// Code/data coverage
+//
+// Records, for every byte of each memory bank, whether it was executed,
+// read as data or written. Marks are made by the bus as the CPU makes each
+// access, so they land in the bank the access reached: main and aux RAM,
+// each LC bank and each ROM bank are kept apart. Instruction bytes fetched
+// at PC count as executed, immediate operands included; every other read
+// counts as data, dummy reads included. The Generic system's flat 64K is
+// kept as main RAM.
+//
+// $E000-$FFFF is the same RAM in both LC banks of a side, so it is kept
+// with bank 1 only.
+//
+// Two exports:
+//
+//   PNG map: 256 pixels a row, 256 rows to a bank, one band per bank that
+//     was touched, in MemoryView order. Green is executed, blue read and red
+//     written; a byte that was more than one of those mixes the colours.
+//   Listing: each touched region of each bank, disassembled where it was
+//     executed and as .byte lines where it was only read or written.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use bitflags::bitflags;
+
+use crate::bus::Bus;
+use crate::cpu::CpuType;
+use crate::disassembler::Disassembler;
+use crate::mmu::MemoryView;
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Access: u8 {
+        const EXECUTE = 0b001;
+        const READ    = 0b010;
+        const WRITE   = 0b100;
+    }
+}
+
+// Bytes of data on each .byte line of the listing
+const BYTES_PER_LINE: usize = 8;
+
+pub struct Coverage {
+    // Access flags by address, for each MemoryView; empty until touched
+    maps: [Vec<u8>; 8],
+    // STEM.png and STEM.lst written here by CPU::stop_coverage (--coverage)
+    pub output: Option<PathBuf>,
+}
+
+impl Coverage {
+    pub fn new(output: Option<PathBuf>) -> Self {
+        Self { maps: Default::default(), output }
+    }
+
+    pub fn reset(&mut self) {
+        self.maps = Default::default();
+    }
+
+    #[inline]
+    pub fn mark(&mut self, view: MemoryView, addr: u16, access: Access) {
+        let view = match view {
+            MemoryView::LcMain2 if addr >= 0xE000 => MemoryView::LcMain1,
+            MemoryView::LcAux2 if addr >= 0xE000 => MemoryView::LcAux1,
+            view => view,
+        };
+        let map = &mut self.maps[view as usize];
+        if map.is_empty() {
+            map.resize(0x10000, 0);
+        }
+        map[addr as usize] |= access.bits();
+    }
+
+    pub fn get(&self, view: MemoryView, addr: u16) -> Access {
+        let map = &self.maps[view as usize];
+        Access::from_bits_truncate(map.get(addr as usize).copied().unwrap_or(0))
+    }
+
+    // Banks with at least one byte touched
+    pub fn views(&self) -> impl Iterator<Item = MemoryView> + '_ {
+        MemoryView::ALL.into_iter().filter(|&view| self.maps[view as usize].iter().any(|&flags| flags != 0))
+    }
+
+    // Bytes executed, read and written, over all banks
+    pub fn counts(&self) -> [usize; 3] {
+        let mut counts = [0; 3];
+        for map in &self.maps {
+            for &flags in map {
+                for (i, access) in [Access::EXECUTE, Access::READ, Access::WRITE].into_iter().enumerate() {
+                    counts[i] += (flags & access.bits() != 0) as usize;
+                }
+            }
+        }
+        counts
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<()> {
+        let views: Vec<MemoryView> = self.views().collect();
+        if views.is_empty() {
+            return Err(anyhow!("nothing covered yet"));
+        }
+        let mut image = image::RgbImage::new(256, 256 * views.len() as u32);
+        for (band, &view) in views.iter().enumerate() {
+            for (addr, &flags) in self.maps[view as usize].iter().enumerate() {
+                let access = Access::from_bits_truncate(flags);
+                let pixel = image::Rgb([
+                    if access.contains(Access::WRITE) { 0xFF } else { 0 },
+                    if access.contains(Access::EXECUTE) { 0xFF } else { 0 },
+                    if access.contains(Access::READ) { 0xFF } else { 0 },
+                ]);
+                image.put_pixel(addr as u32 & 0xFF, band as u32 * 256 + (addr as u32 >> 8), pixel);
+            }
+        }
+        image.save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
+
+    // Write the listing, reading memory through the bus; returns the number
+    // of regions listed
+    pub fn write_listing(&self, path: &Path, bus: &mut Bus, cpu_type: CpuType) -> io::Result<usize> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let mut regions = 0;
+        for view in self.views() {
+            let (first, mut last) = view.range();
+            // The Generic system's main RAM runs on to $FFFF
+            if self.maps[view as usize][last as usize + 1..].iter().any(|&flags| flags != 0) {
+                last = 0xFFFF;
+            }
+            if matches!(view, MemoryView::LcMain2 | MemoryView::LcAux2) {
+                last = 0xDFFF;
+            }
+            writeln!(out, "; ===== {} ${:04X}-${:04X}", view.name(), first, last)?;
+
+            let mut addr = first as u32;
+            while addr <= last as u32 {
+                let access = self.get(view, addr as u16);
+                if access.is_empty() {
+                    addr += 1;
+                    continue;
+                }
+                // A region runs while the bytes stay code, or stay data
+                let code = access.contains(Access::EXECUTE);
+                let mut end = addr;
+                let mut data_access = Access::empty();
+                while end <= last as u32 {
+                    let next = self.get(view, end as u16);
+                    if next.is_empty() || next.contains(Access::EXECUTE) != code {
+                        break;
+                    }
+                    data_access |= next;
+                    end += 1;
+                }
+                let (start, end) = (addr as u16, (end - 1) as u16);
+                regions += 1;
+
+                if code {
+                    writeln!(out, "\n; code ${:04X}-${:04X}", start, end)?;
+                    Self::list_code(&mut out, bus, view, start, end, cpu_type)?;
+                } else {
+                    let how = match (data_access.contains(Access::READ), data_access.contains(Access::WRITE)) {
+                        (true, true) => "read, written",
+                        (true, false) => "read",
+                        _ => "written",
+                    };
+                    writeln!(out, "\n; data ${:04X}-${:04X} ({})", start, end, how)?;
+                    Self::list_data(&mut out, bus, view, start, end)?;
+                }
+                addr = end as u32 + 1;
+            }
+            writeln!(out)?;
+        }
+        out.flush()?;
+        Ok(regions)
+    }
+
+    fn list_code(out: &mut impl Write, bus: &mut Bus, view: MemoryView, start: u16, end: u16, cpu_type: CpuType) -> io::Result<()> {
+        let mut addr = start as u32;
+        while addr <= end as u32 {
+            let at = addr as u16;
+            let bytes = [0, 1, 2].map(|i| peek(bus, view, at.wrapping_add(i)));
+            let (mnemonic, operand, length) = Disassembler::decode(at, bytes, cpu_type);
+            if let Some(label) = bus.symbols.lookup(at, Some(view)) {
+                writeln!(out, "{}:", label)?;
+            }
+            let dump: Vec<String> = bytes[..length].iter().map(|b| format!("{:02X}", b)).collect();
+            writeln!(out, "${:04X}  {:<8}  {:<4} {}", at, dump.join(" "), mnemonic, operand)?;
+            addr += length as u32;
+        }
+        Ok(())
+    }
+
+    fn list_data(out: &mut impl Write, bus: &mut Bus, view: MemoryView, start: u16, end: u16) -> io::Result<()> {
+        let bytes: Vec<u8> = (start..=end).map(|addr| peek(bus, view, addr)).collect();
+        for (line, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+            let at = start.wrapping_add((line * BYTES_PER_LINE) as u16);
+            let values: Vec<String> = chunk.iter().map(|b| format!("${:02X}", b)).collect();
+            writeln!(out, "${:04X}  .byte {}", at, values.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+// STEM.png and STEM.lst: the extension is added, not swapped in
+pub fn export_paths(stem: &Path) -> (PathBuf, PathBuf) {
+    let with = |extension: &str| {
+        let mut name = stem.as_os_str().to_owned();
+        name.push(extension);
+        PathBuf::from(name)
+    };
+    (with(".png"), with(".lst"))
+}
+
+// Byte of a bank as stored; the Generic system has only its flat memory
+fn peek(bus: &mut Bus, view: MemoryView, addr: u16) -> u8 {
+    match bus.peek_view(view, addr) {
+        Some(value) => value,
+        None => bus.peek_byte(addr),
+    }
+}
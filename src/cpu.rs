@@ -1,16 +1,22 @@
 use crate::bus::Bus;
+use crate::coverage;
 use crate::cpu_monitor::CpuTraceEntry;
 use crate::device::speaker::AudioProducer;
-use crate::disassembler::{Disassembler, SymbolTable};
+use crate::disassembler::Disassembler;
 use crate::hooks::{HookContext, HookManager};
 use crate::interrupts::InterruptType;
+use crate::lua::LuaScript;
+use crate::opcodes::{self, InstructionSet};
+use crate::profiler::Profiler;
 use crate::rom::ROM;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+use crate::trace::{TraceHeader, Tracer};
 use bitflags::bitflags;
 use core::fmt;
+use std::path::Path;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SystemType {
-    #[allow(dead_code)]
     Generic,
     AppleIIc,
 }
@@ -19,9 +25,48 @@ pub enum SystemType {
 pub enum CpuType {
     NMOS6502,
     CMOS65C02,
+    R65C02,
     WDC65C02S,
 }
 
+impl CpuType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CpuType::NMOS6502 => "6502",
+            CpuType::CMOS65C02 => "65C02",
+            CpuType::R65C02 => "R65C02",
+            CpuType::WDC65C02S => "65C02S",
+        }
+    }
+
+    // Opcode table for disassembly and traces
+    pub fn instruction_set(&self) -> InstructionSet {
+        match self {
+            CpuType::NMOS6502 => InstructionSet::Nmos,
+            CpuType::CMOS65C02 => InstructionSet::Cmos,
+            CpuType::R65C02 => InstructionSet::Rockwell,
+            CpuType::WDC65C02S => InstructionSet::Wdc,
+        }
+    }
+
+    // RMB/SMB/BBR/BBS: Rockwell added them and WDC carried them over. On
+    // other 65C02s $x7 and $xF are one-byte, one-cycle NOPs.
+    pub fn has_bit_instructions(&self) -> bool {
+        self.instruction_set().has_bit_instructions()
+    }
+
+    // Opcodes a 65C02 runs as one-byte, one-cycle NOPs: columns 3 and B,
+    // apart from WDC's WAI and STP, and columns 7 and F without the bit
+    // instructions
+    fn is_one_cycle_nop(&self, opcode: u8) -> bool {
+        match opcode & 0x07 {
+            0x03 => !(*self == CpuType::WDC65C02S && matches!(opcode, 0xCB | 0xDB)),
+            0x07 => !self.has_bit_instructions(),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Registers {
     pub a: u8,  // Accumulator
@@ -68,62 +113,137 @@ fn format_flags(flags: u8) -> String {
         .collect()
 }
 
-/// Get instruction length in bytes based on opcode
-/// Returns 1, 2, or 3 based on addressing mode
-fn instruction_length(opcode: u8) -> u8 {
-    // 65C02 instruction lengths by addressing mode pattern
-    match opcode {
-        // Implied/Accumulator (1 byte)
-        0x0A | 0x18 | 0x1A | 0x2A | 0x38 | 0x3A | 0x4A | 0x58 |
-        0x5A | 0x6A | 0x78 | 0x7A | 0x88 | 0x8A | 0x98 | 0x9A |
-        0xA8 | 0xAA | 0xB8 | 0xBA | 0xC8 | 0xCA | 0xD8 | 0xDA |
-        0xE8 | 0xEA | 0xF8 | 0xFA |
-        0x00 | 0x40 | 0x60 | 0xCB | 0xDB => 1, // BRK, RTI, RTS, WAI, STP
-        
-        // Immediate, Zero Page, Zero Page X/Y, (ZP,X), (ZP),Y, (ZP) (2 bytes)
-        0x09 | 0x29 | 0x49 | 0x69 | 0x89 | 0xA0 | 0xA2 | 0xA9 |
-        0xC0 | 0xC9 | 0xE0 | 0xE9 | // Immediate
-        0x05 | 0x06 | 0x14 | 0x15 | 0x16 | 0x24 | 0x25 | 0x26 |
-        0x34 | 0x35 | 0x36 | 0x45 | 0x46 | 0x55 | 0x56 | 0x64 |
-        0x65 | 0x66 | 0x74 | 0x75 | 0x76 | 0x84 | 0x85 | 0x86 |
-        0x94 | 0x95 | 0x96 | 0xA4 | 0xA5 | 0xA6 | 0xB4 | 0xB5 |
-        0xB6 | 0xC4 | 0xC5 | 0xC6 | 0xD5 | 0xD6 | 0xE4 | 0xE5 |
-        0xE6 | 0xF5 | 0xF6 | 0x04 | 0x44 => 2, // Zero page variants
-        
-        // Relative branches (2 bytes)
-        0x10 | 0x30 | 0x50 | 0x70 | 0x80 | 0x90 | 0xB0 | 0xD0 | 0xF0 => 2,
-        
-        // (ZP,X), (ZP),Y, (ZP) indirect modes (2 bytes)
-        0x01 | 0x11 | 0x12 | 0x21 | 0x31 | 0x32 | 0x41 | 0x51 |
-        0x52 | 0x61 | 0x71 | 0x72 | 0x81 | 0x91 | 0x92 | 0xA1 |
-        0xB1 | 0xB2 | 0xC1 | 0xD1 | 0xD2 | 0xE1 | 0xF1 | 0xF2 => 2,
-        
-        // Absolute, Absolute X/Y, Indirect, (Abs,X) (3 bytes)
-        _ => 3,
-    }
+/// Get instruction length in bytes from the opcode table of the CPU type
+fn instruction_length(opcode: u8, cpu_type: CpuType) -> u8 {
+    let (_, mode) = opcodes::lookup(opcode, cpu_type.instruction_set());
+    1 + mode.operand_bytes() as u8
 }
 
-
+// 65C02. The one-cycle NOPs are listed as 1 and the bit instructions as 5;
+// page crossings on indexed reads, taken branches and decimal-mode ADC/SBC
+// add cycles on top.
 const CYCLE_TABLE: [u64; 256] = [
-    7, 6, 2, 2, 2, 3, 5, 2, 3, 2, 2, 2, 2, 4, 6, 2,
-    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
-    6, 6, 2, 2, 3, 3, 5, 2, 4, 2, 2, 2, 4, 4, 6, 2,
-    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
-    6, 6, 2, 2, 2, 3, 5, 2, 3, 2, 2, 2, 3, 4, 6, 2,
-    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
-    6, 6, 2, 2, 2, 3, 5, 2, 4, 2, 2, 2, 5, 4, 6, 2,
-    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
-    2, 6, 2, 2, 3, 3, 3, 2, 2, 2, 2, 2, 4, 4, 4, 2,
-    2, 6, 2, 2, 4, 4, 4, 2, 2, 5, 2, 2, 2, 5, 2, 2,
-    2, 6, 2, 2, 3, 3, 3, 2, 2, 2, 2, 2, 4, 4, 4, 2,
-    2, 5, 2, 2, 4, 4, 4, 2, 2, 4, 2, 2, 4, 4, 4, 2,
-    2, 6, 2, 2, 3, 3, 5, 2, 2, 2, 2, 2, 4, 4, 6, 2,
-    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
-    2, 6, 2, 2, 3, 3, 5, 2, 2, 2, 2, 2, 4, 4, 6, 2,
-    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
+    7, 6, 2, 1, 5, 3, 5, 5, 3, 2, 2, 1, 6, 4, 6, 5,
+    2, 5, 5, 1, 5, 4, 6, 5, 2, 4, 2, 1, 6, 4, 6, 5,
+    6, 6, 2, 1, 3, 3, 5, 5, 4, 2, 2, 1, 4, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 2, 1, 4, 4, 6, 5,
+    6, 6, 2, 1, 3, 3, 5, 5, 3, 2, 2, 1, 3, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 3, 1, 8, 4, 6, 5,
+    6, 6, 2, 1, 3, 3, 5, 5, 4, 2, 2, 1, 6, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 4, 1, 6, 4, 6, 5,
+    2, 6, 2, 1, 3, 3, 3, 5, 2, 2, 2, 1, 4, 4, 4, 5,
+    2, 6, 5, 1, 4, 4, 4, 5, 2, 5, 2, 1, 4, 5, 5, 5,
+    2, 6, 2, 1, 3, 3, 3, 5, 2, 2, 2, 1, 4, 4, 4, 5,
+    2, 5, 5, 1, 4, 4, 4, 5, 2, 4, 2, 1, 4, 4, 4, 5,
+    2, 6, 2, 1, 3, 3, 5, 5, 2, 2, 2, 3, 4, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 3, 3, 4, 4, 7, 5,
+    2, 6, 2, 1, 3, 3, 5, 5, 2, 2, 2, 1, 4, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 4, 1, 4, 4, 7, 5,
+];
+
+// NMOS 6502, undocumented opcodes included. JAMs are listed as 2; the CPU
+// stops on them. Page crossings on indexed reads add one cycle on top.
+const NMOS_CYCLE_TABLE: [u64; 256] = [
+    7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
 ];
 
+// Operand addressing of the NMOS and 65C02 decoders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Imm,
+    Zp,
+    ZpX,
+    ZpY,
+    Abs,
+    AbsX,
+    AbsY,
+    IndX,
+    IndY,
+    // (zp), 65C02 only
+    ZpInd,
+}
+
+// What an instruction does at its effective address; decides which dummy
+// cycles the CPU makes on the way there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    Modify,
+}
+
+// Addressing mode of an NMOS opcode from its bbb/cc bits. None for implied,
+// accumulator, stack, branch and jump instructions and the JAMs.
+fn nmos_mode(opcode: u8) -> Option<Mode> {
+    // LDX/STX and their undocumented neighbours index by Y where the rest
+    // of the column uses X
+    let by_y = matches!(opcode, 0x96 | 0x97 | 0x9E | 0x9F | 0xB6 | 0xB7 | 0xBE | 0xBF);
+    let odd = opcode & 0x01 != 0;
+    match (opcode >> 2) & 0x07 {
+        0 if odd => Some(Mode::IndX),
+        0 if opcode >= 0x80 => Some(Mode::Imm),
+        1 => Some(Mode::Zp),
+        2 if odd => Some(Mode::Imm),
+        3 if opcode != 0x4C && opcode != 0x6C => Some(Mode::Abs),
+        4 if odd => Some(Mode::IndY),
+        5 if by_y => Some(Mode::ZpY),
+        5 => Some(Mode::ZpX),
+        6 if odd => Some(Mode::AbsY),
+        7 if by_y => Some(Mode::AbsY),
+        7 => Some(Mode::AbsX),
+        _ => None,
+    }
+}
+
+// Addressing mode of a 65C02 opcode. None for implied, accumulator, stack,
+// branch and jump instructions, the one-cycle NOPs and the bit
+// instructions.
+fn cmos_mode(opcode: u8) -> Option<Mode> {
+    match opcode {
+        // TRB zp, TRB abs, STZ abs and the NOPs at $5C/$DC/$FC sit in
+        // indexed columns without indexing
+        0x14 => return Some(Mode::Zp),
+        0x1C | 0x5C | 0x9C | 0xDC | 0xFC => return Some(Mode::Abs),
+        0x4C | 0x6C | 0x7C => return None,
+        _ => {}
+    }
+    let by_y = matches!(opcode, 0x96 | 0xB6 | 0xBE);
+    match (opcode & 0x03, (opcode >> 2) & 0x07) {
+        (0x03, _) => None,
+        (0x01, 0) => Some(Mode::IndX),
+        (0x01, 2) => Some(Mode::Imm),
+        (0x01, 4) => Some(Mode::IndY),
+        (0x01, 6) => Some(Mode::AbsY),
+        (0x02, 4) => Some(Mode::ZpInd),
+        (0x02, 0) => Some(Mode::Imm),
+        (0x00, 0) if opcode >= 0xA0 => Some(Mode::Imm),
+        (_, 1) => Some(Mode::Zp),
+        (_, 3) => Some(Mode::Abs),
+        (_, 5) if by_y => Some(Mode::ZpY),
+        (_, 5) => Some(Mode::ZpX),
+        (_, 7) if by_y => Some(Mode::AbsY),
+        (_, 7) => Some(Mode::AbsX),
+        _ => None,
+    }
+}
+
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
     pub system_type: SystemType,
     pub cpu_type: CpuType,
@@ -135,16 +255,21 @@ pub struct CPU {
     pub cycles: u64,
     prev_pc: u16,
 
-    symbol_table: SymbolTable,
-
     // target_hz: u32,
 
     pub entry_point_override: Option<u16>,
     pub debug: bool,
     extra_cycles: u64,  // Extra cycles for taken branches / page crosses
+    step_cycles: u64,   // Bus cycles made so far by the current step
     
     /// Hook manager for ROM/execution hooks
     pub hooks: HookManager,
+    /// Lua script callbacks, run after every instruction (--lua)
+    pub lua: Option<Box<LuaScript>>,
+    /// Per-PC cycle counts and call graph (--profile, or the CPU monitor)
+    pub profiler: Option<Box<Profiler>>,
+    /// Instruction trace streamed to a file (--trace, monitor `trace`)
+    pub tracer: Option<Box<Tracer>>,
     
     /// Whether to capture trace entries for the CPU monitor
     pub capture_trace: bool,
@@ -164,10 +289,13 @@ impl CPU {
             p: Flags::from_bits_truncate(0b00110110),
             regs: Registers::default(),
             entry_point_override: None,
-            symbol_table: SymbolTable::new(),
             debug: false,
             extra_cycles: 0,
+            step_cycles: 0,
             hooks: HookManager::new(),
+            lua: None,
+            profiler: None,
+            tracer: None,
             capture_trace: false,
             last_trace: CpuTraceEntry::default(),
         }
@@ -202,9 +330,9 @@ impl CPU {
     }
 
     pub fn init(&mut self) {
-        println!("cpu   {:>12} {:>8}", "65C02", "COLDBOOT");
+        println!("cpu   {:>12} {:>8}", self.cpu_type.name(), "COLDBOOT");
 
-        self.symbol_table.load_symbols();
+        self.bus.symbols.load_symbols();
 
         self.bus.interrupts.clear_all();
 
@@ -223,7 +351,7 @@ impl CPU {
 
         println!(
             "cpu   {:>12} {:>8}    PC={:#06X} SP={:#04X} P={:08b}",
-            "65C02", "READY",
+            self.cpu_type.name(), "READY",
             self.pc,
             self.regs.sp,
             self.p.bits()
@@ -260,11 +388,11 @@ impl CPU {
         );
     }
 
-    pub fn power_cycle(&mut self) {
+    pub fn power_cycle(&mut self, ram_seed: u64) {
         println!("CPU POWER CYCLE: Full cold reboot...");
 
         // Simulate fresh power-on RAM contents (indeterminate state)
-        self.bus.randomize_ram();
+        self.bus.randomize_ram(ram_seed);
 
         // Full re-init: registers, flags, soft switches
         self.initialize_registers();
@@ -320,81 +448,93 @@ impl CPU {
 
     fn handle_interrupt(&mut self) -> bool {
         // if any interrupt is pending before reading vectors
-        if !self.bus.interrupts.nmi && !self.bus.interrupts.reset && !self.bus.interrupts.brk && !self.bus.interrupts.irq {
+        if !self.bus.interrupts.nmi && !self.bus.interrupts.reset && !self.bus.interrupts.irq {
             return false;
         }
 
         // if only IRQ is pending and it is masked, return immediately
-        if self.bus.interrupts.irq && !self.bus.interrupts.nmi && !self.bus.interrupts.reset && !self.bus.interrupts.brk && self.p.contains(Flags::IRQ_DISABLE) {
+        if self.bus.interrupts.irq && !self.bus.interrupts.nmi && !self.bus.interrupts.reset && self.p.contains(Flags::IRQ_DISABLE) {
             return false;
         }
 
-        let nmi_vector = self.bus.read_word(0xFFFA);
-        let reset_vector = self.bus.read_word(0xFFFC);
-        let irq_vector = self.bus.read_word(0xFFFE);
+        let Some(interrupt_type) = self.bus.interrupts.take_pending() else {
+            return false;
+        };
 
-        if let Some((interrupt_type, target_pc)) = self
-            .bus
-            .interrupts
-            .handle_interrupt_with_vectors(nmi_vector, reset_vector, irq_vector)
-        {
-            if self.p.contains(Flags::IRQ_DISABLE) && interrupt_type == InterruptType::IRQ {
-                return false;
-            }
+        if self.p.contains(Flags::IRQ_DISABLE) && interrupt_type == InterruptType::IRQ {
+            return false;
+        }
 
-            if interrupt_type == InterruptType::RST {
-                println!("Handling CPU Reset...");
-                self.pc = target_pc;
-                return true;
-            }
+        if interrupt_type == InterruptType::RST {
+            println!("Handling CPU Reset...");
+            self.pc = self.bus.read_word(interrupt_type.vector());
+            return true;
+        }
 
-            let pushed_pc = match interrupt_type {
-                InterruptType::BRK => match self.cpu_type {
-                    CpuType::NMOS6502 => self.pc.wrapping_add(1),
-                    CpuType::CMOS65C02 | CpuType::WDC65C02S => self.pc.wrapping_add(1),
-                },
-                _ => self.pc,
-            };
+        // The opcode that was due is read twice and dropped, then the entry
+        // sequence runs as for BRK: seven cycles in all
+        self.read(self.pc);
+        self.read(self.pc);
+        self.enter_interrupt(self.pc, false, interrupt_type.vector());
 
-            self.push_stack((pushed_pc >> 8) as u8);
-            self.push_stack((pushed_pc & 0xFF) as u8);
+        if interrupt_type == InterruptType::IRQ {
+            self.bus.interrupts.irq = false;
+        } else {
+            self.bus.interrupts.nmi = false;
+        }
+        self.bus.interrupts.leave_wait();
+        false
+    }
 
-            let mut pushed_p = self.p;
-            pushed_p.insert(Flags::UNUSED);
-            pushed_p.set(Flags::BREAK, interrupt_type == InterruptType::BRK);
+    // Push PC and P, then load PC from the vector. Shared by BRK, IRQ and
+    // NMI, which differ only in the B bit of the pushed P.
+    fn enter_interrupt(&mut self, return_pc: u16, brk: bool, vector: u16) {
+        self.push_stack((return_pc >> 8) as u8);
+        self.push_stack((return_pc & 0xFF) as u8);
 
-            self.push_stack(pushed_p.bits());
+        let mut pushed_p = self.p;
+        pushed_p.insert(Flags::UNUSED);
+        pushed_p.set(Flags::BREAK, brk);
+        self.push_stack(pushed_p.bits());
 
-            self.p.insert(Flags::IRQ_DISABLE);
-            self.p.remove(Flags::BREAK);
+        self.p.insert(Flags::IRQ_DISABLE);
+        self.p.remove(Flags::BREAK);
 
-            if self.cpu_type != CpuType::NMOS6502 {
-                self.p.remove(Flags::DECIMAL);
-            }
+        if self.cpu_type != CpuType::NMOS6502 {
+            self.p.remove(Flags::DECIMAL);
+        }
 
-            self.pc = target_pc;
+        let lo = self.read(vector) as u16;
+        let hi = self.read(vector.wrapping_add(1)) as u16;
+        self.pc = (hi << 8) | lo;
+    }
 
-            if interrupt_type == InterruptType::IRQ {
-                self.bus.interrupts.irq = false;
-                self.bus.interrupts.leave_wait();
-                return false;
-            }
+    // One bus cycle. The bus is ticked before the access so that the access
+    // lands on the cycle it happens in, not at the end of the instruction:
+    // soft switches, the IWM latch and the floating bus all see the true
+    // position of the beam.
+    fn cycle(&mut self) {
+        self.bus.tick(1);
+        self.step_cycles += 1;
+    }
 
-            if interrupt_type == InterruptType::NMI {
-                self.bus.interrupts.nmi = false;
-                self.bus.interrupts.leave_wait();
-                return false;
-            }
+    // Every read the CPU makes, opcode and operand fetches included
+    fn read_as(&mut self, addr: u16, access: coverage::Access) -> u8 {
+        self.cycle();
+        self.bus.read_byte_as(addr, access)
+    }
 
-            self.bus.interrupts.leave_wait();
+    fn read(&mut self, addr: u16) -> u8 {
+        self.read_as(addr, coverage::Access::READ)
+    }
 
-            return true;
-        }
-        false
+    fn write(&mut self, addr: u16, value: u8) {
+        self.cycle();
+        self.bus.write_byte(addr, value);
     }
 
     fn fetch_byte(&mut self) -> u8 {
-        let byte = self.bus.read_byte(self.pc);
+        let byte = self.read_as(self.pc, coverage::Access::EXECUTE);
         self.pc = self.pc.wrapping_add(1);
         byte
     }
@@ -402,50 +542,42 @@ impl CPU {
     fn fetch_word(&mut self) -> u16 {
         let lo = self.fetch_byte() as u16;
         let hi = self.fetch_byte() as u16;
-        let word = (hi << 8) | lo;
-
-        word
-    }
-
-    fn fetch_indirect_x(&mut self) -> u16 {
-        let zp_base = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-        let lo = self.bus.read_byte(zp_base) as u16;
-        let hi = self.bus.read_byte(zp_base.wrapping_add(1)) as u16;
         (hi << 8) | lo
     }
 
-    fn fetch_indirect_y(&mut self) -> u16 {
-        let zp_addr = self.fetch_byte() as u16;
-        let low_byte = self.bus.read_byte(zp_addr) as u16;
-        let high_byte = self.bus.read_byte(zp_addr.wrapping_add(1) & 0xFF) as u16;
-        let base_addr = (high_byte << 8) | low_byte;
-
-        let addr = base_addr.wrapping_add(self.regs.y as u16);
-
-        // +1 cycle if page crossed
-        if (base_addr & 0xFF00) != (addr & 0xFF00) {
-            self.extra_cycles += 1;
-        }
-
-        addr
+    // Pointer in zero page; the high byte of a pointer at $FF comes from $00
+    fn read_zp_word(&mut self, zp_addr: u8) -> u16 {
+        let lo = self.read(zp_addr as u16) as u16;
+        let hi = self.read(zp_addr.wrapping_add(1) as u16) as u16;
+        (hi << 8) | lo
     }
 
     fn push_stack(&mut self, value: u8) {
-        self.bus.write_byte(0x0100 | self.regs.sp as u16, value);
+        self.write(0x0100 | self.regs.sp as u16, value);
         self.regs.sp = self.regs.sp.wrapping_sub(1);
     }
 
     fn pop_stack(&mut self) -> u8 {
         self.regs.sp = self.regs.sp.wrapping_add(1);
-        self.bus.read_byte(0x0100 | self.regs.sp as u16)
+        self.read(0x0100 | self.regs.sp as u16)
     }
 
-    /// Take a relative branch: +1 cycle for taken, +1 more if page crossed
+    // The cycle before the first pull (and JSR's internal cycle) reads the
+    // stack at the current pointer and discards it
+    fn read_stack_top(&mut self) {
+        self.read(0x0100 | self.regs.sp as u16);
+    }
+
+    /// Take a relative branch: +1 cycle for taken, +1 more if page crossed.
+    /// The taken cycle reads the next opcode; the page fix-up reads the
+    /// target with the old high byte.
     fn branch(&mut self, offset: i8) {
         let old_pc = self.pc;
+        self.read(old_pc);
         self.pc = self.pc.wrapping_add_signed(offset as i16);
         self.extra_cycles += 1; // +1 for taken branch
         if (old_pc & 0xFF00) != (self.pc & 0xFF00) {
+            self.read((old_pc & 0xFF00) | (self.pc & 0x00FF));
             self.extra_cycles += 1; // +1 for page cross
         }
     }
@@ -465,9 +597,61 @@ impl CPU {
         self.bus.update_interrupts();
         let cycles = self.step();
         self.cycles += cycles;
+        // Taken out for the call so the script can be lent the whole CPU
+        if let Some(mut lua) = self.lua.take() {
+            lua.tick(self);
+            self.lua = Some(lua);
+        }
         cycles
     }
 
+    // Stop profiling, writing the collapsed stacks if --profile named a file
+    pub fn stop_profiler(&mut self) {
+        let Some(profiler) = self.profiler.take() else { return };
+        let Some(path) = profiler.output.as_ref() else { return };
+        match profiler.write_collapsed(path, &self.bus.symbols) {
+            Ok(lines) => println!("prof  {:>12} {:>8}    {} ({} stacks)", "PROFILE", "SAVED", path.display(), lines),
+            Err(e) => eprintln!("prof  {:>12} {:>8}    {}: {}", "PROFILE", "ERROR", path.display(), e),
+        }
+    }
+
+    pub fn trace_header(&self) -> TraceHeader {
+        TraceHeader { cpu: self.cpu_type.name().to_string(), set: self.cpu_type.instruction_set() }
+    }
+
+    pub fn stop_trace(&mut self) {
+        let Some(tracer) = self.tracer.take() else { return };
+        let path = tracer.path.clone();
+        match tracer.finish() {
+            Ok(records) => println!("trace {:>12} {:>8}    {} ({} instructions)", "TRACE", "SAVED", path.display(), records),
+            Err(e) => eprintln!("trace {:>12} {:>8}    {}: {}", "TRACE", "ERROR", path.display(), e),
+        }
+    }
+
+    // Write the coverage map and listing to STEM.png and STEM.lst; returns
+    // the number of regions listed
+    pub fn save_coverage(&mut self, stem: &Path) -> anyhow::Result<usize> {
+        let Some(coverage) = self.bus.coverage.take() else { anyhow::bail!("coverage is off") };
+        let (png, listing) = coverage::export_paths(stem);
+        let result = coverage
+            .save_png(&png)
+            .and_then(|()| Ok(coverage.write_listing(&listing, &mut self.bus, self.cpu_type)?));
+        self.bus.coverage = Some(coverage);
+        result
+    }
+
+    pub fn stop_coverage(&mut self) {
+        let Some(stem) = self.bus.coverage.as_ref().and_then(|coverage| coverage.output.clone()) else {
+            self.bus.coverage = None;
+            return;
+        };
+        match self.save_coverage(&stem) {
+            Ok(regions) => println!("cov   {:>12} {:>8}    {}.png, .lst ({} regions)", "COVERAGE", "SAVED", stem.display(), regions),
+            Err(e) => eprintln!("cov   {:>12} {:>8}    {}: {}", "COVERAGE", "ERROR", stem.display(), e),
+        }
+        self.bus.coverage = None;
+    }
+
     pub fn video_update(&mut self) {
         self.bus.video_update();
     }
@@ -481,6 +665,9 @@ impl CPU {
     }
 
     pub fn step(&mut self) -> u64 {
+        self.step_cycles = 0;
+        let interrupt_sp = self.regs.sp;
+
         if self.handle_interrupt() {
             self.bus.tick(7);
             return 7;
@@ -515,7 +702,7 @@ impl CPU {
 
         // Check for execution hooks at current PC
         if self.hooks.has_hooks_at(self.pc) {
-            let ctx = HookContext {
+            let mut ctx = HookContext {
                 pc: self.pc,
                 a: self.regs.a,
                 x: self.regs.x,
@@ -523,12 +710,25 @@ impl CPU {
                 sp: self.regs.sp,
                 p: self.p.bits(),
                 cycles: self.cycles,
+                replace_cycles: 0,
+                bus: &mut self.bus,
             };
-            // Provide memory peek function for filter checking
-            let mut peek = |addr: u16| self.bus.peek_byte(addr);
-            let _skip = self.hooks.execute_hooks_filtered(&ctx, &mut peek);
-            // Note: skip functionality would require more complex handling
-            // For now, hooks are for side effects only (like activating Mockingboard)
+            let skip = self.hooks.execute_hooks_filtered(&mut ctx);
+            let replace_cycles = ctx.replace_cycles;
+            self.pc = ctx.pc;
+            self.regs.a = ctx.a;
+            self.regs.x = ctx.x;
+            self.regs.y = ctx.y;
+            self.regs.sp = ctx.sp;
+            self.p = Flags::from_bits_truncate(ctx.p);
+
+            if skip {
+                // The hook stood in for the instruction; charge its cycles,
+                // at least one so a hook that leaves PC alone can't stall time
+                let cycles = replace_cycles.max(1);
+                self.bus.tick(cycles);
+                return self.step_cycles + cycles;
+            }
         }
         
         // Process pending actions from hooks (both timed and address-based)
@@ -545,21 +745,29 @@ impl CPU {
 
         let pc = self.pc;
         self.bus.iou.current_pc.set(pc);
+        let sp = self.regs.sp;
+        let view = if self.profiler.is_some() { self.bus.read_view(pc) } else { None };
 
         let instruction = if self.debug {
-            Disassembler::disassemble(&mut self.bus, pc)
+            Disassembler::disassemble(&mut self.bus, pc, self.cpu_type)
         } else {
             String::new()
         };
 
+        // Cycles spent entering an interrupt just before this instruction
+        let interrupt_cycles = self.step_cycles;
+
         let opcode = self.fetch_byte();
+        if !self.bus.watches.is_empty() {
+            self.bus.watch_execute(pc, opcode);
+        }
 
         // Capture trace entry if monitoring is enabled (fast path - just struct copy)
-        if self.capture_trace {
+        if self.capture_trace || self.tracer.is_some() {
             // Peek at operand bytes without advancing PC
-            let operand1 = self.bus.read_byte(self.pc);
-            let operand2 = self.bus.read_byte(self.pc.wrapping_add(1));
-            let instruction_len = instruction_length(opcode);
+            let operand1 = self.bus.peek_byte(self.pc);
+            let operand2 = self.bus.peek_byte(self.pc.wrapping_add(1));
+            let instruction_len = instruction_length(opcode, self.cpu_type);
             
             self.last_trace = CpuTraceEntry {
                 pc,
@@ -573,26 +781,46 @@ impl CPU {
                 sp: self.regs.sp,
                 p: self.p.bits(),
                 cycles: self.cycles,
+                view: self.bus.read_view(pc),
             };
+            if let Some(tracer) = self.tracer.as_mut() {
+                if let Err(e) = tracer.record(self.last_trace.trace_record()) {
+                    eprintln!("trace {:>12} {:>8}    {}: {}", "TRACE", "ERROR", tracer.path.display(), e);
+                    self.tracer = None;
+                }
+            }
         }
 
-        // tick base cycles *before* execution so that iou.cycles is accurate
-        let base_cycles = CYCLE_TABLE[opcode as usize] as u64;
-        self.bus.tick(base_cycles);
+        let base_cycles = match self.cpu_type {
+            CpuType::NMOS6502 => NMOS_CYCLE_TABLE[opcode as usize],
+            _ if self.cpu_type.is_one_cycle_nop(opcode) => 1,
+            _ => CYCLE_TABLE[opcode as usize],
+        };
 
         self.extra_cycles = 0;
         self.decode_execute(opcode);
 
-        // tick any extra cycles from branches/page crosses
-        if self.extra_cycles > 0 {
-            self.bus.tick(self.extra_cycles);
+        // Each bus cycle was ticked as the decoder made it. Any cycle the
+        // tables count that was not made as an access is ticked here.
+        let cycles = (interrupt_cycles + base_cycles + self.extra_cycles).max(self.step_cycles);
+        if cycles > self.step_cycles {
+            self.bus.tick(cycles - self.step_cycles);
         }
 
-        let cycles = base_cycles + self.extra_cycles;
+        if self.profiler.is_some() {
+            // JSR and BRK call the code they go to
+            let call = matches!(opcode, 0x20 | 0x00).then(|| ((self.bus.read_view(self.pc), self.pc), sp));
+            if let Some(profiler) = self.profiler.as_mut() {
+                if interrupt_cycles > 0 {
+                    profiler.interrupt((view, pc), interrupt_cycles, interrupt_sp);
+                }
+                profiler.record((view, pc), cycles - interrupt_cycles, self.regs.sp, call);
+            }
+        }
 
         if self.debug {
             println!(
-                "{} A:{:02X} X:{:02X} Y:{:02X} P:{}[{:02X}] SP:{:02X}[{:02X}] {} {}{}",
+                "{} A:{:02X} X:{:02X} Y:{:02X} P:{}[{:02X}] SP:{:02X}[{:02X}] {} {}",
                 instruction,
                 self.regs.a,
                 self.regs.x,
@@ -601,10 +829,9 @@ impl CPU {
                 self.p.bits(),
                 self.regs.sp,
                 self.bus
-                    .read_byte(0x0100 | ((self.regs.sp.wrapping_add(1)) as u16)),
+                    .peek_byte(0x0100 | ((self.regs.sp.wrapping_add(1)) as u16)),
                 self.bus.mmu_mem_state_to_string(),
                 self.bus.interrupts.status_string(),
-                self.symbol_table.append_symbol(instruction.clone()),
             );
         }
         self.prev_pc = pc;
@@ -616,81 +843,83 @@ impl CPU {
         self.p.set(Flags::NEGATIVE, (value & 0b1000_0000) != 0);
     }
 
+    // Decimal mode follows Bruce Clark's "Decimal Mode" tutorial (6502.org),
+    // including the flags for invalid BCD operands. The NMOS part takes Z
+    // from the binary sum and N and V from the sum before the high digit is
+    // corrected; the 65C02 sets N and Z from the corrected result.
     fn adc(&mut self, value: u8) {
-        let carry_in = if self.p.contains(Flags::CARRY) { 1 } else { 0 };
+        let carry_in = self.p.contains(Flags::CARRY) as u16;
         let a_before = self.regs.a;
-        let sum_16 = a_before as u16 + value as u16 + carry_in as u16;
-        let mut a_after = (sum_16 & 0xFF) as u8;
-        let mut carry_out = sum_16 > 0xFF;
-
-        if self.p.contains(Flags::DECIMAL) {
-            let mut low_nibble = (a_before & 0x0F)
-                .wrapping_add(value & 0x0F)
-                .wrapping_add(carry_in);
-            let mut high_nibble = (a_before >> 4).wrapping_add(value >> 4);
-
-            if low_nibble > 9 {
-                low_nibble = low_nibble.wrapping_sub(10);
-                high_nibble = high_nibble.wrapping_add(1);
-            }
-
-            if high_nibble > 9 {
-                high_nibble = high_nibble.wrapping_sub(10);
-                carry_out = true;
-            }
-
-            a_after = (high_nibble << 4) | (low_nibble & 0x0F);
+        let binary = a_before as u16 + value as u16 + carry_in;
+
+        if !self.p.contains(Flags::DECIMAL) {
+            self.regs.a = binary as u8;
+            self.p.set(Flags::CARRY, binary > 0xFF);
+            self.p.set(Flags::OVERFLOW, !(a_before ^ value) & (a_before ^ self.regs.a) & 0x80 != 0);
+            self.update_zero_and_negative_flags(self.regs.a);
+            return;
         }
 
-        self.regs.a = a_after;
-
-        self.p.set(Flags::CARRY, carry_out);
-
-        let overflow = ((a_before ^ value) & 0x80 == 0) && ((a_before ^ a_after) & 0x80 != 0);
-        self.p.set(Flags::OVERFLOW, overflow);
+        let mut low = (a_before & 0x0F) as u16 + (value & 0x0F) as u16 + carry_in;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+        let mut sum = (a_before & 0xF0) as u16 + (value & 0xF0) as u16 + low;
+        let signed = (a_before & 0xF0) as i8 as i16 + (value & 0xF0) as i8 as i16 + low as i16;
+        let uncorrected = sum as u8;
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
 
-        self.p.set(Flags::ZERO, self.regs.a == 0);
-        self.p.set(Flags::NEGATIVE, (self.regs.a & 0x80) != 0);
+        self.regs.a = sum as u8;
+        self.p.set(Flags::CARRY, sum > 0xFF);
+        self.p.set(Flags::OVERFLOW, !(-128..=127).contains(&signed));
+        if self.cpu_type == CpuType::NMOS6502 {
+            self.p.set(Flags::ZERO, binary as u8 == 0);
+            self.p.set(Flags::NEGATIVE, uncorrected & 0x80 != 0);
+        } else {
+            self.update_zero_and_negative_flags(self.regs.a);
+        }
     }
 
+    // C and V always come from the binary difference. In decimal mode the
+    // NMOS part leaves N and Z binary too; the 65C02 sets them from the
+    // corrected result.
     fn sbc(&mut self, value: u8) {
-        let carry_in = if self.p.contains(Flags::CARRY) { 1 } else { 0 };
+        let borrow = !self.p.contains(Flags::CARRY) as i16;
         let a_before = self.regs.a;
-        let value_complement = !value;
+        let binary = a_before as i16 - value as i16 - borrow;
+        let binary_result = binary as u8;
 
-        let binary_result = (a_before as u16) + (value_complement as u16) + carry_in as u16;
-        let mut temp_result = (binary_result & 0xFF) as u8;
-        let mut did_borrow = binary_result < 0x100;
+        self.p.set(Flags::CARRY, binary >= 0);
+        self.p.set(Flags::OVERFLOW, (a_before ^ value) & (a_before ^ binary_result) & 0x80 != 0);
 
-        if self.p.contains(Flags::DECIMAL) {
-            let mut low_nibble = (a_before & 0x0F)
-                .wrapping_sub(value & 0x0F)
-                .wrapping_sub(1 - carry_in);
-            let mut high_nibble = (a_before >> 4).wrapping_sub(value >> 4);
+        if !self.p.contains(Flags::DECIMAL) {
+            self.regs.a = binary_result;
+            self.update_zero_and_negative_flags(self.regs.a);
+            return;
+        }
 
-            if (low_nibble & 0x10) != 0 {
-                low_nibble = low_nibble.wrapping_sub(6) & 0x0F;
-                high_nibble = high_nibble.wrapping_sub(1);
+        let low = (a_before & 0x0F) as i16 - (value & 0x0F) as i16 - borrow;
+        if self.cpu_type == CpuType::NMOS6502 {
+            let low = if low < 0 { ((low - 0x06) & 0x0F) - 0x10 } else { low };
+            let mut result = (a_before & 0xF0) as i16 - (value & 0xF0) as i16 + low;
+            if result < 0 {
+                result -= 0x60;
             }
-
-            if high_nibble > 9 {
-                high_nibble = high_nibble.wrapping_sub(6) & 0x0F;
-                did_borrow = true;
+            self.regs.a = result as u8;
+            self.update_zero_and_negative_flags(binary_result);
+        } else {
+            let mut result = binary;
+            if result < 0 {
+                result -= 0x60;
             }
-
-            temp_result = (high_nibble << 4) | (low_nibble & 0x0F);
+            if low < 0 {
+                result -= 0x06;
+            }
+            self.regs.a = result as u8;
+            self.update_zero_and_negative_flags(self.regs.a);
         }
-
-        self.regs.a = temp_result;
-
-        let carry_set = !did_borrow;
-        self.p.set(Flags::CARRY, carry_set);
-
-        let overflow = ((a_before ^ value) & 0x80 != 0) && ((a_before ^ temp_result) & 0x80 != 0);
-        self.p.set(Flags::OVERFLOW, overflow);
-
-        self.p.set(Flags::ZERO, self.regs.a == 0);
-        self.p.set(Flags::NEGATIVE, self.regs.a & 0x80 != 0);
     }
 
     fn asl(&mut self, value: u8) -> u8 {
@@ -735,489 +964,156 @@ impl CPU {
         self.p.set(Flags::NEGATIVE, (result & 0x80) != 0);
     }
 
+    // Opcodes that only exist on some variants keep the check inside the
+    // arm so they fall back to a NOP rather than to another arm
+    #[allow(clippy::collapsible_match)]
     fn decode_execute(&mut self, opcode: u8) {
-        match opcode {
-            0xA9 => {
-                let value = self.fetch_byte();
-                self.regs.a = value;
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
+        let done = match self.cpu_type {
+            CpuType::NMOS6502 => self.execute_nmos(opcode),
+            _ => self.execute_cmos(opcode),
+        };
+        if done {
+            return;
+        }
 
-            0xA5 => {
-                let addr = self.fetch_byte() as u16;
-                self.regs.a = self.bus.read_byte(addr);
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
+        match opcode {
+            0x08 => {
+                let mut pushed_p = self.p;
+                pushed_p.insert(Flags::UNUSED);
+                pushed_p.insert(Flags::BREAK);
 
-            0xAD => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                self.regs.a = value;
-                self.update_zero_and_negative_flags(self.regs.a);
+                self.push_stack(pushed_p.bits());
             }
 
-            0xA2 => {
-                let value = self.fetch_byte();
-                self.regs.x = value;
-                self.update_zero_and_negative_flags(self.regs.x);
-            }
-            0xA6 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                self.regs.x = value;
-                self.update_zero_and_negative_flags(self.regs.x);
-            }
-            0xAE => {
-                let addr = self.fetch_word();
-                self.regs.x = self.bus.read_byte(addr);
-                self.update_zero_and_negative_flags(self.regs.x);
+            0x0A => {
+                self.regs.a = self.asl(self.regs.a);
             }
 
-            0xA0 => {
-                let value = self.fetch_byte();
-                self.regs.y = value;
-                self.update_zero_and_negative_flags(self.regs.y);
+            0x4A => {
+                self.regs.a = self.lsr(self.regs.a);
             }
 
-            0xA4 => {
-                let addr = self.fetch_byte() as u16;
-                self.regs.y = self.bus.read_byte(addr);
-                self.update_zero_and_negative_flags(self.regs.y);
+            0x2A => {
+                self.regs.a = self.rol(self.regs.a);
             }
 
-            0xAC => {
-                let addr = self.fetch_word();
-                self.regs.y = self.bus.read_byte(addr);
-                self.update_zero_and_negative_flags(self.regs.y);
+            0x6A => {
+                self.regs.a = self.ror(self.regs.a);
             }
 
-            0x85 => {
-                let addr = self.fetch_byte() as u16;
-                self.bus.write_byte(addr, self.regs.a);
+            0x4C => {
+                let target = self.fetch_word();
+                self.pc = target;
             }
 
-            0x8D => {
+            0x6C => {
                 let addr = self.fetch_word();
-                let value = self.regs.a;
-                self.bus.write_byte(addr, value);
-            }
+                let hi_addr = match self.cpu_type {
+                    // NMOS 6502 bug: the high byte comes from the start of
+                    // the pointer's page when the pointer is at $xxFF
+                    CpuType::NMOS6502 => (addr & 0xFF00) | (addr.wrapping_add(1) & 0x00FF),
+                    _ => {
+                        // the 65C02 spends a cycle, re-reading the last
+                        // instruction byte, to carry into the high byte
+                        self.read(self.pc.wrapping_sub(1));
+                        addr.wrapping_add(1)
+                    }
+                };
+                let lo = self.read(addr) as u16;
+                let hi = self.read(hi_addr) as u16;
 
-            0x86 => {
-                let addr = self.fetch_byte() as u16;
-                self.bus.write_byte(addr, self.regs.x);
+                self.pc = (hi << 8) | lo;
             }
 
-            0x8E => {
-                let addr = self.fetch_word();
-                self.bus.write_byte(addr, self.regs.x);
-            }
+            0x20 => {
+                // The return address, the last byte of the JSR, is pushed
+                // before the high byte of the target is read
+                let lo = self.fetch_byte() as u16;
+                self.read_stack_top();
+                self.push_stack((self.pc >> 8) as u8);
+                self.push_stack((self.pc & 0xFF) as u8);
+                let hi = self.read(self.pc) as u16;
 
-            0x84 => {
-                let addr = self.fetch_byte() as u16;
-                self.bus.write_byte(addr, self.regs.y);
+                self.pc = (hi << 8) | lo;
             }
 
-            0x8C => {
-                let addr = self.fetch_word();
-                self.bus.write_byte(addr, self.regs.y);
+            0x60 => {
+                self.read_stack_top();
+                let low = self.pop_stack() as u16;
+                let high = self.pop_stack() as u16;
+                let return_addr = (high << 8) | low;
+                // a cycle reading the pulled address while it is incremented
+                self.read(return_addr);
+                self.pc = return_addr.wrapping_add(1);
             }
 
-            0x29 => {
-                let value = self.fetch_byte();
-                self.regs.a &= value;
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-            0x25 => {
-                let addr = self.fetch_byte() as u16;
-                self.regs.a &= self.bus.read_byte(addr);
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-            0x2D => {
-                let addr = self.fetch_word();
-                self.regs.a &= self.bus.read_byte(addr);
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
+            0x40 => {
+                self.read_stack_top();
+                let mut restored_p = Flags::from_bits_truncate(self.pop_stack());
+                restored_p.insert(Flags::UNUSED);
+                restored_p.remove(Flags::BREAK);
+                self.p = restored_p;
 
-            0x49 => {
-                let value = self.fetch_byte();
-                self.regs.a ^= value;
-                self.update_zero_and_negative_flags(self.regs.a);
+                let lo = self.pop_stack() as u16;
+                let hi = self.pop_stack() as u16;
+                self.pc = (hi << 8) | lo;
             }
 
-            0x45 => {
-                let addr = self.fetch_byte() as u16;
-                self.regs.a ^= self.bus.read_byte(addr);
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-            0x4D => {
-                let addr = self.fetch_word();
-                self.regs.a ^= self.bus.read_byte(addr);
-                self.update_zero_and_negative_flags(self.regs.a);
+            0x80 => {
+                let offset = self.fetch_byte() as i8;
+                self.branch(offset);
             }
 
-            0x08 => {
-                let mut pushed_p = self.p;
-                pushed_p.insert(Flags::UNUSED);
-                pushed_p.insert(Flags::BREAK);
-
-                self.push_stack(pushed_p.bits());
+            0xDA => {
+                self.push_stack(self.regs.x);
             }
 
-            0x05 => {
-                let addr = self.fetch_byte() as u16;
-                self.regs.a |= self.bus.read_byte(addr);
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
+            0xFA => {
+                self.read_stack_top();
+                self.regs.x = self.pop_stack();
 
-            0x0D => {
-                let addr = self.fetch_word();
-                self.regs.a |= self.bus.read_byte(addr);
-                self.update_zero_and_negative_flags(self.regs.a);
+                if self.cpu_type != CpuType::NMOS6502 {
+                    self.update_zero_and_negative_flags(self.regs.x);
+                }
             }
 
-            0x24 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                self.execute_bit(value);
+            0x5A => {
+                self.push_stack(self.regs.y);
             }
 
-            0x2C => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                self.execute_bit(value);
-            }
+            0x7A => {
+                self.read_stack_top();
+                self.regs.y = self.pop_stack();
 
-            0x34 => {
-                let addr = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-                let value = self.bus.read_byte(addr);
-                self.execute_bit(value);
+                if self.cpu_type != CpuType::NMOS6502 {
+                    self.update_zero_and_negative_flags(self.regs.y);
+                }
             }
 
-            0x3C => {
-                // BIT abs,X - +1 cycle if page crossed (65C02)
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+            0xCB => {
+                if self.cpu_type == CpuType::WDC65C02S {
+                    self.bus.interrupts.enter_wait();
                 }
-                let value = self.bus.read_byte(addr);
-                self.execute_bit(value);
             }
 
-            0x69 => {
-                let value = self.fetch_byte();
-                self.adc(value);
+            0xDB => {
+                if self.cpu_type == CpuType::WDC65C02S {
+                    self.bus.interrupts.enter_halt();
+                }
             }
 
-            0x65 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                self.adc(value);
+            0xD8 => {
+                self.p.remove(Flags::DECIMAL);
             }
 
-            0x6D => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                self.adc(value);
+            0xF8 => {
+                self.p.insert(Flags::DECIMAL);
             }
 
-            0xE9 => {
-                let value = self.fetch_byte();
-                self.sbc(value);
-            }
-
-            0xE5 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                self.sbc(value);
-            }
-
-            0xED => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                self.sbc(value);
-            }
-
-            0xC9 => {
-                let value = self.fetch_byte();
-                self.compare(self.regs.a, value);
-            }
-
-            0xC5 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                self.compare(self.regs.a, value);
-            }
-
-            0xCD => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                self.compare(self.regs.a, value);
-            }
-
-            0xE0 => {
-                let value = self.fetch_byte();
-                self.compare(self.regs.x, value);
-            }
-
-            0xE4 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                self.compare(self.regs.x, value);
-            }
-
-            0xEC => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                self.compare(self.regs.x, value);
-            }
-
-            0xC0 => {
-                let value = self.fetch_byte();
-                let result = self.regs.y.wrapping_sub(value);
-                self.p.set(Flags::ZERO, self.regs.y == value);
-                self.p.set(Flags::NEGATIVE, result & 0x80 != 0);
-                self.p.set(Flags::CARRY, self.regs.y >= value);
-            }
-
-            0xC4 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                self.compare(self.regs.y, value);
-            }
-
-            0xCC => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                self.compare(self.regs.y, value);
-            }
-
-            0x0A => {
-                self.regs.a = self.asl(self.regs.a);
-            }
-
-            0x06 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                let result = self.asl(value);
-                self.bus.write_byte(addr, result);
-            }
-
-            0x0E => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                let result = self.asl(value);
-                self.bus.write_byte(addr, result);
-            }
-
-            0x4A => {
-                self.regs.a = self.lsr(self.regs.a);
-            }
-
-            0x46 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                let result = self.lsr(value);
-                self.bus.write_byte(addr, result);
-            }
-
-            0x4E => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                let result = self.lsr(value);
-                self.bus.write_byte(addr, result);
-            }
-
-            0x2A => {
-                self.regs.a = self.rol(self.regs.a);
-            }
-
-            0x26 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                let result = self.rol(value);
-                self.bus.write_byte(addr, result);
-            }
-
-            0x2E => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                let result = self.rol(value);
-                self.bus.write_byte(addr, result);
-            }
-
-            0x6A => {
-                self.regs.a = self.ror(self.regs.a);
-            }
-
-            0x66 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                let result = self.ror(value);
-                self.bus.write_byte(addr, result);
-            }
-
-            0x6E => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                let result = self.ror(value);
-                self.bus.write_byte(addr, result);
-            }
-
-            0x4C => {
-                let target = self.fetch_word();
-                self.pc = target;
-            }
-
-            0x6C => {
-                let addr = self.fetch_word();
-                let lo = self.bus.read_byte(addr) as u16;
-                let hi = match self.cpu_type {
-                    CpuType::NMOS6502 if addr & 0x00FF == 0xFF => {
-                        // NMOS 6502 bug: Reads high byte from addr & 0xFF00
-                        self.bus.read_byte(addr & 0xFF00) as u16
-                    }
-                    _ => {
-                        // reads high byte from addr + 1
-                        self.bus.read_byte(addr.wrapping_add(1)) as u16
-                    }
-                };
-
-                self.pc = (hi << 8) | lo;
-            }
-
-            0x20 => {
-                let addr = self.fetch_word();
-
-                self.pc = self.pc.wrapping_sub(1);
-                self.push_stack((self.pc >> 8) as u8);
-                self.push_stack((self.pc & 0xFF) as u8);
-
-                self.pc = addr;
-            }
-
-            0x60 => {
-                let low = self.pop_stack() as u16;
-                let high = self.pop_stack() as u16;
-                let return_addr = (high << 8) | low;
-                self.pc = return_addr.wrapping_add(1);
-            }
-
-            0x40 => {
-                let mut restored_p = Flags::from_bits_truncate(self.pop_stack());
-                restored_p.insert(Flags::UNUSED);
-                restored_p.remove(Flags::BREAK);
-                self.p = restored_p;
-
-                let lo = self.pop_stack() as u16;
-                let hi = self.pop_stack() as u16;
-                self.pc = (hi << 8) | lo;
-            }
-
-            0x80 => {
-                let offset = self.fetch_byte() as i8;
-                self.branch(offset);
-            }
-
-            0x9C => {
-                let addr = self.fetch_word();
-                self.bus.write_byte(addr, 0x00);
-            }
-
-            0x9E => {
-                let addr = self.fetch_word() + self.regs.x as u16;
-                self.bus.write_byte(addr, 0x00);
-            }
-
-            0x64 => {
-                let addr = self.fetch_byte() as u16;
-                self.bus.write_byte(addr, 0x00);
-            }
-
-            0x74 => {
-                let addr = (self.fetch_byte() as u16 + self.regs.x as u16) & 0xFF;
-                self.bus.write_byte(addr, 0x00);
-            }
-
-            0x1C => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                self.p.set(Flags::ZERO, (value & self.regs.a) == 0);
-                self.bus.write_byte(addr, value & !self.regs.a);
-            }
-
-            0x14 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                self.p.set(Flags::ZERO, (value & self.regs.a) == 0);
-                self.bus.write_byte(addr, value & !self.regs.a);
-            }
-
-            0x0C => {
-                let addr = self.fetch_word();
-                let value = self.bus.read_byte(addr);
-                self.p.set(Flags::ZERO, (value & self.regs.a) == 0);
-                self.bus.write_byte(addr, value | self.regs.a);
-            }
-
-            0x04 => {
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                self.p.set(Flags::ZERO, (value & self.regs.a) == 0);
-                self.bus.write_byte(addr, value | self.regs.a);
-            }
-
-            0xDA => {
-                self.push_stack(self.regs.x);
-            }
-
-            0xFA => {
-                self.regs.x = self.pop_stack();
-
-                if matches!(self.cpu_type, CpuType::CMOS65C02 | CpuType::WDC65C02S) {
-                    self.update_zero_and_negative_flags(self.regs.x);
-                }
-            }
-
-            0x5A => {
-                self.push_stack(self.regs.y);
-            }
-
-            0x7A => {
-                self.regs.y = self.pop_stack();
-
-                if matches!(self.cpu_type, CpuType::CMOS65C02 | CpuType::WDC65C02S) {
-                    self.update_zero_and_negative_flags(self.regs.y);
-                }
-            }
-
-            0x89 => {
-                let value = self.fetch_byte();
-                self.p.set(Flags::ZERO, (self.regs.a & value) == 0);
-            }
-
-            0xCB => {
-                if self.cpu_type == CpuType::WDC65C02S {
-                    self.bus.interrupts.enter_wait();
-                }
-            }
-
-            0xDB => {
-                if self.cpu_type == CpuType::WDC65C02S {
-                    self.bus.interrupts.enter_halt();
-                }
-            }
-
-            0xD8 => {
-                self.p.remove(Flags::DECIMAL);
-            }
-
-            0xF8 => {
-                self.p.insert(Flags::DECIMAL);
-            }
-
-            0x00 => {
-                self.bus.interrupts.request_brk();
+            0x00 => {
+                // The byte after BRK is skipped; the pushed PC points past it
+                self.fetch_byte();
+                self.enter_interrupt(self.pc, true, InterruptType::IRQ.vector());
             }
 
             0xEA => {
@@ -1243,17 +1139,6 @@ impl CPU {
                 }
             }
 
-            0xBC => {
-                // LDY abs,X - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
-                }
-                self.regs.y = self.bus.read_byte(addr);
-                self.update_zero_and_negative_flags(self.regs.y);
-            }
-
             0xF0 => {
                 let offset = self.fetch_byte() as i8;
                 if self.p.contains(Flags::ZERO) {
@@ -1266,17 +1151,11 @@ impl CPU {
             }
 
             0x68 => {
+                self.read_stack_top();
                 self.regs.a = self.pop_stack();
                 self.update_zero_and_negative_flags(self.regs.a);
             }
 
-            0xFE => {
-                let addr = self.fetch_word().wrapping_add(self.regs.x as u16);
-                let value = self.bus.read_byte(addr).wrapping_add(1);
-                self.bus.write_byte(addr, value);
-                self.update_zero_and_negative_flags(value);
-            }
-
             0x88 => {
                 self.regs.y = self.regs.y.wrapping_sub(1);
                 self.update_zero_and_negative_flags(self.regs.y);
@@ -1343,6 +1222,7 @@ impl CPU {
             }
 
             0x28 => {
+                self.read_stack_top();
                 let popped = self.pop_stack();
                 let mut restored_p = Flags::from_bits(popped).unwrap_or(Flags::empty());
 
@@ -1376,18 +1256,6 @@ impl CPU {
                 self.update_zero_and_negative_flags(self.regs.y);
             }
 
-            0xBD => {
-                // LDA abs,X - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
-                }
-                let value = self.bus.read_byte(addr);
-                self.regs.a = value;
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
             0x58 => {
                 self.p.remove(Flags::IRQ_DISABLE);
             }
@@ -1400,771 +1268,587 @@ impl CPU {
                 self.p.remove(Flags::OVERFLOW);
             }
 
-            0xB6 => {
-                let base_addr = self.fetch_byte() as u16;
-                let addr = (base_addr.wrapping_add(self.regs.y as u16) & 0x00FF) as u16;
-                let value = self.bus.read_byte(addr);
+            0x0F | 0x1F | 0x2F | 0x3F | 0x4F | 0x5F | 0x6F | 0x7F => {
+                let zp_addr = self.fetch_byte() as u16;
+                let value = self.read(zp_addr);
+                self.read(zp_addr);
+                let rel_offset = self.fetch_byte() as i8;
+                let bit = 1 << ((opcode.wrapping_sub(0x0F)) / 0x10);
 
-                self.regs.x = value;
-                self.update_zero_and_negative_flags(self.regs.x);
+                if (value & bit) == 0 {
+                    self.branch(rel_offset);
+                }
             }
 
-            0x99 => {
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.y as u16);
+            0x8F | 0x9F | 0xAF | 0xBF | 0xCF | 0xDF | 0xEF | 0xFF => {
+                let zp_addr = self.fetch_byte() as u16;
+                let value = self.read(zp_addr);
+                self.read(zp_addr);
+                let rel_offset = self.fetch_byte() as i8;
+                let bit = 1 << ((opcode.wrapping_sub(0x8F)) / 0x10);
 
-                let addr = if base_addr < 0x100 { addr & 0xFF } else { addr };
+                if (value & bit) != 0 {
+                    self.branch(rel_offset);
+                }
+            }
 
-                self.bus.write_byte(addr, self.regs.a);
+            0x7C => {
+                let base = self.fetch_word();
+                self.read(self.pc.wrapping_sub(1));
+                let addr = base.wrapping_add(self.regs.x as u16);
+                let lo = self.read(addr) as u16;
+                let hi = self.read(addr.wrapping_add(1)) as u16;
+                self.pc = (hi << 8) | lo;
             }
 
-            0xD9 => {
-                // CMP abs,Y - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.y as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+            0x1A => {
+                if self.cpu_type != CpuType::NMOS6502 {
+                    self.regs.a = self.regs.a.wrapping_add(1);
+                    self.update_zero_and_negative_flags(self.regs.a);
                 }
-                let value = self.bus.read_byte(addr);
-                self.compare(self.regs.a, value);
             }
 
-            0xBE => {
-                // LDX abs,Y - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.y as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+            0x3A => {
+                if self.cpu_type != CpuType::NMOS6502 {
+                    self.regs.a = self.regs.a.wrapping_sub(1);
+                    self.update_zero_and_negative_flags(self.regs.a);
                 }
-                let value = self.bus.read_byte(addr);
-                self.regs.x = value;
-                self.update_zero_and_negative_flags(self.regs.x);
             }
 
-            0x96 => {
-                let base_addr = self.fetch_byte() as u16;
-                let addr = (base_addr.wrapping_add(self.regs.y as u16)) & 0x00FF;
+            0x07 | 0x17 | 0x27 | 0x37 | 0x47 | 0x57 | 0x67 | 0x77 => {
+                let bit_n = (opcode >> 4) & 0b111;
+                let mask = !(1 << bit_n);
 
-                self.bus.write_byte(addr, self.regs.x);
+                let addr = self.fetch_byte() as u16;
+                let value = self.read(addr);
+                self.read(addr);
+                let result = value & mask;
+                self.write(addr, result);
             }
 
-            0xB9 => {
-                // LDA abs,Y - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.y as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
-                }
-                let value = self.bus.read_byte(addr);
-                self.regs.a = value;
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
+            0x87 | 0x97 | 0xA7 | 0xB7 | 0xC7 | 0xD7 | 0xE7 | 0xF7 => {
+                let bit_n = (opcode >> 4) & 0b111;
+                let mask = 1 << bit_n;
 
-            0xB4 => {
-                let base = self.fetch_byte();
-                let addr = base.wrapping_add(self.regs.x) as u16;
-                let value = self.bus.read_byte(addr);
-                self.regs.y = value;
-                self.update_zero_and_negative_flags(self.regs.y);
+                let addr = self.fetch_byte() as u16;
+                let value = self.read(addr);
+                self.read(addr);
+                let result = value | mask;
+                self.write(addr, result);
             }
 
-            0x9D => {
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
+            // Everything else was decoded by execute_nmos or execute_cmos
+            _ => {}
+        }
+    }
 
-                self.bus.write_byte(addr, self.regs.a);
-            }
+    // NMOS 6502 decoder for every instruction with an operand. It makes the
+    // dummy bus cycles the NMOS part does: the unindexed read on zp,X and
+    // (zp,X), the read at the unfixed address on indexed accesses (always
+    // for stores and read-modify-write, only on a page cross for reads), and
+    // the write of the unmodified value in read-modify-write. Opcodes the
+    // 65C02 reassigned run as their NMOS undocumented meaning. Returns false
+    // for the implied, stack and control-flow instructions, which the shared
+    // decoder executes.
+    fn execute_nmos(&mut self, opcode: u8) -> bool {
+        let Some(mode) = nmos_mode(opcode) else {
+            return self.execute_nmos_implied(opcode);
+        };
 
-            0xDD => {
-                // CMP abs,X - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+        let op = opcode >> 5;
+        match opcode & 0x03 {
+            // ORA AND EOR ADC STA LDA CMP SBC
+            0x01 => match op {
+                4 if mode == Mode::Imm => {
+                    self.fetch_byte(); // NOP #imm
                 }
-                let value = self.bus.read_byte(addr);
-
-                self.compare(self.regs.a, value);
-            }
-
-            0x94 => {
-                let base = self.fetch_byte();
-                let addr = base.wrapping_add(self.regs.x) as u16;
-                self.bus.write_byte(addr, self.regs.y);
-            }
+                4 => self.write_operand(mode, self.regs.a),
+                _ => {
+                    let value = self.read_operand(mode);
+                    self.alu(op, value);
+                }
+            },
 
-            0xD5 => {
-                let base = self.fetch_byte();
-                let addr = (base.wrapping_add(self.regs.x) & 0x00FF) as u16;
-                let value = self.bus.read_byte(addr);
-                self.compare(self.regs.a, value);
-            }
-
-            0xB5 => {
-                let base = self.fetch_byte();
-                let addr = (base.wrapping_add(self.regs.x) & 0x00FF) as u16;
-                let value = self.bus.read_byte(addr);
-                self.regs.a = value;
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0xC1 => {
-                let base_addr = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-                let addr = self.bus.read_word(base_addr);
-                let value = self.bus.read_byte(addr);
-
-                self.compare(self.regs.a, value);
-            }
-
-            0xD1 => {
-                let base_addr = self.fetch_byte() as u16;
-                let addr = self
-                    .bus
-                    .read_word(base_addr)
-                    .wrapping_add(self.regs.y as u16);
-                let value = self.bus.read_byte(addr);
-
-                self.compare(self.regs.a, value);
-            }
-
-            0x95 => {
-                let base_address = self.fetch_byte() as u16;
-                let effective_address = (base_address.wrapping_add(self.regs.x as u16)) & 0x00FF;
-                self.bus.write_byte(effective_address, self.regs.a);
-            }
-
-            0xB1 => {
-                let base_address = self.fetch_byte();
-                let pointer_address = self.bus.read_word(base_address as u16 & 0xFF);
-
-                let effective_address = pointer_address.wrapping_add(self.regs.y as u16);
-                let value = self.bus.read_byte(effective_address);
-
-                self.regs.a = value;
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x91 => {
-                let base_address = self.fetch_byte();
-                let pointer_address = self.bus.read_word(base_address as u16 & 0xFF);
-
-                let effective_address = pointer_address.wrapping_add(self.regs.y as u16);
-                self.bus.write_byte(effective_address, self.regs.a);
-            }
-
-            0xA1 => {
-                let base_address = self.fetch_byte();
-                let zp_addr = base_address.wrapping_add(self.regs.x) & 0xFF;
-                let effective_address = self.bus.read_word(zp_addr as u16);
-                self.regs.a = self.bus.read_byte(effective_address);
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x81 => {
-                let base_address = self.fetch_byte();
-                let zp_addr = (base_address.wrapping_add(self.regs.x)) & 0xFF;
-                let effective_address = self.bus.read_word(zp_addr as u16);
-
-                self.bus.write_byte(effective_address, self.regs.a);
-            }
-
-            0x16 => {
-                let addr = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-                let mut value = self.bus.read_byte(addr);
-
-                let carry = value & 0x80 != 0;
-                value <<= 1;
-
-                self.bus.write_byte(addr, value);
-
-                self.p.set(Flags::CARRY, carry);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0x56 => {
-                let addr = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-                let mut value = self.bus.read_byte(addr);
-
-                let carry = value & 0x01 != 0;
-                value >>= 1;
-
-                self.bus.write_byte(addr, value);
-
-                self.p.set(Flags::CARRY, carry);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0x36 => {
-                let addr = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-                let mut value = self.bus.read_byte(addr);
-
-                let old_carry = self.p.contains(Flags::CARRY);
-                let new_carry = value & 0x80 != 0;
-
-                value = (value << 1) | if old_carry { 1 } else { 0 };
-
-                self.bus.write_byte(addr, value);
-
-                self.p.set(Flags::CARRY, new_carry);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0x76 => {
-                let addr = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-                let mut value = self.bus.read_byte(addr);
-
-                let old_carry = self.p.contains(Flags::CARRY);
-                let new_carry = value & 0x01 != 0;
-
-                value = (value >> 1) | if old_carry { 0x80 } else { 0 };
-
-                self.bus.write_byte(addr, value);
-
-                self.p.set(Flags::CARRY, new_carry);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0x1E => {
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                let mut value = self.bus.read_byte(addr);
-
-                let new_carry = value & 0x80 != 0;
-
-                value <<= 1;
-
-                self.bus.write_byte(addr, value);
-
-                self.p.set(Flags::CARRY, new_carry);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0x5E => {
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                let mut value = self.bus.read_byte(addr);
-
-                let new_carry = value & 0x01 != 0;
-
-                value >>= 1;
-
-                self.bus.write_byte(addr, value);
-
-                self.p.set(Flags::CARRY, new_carry);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0x3E => {
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                let mut value = self.bus.read_byte(addr);
-
-                let new_carry = (value & 0x80) != 0;
-
-                value = (value << 1) | (self.p.contains(Flags::CARRY) as u8);
-
-                self.bus.write_byte(addr, value);
-
-                self.p.set(Flags::CARRY, new_carry);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0x7E => {
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                let mut value = self.bus.read_byte(addr);
-
-                let new_carry = (value & 0x01) != 0;
-
-                value = (value >> 1) | ((self.p.contains(Flags::CARRY) as u8) << 7);
-
-                self.bus.write_byte(addr, value);
-
-                self.p.set(Flags::CARRY, new_carry);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0xE6 => {
-                let addr = self.fetch_byte() as u16;
-                let mut value = self.bus.read_byte(addr);
-
-                value = value.wrapping_add(1);
-
-                self.bus.write_byte(addr, value);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0xC6 => {
-                let addr = self.fetch_byte() as u16;
-                let mut value = self.bus.read_byte(addr);
-
-                value = value.wrapping_sub(1);
-
-                self.bus.write_byte(addr, value);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0xEE => {
-                let addr = self.fetch_word();
-                let mut value = self.bus.read_byte(addr);
-
-                value = value.wrapping_add(1);
-
-                self.bus.write_byte(addr, value);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0xCE => {
-                let addr = self.fetch_word();
-                let mut value = self.bus.read_byte(addr);
-
-                value = value.wrapping_sub(1);
-
-                self.bus.write_byte(addr, value);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0xF6 => {
-                let base_addr = self.fetch_byte();
-                let addr = (base_addr.wrapping_add(self.regs.x)) as u8 as u16;
-
-                let mut value = self.bus.read_byte(addr);
-                value = value.wrapping_add(1);
-
-                self.bus.write_byte(addr, value);
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0x09 => {
-                let value = self.fetch_byte();
-                self.regs.a |= value;
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0xD6 => {
-                let addr = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-                let value = self.bus.read_byte(addr).wrapping_sub(1);
-                self.bus.write_byte(addr, value);
-
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0xDE => {
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                let value = self.bus.read_byte(addr).wrapping_sub(1);
-                self.bus.write_byte(addr, value);
-
-                self.update_zero_and_negative_flags(value);
-            }
-
-            0x35 => {
-                let addr = self.fetch_byte().wrapping_add(self.regs.x);
-                let value = self.bus.read_byte(addr as u16);
-                self.regs.a &= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x3D => {
-                // AND abs,X - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+            // ASL ROL LSR ROR STX LDX DEC INC
+            0x02 => match op {
+                4 if opcode == 0x9E => self.nmos_write_high(mode, self.regs.x), // SHX
+                4 | 6 | 7 if mode == Mode::Imm => {
+                    self.fetch_byte(); // NOP #imm
                 }
-                let value = self.bus.read_byte(addr);
-                self.regs.a &= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x39 => {
-                // AND abs,Y - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.y as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+                4 => self.write_operand(mode, self.regs.x),
+                5 => {
+                    self.regs.x = self.read_operand(mode);
+                    self.update_zero_and_negative_flags(self.regs.x);
                 }
-                let value = self.bus.read_byte(addr);
-                self.regs.a &= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x21 => {
-                let zp_addr = self.fetch_byte().wrapping_add(self.regs.x);
-                let addr = self.bus.read_word(zp_addr as u16);
-                let value = self.bus.read_byte(addr);
-                self.regs.a &= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x31 => {
-                let zp_addr = self.fetch_byte() as u16;
-                let base_addr = self.bus.read_word(zp_addr);
-                let addr = base_addr.wrapping_add(self.regs.y as u16);
-                let value = self.bus.read_byte(addr);
-                self.regs.a &= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x55 => {
-                let zp_addr = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-                let value = self.bus.read_byte(zp_addr);
-                self.regs.a ^= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x5D => {
-                // EOR abs,X - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+                _ => {
+                    self.modify_operand(mode, op);
                 }
-                let value = self.bus.read_byte(addr);
-                self.regs.a ^= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x59 => {
-                // EOR abs,Y - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.y as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+            },
+
+            // Undocumented: the read-modify-write op of the column combined
+            // with the ALU op of the row, plus the immediate oddities
+            0x03 => match (op, mode) {
+                (0 | 1, Mode::Imm) => {
+                    // ANC: AND, then C = N
+                    self.regs.a &= self.fetch_byte();
+                    self.update_zero_and_negative_flags(self.regs.a);
+                    self.p.set(Flags::CARRY, self.regs.a & 0x80 != 0);
                 }
-                let value = self.bus.read_byte(addr);
-                self.regs.a ^= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x41 => {
-                let zero_page_addr = self.fetch_byte().wrapping_add(self.regs.x);
-                let addr = self.bus.read_word(zero_page_addr as u16);
-                let value = self.bus.read_byte(addr);
-                self.regs.a ^= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x51 => {
-                let zero_page_addr = self.fetch_byte() as u16;
-                let base_addr = self.bus.read_word(zero_page_addr);
-                let addr = base_addr.wrapping_add(self.regs.y as u16);
-                let value = self.bus.read_byte(addr);
-                self.regs.a ^= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x15 => {
-                let addr = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-                let value = self.bus.read_byte(addr);
-                self.regs.a |= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x1D => {
-                // ORA abs,X - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+                (2, Mode::Imm) => {
+                    // ALR: AND, then LSR A
+                    let value = self.regs.a & self.fetch_byte();
+                    self.regs.a = self.lsr(value);
                 }
-                let value = self.bus.read_byte(addr);
-                self.regs.a |= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x19 => {
-                // ORA abs,Y - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.y as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+                (3, Mode::Imm) => {
+                    let value = self.fetch_byte();
+                    self.arr(value);
                 }
-                let value = self.bus.read_byte(addr);
-                self.regs.a |= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x01 => {
-                let zp_addr = self.fetch_byte().wrapping_add(self.regs.x);
-                let addr = self.bus.read_word(zp_addr as u16);
-                let value = self.bus.read_byte(addr);
-                self.regs.a |= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x11 => {
-                let zp_addr = self.fetch_byte() as u16;
-                let addr = self.bus.read_word(zp_addr).wrapping_add(self.regs.y as u16);
-                let value = self.bus.read_byte(addr);
-                self.regs.a |= value;
-
-                self.update_zero_and_negative_flags(self.regs.a);
-            }
-
-            0x75 => {
-                let zp_addr = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-                let value = self.bus.read_byte(zp_addr);
-                self.adc(value);
-            }
-
-            0xF5 => {
-                let zp_addr = self.fetch_byte().wrapping_add(self.regs.x) as u16;
-                let value = self.bus.read_byte(zp_addr);
-                self.sbc(value);
-            }
-
-            0x7D => {
-                // ADC abs,X - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+                (4, Mode::Imm) => {
+                    // ANE: unstable, uses the common $EE for the bus-dependent magic
+                    self.regs.a = (self.regs.a | 0xEE) & self.regs.x & self.fetch_byte();
+                    self.update_zero_and_negative_flags(self.regs.a);
                 }
-                let value = self.bus.read_byte(addr);
-                self.adc(value);
-            }
-
-            0xFD => {
-                // SBC abs,X - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.x as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+                (5, Mode::Imm) => {
+                    // LXA: unstable, same magic as ANE
+                    self.regs.a = (self.regs.a | 0xEE) & self.fetch_byte();
+                    self.regs.x = self.regs.a;
+                    self.update_zero_and_negative_flags(self.regs.a);
                 }
-                let value = self.bus.read_byte(addr);
-                self.sbc(value);
-            }
-
-            0x79 => {
-                // ADC abs,Y - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.y as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+                (6, Mode::Imm) => {
+                    // SBX: X = (A & X) - imm, carry as CMP, no decimal
+                    let value = self.fetch_byte();
+                    let ax = self.regs.a & self.regs.x;
+                    self.p.set(Flags::CARRY, ax >= value);
+                    self.regs.x = ax.wrapping_sub(value);
+                    self.update_zero_and_negative_flags(self.regs.x);
                 }
-                let value = self.bus.read_byte(addr);
-                self.adc(value);
-            }
-
-            0xF9 => {
-                // SBC abs,Y - +1 cycle if page crossed
-                let base_addr = self.fetch_word();
-                let addr = base_addr.wrapping_add(self.regs.y as u16);
-                if (base_addr & 0xFF00) != (addr & 0xFF00) {
-                    self.extra_cycles += 1;
+                (7, Mode::Imm) => {
+                    let value = self.fetch_byte();
+                    self.sbc(value);
                 }
-                let value = self.bus.read_byte(addr);
-                self.sbc(value);
-            }
-
-            0x61 => {
-                let addr = self.fetch_indirect_x();
-                let value = self.bus.read_byte(addr);
-                self.adc(value);
-            }
-
-            0xF1 => {
-                let addr = self.fetch_indirect_y();
-                let value = self.bus.read_byte(addr);
-                self.sbc(value);
-            }
-
-            0xE1 => {
-                let addr = self.fetch_indirect_x();
-                let value = self.bus.read_byte(addr);
-                self.sbc(value);
-            }
-
-            0x71 => {
-                let addr = self.fetch_indirect_y();
-                let value = self.bus.read_byte(addr);
-                self.adc(value);
-            }
-
-            0x0F | 0x1F | 0x2F | 0x3F | 0x4F | 0x5F | 0x6F | 0x7F => {
-                let zp_addr = self.fetch_byte() as u16;
-                let rel_offset = self.fetch_byte() as i8;
-                let value = self.bus.read_byte(zp_addr);
-                let bit = 1 << ((opcode.wrapping_sub(0x0F)) / 0x10);
-
-                if (value & bit) == 0 {
-                    self.branch(rel_offset);
+                (4, Mode::IndY | Mode::AbsY) if opcode != 0x9B => {
+                    self.nmos_write_high(mode, self.regs.a & self.regs.x); // SHA
                 }
-            }
-
-            0x8F | 0x9F | 0xAF | 0xBF | 0xCF | 0xDF | 0xEF | 0xFF => {
-                let zp_addr = self.fetch_byte() as u16;
-                let rel_offset = self.fetch_byte() as i8;
-                let value = self.bus.read_byte(zp_addr);
-                let bit = 1 << ((opcode.wrapping_sub(0x8F)) / 0x10);
+                (4, Mode::AbsY) => {
+                    // TAS
+                    self.regs.sp = self.regs.a & self.regs.x;
+                    self.nmos_write_high(mode, self.regs.sp);
+                }
+                (4, _) => self.write_operand(mode, self.regs.a & self.regs.x), // SAX
+                (5, Mode::AbsY) if opcode == 0xBB => {
+                    // LAS
+                    let value = self.read_operand(mode) & self.regs.sp;
+                    self.regs.a = value;
+                    self.regs.x = value;
+                    self.regs.sp = value;
+                    self.update_zero_and_negative_flags(value);
+                }
+                (5, _) => {
+                    // LAX
+                    self.regs.a = self.read_operand(mode);
+                    self.regs.x = self.regs.a;
+                    self.update_zero_and_negative_flags(self.regs.a);
+                }
+                _ => {
+                    // SLO RLA SRE RRA DCP ISC
+                    let value = self.modify_operand(mode, op);
+                    self.alu(op, value);
+                }
+            },
 
-                if (value & bit) != 0 {
-                    self.branch(rel_offset);
+            // NOPs with operands, BIT, STY, LDY, CPY, CPX
+            _ => match op {
+                4 if opcode == 0x9C => self.nmos_write_high(mode, self.regs.y), // SHY
+                4 if mode == Mode::Imm => {
+                    self.fetch_byte(); // NOP #imm
                 }
-            }
+                4 => self.write_operand(mode, self.regs.y),
+                5 => {
+                    self.regs.y = self.read_operand(mode);
+                    self.update_zero_and_negative_flags(self.regs.y);
+                }
+                1 if matches!(mode, Mode::Zp | Mode::Abs) => {
+                    let value = self.read_operand(mode);
+                    self.execute_bit(value);
+                }
+                6 if matches!(mode, Mode::Imm | Mode::Zp | Mode::Abs) => {
+                    let value = self.read_operand(mode);
+                    self.compare(self.regs.y, value);
+                }
+                7 if matches!(mode, Mode::Imm | Mode::Zp | Mode::Abs) => {
+                    let value = self.read_operand(mode);
+                    self.compare(self.regs.x, value);
+                }
+                _ => {
+                    self.read_operand(mode); // NOP, the operand is still read
+                }
+            },
+        }
+        true
+    }
 
-            0x7C => {
-                let base = self.fetch_word();
-                let addr = base.wrapping_add(self.regs.x as u16);
-                let lo = self.bus.read_byte(addr) as u16;
-                let hi = self.bus.read_byte(addr.wrapping_add(1)) as u16;
-                self.pc = (hi << 8) | lo;
+    fn execute_nmos_implied(&mut self, opcode: u8) -> bool {
+        match opcode {
+            // JAM: the CPU locks up until reset
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => {
+                self.bus.interrupts.enter_halt();
+                true
             }
 
-            0x03 | 0x13 | 0x23 | 0x33 | 0x43 | 0x53 | 0x63 | 0x73 | 0x83 | 0x93 | 0xA3 | 0xB3
-            | 0xC3 | 0xD3 | 0xE3 | 0xF3 | 0x0B | 0x1B | 0x2B | 0x3B | 0x4B | 0x5B | 0x6B | 0x7B
-            | 0x8B | 0x9B | 0xAB | 0xBB | 0xEB | 0xFB => {}
+            // BRK, JSR, JMP and branches fetch their own operands
+            0x00 | 0x20 | 0x4C | 0x6C => false,
+            _ if opcode & 0x1F == 0x10 => false,
 
-            0x02 | 0x22 | 0x42 | 0x62 | 0x82 | 0xC2 | 0xE2 => {
-                self.pc = self.pc.wrapping_add(1);
+            // Single-byte instructions read the byte after the opcode and
+            // discard it
+            _ => {
+                self.read(self.pc);
+                matches!(opcode, 0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA) // NOP
             }
+        }
+    }
 
-            0x44 => {
-                self.pc = self.pc.wrapping_add(1);
-            }
+    // 65C02 decoder for every instruction with an operand, the 65C02's own
+    // STZ/TSB/TRB/BIT modes, (zp) and the NOPs with operands included.
+    // Returns false for the implied, stack and control-flow instructions and
+    // the bit instructions, which the shared decoder executes.
+    fn execute_cmos(&mut self, opcode: u8) -> bool {
+        let Some(mode) = cmos_mode(opcode) else {
+            return self.execute_cmos_implied(opcode);
+        };
 
-            0x54 | 0xD4 | 0xF4 => {
-                self.pc = self.pc.wrapping_add(1);
+        let op = opcode >> 5;
+        match opcode {
+            0x89 => {
+                // BIT #imm only sets Z
+                let value = self.read_operand(mode);
+                self.p.set(Flags::ZERO, (self.regs.a & value) == 0);
             }
-
+            0x04 | 0x0C => self.test_bits(mode, true),  // TSB
+            0x14 | 0x1C => self.test_bits(mode, false), // TRB
+            0x64 | 0x74 | 0x9C | 0x9E => self.write_operand(mode, 0x00), // STZ
             0x5C => {
-                self.pc = self.pc.wrapping_add(2);
+                // NOP abs: eight cycles, reading $FFxx and then $FFFF
+                let (_, addr) = self.operand_address(mode, Access::Read);
+                self.read(0xFF00 | (addr & 0x00FF));
+                for _ in 0..4 {
+                    self.read(0xFFFF);
+                }
             }
 
-            0xDC | 0xFC => {
-                self.pc = self.pc.wrapping_add(2);
-            }
+            _ => match (opcode & 0x03, mode) {
+                // ORA AND EOR ADC STA LDA CMP SBC, (zp) in column 2
+                (0x01, _) | (0x02, Mode::ZpInd) => match op {
+                    4 => self.write_operand(mode, self.regs.a),
+                    _ => {
+                        let value = self.read_operand(mode);
+                        self.alu(op, value);
+                        if matches!(op, 3 | 7) && self.p.contains(Flags::DECIMAL) {
+                            // the decimal fix-up takes a cycle, which
+                            // reads the next opcode
+                            self.read(self.pc);
+                            self.extra_cycles += 1;
+                        }
+                    }
+                },
 
-            0x1A => {
-                if self.cpu_type != CpuType::NMOS6502 {
-                    self.regs.a = self.regs.a.wrapping_add(1);
-                    self.update_zero_and_negative_flags(self.regs.a);
-                }
-            }
+                // ASL ROL LSR ROR STX LDX DEC INC
+                (0x02, _) => match op {
+                    5 => {
+                        self.regs.x = self.read_operand(mode);
+                        self.update_zero_and_negative_flags(self.regs.x);
+                    }
+                    _ if mode == Mode::Imm => {
+                        self.fetch_byte(); // NOP #imm
+                    }
+                    4 => self.write_operand(mode, self.regs.x),
+                    _ => {
+                        self.modify_operand(mode, op);
+                    }
+                },
 
-            0x3A => {
-                if self.cpu_type != CpuType::NMOS6502 {
-                    self.regs.a = self.regs.a.wrapping_sub(1);
-                    self.update_zero_and_negative_flags(self.regs.a);
-                }
-            }
+                // BIT, STY, LDY, CPY, CPX and the NOPs with operands
+                _ => match op {
+                    1 => {
+                        let value = self.read_operand(mode);
+                        self.execute_bit(value);
+                    }
+                    4 => self.write_operand(mode, self.regs.y),
+                    5 => {
+                        self.regs.y = self.read_operand(mode);
+                        self.update_zero_and_negative_flags(self.regs.y);
+                    }
+                    6 | 7 if opcode & 0x10 == 0 => {
+                        let value = self.read_operand(mode);
+                        let reg = if op == 6 { self.regs.y } else { self.regs.x };
+                        self.compare(reg, value);
+                    }
+                    _ => {
+                        self.read_operand(mode); // NOP, the operand is still read
+                    }
+                },
+            },
+        }
+        true
+    }
 
-            0xB2 => {
-                if self.cpu_type != CpuType::NMOS6502 {
-                    let zp_addr = self.fetch_byte() as u16;
-                    let indirect_addr = self.bus.read_word(zp_addr);
-                    self.regs.a = self.bus.read_byte(indirect_addr);
-                    self.update_zero_and_negative_flags(self.regs.a);
-                }
-            }
+    fn execute_cmos_implied(&mut self, opcode: u8) -> bool {
+        match opcode {
+            _ if self.cpu_type.is_one_cycle_nop(opcode) => true,
 
-            0x92 => {
-                if self.cpu_type != CpuType::NMOS6502 {
-                    let zp_addr = self.fetch_byte() as u16;
-                    let indirect_addr = self.bus.read_word(zp_addr);
-                    self.bus.write_byte(indirect_addr, self.regs.a);
-                }
+            // WAI and STP take two cycles after the opcode before stopping
+            0xCB | 0xDB => {
+                self.read(self.pc);
+                self.read(self.pc);
+                false
             }
 
-            0x07 | 0x17 | 0x27 | 0x37 | 0x47 | 0x57 | 0x67 | 0x77 => {
-                let bit_n = (opcode >> 4) & 0b111;
-                let mask = !(1 << bit_n);
+            // BRK, JSR, JMP, branches and the bit instructions fetch their
+            // own operands
+            0x00 | 0x20 | 0x4C | 0x6C | 0x7C | 0x80 => false,
+            _ if opcode & 0x1F == 0x10 || opcode & 0x07 == 0x07 => false,
 
-                let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                let result = value & mask;
-                self.bus.write_byte(addr, result);
+            // Single-byte instructions read the byte after the opcode and
+            // discard it
+            _ => {
+                self.read(self.pc);
+                false
             }
+        }
+    }
 
-            0x87 | 0x97 | 0xA7 | 0xB7 | 0xC7 | 0xD7 | 0xE7 | 0xF7 => {
-                let bit_n = (opcode >> 4) & 0b111;
-                let mask = 1 << bit_n;
+    // TSB/TRB: Z from A AND memory, then A's bits set in or cleared from
+    // memory. Read-modify-write timing, with the 65C02's double read.
+    fn test_bits(&mut self, mode: Mode, set: bool) {
+        let (_, addr) = self.operand_address(mode, Access::Modify);
+        let value = self.read(addr);
+        self.read(addr);
+        self.p.set(Flags::ZERO, (value & self.regs.a) == 0);
+        let result = if set { value | self.regs.a } else { value & !self.regs.a };
+        self.write(addr, result);
+    }
 
+    // Effective address of an operand, making the dummy reads along the
+    // way. Where the NMOS part reads a half-formed address, the 65C02
+    // re-reads the last instruction byte, except that an indexed store or
+    // read-modify-write that stays within its page reads its target first.
+    // Returns (unindexed base, effective address).
+    fn operand_address(&mut self, mode: Mode, access: Access) -> (u16, u16) {
+        let nmos = self.cpu_type == CpuType::NMOS6502;
+        match mode {
+            Mode::Imm => {
+                let addr = self.pc;
+                self.pc = self.pc.wrapping_add(1);
+                (addr, addr)
+            }
+            Mode::Zp => {
                 let addr = self.fetch_byte() as u16;
-                let value = self.bus.read_byte(addr);
-                let result = value | mask;
-                self.bus.write_byte(addr, result);
+                (addr, addr)
             }
-
-            0xD2 => {
-                let addr_ptr = self.fetch_byte() as u16;
-                let addr = self.bus.read_word(addr_ptr);
-                let value = self.bus.read_byte(addr);
-
-                let result = self.regs.a.wrapping_sub(value);
-
-                self.p.set(Flags::CARRY, self.regs.a >= value);
-                self.p.set(Flags::ZERO, self.regs.a == value);
-                self.p.set(Flags::NEGATIVE, (result & 0x80) != 0);
+            Mode::ZpX | Mode::ZpY => {
+                let base = self.fetch_byte();
+                let dummy = if nmos { base as u16 } else { self.pc.wrapping_sub(1) };
+                self.read(dummy);
+                let index = if mode == Mode::ZpX { self.regs.x } else { self.regs.y };
+                (base as u16, base.wrapping_add(index) as u16)
             }
-
-            0x32 => {
-                let addr_ptr = self.fetch_byte() as u16;
-                let addr = self.bus.read_word(addr_ptr);
-                let value = self.bus.read_byte(addr);
-
-                self.regs.a &= value;
-
-                self.p.set(Flags::ZERO, self.regs.a == 0);
-                self.p.set(Flags::NEGATIVE, (self.regs.a & 0x80) != 0);
+            Mode::Abs => {
+                let addr = self.fetch_word();
+                (addr, addr)
             }
-
-            0x52 => {
-                let addr_ptr = self.fetch_byte() as u16;
-
-                let addr = self.bus.read_word(addr_ptr);
-                let value = self.bus.read_byte(addr);
-
-                self.regs.a ^= value;
-
-                self.p.set(Flags::ZERO, self.regs.a == 0);
-                self.p.set(Flags::NEGATIVE, (self.regs.a & 0x80) != 0);
+            Mode::IndX => {
+                let base = self.fetch_byte();
+                let dummy = if nmos { base as u16 } else { self.pc.wrapping_sub(1) };
+                self.read(dummy);
+                let addr = self.read_zp_word(base.wrapping_add(self.regs.x));
+                (addr, addr)
+            }
+            Mode::ZpInd => {
+                let zp = self.fetch_byte();
+                let addr = self.read_zp_word(zp);
+                (addr, addr)
+            }
+            Mode::AbsX | Mode::AbsY | Mode::IndY => {
+                let base = match mode {
+                    Mode::IndY => {
+                        let zp = self.fetch_byte();
+                        self.read_zp_word(zp)
+                    }
+                    _ => self.fetch_word(),
+                };
+                let index = if mode == Mode::AbsX { self.regs.x } else { self.regs.y };
+                let addr = base.wrapping_add(index as u16);
+                let crossed = (base & 0xFF00) != (addr & 0xFF00);
+                if crossed || access != Access::Read {
+                    let dummy = if nmos {
+                        // the low byte is added first; the high byte is
+                        // fixed up a cycle later
+                        (base & 0xFF00) | (addr & 0x00FF)
+                    } else if crossed {
+                        self.pc.wrapping_sub(1)
+                    } else {
+                        addr
+                    };
+                    self.read(dummy);
+                }
+                if crossed && access == Access::Read {
+                    self.extra_cycles += 1;
+                }
+                (base, addr)
             }
+        }
+    }
 
-            0x12 => {
-                let addr_ptr = self.fetch_byte() as u16;
+    fn read_operand(&mut self, mode: Mode) -> u8 {
+        // An immediate operand is part of the instruction, not a data read
+        if mode == Mode::Imm {
+            return self.fetch_byte();
+        }
+        let (_, addr) = self.operand_address(mode, Access::Read);
+        self.read(addr)
+    }
 
-                let addr = self.bus.read_word(addr_ptr);
-                let value = self.bus.read_byte(addr);
+    fn write_operand(&mut self, mode: Mode, value: u8) {
+        let (_, addr) = self.operand_address(mode, Access::Write);
+        self.write(addr, value);
+    }
 
-                self.regs.a |= value;
+    // SHA/SHX/SHY/TAS store value & (base high byte + 1). When the index
+    // crosses a page the stored value also replaces the address high byte.
+    fn nmos_write_high(&mut self, mode: Mode, value: u8) {
+        let (base, addr) = self.operand_address(mode, Access::Write);
+        let value = value & ((base >> 8) as u8).wrapping_add(1);
+        let addr = if (base & 0xFF00) != (addr & 0xFF00) {
+            ((value as u16) << 8) | (addr & 0x00FF)
+        } else {
+            addr
+        };
+        self.write(addr, value);
+    }
 
-                self.p.set(Flags::ZERO, self.regs.a == 0);
-                self.p.set(Flags::NEGATIVE, (self.regs.a & 0x80) != 0);
-            }
+    // Read-modify-write. The NMOS part writes the unmodified value back
+    // while the ALU works; the 65C02 reads it a second time instead, and
+    // only spends the index fix-up cycle on shifts and rotates that cross a
+    // page. op is the aaa row: ASL, ROL, LSR, ROR, -, -, DEC, INC.
+    fn modify_operand(&mut self, mode: Mode, op: u8) -> u8 {
+        let nmos = self.cpu_type == CpuType::NMOS6502;
+        let access = if !nmos && op < 4 { Access::Read } else { Access::Modify };
+        let (_, addr) = self.operand_address(mode, access);
+        let value = self.read(addr);
+        if nmos {
+            self.write(addr, value);
+        } else {
+            self.read(addr);
+        }
+        let result = match op {
+            0 => self.asl(value),
+            1 => self.rol(value),
+            2 => self.lsr(value),
+            3 => self.ror(value),
+            6 => value.wrapping_sub(1),
+            _ => value.wrapping_add(1),
+        };
+        if op >= 6 {
+            self.update_zero_and_negative_flags(result);
+        }
+        self.write(addr, result);
+        result
+    }
 
-            0x72 => {
-                let addr_ptr = self.fetch_byte() as u16;
+    // Accumulator op of the aaa row: ORA, AND, EOR, ADC, -, LDA, CMP, SBC
+    fn alu(&mut self, op: u8, value: u8) {
+        match op {
+            0 => self.regs.a |= value,
+            1 => self.regs.a &= value,
+            2 => self.regs.a ^= value,
+            3 => return self.adc(value),
+            5 => self.regs.a = value,
+            6 => return self.compare(self.regs.a, value),
+            _ => return self.sbc(value),
+        }
+        self.update_zero_and_negative_flags(self.regs.a);
+    }
 
-                let addr = self.bus.read_word(addr_ptr);
-                let value = self.bus.read_byte(addr);
+    // ARR: AND, then ROR A, with C and V taken from bits 6 and 5. In decimal
+    // mode the NMOS part also runs a BCD fix-up on each digit.
+    fn arr(&mut self, value: u8) {
+        let and = self.regs.a & value;
+        let carry_in = self.p.contains(Flags::CARRY) as u8;
+        let mut result = (and >> 1) | (carry_in << 7);
+        self.update_zero_and_negative_flags(result);
 
-                self.adc(value);
+        if !self.p.contains(Flags::DECIMAL) {
+            self.p.set(Flags::CARRY, result & 0x40 != 0);
+            self.p.set(Flags::OVERFLOW, ((result >> 6) ^ (result >> 5)) & 0x01 != 0);
+        } else {
+            self.p.set(Flags::OVERFLOW, (and ^ result) & 0x40 != 0);
+            if (and & 0x0F) + (and & 0x01) > 0x05 {
+                result = (result & 0xF0) | (result.wrapping_add(0x06) & 0x0F);
             }
+            let carry = (and >> 4) + ((and >> 4) & 0x01) > 0x05;
+            if carry {
+                result = result.wrapping_add(0x60);
+            }
+            self.p.set(Flags::CARRY, carry);
+        }
+        self.regs.a = result;
+    }
+}
 
-            0xF2 => {
-                let addr_ptr = self.fetch_byte() as u16;
-
-                let addr = self.bus.read_word(addr_ptr);
-                let value = self.bus.read_byte(addr);
+impl SaveState for CPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"CPU ");
+        w.u8(match self.system_type {
+            SystemType::Generic => 0,
+            SystemType::AppleIIc => 1,
+        });
+        w.u8(match self.cpu_type {
+            CpuType::NMOS6502 => 0,
+            CpuType::CMOS65C02 => 1,
+            CpuType::WDC65C02S => 2,
+            CpuType::R65C02 => 3,
+        });
+        w.u16(self.pc);
+        w.u8(self.regs.a);
+        w.u8(self.regs.x);
+        w.u8(self.regs.y);
+        w.u8(self.regs.sp);
+        w.u8(self.p.bits());
+        w.u64(self.cycles);
+        w.u16(self.prev_pc);
+
+        self.hooks.save_state(w);
+        self.bus.save_state(w);
+    }
 
-                self.sbc(value);
-            }
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"CPU ")?;
+        let system_type = match r.u8()? {
+            0 => SystemType::Generic,
+            1 => SystemType::AppleIIc,
+            other => anyhow::bail!("save state: unknown system type {}", other),
+        };
+        if system_type != self.system_type {
+            anyhow::bail!("save state is for a {:?} system, not {:?}", system_type, self.system_type);
         }
+        self.cpu_type = match r.u8()? {
+            0 => CpuType::NMOS6502,
+            1 => CpuType::CMOS65C02,
+            2 => CpuType::WDC65C02S,
+            3 => CpuType::R65C02,
+            other => anyhow::bail!("save state: unknown CPU type {}", other),
+        };
+        self.pc = r.u16()?;
+        self.regs.a = r.u8()?;
+        self.regs.x = r.u8()?;
+        self.regs.y = r.u8()?;
+        self.regs.sp = r.u8()?;
+        self.p = Flags::from_bits_truncate(r.u8()?);
+        self.cycles = r.u64()?;
+        self.prev_pc = r.u16()?;
+        self.extra_cycles = 0;
+
+        self.hooks.load_state(r)?;
+        self.bus.load_state(r)
     }
 }
@@ -5,14 +5,24 @@
 //! values during execution and only formatted when rendered (~60fps).
 
 use std::collections::VecDeque;
-use crate::cpu::Flags;
+use crate::disassembler::SymbolTable;
+use crate::mmu::MemoryView;
+use crate::opcodes;
+use crate::profiler::{self, Profiler};
+use crate::trace::{TraceRecord, NO_BANK};
 
-/// Maximum number of trace entries to keep in the ring buffer
+/// Default number of trace entries to keep in the ring buffer
 const MAX_TRACE_ENTRIES: usize = 2000;
 
+/// Largest ring buffer the toolbar allows; longer traces go to a file (--trace)
+const TRACE_CAPACITY_LIMIT: usize = 1_000_000;
+
 /// Maximum number of memory watch entries
 const MAX_WATCHES: usize = 16;
 
+/// Rows in the profiler hot-spot table
+const HOT_SPOT_ROWS: usize = 32;
+
 /// A single CPU trace entry - captured each instruction when monitor is active
 #[derive(Clone, Copy, Debug)]
 #[allow(dead_code)]
@@ -28,6 +38,8 @@ pub struct CpuTraceEntry {
     pub sp: u8,
     pub p: u8,
     pub cycles: u64,
+    /// Bank the instruction was fetched from, for bank-aware labels
+    pub view: Option<MemoryView>,
 }
 
 impl Default for CpuTraceEntry {
@@ -44,6 +56,7 @@ impl Default for CpuTraceEntry {
             sp: 0xFF,
             p: 0x34,
             cycles: 0,
+            view: None,
         }
     }
 }
@@ -51,18 +64,23 @@ impl Default for CpuTraceEntry {
 impl CpuTraceEntry {
     /// Format flags as a string like "NV-BDIZC"
     pub fn format_flags(&self) -> String {
-        let flags = Flags::from_bits_truncate(self.p);
-        format!(
-            "{}{}{}{}{}{}{}{}",
-            if flags.contains(Flags::NEGATIVE) { 'N' } else { 'n' },
-            if flags.contains(Flags::OVERFLOW) { 'V' } else { 'v' },
-            '-',
-            if flags.contains(Flags::BREAK) { 'B' } else { 'b' },
-            if flags.contains(Flags::DECIMAL) { 'D' } else { 'd' },
-            if flags.contains(Flags::IRQ_DISABLE) { 'I' } else { 'i' },
-            if flags.contains(Flags::ZERO) { 'Z' } else { 'z' },
-            if flags.contains(Flags::CARRY) { 'C' } else { 'c' },
-        )
+        opcodes::format_flags(self.p)
+    }
+
+    /// As a record for a trace file
+    pub fn trace_record(&self) -> TraceRecord {
+        TraceRecord {
+            pc: self.pc,
+            bank: self.view.map_or(NO_BANK, |view| view as u8),
+            length: self.instruction_len,
+            bytes: [self.opcode, self.operand1, self.operand2],
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            sp: self.sp,
+            p: self.p,
+            cycles: self.cycles,
+        }
     }
 
     /// Format the instruction bytes as hex
@@ -127,6 +145,9 @@ pub struct CpuMonitor {
     
     /// Ring buffer of recent trace entries
     pub trace_buffer: VecDeque<CpuTraceEntry>,
+
+    /// Number of entries the ring buffer keeps
+    pub trace_capacity: usize,
     
     /// Memory watch addresses
     pub watches: Vec<MemoryWatch>,
@@ -146,6 +167,12 @@ pub struct CpuMonitor {
     pub show_memory: bool,
     pub show_watches: bool,
     pub show_stack: bool,
+    pub show_profile: bool,
+
+    /// Profiler wanted on; App starts or stops CPU::profiler to match
+    pub profiling: bool,
+    /// Profiler counts to be cleared (taken by App)
+    pub reset_profile: bool,
     
     /// New watch address input buffer
     pub new_watch_addr: String,
@@ -161,6 +188,7 @@ impl Default for CpuMonitor {
             enabled: false,
             visible: false,
             trace_buffer: VecDeque::with_capacity(MAX_TRACE_ENTRIES),
+            trace_capacity: MAX_TRACE_ENTRIES,
             watches: Vec::with_capacity(MAX_WATCHES),
             auto_scroll: true,
             paused: false,
@@ -170,6 +198,9 @@ impl Default for CpuMonitor {
             show_memory: false,
             show_watches: false,
             show_stack: true,
+            show_profile: false,
+            profiling: false,
+            reset_profile: false,
             new_watch_addr: String::new(),
             new_watch_label: String::new(),
             goto_address: String::new(),
@@ -195,7 +226,7 @@ impl CpuMonitor {
             return;
         }
         
-        if self.trace_buffer.len() >= MAX_TRACE_ENTRIES {
+        while self.trace_buffer.len() >= self.trace_capacity.max(1) {
             self.trace_buffer.pop_front();
         }
         self.trace_buffer.push_back(entry);
@@ -221,7 +252,7 @@ impl CpuMonitor {
     }
 
     /// Render the CPU monitor as an egui::Window (returns whether window is open)
-    pub fn render(&mut self, ctx: &egui::Context, cpu_state: &CpuState, memory_reader: &dyn Fn(u16) -> u8) -> bool {
+    pub fn render(&mut self, ctx: &egui::Context, cpu_state: &CpuState, memory_reader: &dyn Fn(u16) -> u8, symbols: &SymbolTable, profiler: Option<&Profiler>) -> bool {
         if !self.visible {
             return false;
         }
@@ -235,7 +266,7 @@ impl CpuMonitor {
             .min_height(300.0)
             .resizable(true)
             .show(ctx, |ui| {
-                self.render_ui(ui, cpu_state, memory_reader);
+                self.render_ui(ui, cpu_state, memory_reader, symbols, profiler);
             });
         
         self.visible = open;
@@ -246,7 +277,7 @@ impl CpuMonitor {
         open
     }
 
-    fn render_ui(&mut self, ui: &mut egui::Ui, cpu_state: &CpuState, memory_reader: &dyn Fn(u16) -> u8) {
+    fn render_ui(&mut self, ui: &mut egui::Ui, cpu_state: &CpuState, memory_reader: &dyn Fn(u16) -> u8, symbols: &SymbolTable, profiler: Option<&Profiler>) {
         // Toolbar
         ui.horizontal(|ui| {
             if ui.button(if self.paused { "Resume" } else { "Pause" }).clicked() {
@@ -256,12 +287,15 @@ impl CpuMonitor {
                 self.clear_trace();
             }
             ui.checkbox(&mut self.auto_scroll, "Auto-scroll");
+            ui.label("Ring:");
+            ui.add(egui::DragValue::new(&mut self.trace_capacity).range(100..=TRACE_CAPACITY_LIMIT).speed(100));
             ui.separator();
             ui.checkbox(&mut self.show_registers, "Regs");
             ui.checkbox(&mut self.show_trace, "Trace");
             ui.checkbox(&mut self.show_memory, "Memory");
             ui.checkbox(&mut self.show_stack, "Stack");
             ui.checkbox(&mut self.show_watches, "Watches");
+            ui.checkbox(&mut self.show_profile, "Profile");
         });
 
         ui.separator();
@@ -278,12 +312,16 @@ impl CpuMonitor {
             ui.vertical(|ui| {
                 ui.set_min_width(350.0);
                 if self.show_trace {
-                    self.render_trace(ui);
+                    self.render_trace(ui, symbols);
                 }
                 if self.show_memory {
                     ui.separator();
                     self.render_memory(ui, memory_reader);
                 }
+                if self.show_profile {
+                    ui.separator();
+                    self.render_profile(ui, symbols, profiler);
+                }
             });
 
             ui.separator();
@@ -307,13 +345,13 @@ impl CpuMonitor {
             ui.monospace(format!(
                 "PC:{:04X}  A:{:02X}  X:{:02X}  Y:{:02X}  SP:{:02X}  P:{:02X} [{}]  CYC:{}",
                 state.pc, state.a, state.x, state.y, state.sp, state.p,
-                format_flags_short(state.p),
+                opcodes::format_flags(state.p),
                 state.cycles
             ));
         });
     }
 
-    fn render_trace(&mut self, ui: &mut egui::Ui) {
+    fn render_trace(&mut self, ui: &mut egui::Ui, symbols: &SymbolTable) {
         ui.label(format!("Trace ({} entries)", self.trace_buffer.len()));
         
         let available_height = (ui.available_height() - 20.0).max(100.0);
@@ -326,19 +364,64 @@ impl CpuMonitor {
                 for entry in self.trace_buffer.iter() {
                     ui.horizontal(|ui| {
                         ui.monospace(format!(
-                            "{:04X}: {} A:{:02X} X:{:02X} Y:{:02X} P:{}",
+                            "{:04X}: {} A:{:02X} X:{:02X} Y:{:02X} P:{} {}",
                             entry.pc,
                             entry.format_bytes(),
                             entry.a,
                             entry.x,
                             entry.y,
                             entry.format_flags(),
+                            symbols.lookup(entry.pc, entry.view).unwrap_or(""),
                         ));
                     });
                 }
             });
     }
 
+    fn render_profile(&mut self, ui: &mut egui::Ui, symbols: &SymbolTable, profiler: Option<&Profiler>) {
+        let Some(profiler) = profiler else {
+            ui.horizontal(|ui| {
+                ui.label("Profiler off");
+                if ui.button("Start").clicked() {
+                    self.profiling = true;
+                }
+            });
+            return;
+        };
+
+        let total = profiler.total();
+        ui.horizontal(|ui| {
+            ui.label(format!("Hot spots ({} instructions, {} cycles)", total.instructions, total.cycles));
+            if ui.small_button("Reset").clicked() {
+                self.reset_profile = true;
+            }
+            if ui.small_button("Stop").clicked() {
+                self.profiling = false;
+            }
+        });
+
+        egui::ScrollArea::vertical()
+            .id_salt("profile")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                ui.monospace(format!(
+                    "{:<8} {:>5}  {:<16} {:>10} {:>12} {:>6}",
+                    "BANK", "ADDR", "LABEL", "INSTR", "CYCLES", "%"
+                ));
+                for ((view, addr), stats) in profiler.hot_spots(HOT_SPOT_ROWS) {
+                    ui.monospace(format!(
+                        "{:<8} ${:04X}  {:<16} {:>10} {:>12} {:>5.1}%",
+                        profiler::bank_name(view),
+                        addr,
+                        symbols.lookup(addr, view).unwrap_or(""),
+                        stats.instructions,
+                        stats.cycles,
+                        profiler.percent(stats.cycles),
+                    ));
+                }
+            });
+    }
+
     fn render_memory(&mut self, ui: &mut egui::Ui, memory_reader: &dyn Fn(u16) -> u8) {
         ui.horizontal(|ui| {
             ui.label("Page:");
@@ -367,7 +450,7 @@ impl CpuMonitor {
                     for col in 0..16 {
                         let byte = memory_reader(addr + col);
                         line.push_str(&format!("{:02X} ", byte));
-                        ascii.push(if byte >= 0x20 && byte < 0x7F {
+                        ascii.push(if (0x20..0x7F).contains(&byte) {
                             byte as char
                         } else {
                             '.'
@@ -458,18 +541,3 @@ pub struct CpuState {
     pub cycles: u64,
 }
 
-/// Format flags as compact string
-fn format_flags_short(p: u8) -> String {
-    let flags = Flags::from_bits_truncate(p);
-    format!(
-        "{}{}{}{}{}{}{}{}",
-        if flags.contains(Flags::NEGATIVE) { 'N' } else { 'n' },
-        if flags.contains(Flags::OVERFLOW) { 'V' } else { 'v' },
-        '-',
-        if flags.contains(Flags::BREAK) { 'B' } else { 'b' },
-        if flags.contains(Flags::DECIMAL) { 'D' } else { 'd' },
-        if flags.contains(Flags::IRQ_DISABLE) { 'I' } else { 'i' },
-        if flags.contains(Flags::ZERO) { 'Z' } else { 'z' },
-        if flags.contains(Flags::CARRY) { 'C' } else { 'c' },
-    )
-}
@@ -0,0 +1,881 @@
+// Debug Adapter Protocol server (--dap PORT)
+//
+// Listens on 127.0.0.1 for one client at a time, such as VS Code with
+// `"debugServer": PORT` in its launch configuration. Like the GDB stub, the
+// socket is polled from the emulator's own loop, so the machine keeps its
+// window and timing; while the client has it stopped, no instructions run.
+//
+// launch and attach take:
+//
+//   "symbols": "app.dbg" or [...]   ld65 .dbg or VICE label files (see debug_info)
+//   "stopOnEntry": true             stop once configuration is done
+//
+// Scopes are Registers (P expands to its flags), Trace (the last
+// instructions run, kept in a CPU monitor trace buffer while a client is
+// attached) and Memory. Memory references are "view:address": view cpu is
+// what the CPU sees now, read without soft-switch side effects; main, aux,
+// lcmain1, lcmain2, lcaux1, lcaux2, rom1 and rom2 are the IIc banks whatever
+// the switches select. A bare address is in the cpu view. Breakpoints go on
+// source lines, labels (function breakpoints) or addresses (instruction
+// breakpoints), and stop before the instruction there runs. next and stepIn
+// run to the next source line unless the client asks for instruction
+// granularity or PC has no line; next steps over JSRs either way.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use json::{object, JsonValue};
+
+use crate::cpu::{Flags, CPU};
+use crate::cpu_monitor::{CpuMonitor, CpuTraceEntry};
+use crate::debug_link::{DebugLink, DebugServer};
+use crate::debug_info::DebugInfo;
+use crate::disassembler::Disassembler;
+use crate::mmu::MemoryView;
+
+// The one thread
+const THREAD_ID: i64 = 1;
+
+// variablesReference of each scope, and of P's flags
+const REGISTERS: i64 = 1;
+const FLAGS: i64 = 2;
+const TRACE: i64 = 3;
+const MEMORY: i64 = 4;
+
+#[rustfmt::skip]
+const FLAG_NAMES: [(&str, Flags); 7] = [
+    ("N", Flags::NEGATIVE), ("V", Flags::OVERFLOW), ("B", Flags::BREAK), ("D", Flags::DECIMAL),
+    ("I", Flags::IRQ_DISABLE), ("Z", Flags::ZERO), ("C", Flags::CARRY),
+];
+
+// Where a step in progress stops
+enum Step {
+    // Back at pc with the stack where it was: over a JSR
+    Over { pc: u16, sp: u8 },
+    // After an RTS or RTI that pops the stack above sp
+    Out { sp: u8 },
+    // On a source line other than this one; with sp, not in a routine
+    // called from it
+    Line { file: PathBuf, line: u32, sp: Option<u8> },
+}
+
+pub struct DapServer {
+    // Received bytes not yet making a whole message stay in link.input
+    link: DebugLink,
+    seq: i64,
+    // Events waiting for the response to the request that caused them
+    events: Vec<JsonValue>,
+    stopped: bool,
+    stop_on_entry: bool,
+    lines_start_at1: bool,
+    debug_info: DebugInfo,
+    // Addresses of the breakpoints of each kind; `breakpoints` is all of them
+    source_breakpoints: HashMap<String, Vec<u16>>,
+    function_breakpoints: Vec<u16>,
+    instruction_breakpoints: Vec<u16>,
+    breakpoints: HashSet<u16>,
+    step: Option<Step>,
+    monitor: CpuMonitor,
+    // cpu.capture_trace before the client attached
+    capture_trace: bool,
+}
+
+impl DapServer {
+    // Listen on port. With `wait`, the CPU stays stopped until a client
+    // attaches and its configuration is done.
+    pub fn listen(port: u16, wait: bool) -> io::Result<Self> {
+        let link = DebugLink::listen(port)?;
+        let mut monitor = CpuMonitor::new();
+        monitor.enabled = true;
+        Ok(Self {
+            link,
+            seq: 0,
+            events: Vec::new(),
+            stopped: wait,
+            stop_on_entry: false,
+            lines_start_at1: true,
+            debug_info: DebugInfo::default(),
+            source_breakpoints: HashMap::new(),
+            function_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            breakpoints: HashSet::new(),
+            step: None,
+            monitor,
+            capture_trace: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.link.local_addr()
+    }
+
+    fn step_done(&self, cpu: &CPU) -> bool {
+        match &self.step {
+            Some(Step::Over { pc, sp }) => cpu.pc == *pc && cpu.regs.sp == *sp,
+            Some(Step::Out { sp }) => matches!(cpu.last_trace.opcode, 0x40 | 0x60) && cpu.regs.sp > *sp,
+            Some(Step::Line { file, line, sp }) => {
+                sp.is_none_or(|sp| cpu.regs.sp >= sp)
+                    && self.debug_info.line_at(cpu.pc).is_some_and(|at| at != (file.as_path(), *line))
+            }
+            None => false,
+        }
+    }
+
+    fn stop(&mut self, reason: &str) {
+        self.stopped = true;
+        self.step = None;
+        let mut body = object! { reason: reason, threadId: THREAD_ID, allThreadsStopped: true };
+        if reason == "exception" {
+            body["description"] = "CPU halted".into();
+        }
+        self.queue_event("stopped", body);
+    }
+
+    fn resume(&mut self) {
+        self.stopped = false;
+    }
+
+    fn detach(&mut self, cpu: &mut CPU) {
+        self.source_breakpoints.clear();
+        self.function_breakpoints.clear();
+        self.instruction_breakpoints.clear();
+        self.breakpoints.clear();
+        self.debug_info = DebugInfo::default();
+        self.step = None;
+        self.events.clear();
+        self.link.disconnect();
+        self.stopped = false;
+        cpu.capture_trace = self.capture_trace;
+        println!("dap   {:>12} {:>8}", "CLIENT", "DETACHED");
+    }
+
+    // One whole Content-Length framed message from the input, if there is one
+    fn next_message(&mut self) -> Option<JsonValue> {
+        loop {
+            let header_end = self.link.input.windows(4).position(|w| w == b"\r\n\r\n")?;
+            let header = String::from_utf8_lossy(&self.link.input[..header_end]).into_owned();
+            let length = header.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim().eq_ignore_ascii_case("Content-Length").then(|| value.trim().parse::<usize>().ok())?
+            });
+            let Some(length) = length else {
+                // Not a header we understand: skip it
+                self.link.input.drain(..header_end + 4);
+                continue;
+            };
+            let body_start = header_end + 4;
+            if self.link.input.len() < body_start + length {
+                return None;
+            }
+            let body: Vec<u8> = self.link.input.drain(..body_start + length).skip(body_start).collect();
+            match json::parse(&String::from_utf8_lossy(&body)) {
+                Ok(message) => return Some(message),
+                Err(e) => eprintln!("dap   {:>12} {:>8}    {}", "MESSAGE", "ERROR", e),
+            }
+        }
+    }
+
+    fn handle(&mut self, cpu: &mut CPU, message: &JsonValue) {
+        if message["type"] != "request" {
+            return;
+        }
+        let command = message["command"].as_str().unwrap_or("");
+        let args = &message["arguments"];
+        let result = match command {
+            "initialize" => {
+                self.lines_start_at1 = args["linesStartAt1"].as_bool().unwrap_or(true);
+                self.queue_event("initialized", JsonValue::Null);
+                Ok(capabilities())
+            }
+            "launch" | "attach" => self.configure(args),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.stop("entry");
+                } else {
+                    self.resume();
+                }
+                Ok(JsonValue::Null)
+            }
+            "setBreakpoints" => Ok(self.set_source_breakpoints(args)),
+            "setFunctionBreakpoints" => Ok(self.set_function_breakpoints(args)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(object! { breakpoints: [] }),
+            "threads" => Ok(object! { threads: [{ id: THREAD_ID, name: "6502" }] }),
+            "stackTrace" => Ok(self.stack_trace(cpu)),
+            "scopes" => Ok(object! {
+                scopes: [
+                    { name: "Registers", presentationHint: "registers", variablesReference: REGISTERS, expensive: false },
+                    { name: "Trace", variablesReference: TRACE, indexedVariables: self.monitor.trace_buffer.len(), expensive: true },
+                    { name: "Memory", variablesReference: MEMORY, expensive: false },
+                ]
+            }),
+            "variables" => Ok(self.variables(cpu, args)),
+            "setVariable" => set_variable(cpu, args),
+            "evaluate" => self.evaluate(cpu, args),
+            "readMemory" => read_memory(cpu, args),
+            "writeMemory" => write_memory(cpu, args),
+            "disassemble" => Ok(self.disassemble(cpu, args)),
+            "continue" => {
+                self.resume();
+                Ok(object! { allThreadsContinued: true })
+            }
+            "next" => {
+                if !(by_line(args) && self.step_line(cpu, true)) {
+                    self.step_over(cpu);
+                }
+                Ok(JsonValue::Null)
+            }
+            "stepIn" => {
+                if !(by_line(args) && self.step_line(cpu, false)) {
+                    self.step_instruction(cpu);
+                }
+                Ok(JsonValue::Null)
+            }
+            "stepOut" => {
+                self.step = Some(Step::Out { sp: cpu.regs.sp });
+                self.resume();
+                Ok(JsonValue::Null)
+            }
+            "pause" => {
+                if !self.stopped {
+                    self.stop("pause");
+                }
+                Ok(JsonValue::Null)
+            }
+            "disconnect" => Ok(JsonValue::Null),
+            _ => Err(format!("{} is not supported", command)),
+        };
+
+        let mut response = object! {
+            type: "response",
+            request_seq: message["seq"].clone(),
+            command: command,
+        };
+        match result {
+            Ok(body) => {
+                response["success"] = true.into();
+                if !body.is_null() {
+                    response["body"] = body;
+                }
+            }
+            Err(error) => {
+                response["success"] = false.into();
+                response["message"] = error.into();
+            }
+        }
+        self.send(response);
+        self.flush_events();
+        if command == "disconnect" {
+            self.link.disconnect();
+        }
+    }
+
+    // launch/attach: load symbols and note stopOnEntry
+    fn configure(&mut self, args: &JsonValue) -> Result<JsonValue, String> {
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        let paths: Vec<&str> = match &args["symbols"] {
+            JsonValue::Array(paths) => paths.iter().filter_map(JsonValue::as_str).collect(),
+            symbols => symbols.as_str().into_iter().collect(),
+        };
+        for path in paths {
+            self.debug_info.load(path).map_err(|e| e.to_string())?;
+            let loaded = format!(
+                "{} ({} labels, {} lines)",
+                path,
+                self.debug_info.label_count(),
+                self.debug_info.line_count()
+            );
+            println!("dap   {:>12} {:>8}    {}", "SYMBOLS", "LOADED", loaded);
+            self.queue_event("output", object! { category: "console", output: format!("Loaded {}\n", loaded) });
+        }
+        Ok(JsonValue::Null)
+    }
+
+    fn set_source_breakpoints(&mut self, args: &JsonValue) -> JsonValue {
+        let path = args["source"]["path"].as_str().unwrap_or("").to_string();
+        let mut addrs = Vec::new();
+        let mut results = Vec::new();
+        for breakpoint in args["breakpoints"].members() {
+            let line = breakpoint["line"].as_u32().unwrap_or(0);
+            let line = if self.lines_start_at1 { line } else { line.saturating_add(1) };
+            results.push(match self.debug_info.line_addresses(&path, line) {
+                Some((found, at)) => {
+                    addrs.extend_from_slice(at);
+                    object! {
+                        verified: true,
+                        line: self.client_line(found),
+                        instructionReference: reference(None, at[0]),
+                    }
+                }
+                None => object! { verified: false, message: "No code at this line" },
+            });
+        }
+        self.source_breakpoints.insert(path, addrs);
+        self.rebuild_breakpoints();
+        object! { breakpoints: results }
+    }
+
+    fn set_function_breakpoints(&mut self, args: &JsonValue) -> JsonValue {
+        self.function_breakpoints.clear();
+        let mut results = Vec::new();
+        for breakpoint in args["breakpoints"].members() {
+            let name = breakpoint["name"].as_str().unwrap_or("");
+            let addr = self.debug_info.label(name).or_else(|| parse_value(name).and_then(|v| u16::try_from(v).ok()));
+            results.push(match addr {
+                Some(addr) => {
+                    self.function_breakpoints.push(addr);
+                    object! { verified: true, instructionReference: reference(None, addr) }
+                }
+                None => object! { verified: false, message: format!("No label {}", name) },
+            });
+        }
+        self.rebuild_breakpoints();
+        object! { breakpoints: results }
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &JsonValue) -> JsonValue {
+        self.instruction_breakpoints.clear();
+        let mut results = Vec::new();
+        for breakpoint in args["breakpoints"].members() {
+            let addr = breakpoint["instructionReference"]
+                .as_str()
+                .and_then(parse_reference)
+                .and_then(|(_, addr)| u16::try_from(addr as i64 + breakpoint["offset"].as_i64().unwrap_or(0)).ok());
+            results.push(match addr {
+                Some(addr) => {
+                    self.instruction_breakpoints.push(addr);
+                    object! { verified: true, instructionReference: reference(None, addr) }
+                }
+                None => object! { verified: false, message: "Bad instruction reference" },
+            });
+        }
+        self.rebuild_breakpoints();
+        object! { breakpoints: results }
+    }
+
+    fn rebuild_breakpoints(&mut self) {
+        self.breakpoints = self
+            .source_breakpoints
+            .values()
+            .flatten()
+            .chain(&self.function_breakpoints)
+            .chain(&self.instruction_breakpoints)
+            .copied()
+            .collect();
+    }
+
+    fn step_instruction(&mut self, cpu: &mut CPU) {
+        cpu.tick();
+        self.monitor.record(cpu.last_trace);
+        self.stop("step");
+    }
+
+    // One instruction, running a JSR's routine through to its return
+    fn step_over(&mut self, cpu: &mut CPU) {
+        if cpu.bus.peek_byte(cpu.pc) == 0x20 {
+            self.step = Some(Step::Over { pc: cpu.pc.wrapping_add(3), sp: cpu.regs.sp });
+            self.resume();
+        } else {
+            self.step_instruction(cpu);
+        }
+    }
+
+    // Run to the next source line, over calls with `over`. Returns false,
+    // leaving the CPU stopped, when PC has no line to step from.
+    fn step_line(&mut self, cpu: &CPU, over: bool) -> bool {
+        let Some((file, line)) = self.debug_info.line_at(cpu.pc) else { return false };
+        self.step = Some(Step::Line { file: file.to_path_buf(), line, sp: over.then_some(cpu.regs.sp) });
+        self.resume();
+        true
+    }
+
+    // One frame, at PC: 6502 code keeps no frame chain to walk
+    fn stack_trace(&self, cpu: &CPU) -> JsonValue {
+        let mut frame = object! {
+            id: 0,
+            name: self.address_name(cpu.pc),
+            line: 0,
+            column: 0,
+            instructionPointerReference: reference(None, cpu.pc),
+        };
+        if let Some((path, line)) = self.debug_info.line_at(cpu.pc) {
+            frame["source"] = source(path);
+            frame["line"] = self.client_line(line).into();
+            frame["column"] = self.client_line(1).into();
+        }
+        object! { stackFrames: [frame], totalFrames: 1 }
+    }
+
+    // "label+offset", or the address when no label is within a page below it
+    fn address_name(&self, addr: u16) -> String {
+        match self.debug_info.nearest_label(addr) {
+            Some((label, 0)) => label.to_string(),
+            Some((label, offset)) if offset < 0x100 => format!("{}+{}", label, offset),
+            _ => format!("${:04X}", addr),
+        }
+    }
+
+    fn client_line(&self, line: u32) -> u32 {
+        if self.lines_start_at1 { line } else { line.saturating_sub(1) }
+    }
+
+    fn variables(&self, cpu: &CPU, args: &JsonValue) -> JsonValue {
+        let byte = |name: &str, value: u8| object! { name: name, value: format!("${:02X}", value), variablesReference: 0 };
+        let variables = match args["variablesReference"].as_i64() {
+            Some(REGISTERS) => {
+                let mut p = byte("P", cpu.p.bits());
+                p["variablesReference"] = FLAGS.into();
+                vec![
+                    byte("A", cpu.regs.a),
+                    byte("X", cpu.regs.x),
+                    byte("Y", cpu.regs.y),
+                    byte("SP", cpu.regs.sp),
+                    p,
+                    object! {
+                        name: "PC",
+                        value: format!("${:04X}", cpu.pc),
+                        variablesReference: 0,
+                        memoryReference: reference(None, cpu.pc),
+                    },
+                ]
+            }
+            Some(FLAGS) => FLAG_NAMES
+                .iter()
+                .map(|&(name, flag)| object! { name: name, value: cpu.p.contains(flag) as u8, variablesReference: 0 })
+                .collect(),
+            Some(TRACE) => {
+                let trace = &self.monitor.trace_buffer;
+                let start = args["start"].as_usize().unwrap_or(0).min(trace.len());
+                let count = args["count"].as_usize().filter(|&n| n > 0).unwrap_or(trace.len());
+                trace
+                    .iter()
+                    .enumerate()
+                    .skip(start)
+                    .take(count)
+                    .map(|(i, entry)| object! {
+                        name: format!("{}", i as i64 - trace.len() as i64),
+                        value: format_trace(entry, cpu),
+                        variablesReference: 0,
+                        memoryReference: reference(None, entry.pc),
+                    })
+                    .collect()
+            }
+            Some(MEMORY) => {
+                let mut views = vec![object! {
+                    name: "cpu",
+                    value: "$0000-$FFFF as the CPU sees it",
+                    variablesReference: 0,
+                    memoryReference: reference(None, 0),
+                }];
+                if cpu.bus.peek_view(MemoryView::Main, 0).is_some() {
+                    views.extend(MemoryView::ALL.iter().map(|&view| {
+                        let (first, last) = view.range();
+                        object! {
+                            name: view.name(),
+                            value: format!("${:04X}-${:04X}", first, last),
+                            variablesReference: 0,
+                            memoryReference: reference(Some(view), first),
+                        }
+                    }));
+                }
+                views
+            }
+            _ => Vec::new(),
+        };
+        object! { variables: variables }
+    }
+
+    // Registers, labels, and memory as $addr or view:addr
+    fn evaluate(&self, cpu: &mut CPU, args: &JsonValue) -> Result<JsonValue, String> {
+        let expression = args["expression"].as_str().unwrap_or("").trim();
+        if let Some(value) = register(cpu, expression) {
+            let width = if expression.eq_ignore_ascii_case("pc") { 4 } else { 2 };
+            return Ok(object! { result: format!("${:0width$X}", value, width = width), variablesReference: 0 });
+        }
+        if let Some(addr) = self.debug_info.label(expression) {
+            return Ok(object! {
+                result: format!("${:04X}", addr),
+                variablesReference: 0,
+                memoryReference: reference(None, addr),
+            });
+        }
+        let (view, addr) = parse_reference(expression).ok_or_else(|| format!("Unknown expression {}", expression))?;
+        let addr = u16::try_from(addr).map_err(|_| format!("{} is outside memory", expression))?;
+        let value = peek(cpu, view, addr).ok_or_else(|| format!("{} is not in {}", expression, view_name(view)))?;
+        Ok(object! {
+            result: format!("${:02X}", value),
+            variablesReference: 0,
+            memoryReference: reference(view, addr),
+        })
+    }
+
+    fn disassemble(&self, cpu: &mut CPU, args: &JsonValue) -> JsonValue {
+        let base = args["memoryReference"].as_str().and_then(parse_reference).map_or(0, |(_, addr)| addr as i64);
+        let addr = base.saturating_add(args["offset"].as_i64().unwrap_or(0)).clamp(0, 0xFFFF) as u16;
+        let count = args["instructionCount"].as_usize().unwrap_or(0);
+        let starts = instruction_starts(cpu, addr, args["instructionOffset"].as_i64().unwrap_or(0), count);
+
+        let instructions: Vec<JsonValue> = starts
+            .into_iter()
+            .map(|start| {
+                let Some(at) = start else {
+                    return object! { address: "0x0000", instruction: "", presentationHint: "invalid" };
+                };
+                let bytes = instruction_bytes(cpu, at);
+                let (mnemonic, operand, length) = Disassembler::decode(at, bytes, cpu.cpu_type);
+                let hex: Vec<String> = bytes[..length].iter().map(|b| format!("{:02X}", b)).collect();
+                let mut instruction = object! {
+                    address: reference(None, at),
+                    instructionBytes: hex.join(" "),
+                    instruction: format!("{} {}", mnemonic, operand).trim_end(),
+                };
+                if let Some((label, 0)) = self.debug_info.nearest_label(at) {
+                    instruction["symbol"] = label.into();
+                }
+                if let Some((path, line)) = self.debug_info.line_at(at) {
+                    instruction["location"] = source(path);
+                    instruction["line"] = self.client_line(line).into();
+                }
+                instruction
+            })
+            .collect();
+        object! { instructions: instructions }
+    }
+
+    fn queue_event(&mut self, event: &str, body: JsonValue) {
+        let mut message = object! { type: "event", event: event };
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.events.push(message);
+    }
+
+    fn flush_events(&mut self) {
+        for event in std::mem::take(&mut self.events) {
+            self.send(event);
+        }
+    }
+
+    fn send(&mut self, mut message: JsonValue) {
+        self.seq += 1;
+        message["seq"] = self.seq.into();
+        let body = message.dump();
+        let frame = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        self.link.write(frame.as_bytes());
+    }
+}
+
+impl DebugServer for DapServer {
+    fn poll(&mut self, cpu: &mut CPU) {
+        if !self.link.is_attached() {
+            let Some(addr) = self.link.accept("dap") else { return };
+            println!("dap   {:>12} {:>8}    {}", "CLIENT", "ATTACHED", addr);
+            // Stopped until the client has set its breakpoints
+            self.stopped = true;
+            self.capture_trace = cpu.capture_trace;
+            cpu.capture_trace = true;
+            self.monitor.clear_trace();
+        }
+        if !self.link.receive() {
+            self.detach(cpu);
+            return;
+        }
+        while let Some(message) = self.next_message() {
+            self.handle(cpu, &message);
+            if !self.link.is_attached() {
+                self.detach(cpu);
+                return;
+            }
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    fn is_attached(&self) -> bool {
+        self.link.is_attached()
+    }
+
+    // Record the instruction that has just run, then stop on a breakpoint,
+    // the end of a step or a halt
+    fn check(&mut self, cpu: &CPU) -> bool {
+        if self.stopped || !self.link.is_attached() {
+            return false;
+        }
+        self.monitor.record(cpu.last_trace);
+        let reason = if self.breakpoints.contains(&cpu.pc) {
+            "breakpoint"
+        } else if self.step_done(cpu) {
+            "step"
+        } else if cpu.bus.interrupts.halted {
+            "exception"
+        } else {
+            return false;
+        };
+        self.stop(reason);
+        self.flush_events();
+        true
+    }
+}
+
+fn capabilities() -> JsonValue {
+    object! {
+        supportsConfigurationDoneRequest: true,
+        supportsFunctionBreakpoints: true,
+        supportsInstructionBreakpoints: true,
+        supportsSetVariable: true,
+        supportsEvaluateForHovers: true,
+        supportsReadMemoryRequest: true,
+        supportsWriteMemoryRequest: true,
+        supportsDisassembleRequest: true,
+        supportsSteppingGranularity: true,
+    }
+}
+
+// next and stepIn go by statement unless asked for instructions
+fn by_line(args: &JsonValue) -> bool {
+    args["granularity"] != "instruction"
+}
+
+fn source(path: &Path) -> JsonValue {
+    object! {
+        name: path.file_name().map(|n| n.to_string_lossy().into_owned()),
+        path: path.to_string_lossy().into_owned(),
+    }
+}
+
+fn view_name(view: Option<MemoryView>) -> &'static str {
+    view.map_or("cpu", MemoryView::name)
+}
+
+fn reference(view: Option<MemoryView>, addr: u16) -> String {
+    match view {
+        Some(view) => format!("{}:0x{:04X}", view.name(), addr),
+        None => format!("0x{:04X}", addr),
+    }
+}
+
+// "view:address", "view" or "address"; a view of None is the cpu view
+fn parse_reference(text: &str) -> Option<(Option<MemoryView>, u32)> {
+    let (view, addr) = match text.split_once(':') {
+        Some((view, addr)) => (view, parse_value(addr.trim())?),
+        None if parse_value(text).is_some() => ("cpu", parse_value(text)?),
+        None => (text, 0),
+    };
+    let view = match view.trim() {
+        name if name.eq_ignore_ascii_case("cpu") => None,
+        name => Some(MemoryView::parse(name)?),
+    };
+    Some((view, addr))
+}
+
+// $hex, 0xhex or decimal
+fn parse_value(text: &str) -> Option<u32> {
+    if let Some(hex) = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")).or_else(|| text.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+fn peek(cpu: &mut CPU, view: Option<MemoryView>, addr: u16) -> Option<u8> {
+    match view {
+        Some(view) => cpu.bus.peek_view(view, addr),
+        None => Some(cpu.bus.peek_byte(addr)),
+    }
+}
+
+fn register(cpu: &CPU, name: &str) -> Option<u16> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "a" => cpu.regs.a as u16,
+        "x" => cpu.regs.x as u16,
+        "y" => cpu.regs.y as u16,
+        "sp" => cpu.regs.sp as u16,
+        "p" => cpu.p.bits() as u16,
+        "pc" => cpu.pc,
+        _ => return None,
+    })
+}
+
+fn set_variable(cpu: &mut CPU, args: &JsonValue) -> Result<JsonValue, String> {
+    let name = args["name"].as_str().unwrap_or("");
+    let text = args["value"].as_str().unwrap_or("").trim();
+    let value = parse_value(text).ok_or_else(|| format!("Bad value {}", text))?;
+    let byte = || u8::try_from(value).map_err(|_| format!("{} does not fit in a byte", text));
+    match (args["variablesReference"].as_i64(), name) {
+        (Some(FLAGS), _) => {
+            let &(_, flag) = FLAG_NAMES.iter().find(|(n, _)| *n == name).ok_or_else(|| format!("No flag {}", name))?;
+            cpu.p.set(flag, value != 0);
+            return Ok(object! { value: cpu.p.contains(flag) as u8 });
+        }
+        (Some(REGISTERS), "PC") => {
+            cpu.pc = u16::try_from(value).map_err(|_| format!("{} is outside memory", text))?;
+            return Ok(object! { value: format!("${:04X}", cpu.pc) });
+        }
+        (Some(REGISTERS), "A") => cpu.regs.a = byte()?,
+        (Some(REGISTERS), "X") => cpu.regs.x = byte()?,
+        (Some(REGISTERS), "Y") => cpu.regs.y = byte()?,
+        (Some(REGISTERS), "SP") => cpu.regs.sp = byte()?,
+        (Some(REGISTERS), "P") => cpu.p = Flags::from_bits_truncate(byte()?),
+        _ => return Err(format!("{} cannot be set", name)),
+    }
+    Ok(object! { value: format!("${:02X}", register(cpu, name).unwrap_or(0)) })
+}
+
+// The readable bytes from the first one in range; readMemory reports the
+// rest as unreadable
+fn read_memory(cpu: &mut CPU, args: &JsonValue) -> Result<JsonValue, String> {
+    let reference = args["memoryReference"].as_str().unwrap_or("");
+    let (view, base) = parse_reference(reference).ok_or_else(|| format!("Bad memory reference {}", reference))?;
+    let start = base as i64 + args["offset"].as_i64().unwrap_or(0);
+    let count = args["count"].as_i64().unwrap_or(0).max(0);
+
+    let mut address = start;
+    let mut data = Vec::new();
+    for at in start.max(0)..(start + count).min(0x10000) {
+        match peek(cpu, view, at as u16) {
+            Some(value) => {
+                if data.is_empty() {
+                    address = at;
+                }
+                data.push(value);
+            }
+            None if data.is_empty() => continue,
+            None => break,
+        }
+    }
+    let unreadable = count - (address - start) - data.len() as i64;
+    Ok(object! {
+        address: format!("0x{:04X}", address.clamp(0, 0xFFFF)),
+        data: base64_encode(&data),
+        unreadableBytes: unreadable.max(0),
+    })
+}
+
+fn write_memory(cpu: &mut CPU, args: &JsonValue) -> Result<JsonValue, String> {
+    let reference = args["memoryReference"].as_str().unwrap_or("");
+    let (view, base) = parse_reference(reference).ok_or_else(|| format!("Bad memory reference {}", reference))?;
+    let start = base as i64 + args["offset"].as_i64().unwrap_or(0);
+    let data = base64_decode(args["data"].as_str().unwrap_or("")).ok_or("Bad base64 data")?;
+    let mut written = 0;
+    for (i, &value) in data.iter().enumerate() {
+        let Ok(at) = u16::try_from(start + i as i64) else { break };
+        match view {
+            Some(view) if !cpu.bus.poke_view(view, at, value) => break,
+            Some(_) => {}
+            None => {
+                cpu.bus.write_byte(at, value);
+            }
+        }
+        written += 1;
+    }
+    Ok(object! { bytesWritten: written })
+}
+
+fn instruction_bytes(cpu: &mut CPU, addr: u16) -> [u8; 3] {
+    [0, 1, 2].map(|i| cpu.bus.peek_byte(addr.wrapping_add(i)))
+}
+
+fn instruction_length(cpu: &mut CPU, addr: u16) -> i64 {
+    Disassembler::decode(addr, instruction_bytes(cpu, addr), cpu.cpu_type).2 as i64
+}
+
+// Start addresses of `count` instructions from the one `offset` instructions
+// away from addr; None past either end of memory. Counting back is a guess:
+// the first start up to three bytes an instruction back whose instructions
+// end exactly at addr.
+pub fn instruction_starts(cpu: &mut CPU, addr: u16, offset: i64, count: usize) -> Vec<Option<u16>> {
+    // No more instructions than bytes in memory; the rest would all be None
+    let count = count.min(0x10000);
+    let mut starts = Vec::new();
+    let mut at = addr as i64;
+    if offset < 0 {
+        let back = usize::try_from(offset.unsigned_abs()).unwrap_or(usize::MAX);
+        let mut before = Vec::new();
+        for skew in 0..3 {
+            let mut pc = (at - 3 * back.min(0x10000) as i64 + skew).max(0);
+            let mut found = Vec::new();
+            while pc < at {
+                found.push(pc as u16);
+                pc += instruction_length(cpu, pc as u16);
+            }
+            if pc == at {
+                before = found;
+                break;
+            }
+        }
+        let keep = before.len().min(back);
+        // Only the first `count` of the starts before memory are returned
+        starts.resize((back - keep).min(count), None);
+        starts.extend(before[before.len() - keep..].iter().map(|&pc| Some(pc)));
+    } else {
+        for _ in 0..offset {
+            if at > 0xFFFF {
+                break;
+            }
+            at += instruction_length(cpu, at as u16);
+        }
+    }
+    while starts.len() < count {
+        if at > 0xFFFF {
+            starts.push(None);
+        } else {
+            starts.push(Some(at as u16));
+            at += instruction_length(cpu, at as u16);
+        }
+    }
+    starts.truncate(count);
+    starts
+}
+
+fn format_trace(entry: &CpuTraceEntry, cpu: &CPU) -> String {
+    let bytes = [entry.opcode, entry.operand1, entry.operand2];
+    let (mnemonic, operand, _) = Disassembler::decode(entry.pc, bytes, cpu.cpu_type);
+    format!(
+        "${:04X}  {}  {:<4} {:<10} A={:02X} X={:02X} Y={:02X} P={} SP={:02X}",
+        entry.pc,
+        entry.format_bytes(),
+        mnemonic,
+        operand,
+        entry.a,
+        entry.x,
+        entry.y,
+        entry.format_flags(),
+        entry.sp,
+    )
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut n, mut bits) = (0u32, 0);
+    for c in text.bytes().filter(|&c| c != b'=' && !c.is_ascii_whitespace()) {
+        n = n << 6 | BASE64.iter().position(|&b| b == c)? as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
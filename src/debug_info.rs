@@ -0,0 +1,225 @@
+// Source-level debug information for programs running in the emulator.
+//
+// Two formats are read:
+//
+//   ld65 debug files (.dbg, `ld65 --dbgfile`): source lines and labels.
+//     Each line record names spans, and a span's address is the start of
+//     its segment plus its offset. Lines of C source (type 1, from cc65)
+//     win over the assembler lines generated from them; macro expansion
+//     lines (type 2) are left out so stepping stays on the invocation.
+//   VICE label files (`al C:0803 .main`, ld65 -Ln): labels only.
+//
+// Line numbers are 1-based, as in the source files.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::disassembler::SymbolFormat;
+
+// Source line of a range of addresses
+#[derive(Clone, Copy, Debug)]
+struct LineSpan {
+    // One past the last address
+    end: u32,
+    file: usize,
+    line: u32,
+}
+
+#[derive(Default)]
+pub struct DebugInfo {
+    files: Vec<PathBuf>,
+    // By start address: C lines first, then assembler lines
+    c_lines: BTreeMap<u16, LineSpan>,
+    asm_lines: BTreeMap<u16, LineSpan>,
+    // Start addresses of each line, by file
+    line_addrs: HashMap<usize, BTreeMap<u32, Vec<u16>>>,
+    labels: HashMap<String, u16>,
+    names: BTreeMap<u16, String>,
+}
+
+impl DebugInfo {
+    // Add the lines and labels of a .dbg or VICE label file
+    pub fn load(&mut self, path: &str) -> Result<()> {
+        let text = fs::read_to_string(path).map_err(|e| anyhow!("{}: {}", path, e))?;
+        if text.starts_with("version\t") {
+            let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+            self.load_dbg(&text, dir).map_err(|e| anyhow!("{}: {}", path, e))
+        } else {
+            self.load_vice(&text);
+            Ok(())
+        }
+    }
+
+    pub fn label_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_addrs.values().map(BTreeMap::len).sum()
+    }
+
+    // Address of a label
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).copied()
+    }
+
+    // Nearest label at or below addr, and addr's offset from it
+    pub fn nearest_label(&self, addr: u16) -> Option<(&str, u16)> {
+        self.names.range(..=addr).next_back().map(|(&at, name)| (name.as_str(), addr - at))
+    }
+
+    // Source file and line of the code at addr
+    pub fn line_at(&self, addr: u16) -> Option<(&Path, u32)> {
+        let covering = |lines: &BTreeMap<u16, LineSpan>| {
+            lines.range(..=addr).next_back().map(|(_, span)| *span).filter(|span| (addr as u32) < span.end)
+        };
+        let span = covering(&self.c_lines).or_else(|| covering(&self.asm_lines))?;
+        Some((&self.files[span.file], span.line))
+    }
+
+    // Start addresses of the first line at or after `line` in a source file
+    // that has code, and that line. The file matches by full path, or failing
+    // that by file name.
+    pub fn line_addresses(&self, path: &str, line: u32) -> Option<(u32, &[u16])> {
+        let file = self.file_index(path)?;
+        let (&found, addrs) = self.line_addrs.get(&file)?.range(line..).next()?;
+        Some((found, addrs))
+    }
+
+    fn file_index(&self, path: &str) -> Option<usize> {
+        let wanted = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        let name = wanted.file_name()?;
+        self.files
+            .iter()
+            .position(|f| fs::canonicalize(f).is_ok_and(|f| f == wanted))
+            .or_else(|| self.files.iter().position(|f| f.file_name() == Some(name)))
+    }
+
+    fn add_label(&mut self, name: &str, addr: u16) {
+        self.labels.insert(name.to_string(), addr);
+        // The first name given to an address is the one shown for it
+        self.names.entry(addr).or_insert_with(|| name.to_string());
+    }
+
+    fn load_vice(&mut self, text: &str) {
+        for (addr, name) in text.lines().filter_map(|line| SymbolFormat::Vice.parse_line(line)) {
+            self.add_label(name, addr);
+        }
+    }
+
+    fn load_dbg(&mut self, text: &str, dir: &Path) -> Result<()> {
+        let mut files = HashMap::new();
+        let mut segments = HashMap::new();
+        let mut spans = HashMap::new();
+        // (record line number, file id, line, line type, span ids)
+        let mut lines = Vec::new();
+
+        for (number, record) in text.lines().enumerate() {
+            let Some((kind, fields)) = record.split_once('\t') else {
+                continue;
+            };
+            let fields = parse_fields(fields);
+            let int = |key: &str| fields.get(key).and_then(|v| parse_int(v));
+            let bad = || anyhow!("line {}: bad {} record", number + 1, kind);
+            match kind {
+                "file" => {
+                    let name = fields.get("name").ok_or_else(bad)?;
+                    files.insert(int("id").ok_or_else(bad)?, dir.join(name));
+                }
+                "seg" => {
+                    segments.insert(int("id").ok_or_else(bad)?, int("start").ok_or_else(bad)?);
+                }
+                "span" => {
+                    let (id, seg, start, size) = (int("id"), int("seg"), int("start"), int("size"));
+                    let (Some(id), Some(seg), Some(start), Some(size)) = (id, seg, start, size) else {
+                        return Err(bad());
+                    };
+                    spans.insert(id, (seg, start, size));
+                }
+                "line" => {
+                    let (Some(file), Some(line)) = (int("file"), int("line")) else {
+                        return Err(bad());
+                    };
+                    let Some(span_ids) = fields.get("span") else { continue };
+                    let span_ids: Vec<u32> = span_ids.split('+').filter_map(parse_int).collect();
+                    lines.push((number + 1, file, line, int("type").unwrap_or(0), span_ids));
+                }
+                // Labels only: equates are constants, and cheap locals
+                // (with a parent) would crowd out the labels they belong to
+                "sym" if fields.get("type").map(String::as_str) == Some("lab") && !fields.contains_key("parent") => {
+                    let (Some(name), Some(addr)) = (fields.get("name"), int("val")) else {
+                        return Err(bad());
+                    };
+                    if let Ok(addr) = u16::try_from(addr) {
+                        self.add_label(name, addr);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut file_ids = HashMap::new();
+        for (number, file, line, line_type, span_ids) in lines {
+            if line_type == 2 {
+                continue;
+            }
+            let path = files.get(&file).ok_or_else(|| anyhow!("line {}: no file {}", number, file))?;
+            let index = *file_ids.entry(file).or_insert_with(|| {
+                self.files.push(path.clone());
+                self.files.len() - 1
+            });
+            for id in span_ids {
+                let &(seg, offset, size) = spans.get(&id).ok_or_else(|| anyhow!("line {}: no span {}", number, id))?;
+                let base = *segments.get(&seg).ok_or_else(|| anyhow!("span {}: no segment {}", id, seg))?;
+                // Spans lie within the 64K address space: end is at most $10000
+                let outside = || anyhow!("span {}: outside the address space", id);
+                let start = base.checked_add(offset).ok_or_else(outside)?;
+                let end = start.checked_add(size).filter(|&end| end <= 0x10000).ok_or_else(outside)?;
+                if size == 0 {
+                    continue;
+                }
+                let start = start as u16;
+                let span = LineSpan { end, file: index, line };
+                if line_type == 1 {
+                    self.c_lines.insert(start, span);
+                } else {
+                    self.asm_lines.insert(start, span);
+                }
+                let addrs = self.line_addrs.entry(index).or_default().entry(line).or_default();
+                if !addrs.contains(&start) {
+                    addrs.push(start);
+                    addrs.sort_unstable();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// key=value,key="quoted, value" fields of a .dbg record
+fn parse_fields(text: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    let mut rest = text;
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, next) = if let Some(quoted) = after.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = after.find(',').unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+        fields.insert(key.trim().to_string(), value.to_string());
+        rest = next.strip_prefix(',').unwrap_or(next);
+    }
+    fields
+}
+
+fn parse_int(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
@@ -0,0 +1,130 @@
+// The TCP side of the debugger servers (--gdb, --dap)
+//
+// Both listen on 127.0.0.1 for one client at a time and are polled from the
+// emulator's own loop, so the socket is non-blocking: `receive` collects
+// whatever the client has sent so far and the server frames it itself.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use crate::cpu::CPU;
+
+// Instructions run between socket polls in serve()
+const SERVE_BATCH: usize = 1000;
+
+pub struct DebugLink {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    // Received bytes the server has not consumed yet
+    pub input: Vec<u8>,
+}
+
+impl DebugLink {
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, client: None, input: Vec::new() })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.client.is_some()
+    }
+
+    // Take a waiting client, if there is one; `tag` prefixes error messages
+    pub fn accept(&mut self, tag: &str) -> Option<SocketAddr> {
+        match self.listener.accept() {
+            Ok((stream, addr)) => {
+                stream.set_nonblocking(true).and_then(|_| stream.set_nodelay(true)).ok()?;
+                self.client = Some(stream);
+                self.input.clear();
+                Some(addr)
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => None,
+            Err(e) => {
+                eprintln!("{:<5} {:>12} {:>8}    {}", tag, "CLIENT", "ERROR", e);
+                None
+            }
+        }
+    }
+
+    // Read everything the client has sent into `input`. Returns false once
+    // the client has gone.
+    pub fn receive(&mut self) -> bool {
+        let mut buf = [0u8; 4096];
+        loop {
+            let Some(stream) = self.client.as_mut() else { return false };
+            match stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return true,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+        self.client = None;
+        false
+    }
+
+    // Send bytes to the client, dropping it if the write fails
+    pub fn write(&mut self, mut bytes: &[u8]) {
+        let Some(stream) = self.client.as_mut() else { return };
+        // The socket is non-blocking; replies are small enough to wait out
+        while !bytes.is_empty() {
+            match stream.write(bytes) {
+                Ok(0) => break,
+                Ok(n) => bytes = &bytes[n..],
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(1)),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => {
+                    self.client = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn disconnect(&mut self) {
+        self.client = None;
+    }
+}
+
+// A debugger protocol served over a DebugLink
+pub trait DebugServer {
+    // Accept a client and answer whatever it has sent
+    fn poll(&mut self, cpu: &mut CPU);
+
+    fn is_stopped(&self) -> bool;
+
+    fn is_attached(&self) -> bool;
+
+    // Check for a breakpoint after an instruction has run. Returns true if
+    // the CPU has just stopped.
+    fn check(&mut self, cpu: &CPU) -> bool;
+}
+
+// Run the CPU under a debugger with no display: headless and Generic
+// systems. Returns when the CPU halts with no client attached.
+pub fn serve(cpu: &mut CPU, server: &mut impl DebugServer) {
+    loop {
+        server.poll(cpu);
+        if server.is_stopped() {
+            thread::sleep(Duration::from_millis(1));
+            continue;
+        }
+        for _ in 0..SERVE_BATCH {
+            cpu.tick();
+            if server.check(cpu) {
+                break;
+            }
+            if cpu.bus.interrupts.halted && !server.is_attached() {
+                return;
+            }
+        }
+    }
+}
@@ -4,6 +4,7 @@ use ringbuf::wrap::caching::Caching;
 
 pub type AudioProducer = Caching<Arc<HeapRb<f32>>, true, false>;
 
+use crate::audio_mixer::SampleTap;
 use crate::timing;
 
 const CYCLES_PER_SECOND: f64 = timing::CYCLES_PER_SECOND;
@@ -250,6 +251,7 @@ impl ImpactSynthesizer {
         }
     }
     
+    #[allow(clippy::too_many_arguments)]
     fn configure(&mut self, body_freq: f32, body_decay_ms: f32, attack_mix: f32, 
                  attack_decay_ms: f32, pitch_sweep_start: f32, pitch_sweep_ms: f32,
                  harmonic_mix: f32) {
@@ -441,6 +443,9 @@ pub struct DriveAudio {
     noise_eject35: NoiseGen,
 
     events: std::collections::VecDeque<(u64, DriveEvent)>,
+
+    // Recording copy of generated samples
+    pub tap: SampleTap,
 }
 
 impl DriveAudio {
@@ -546,6 +551,7 @@ impl DriveAudio {
             noise_eject35: NoiseGen::with_seed(0xC3C3C3C3),
 
             events: std::collections::VecDeque::new(),
+            tap: SampleTap::default(),
         }
     }
 
@@ -631,6 +637,15 @@ impl DriveAudio {
         self.events.push_back((cycle, event));
     }
 
+    // Drop queued events and jump to `cycle` after the machine state was
+    // replaced (save-state restore). Motors take the restored state silently.
+    pub fn resync(&mut self, cycle: u64, motor_on: bool, motor35_on: bool) {
+        self.events.clear();
+        self.last_cycle = cycle;
+        self.motor_on = motor_on;
+        self.motor35_on = motor35_on;
+    }
+
     pub fn update(&mut self, current_cycle: u64) {
         if self.producer.is_none() || !self.params.enabled {
             self.last_cycle = current_cycle;
@@ -677,6 +692,7 @@ impl DriveAudio {
         if let Some(producer) = &mut self.producer {
             let _ = producer.push_slice(&samples);
         }
+        self.tap.push(&samples);
 
         self.last_cycle = current_cycle;
     }
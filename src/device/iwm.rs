@@ -2,6 +2,7 @@ use std::path::Path;
 use std::time::Instant;
 use a2kit::img::DiskImage;
 
+use crate::savestate::{SaveState, StateReader, StateWriter};
 use crate::timing;
 use super::drive_audio::{DriveAudio, DriveEvent, AudioProducer};
 use super::smartport::SmartPort;
@@ -193,13 +194,19 @@ impl Iwm {
 
     /// Update drive audio synthesis (call once per frame)
     pub fn update_audio(&mut self) {
-        self.drive_audio.update(self.audio_cycle);
+        self.update_drive_audio();
         // Tick down 3.5" drive activity indicators
         for floppy in &mut self.smartport.floppies {
             floppy.tick_activity();
         }
     }
 
+    /// Synthesize drive sounds up to the current cycle, without ticking
+    /// the activity indicators that update_audio counts down once a frame.
+    pub fn update_drive_audio(&mut self) {
+        self.drive_audio.update(self.audio_cycle);
+    }
+
     /// Reset IWM chip state as if the hardware reset line was asserted.
     /// Disk contents and head positions are preserved.
     pub fn reset(&mut self) {
@@ -383,6 +390,21 @@ impl Iwm {
         })
     }
 
+    /// Get the full disk image path for a 5.25" drive.
+    pub fn disk_path(&self, drive: usize) -> Option<&str> {
+        self.drives[drive].disk_path.as_deref()
+    }
+
+    /// Get the full disk image path for a 3.5" SmartPort floppy.
+    pub fn disk_path_35(&self, drive: usize) -> Option<&str> {
+        let floppy = self.smartport.floppies.get(drive)?;
+        if floppy.has_disk() && !floppy.device.path.is_empty() {
+            Some(&floppy.device.path)
+        } else {
+            None
+        }
+    }
+
     /// Get the disk image filename (not full path) for a 3.5" SmartPort floppy.
     pub fn disk_filename_35(&self, drive: usize) -> Option<String> {
         if drive < self.smartport.floppies.len() && self.smartport.floppies[drive].has_disk() {
@@ -719,8 +741,8 @@ impl Iwm {
         self.drives[d].cycles_since_save_check += cycles;
 
         // Check if we need to load track
-        if self.drives[d].head_pos % 4 == 0 {
-            let track_num = (self.drives[d].head_pos / 4) as u16;
+        if self.drives[d].head_pos.is_multiple_of(4) {
+            let track_num = self.drives[d].head_pos / 4;
 
             if track_num < 35 && self.drives[d].loaded_track != Some(track_num as u8) {
                 if self.drives[d].dirty {
@@ -1423,20 +1445,18 @@ impl Iwm {
                 // Motor-On=0 + L6=1+L7=1 → mode register
                 // Motor-On=1 + L6=1+L7=1 → write data register (write load)
                 self.q6 = true;
-                if write {
-                    if self.q7 {
-                        if self.motor_on {
-                            // Write Load: load data into write buffer
-                            if self.is_smartport_write_routed(effective_disk35_mode) {
-                                self.smartport_write_load(val);
-                            } else {
-                                self.disk_write_load(val);
-                            }
+                if write && self.q7 {
+                    if self.motor_on {
+                        // Write Load: load data into write buffer
+                        if self.is_smartport_write_routed(effective_disk35_mode) {
+                            self.smartport_write_load(val);
                         } else {
-                            // Mode Set: write mode register
-                            self.mode = val;
-                            if self.debug { println!("IWM Mode set to: {:02X} (via Q6H)", self.mode); }
+                            self.disk_write_load(val);
                         }
+                    } else {
+                        // Mode Set: write mode register
+                        self.mode = val;
+                        if self.debug { println!("IWM Mode set to: {:02X} (via Q6H)", self.mode); }
                     }
                 }
             },
@@ -1545,9 +1565,7 @@ impl Iwm {
             //   L7=1, L6=1, Motor=1  →  (write load state, return buffer for verify)
             match (self.q7, self.q6) {
                 (false, false) => {
-                     if self.is_smartport_data_active(effective_disk35_mode) {
-                         self.read_data(floating_bus, effective_disk35_mode)
-                     } else if self.motor_on {
+                     if self.is_smartport_data_active(effective_disk35_mode) || self.motor_on {
                          self.read_data(floating_bus, effective_disk35_mode)
                      } else {
                          floating_bus  // Motor off returns floating bus
@@ -1622,3 +1640,132 @@ impl Iwm {
         result
     }
 }
+
+// The raw WOZ bytes are part of the state so that unsaved writes survive a
+// restore. fast_disk, writes_enabled and the metrics are user settings.
+impl SaveState for DriveState {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.opt_string(self.disk_path.as_deref());
+        w.u8(match self.woz_format {
+            WozFormat::Woz1 => 1,
+            WozFormat::Woz2 => 2,
+            WozFormat::Unknown => 0,
+        });
+        w.bytes(&self.woz_raw);
+        w.array(&self.woz_tmap);
+        for &count in &self.woz_bit_counts {
+            w.u32(count);
+        }
+        w.bool(self.dirty);
+        w.u16(self.head_pos);
+        w.bytes(&self.track_data);
+        w.usize(self.track_bit_count);
+        w.opt_u8(self.loaded_track);
+        w.usize(self.bit_index);
+        w.u8(self.shift_register);
+        w.u8(self.data_latch);
+        w.u8(self.bit_cycle);
+        w.bool(self.write_protect);
+        w.bool(self.nibbles_valid);
+        w.u16(self.consumed_epoch);
+        w.bool(self.data_ready);
+        w.u8(self.write_data_reg);
+        w.bool(self.write_data_pending);
+        w.u8(self.write_shift);
+        w.u8(self.write_bits_left);
+        w.bool(self.was_writing);
+        w.u64(self.cycles_since_save_check);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        let disk_path = r.opt_string()?;
+        if disk_path != self.disk_path {
+            self.disk = disk_path.as_deref().and_then(|p| a2kit::create_img_from_file(p).ok());
+            self.disk_path = disk_path;
+        }
+        self.woz_format = match r.u8()? {
+            1 => WozFormat::Woz1,
+            2 => WozFormat::Woz2,
+            _ => WozFormat::Unknown,
+        };
+        self.woz_raw = r.bytes()?;
+        r.array(&mut self.woz_tmap)?;
+        for count in &mut self.woz_bit_counts {
+            *count = r.u32()?;
+        }
+        self.dirty = r.bool()?;
+        self.head_pos = r.u16()?;
+        self.track_data = r.bytes()?;
+        self.track_bit_count = r.usize()?;
+        self.loaded_track = r.opt_u8()?;
+        self.bit_index = r.usize()?;
+        self.shift_register = r.u8()?;
+        self.data_latch = r.u8()?;
+        self.bit_cycle = r.u8()?;
+        self.write_protect = r.bool()?;
+        self.nibbles_valid = r.bool()?;
+        self.consumed_epoch = r.u16()?;
+        self.data_ready = r.bool()?;
+        self.write_data_reg = r.u8()?;
+        self.write_data_pending = r.bool()?;
+        self.write_shift = r.u8()?;
+        self.write_bits_left = r.u8()?;
+        self.was_writing = r.bool()?;
+        self.cycles_since_save_check = r.u64()?;
+        Ok(())
+    }
+}
+
+impl SaveState for Iwm {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"IWM ");
+        w.bool(self.motor_on);
+        w.bool(self.q6);
+        w.bool(self.q7);
+        w.bool(self.write_mode);
+        w.u8(self.latch);
+        w.u8(self.phases);
+        w.u8(self.mode);
+        w.bool(self.drive_select);
+        w.u64(self.cycles_since_last_read);
+        w.bool(self.motor_off_pending);
+        w.u64(self.motor_off_timer);
+        w.u64(self.motor_on_cycles);
+        w.bool(self.motor_on35);
+        w.u8(self.head35);
+        w.u8(self.smartport_idle_counter);
+        w.u16(self.smartport_response_cooldown);
+        w.u64(self.audio_cycle);
+        for drive in &self.drives {
+            drive.save_state(w);
+        }
+        self.smartport.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"IWM ")?;
+        self.motor_on = r.bool()?;
+        self.q6 = r.bool()?;
+        self.q7 = r.bool()?;
+        self.write_mode = r.bool()?;
+        self.latch = r.u8()?;
+        self.phases = r.u8()?;
+        self.mode = r.u8()?;
+        self.drive_select = r.bool()?;
+        self.cycles_since_last_read = r.u64()?;
+        self.motor_off_pending = r.bool()?;
+        self.motor_off_timer = r.u64()?;
+        self.motor_on_cycles = r.u64()?;
+        self.motor_on35 = r.bool()?;
+        self.head35 = r.u8()?;
+        self.smartport_idle_counter = r.u8()?;
+        self.smartport_response_cooldown = r.u16()?;
+        self.audio_cycle = r.u64()?;
+        for drive in &mut self.drives {
+            drive.load_state(r)?;
+        }
+        self.smartport.load_state(r)?;
+        self.drive_audio.resync(self.audio_cycle, self.motor_on, self.motor_on35);
+        Ok(())
+    }
+}
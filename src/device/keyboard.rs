@@ -1,6 +1,7 @@
 use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, VecDeque};
 
+use crate::savestate::{SaveState, StateReader, StateWriter};
 use crate::timing;
 
 pub struct Keyboard {
@@ -212,6 +213,12 @@ impl Keyboard {
     pub fn write_strobe(&self) {
         self.strobe.set(false);
     }
+
+    // Forget all physically held keys (e.g. after restoring a save state,
+    // so keys held when the state was taken don't auto-repeat forever)
+    pub fn release_all(&self) {
+        self.held_keys.borrow_mut().clear();
+    }
 }
 
 impl Default for Keyboard {
@@ -219,3 +226,54 @@ impl Default for Keyboard {
         Self::new()
     }
 }
+
+impl SaveState for Keyboard {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"KBD ");
+        w.u8(self.last_key.get());
+        w.bool(self.strobe.get());
+        w.bool(self.strobe_read.get());
+
+        // Sorted so identical machine states produce identical files
+        let mut held: Vec<(u16, u8)> = self.held_keys.borrow().iter().map(|(&k, &v)| (k, v)).collect();
+        held.sort_unstable();
+        w.u32(held.len() as u32);
+        for (phys, code) in held {
+            w.u16(phys);
+            w.u8(code);
+        }
+
+        let queue = self.key_queue.borrow();
+        w.u32(queue.len() as u32);
+        for &code in queue.iter() {
+            w.u8(code);
+        }
+
+        w.u64(self.repeat_cycle.get());
+        w.bool(self.first_repeat_done.get());
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"KBD ")?;
+        self.last_key.set(r.u8()?);
+        self.strobe.set(r.bool()?);
+        self.strobe_read.set(r.bool()?);
+
+        let mut held = HashMap::new();
+        for _ in 0..r.u32()? {
+            let phys = r.u16()?;
+            held.insert(phys, r.u8()?);
+        }
+        *self.held_keys.borrow_mut() = held;
+
+        let mut queue = VecDeque::new();
+        for _ in 0..r.u32()? {
+            queue.push_back(r.u8()?);
+        }
+        *self.key_queue.borrow_mut() = queue;
+
+        self.repeat_cycle.set(r.u64()?);
+        self.first_repeat_done.set(r.bool()?);
+        Ok(())
+    }
+}
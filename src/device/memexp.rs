@@ -1,3 +1,5 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 // Memory Expansion Card state
 pub struct MemoryExpansion {
     // Expansion RAM (1MB = 1,048,576 bytes)
@@ -93,6 +95,10 @@ impl MemoryExpansion {
         }
     }
     
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
     // Enable or disable the card
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
@@ -107,3 +113,22 @@ impl Default for MemoryExpansion {
     }
 }
 
+impl SaveState for MemoryExpansion {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"MEMX");
+        w.bool(self.enabled);
+        w.u8(self.addr_lo);
+        w.u8(self.addr_mid);
+        w.u8(self.addr_hi);
+        w.bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"MEMX")?;
+        self.enabled = r.bool()?;
+        self.addr_lo = r.u8()?;
+        self.addr_mid = r.u8()?;
+        self.addr_hi = r.u8()?;
+        r.bytes_into(&mut self.ram)
+    }
+}
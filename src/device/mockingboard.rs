@@ -23,6 +23,8 @@ use ringbuf::wrap::caching::Caching;
 pub type AudioProducer = Caching<Arc<HeapRb<f32>>, true, false>;
 
 const AMPLITUDE: f32 = 0.5;
+use crate::audio_mixer::SampleTap;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 use crate::timing;
 const CYCLES_PER_SECOND: f64 = timing::CYCLES_PER_SECOND;
 
@@ -216,7 +218,7 @@ impl Ay8910 {
             if self.noise_counter == 0 {
                 self.noise_counter = self.noise_period;
                 // 17-bit LFSR: taps at bits 0 and 2
-                let bit = ((self.rng >> 0) ^ (self.rng >> 2)) & 1;
+                let bit = (self.rng ^ (self.rng >> 2)) & 1;
                 self.rng = (self.rng >> 1) | (bit << 16);
                 self.noise_output = (self.rng & 1) != 0;
             } else {
@@ -513,6 +515,9 @@ pub struct Mockingboard {
     
     // When true, use hook-based activation instead of countdown timer
     use_hook_activation: bool,
+
+    // Recording copy of generated samples (interleaved L/R)
+    pub tap: SampleTap,
 }
 
 // Pre-computed cycles per sample in 8.24 fixed point: (1022727 / 44100) * 256 ≈ 5942
@@ -534,6 +539,7 @@ impl Default for Mockingboard {
             activated: false,
             activation_countdown: ACTIVATION_DELAY_CYCLES,
             use_hook_activation: false,
+            tap: SampleTap::default(),
         }
     }
 }
@@ -793,6 +799,7 @@ impl Mockingboard {
             if let Some(producer) = &mut self.producer {
                 let _ = producer.push_slice(&samples);
             }
+            self.tap.push(&samples);
         }
     }
     
@@ -823,3 +830,147 @@ impl Mockingboard {
         }
     }
 }
+
+impl SaveState for Via6522 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.ora);
+        w.u8(self.orb);
+        w.u8(self.ira);
+        w.u8(self.irb);
+        w.u8(self.ddra);
+        w.u8(self.ddrb);
+        w.u16(self.t1c);
+        w.u16(self.t1l);
+        w.u16(self.t2c);
+        w.u8(self.t2l);
+        w.u8(self.sr);
+        w.u8(self.acr);
+        w.u8(self.pcr);
+        w.u8(self.ifr);
+        w.u8(self.ier);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        self.ora = r.u8()?;
+        self.orb = r.u8()?;
+        self.ira = r.u8()?;
+        self.irb = r.u8()?;
+        self.ddra = r.u8()?;
+        self.ddrb = r.u8()?;
+        self.t1c = r.u16()?;
+        self.t1l = r.u16()?;
+        self.t2c = r.u16()?;
+        self.t2l = r.u8()?;
+        self.sr = r.u8()?;
+        self.acr = r.u8()?;
+        self.pcr = r.u8()?;
+        self.ifr = r.u8()?;
+        self.ier = r.u8()?;
+        Ok(())
+    }
+}
+
+impl SaveState for Ay8910 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.array(&self.registers);
+        w.u8(self.selected_register);
+        for ch in &self.channels {
+            w.u16(ch.period);
+            w.u8(ch.amplitude);
+            w.u16(ch.counter);
+            w.bool(ch.output);
+        }
+        w.u8(self.noise_period);
+        w.u8(self.noise_counter);
+        w.bool(self.noise_output);
+        w.u32(self.rng);
+        w.u16(self.envelope_period);
+        w.u16(self.envelope_counter);
+        w.u8(self.envelope_step);
+        w.u8(self.envelope_shape);
+        w.u8(self.envelope_volume);
+        w.bool(self.envelope_holding);
+        w.bool(self.envelope_attack);
+        w.u8(self.envelope_prescaler);
+        w.u8(self.prescaler);
+        w.f32(self.filter_state);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.array(&mut self.registers)?;
+        self.selected_register = r.u8()?;
+        for ch in &mut self.channels {
+            ch.period = r.u16()?;
+            ch.amplitude = r.u8()?;
+            ch.counter = r.u16()?;
+            ch.output = r.bool()?;
+        }
+        self.noise_period = r.u8()?;
+        self.noise_counter = r.u8()?;
+        self.noise_output = r.bool()?;
+        self.rng = r.u32()?;
+        self.envelope_period = r.u16()?;
+        self.envelope_counter = r.u16()?;
+        self.envelope_step = r.u8()?;
+        self.envelope_shape = r.u8()?;
+        self.envelope_volume = r.u8()?;
+        self.envelope_holding = r.bool()?;
+        self.envelope_attack = r.bool()?;
+        self.envelope_prescaler = r.u8()?;
+        self.prescaler = r.u8()?;
+        self.filter_state = r.f32()?;
+        Ok(())
+    }
+}
+
+// The audio producer and sample rate are host configuration and are left alone.
+impl SaveState for Mockingboard {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"MB  ");
+        for via in &self.via {
+            via.save_state(w);
+        }
+        for psg in &self.psg {
+            psg.save_state(w);
+        }
+        for i in 0..2 {
+            w.bool(self.psg_bc1[i]);
+            w.bool(self.psg_bdir[i]);
+        }
+        w.u64(self.last_cycle);
+        w.u32(self.sample_accum);
+        w.bool(self.enabled);
+        w.u8(match self.mb_type {
+            MockingboardType::TypeA => 0,
+            MockingboardType::TypeC => 1,
+        });
+        w.bool(self.activated);
+        w.u64(self.activation_countdown);
+        w.bool(self.use_hook_activation);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"MB  ")?;
+        for via in &mut self.via {
+            via.load_state(r)?;
+        }
+        for psg in &mut self.psg {
+            psg.load_state(r)?;
+        }
+        for i in 0..2 {
+            self.psg_bc1[i] = r.bool()?;
+            self.psg_bdir[i] = r.bool()?;
+        }
+        self.last_cycle = r.u64()?;
+        self.sample_accum = r.u32()?;
+        self.enabled = r.bool()?;
+        self.mb_type = match r.u8()? {
+            0 => MockingboardType::TypeA,
+            _ => MockingboardType::TypeC,
+        };
+        self.activated = r.bool()?;
+        self.activation_countdown = r.u64()?;
+        self.use_hook_activation = r.bool()?;
+        Ok(())
+    }
+}
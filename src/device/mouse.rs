@@ -1,5 +1,7 @@
 use std::cell::Cell;
 
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 /// Cycles between quadrature edge generation.
 /// Must be fast enough that the firmware's IRQ handler becomes the
 /// bottleneck (~100 cycles to service an interrupt). We use a small
@@ -170,3 +172,50 @@ impl Mouse {
     }
 
 }
+
+impl SaveState for Mouse {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"MOUS");
+        w.u16(self.x.get());
+        w.u16(self.y.get());
+        w.bool(self.button0.get());
+        w.bool(self.button1.get());
+        w.bool(self.x_dir.get());
+        w.bool(self.y_dir.get());
+        w.f32(self.accum_x.get());
+        w.f32(self.accum_y.get());
+        w.i16(self.pending_x.get());
+        w.i16(self.pending_y.get());
+        w.u64(self.edge_timer.get());
+        w.bool(self.xy_mask.get());
+        w.bool(self.vbl_mask.get());
+        w.bool(self.x0_edge.get());
+        w.bool(self.y0_edge.get());
+        w.bool(self.x_int.get());
+        w.bool(self.y_int.get());
+        w.bool(self.vbl_int.get());
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"MOUS")?;
+        self.x.set(r.u16()?);
+        self.y.set(r.u16()?);
+        self.button0.set(r.bool()?);
+        self.button1.set(r.bool()?);
+        self.x_dir.set(r.bool()?);
+        self.y_dir.set(r.bool()?);
+        self.accum_x.set(r.f32()?);
+        self.accum_y.set(r.f32()?);
+        self.pending_x.set(r.i16()?);
+        self.pending_y.set(r.i16()?);
+        self.edge_timer.set(r.u64()?);
+        self.xy_mask.set(r.bool()?);
+        self.vbl_mask.set(r.bool()?);
+        self.x0_edge.set(r.bool()?);
+        self.y0_edge.set(r.bool()?);
+        self.x_int.set(r.bool()?);
+        self.y_int.set(r.bool()?);
+        self.vbl_int.set(r.bool()?);
+        Ok(())
+    }
+}
@@ -12,6 +12,8 @@
 use std::cell::Cell;
 use gilrs::{Gilrs, GamepadId, Event, EventType, Axis, Button};
 
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 // Each position unit ≈ 11 CPU cycles at 1.023 MHz.
 const CYCLES_PER_POSITION: u64 = 11;
 
@@ -166,6 +168,24 @@ impl Paddle {
         }
     }
 
+    // Current paddle position (None = open circuit).
+    pub fn position(&self, paddle: u8) -> Option<u8> {
+        match paddle {
+            0 => self.paddle0.get(),
+            1 => self.paddle1.get(),
+            _ => None,
+        }
+    }
+
+    // Set a paddle position directly (movie playback, scripting).
+    pub fn set_position(&self, paddle: u8, position: Option<u8>) {
+        match paddle {
+            0 => self.paddle0.set(position),
+            1 => self.paddle1.set(position),
+            _ => {}
+        }
+    }
+
     /// Returns true if a gamepad is enabled (gilrs initialized).
     #[allow(dead_code)]
     pub fn is_enabled(&self) -> bool {
@@ -178,3 +198,25 @@ impl Default for Paddle {
         Self::new()
     }
 }
+
+// The gamepad handle is host state; only the timer and last positions are saved.
+impl SaveState for Paddle {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"PDL ");
+        w.u64(self.trigger_cycle.get());
+        w.opt_u8(self.paddle0.get());
+        w.opt_u8(self.paddle1.get());
+        w.bool(self.button0.get());
+        w.bool(self.button1.get());
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"PDL ")?;
+        self.trigger_cycle.set(r.u64()?);
+        self.paddle0.set(r.opt_u8()?);
+        self.paddle1.set(r.opt_u8()?);
+        self.button0.set(r.bool()?);
+        self.button1.set(r.bool()?);
+        Ok(())
+    }
+}
@@ -23,6 +23,7 @@ use std::collections::VecDeque;
 use std::io::{Read, Write};
 use std::net::TcpStream;
 
+use crate::savestate::{SaveState, StateReader, StateWriter};
 use crate::timing;
 use super::modem::{HayesModem, ModemAction, ModemState};
 
@@ -284,10 +285,10 @@ impl SccChannel {
                     let clock_mode = match (value >> 6) & 0x03 {
                         0 => "x1", 1 => "x16", 2 => "x32", 3 => "x64", _ => "?",
                     };
-                    println!("SCC[{}]: WR4={:#04X} parity={}{} stop={} clock={}",
+                    println!("SCC[{}]: WR4={:#04X} parity={} stop={} clock={}",
                         self.id, value,
                         if parity_en { if parity_even { "even" } else { "odd" } } else { "none" },
-                        "", stop, clock_mode);
+                        stop, clock_mode);
                 }
             }
 
@@ -588,6 +589,11 @@ impl Scc {
         self.ch_b.reset();
     }
 
+    // Register the next command port access at addr ($C038/$C039) will reach
+    pub fn register_pointer(&self, addr: u16) -> u8 {
+        if addr & 0x01 == 0 { self.ch_b.reg_ptr } else { self.ch_a.reg_ptr }
+    }
+
     // Access SCC via Apple IIc motherboard addresses ($C038–$C03B)
     // addr is the raw address; returns read value for reads
     pub fn read(&mut self, addr: u16) -> u8 {
@@ -745,3 +751,62 @@ impl Scc {
         mie && (self.ch_a.irq_pending() || self.ch_b.irq_pending())
     }
 }
+
+// Only the register-level state is saved. The TCP stream and the virtual
+// modem belong to the host and keep whatever connection they currently have.
+impl SaveState for SccChannel {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.array(&self.wr);
+        w.u8(self.reg_ptr);
+        let rx: Vec<u8> = self.rx_buffer.iter().copied().collect();
+        w.bytes(&rx);
+        w.bool(self.rx_overrun);
+        w.bool(self.tx_empty);
+        w.bool(self.dcd);
+        w.bool(self.cts);
+        w.bool(self.rx_ip);
+        w.bool(self.tx_ip);
+        w.bool(self.ext_ip);
+        w.bool(self.loopback);
+        w.u8(self.acia_command);
+        w.u8(self.acia_control);
+        w.u64(self.poll_countdown);
+        w.u64(self.poll_interval);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.array(&mut self.wr)?;
+        self.reg_ptr = r.u8()?;
+        self.rx_buffer = r.bytes()?.into();
+        self.rx_overrun = r.bool()?;
+        self.tx_empty = r.bool()?;
+        self.dcd = r.bool()?;
+        self.cts = r.bool()?;
+        self.rx_ip = r.bool()?;
+        self.tx_ip = r.bool()?;
+        self.ext_ip = r.bool()?;
+        self.loopback = r.bool()?;
+        self.acia_command = r.u8()?;
+        self.acia_control = r.u8()?;
+        self.poll_countdown = r.u64()?;
+        self.poll_interval = r.u64()?;
+        Ok(())
+    }
+}
+
+impl SaveState for Scc {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"SCC ");
+        w.bool(self.crossloop);
+        self.ch_a.save_state(w);
+        self.ch_b.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"SCC ")?;
+        self.crossloop = r.bool()?;
+        self.ch_a.load_state(r)?;
+        self.ch_b.load_state(r)?;
+        Ok(())
+    }
+}
@@ -26,6 +26,7 @@ use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
+use crate::savestate::{SaveState, StateReader, StateWriter};
 use super::drive_audio::DriveEvent;
 use super::unidisk::UniDisk35;
 
@@ -36,6 +37,7 @@ pub const BLOCK_SIZE: usize = 512;
 pub const MAX_BLOCKS: u32 = 65535;
 
 // SmartPort block device (hard drive image)
+#[derive(Default)]
 pub struct SmartPortDevice {
     // Path to the image file
     pub path: String,
@@ -55,21 +57,6 @@ pub struct SmartPortDevice {
     pub debug: bool,
 }
 
-impl Default for SmartPortDevice {
-    fn default() -> Self {
-        Self {
-            path: String::new(),
-            file: None,
-            data_offset: 0,
-            block_count: 0,
-            write_protected: false,
-            enabled: false,
-            dirty: false,
-            debug: false,
-        }
-    }
-}
-
 impl SmartPortDevice {
     pub fn new() -> Self {
         Self::default()
@@ -925,11 +912,11 @@ impl SmartPort {
         // Odd section
         if odd_bytes > 0 {
             let mut topbits = 0x80u8;
-            for i in 0..odd_bytes {
-                if payload[i] & 0x80 != 0 { topbits |= 0x40 >> i; }
+            for (i, &b) in payload[..odd_bytes].iter().enumerate() {
+                if b & 0x80 != 0 { topbits |= 0x40 >> i; }
             }
             self.resp_buffer.push(topbits);
-            for i in 0..odd_bytes { self.resp_buffer.push(payload[i] | 0x80); }
+            for &b in &payload[..odd_bytes] { self.resp_buffer.push(b | 0x80); }
         }
 
         // Groups of 7
@@ -1042,4 +1029,73 @@ impl SmartPort {
             }
         }
     }
-}
\ No newline at end of file
+}
+// Block devices write through to their image files, so only the wire
+// protocol and the 3.5" mechanism state are saved.
+impl SaveState for SmartPort {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"SPRT");
+        w.u8(match self.state {
+            ProtocolState::WaitingForSync => 0,
+            ProtocolState::ReceivingCommand => 1,
+            ProtocolState::ResponsePending => 2,
+            ProtocolState::SendingResponse => 3,
+            ProtocolState::ResponseDone => 4,
+            ProtocolState::Idle => 5,
+        });
+        w.bytes(&self.cmd_buffer);
+        w.bytes(&self.resp_buffer);
+        w.usize(self.resp_index);
+        w.u8(self.sync_count);
+        w.u8(self.header_count);
+        w.array(&self.header);
+        w.bool(self.bsy);
+        w.u64(self.response_delay_cycles);
+        w.bool(self.response_waiting_for_req_low);
+        w.bool(self.req_high);
+        w.u8(self.unit_offset);
+        w.u8(self.devices_initialized);
+        w.u8(self.current_dest);
+        w.u32(self.cmd_count);
+        for floppy in &self.floppies {
+            w.bool(floppy.motor_on);
+            w.u16(floppy.cur_qtr_track);
+            w.u16(floppy.num_qtr_tracks);
+            w.bool(floppy.just_ejected);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"SPRT")?;
+        self.state = match r.u8()? {
+            0 => ProtocolState::WaitingForSync,
+            1 => ProtocolState::ReceivingCommand,
+            2 => ProtocolState::ResponsePending,
+            3 => ProtocolState::SendingResponse,
+            4 => ProtocolState::ResponseDone,
+            _ => ProtocolState::Idle,
+        };
+        self.cmd_buffer = r.bytes()?;
+        self.resp_buffer = r.bytes()?;
+        self.resp_index = r.usize()?;
+        self.sync_count = r.u8()?;
+        self.header_count = r.u8()?;
+        r.array(&mut self.header)?;
+        self.bsy = r.bool()?;
+        self.response_delay_cycles = r.u64()?;
+        self.response_waiting_for_req_low = r.bool()?;
+        self.req_high = r.bool()?;
+        self.unit_offset = r.u8()?;
+        self.devices_initialized = r.u8()?;
+        self.current_dest = r.u8()?;
+        self.cmd_count = r.u32()?;
+        for floppy in &mut self.floppies {
+            floppy.motor_on = r.bool()?;
+            floppy.cur_qtr_track = r.u16()?;
+            floppy.num_qtr_tracks = r.u16()?;
+            floppy.just_ejected = r.bool()?;
+        }
+        self.pending_audio.clear();
+        Ok(())
+    }
+}
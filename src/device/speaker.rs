@@ -4,6 +4,8 @@ use ringbuf::wrap::caching::Caching;
 use std::collections::VecDeque;
 
 const AMPLITUDE: f32 = 0.1;
+use crate::audio_mixer::SampleTap;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 use crate::timing;
 
 const CYCLES_PER_SECOND: f64 = timing::CYCLES_PER_SECOND;
@@ -19,6 +21,7 @@ pub struct Speaker {
     filtered: f32, // Single-pole low-pass filter state
     last_toggle_cycle: u64, // Cycle of most recent toggle (for idle detection)
     toggles: VecDeque<u64>, // Cycle counts when toggles occurred
+    pub tap: SampleTap, // Recording copy of generated samples
 }
 
 impl Speaker {
@@ -31,9 +34,14 @@ impl Speaker {
             filtered: 0.0,
             last_toggle_cycle: 0,
             toggles: VecDeque::new(),
+            tap: SampleTap::default(),
         }
     }
 
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
     // Called when $C030 is accessed.
     pub fn toggle(&mut self, cycle: u64) {
         self.toggles.push_back(cycle);
@@ -102,7 +110,35 @@ impl Speaker {
 
         // Batch push all samples at once
         let _ = self.producer.push_slice(&samples);
+        self.tap.push(&samples);
 
         self.last_cycle = current_cycle;
     }
 }
+
+impl SaveState for Speaker {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"SPKR");
+        w.u64(self.last_cycle);
+        w.f32(self.state);
+        w.f32(self.filtered);
+        w.u64(self.last_toggle_cycle);
+        w.u32(self.toggles.len() as u32);
+        for &cycle in &self.toggles {
+            w.u64(cycle);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"SPKR")?;
+        self.last_cycle = r.u64()?;
+        self.state = r.f32()?;
+        self.filtered = r.f32()?;
+        self.last_toggle_cycle = r.u64()?;
+        self.toggles.clear();
+        for _ in 0..r.u32()? {
+            self.toggles.push_back(r.u64()?);
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
 use crate::timing;
 
 // ZIP CHIP II-8 (Model 8000) Accelerator emulation.
@@ -100,3 +101,24 @@ impl Default for ZipChip {
         Self::new(false)
     }
 }
+
+impl SaveState for ZipChip {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"ZIP ");
+        w.bool(self.present);
+        w.bool(self.enabled);
+        w.u32(self.boot_window_cycles);
+        w.bool(self.boot_window_active);
+        w.u32(self.slowdown_cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"ZIP ")?;
+        self.present = r.bool()?;
+        self.enabled = r.bool()?;
+        self.boot_window_cycles = r.u32()?;
+        self.boot_window_active = r.bool()?;
+        self.slowdown_cycles = r.u32()?;
+        Ok(())
+    }
+}
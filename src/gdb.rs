@@ -0,0 +1,464 @@
+// GDB remote serial protocol stub (--gdb PORT)
+//
+// Listens on 127.0.0.1 for one debugger at a time. The socket is polled
+// from the emulator's own loop, so the machine keeps its window and timing;
+// while the debugger has it stopped, no instructions run.
+//
+// Registers, as described to the debugger by target.xml:
+//
+//   0 a   1 x   2 y   3 p   4 sp   (8 bits)
+//   5 pc                           (16 bits, little-endian)
+//
+// Memory reads go through Bus::peek_byte, so they never trigger soft
+// switches; writes go through Bus::write_byte, as a CPU store would.
+// Breakpoints (Z0/Z1) stop before the instruction at their address runs.
+// Watchpoints (Z2 write, Z3 read, Z4 access) are bus watches and stop after
+// the instruction making the access. `monitor reset` and `monitor text`
+// work through qRcmd.
+
+use std::collections::HashSet;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::cpu::{Flags, CPU};
+use crate::debug_link::{DebugLink, DebugServer};
+use crate::hooks::{HookResult, MemoryWatch, WatchHit, WatchKind};
+
+pub const PACKET_SIZE: usize = 0x4000;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rust-iic.6502">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+// Stop reply signals
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+pub struct GdbServer {
+    // Received bytes not yet making a whole packet stay in link.input
+    link: DebugLink,
+    no_ack: bool,
+    stopped: bool,
+    breakpoints: HashSet<u16>,
+    // (Z type, first, last) of each watchpoint set on the bus
+    watchpoints: Vec<(u8, u16, u16)>,
+    hits: Arc<Mutex<Vec<WatchHit>>>,
+}
+
+impl GdbServer {
+    // Listen on port. With `wait`, the CPU stays stopped until a debugger
+    // attaches and continues it.
+    pub fn listen(port: u16, wait: bool) -> io::Result<Self> {
+        Ok(Self {
+            link: DebugLink::listen(port)?,
+            no_ack: false,
+            stopped: wait,
+            breakpoints: HashSet::new(),
+            watchpoints: Vec::new(),
+            hits: Arc::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.link.local_addr()
+    }
+
+    fn stop(&mut self, reply: &str) {
+        self.stopped = true;
+        self.send(reply);
+    }
+
+    fn resume(&mut self) {
+        self.hits.lock().unwrap().clear();
+        self.stopped = false;
+    }
+
+    fn detach(&mut self, cpu: &mut CPU) {
+        for (kind, first, last) in self.watchpoints.drain(..) {
+            cpu.bus.watches.remove_by_name(&watch_name(kind, first, last));
+        }
+        self.breakpoints.clear();
+        self.link.disconnect();
+        self.stopped = false;
+        println!("gdb   {:>12} {:>8}", "CLIENT", "DETACHED");
+    }
+
+    fn process_input(&mut self, cpu: &mut CPU) {
+        let mut pos = 0;
+        while pos < self.link.input.len() {
+            match self.link.input[pos] {
+                b'$' => {
+                    let Some(end) = self.link.input[pos..].iter().position(|&b| b == b'#').map(|i| pos + i) else {
+                        break;
+                    };
+                    if end + 2 >= self.link.input.len() {
+                        break;
+                    }
+                    let body = self.link.input[pos + 1..end].to_vec();
+                    let checksum = std::str::from_utf8(&self.link.input[end + 1..end + 3])
+                        .ok()
+                        .and_then(|s| u8::from_str_radix(s, 16).ok());
+                    pos = end + 3;
+                    if !self.no_ack {
+                        let sum = body.iter().fold(0u8, |s, &b| s.wrapping_add(b));
+                        if checksum != Some(sum) {
+                            self.link.write(b"-");
+                            continue;
+                        }
+                        self.link.write(b"+");
+                    }
+                    let packet = String::from_utf8_lossy(&body).into_owned();
+                    if let Some(reply) = self.handle(cpu, &packet) {
+                        self.send(&reply);
+                    }
+                    if !self.link.is_attached() {
+                        self.detach(cpu);
+                        return;
+                    }
+                }
+                // Ctrl-C from the debugger
+                0x03 => {
+                    pos += 1;
+                    if !self.stopped {
+                        self.stop(&format!("T{:02X}", SIGINT));
+                    }
+                }
+                _ => pos += 1,
+            }
+        }
+        self.link.input.drain(..pos);
+    }
+
+    // Answer one packet; None when the reply comes later (continue) or
+    // never (kill)
+    fn handle(&mut self, cpu: &mut CPU, packet: &str) -> Option<String> {
+        let (command, args) = packet.split_at(packet.char_indices().nth(1).map_or(packet.len(), |(i, _)| i));
+        let reply = match command {
+            "?" => format!("S{:02X}", SIGTRAP),
+            "g" => encode(&registers(cpu)),
+            "G" => match decode(args) {
+                Some(bytes) if bytes.len() >= 7 => {
+                    set_registers(cpu, &bytes);
+                    "OK".into()
+                }
+                _ => "E01".into(),
+            },
+            "p" => match usize::from_str_radix(args, 16).ok().and_then(|n| register(cpu, n)) {
+                Some(bytes) => encode(&bytes),
+                None => "E01".into(),
+            },
+            "P" => match args.split_once('=').and_then(|(n, v)| Some((usize::from_str_radix(n, 16).ok()?, decode(v)?))) {
+                Some((n, bytes)) if set_register(cpu, n, &bytes) => "OK".into(),
+                _ => "E01".into(),
+            },
+            "m" => match parse_range(args) {
+                Some((addr, len)) => {
+                    let bytes: Vec<u8> = (0..len).map(|i| cpu.bus.peek_byte(addr.wrapping_add(i as u16))).collect();
+                    encode(&bytes)
+                }
+                None => "E01".into(),
+            },
+            "M" => match args.split_once(':').and_then(|(range, data)| Some((parse_range(range)?, decode(data)?))) {
+                Some(((addr, len), bytes)) if bytes.len() == len => {
+                    cpu.bus.write_bytes(addr, &bytes);
+                    "OK".into()
+                }
+                _ => "E01".into(),
+            },
+            "c" => {
+                set_resume_address(cpu, args);
+                self.resume();
+                return None;
+            }
+            "s" => {
+                set_resume_address(cpu, args);
+                return Some(self.step(cpu));
+            }
+            "Z" | "z" => self.set_point(cpu, command == "Z", args),
+            "D" => {
+                self.send("OK");
+                self.link.disconnect();
+                return None;
+            }
+            "k" => {
+                self.link.disconnect();
+                return None;
+            }
+            "H" => "OK".into(),
+            "T" => "OK".into(),
+            "q" | "Q" | "v" => return self.query(cpu, packet),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn query(&mut self, cpu: &mut CPU, packet: &str) -> Option<String> {
+        if packet.starts_with("qSupported") {
+            return Some(format!(
+                "PacketSize={:X};qXfer:features:read+;QStartNoAckMode+;swbreak+;hwbreak+;vContSupported+",
+                PACKET_SIZE
+            ));
+        }
+        if let Some(annex) = packet.strip_prefix("qXfer:features:read:") {
+            return Some(match annex.split_once(':') {
+                Some(("target.xml", range)) => xfer_chunk(TARGET_XML, range),
+                _ => "E00".into(),
+            });
+        }
+        if let Some(hex) = packet.strip_prefix("qRcmd,") {
+            let command = decode(hex).map(|b| String::from_utf8_lossy(&b).trim().to_string()).unwrap_or_default();
+            return Some(self.monitor_command(cpu, &command));
+        }
+        if let Some(actions) = packet.strip_prefix("vCont;") {
+            // One thread: the first action decides
+            return match actions.as_bytes().first() {
+                Some(b's' | b'S') => Some(self.step(cpu)),
+                Some(b'c' | b'C') => {
+                    self.resume();
+                    None
+                }
+                _ => Some("E01".into()),
+            };
+        }
+        let reply = match packet {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK"
+            }
+            "qAttached" => "1",
+            "qC" => "QC1",
+            "qfThreadInfo" => "m1",
+            "qsThreadInfo" => "l",
+            "vCont?" => "vCont;c;C;s;S",
+            _ => "",
+        };
+        Some(reply.into())
+    }
+
+    fn monitor_command(&mut self, cpu: &mut CPU, command: &str) -> String {
+        let output = match command {
+            "reset" => {
+                cpu.reset();
+                format!("Reset, PC={:04X}\n", cpu.pc)
+            }
+            "text" => cpu.bus.text_screen().to_string(),
+            _ => "Commands: reset, text\n".to_string(),
+        };
+        self.send(&format!("O{}", encode(output.as_bytes())));
+        "OK".into()
+    }
+
+    fn step(&mut self, cpu: &mut CPU) -> String {
+        self.hits.lock().unwrap().clear();
+        cpu.tick();
+        let hit = self.hits.lock().unwrap().drain(..).next();
+        match hit {
+            Some(hit) => self.watch_reply(&hit),
+            None => format!("T{:02X}", SIGTRAP),
+        }
+    }
+
+    // Stop reply naming the watchpoint an access hit
+    fn watch_reply(&self, hit: &WatchHit) -> String {
+        let matches = |&&(kind, first, last): &&(u8, u16, u16)| {
+            (first..=last).contains(&hit.addr)
+                && match kind {
+                    2 => hit.kind == WatchKind::WRITE,
+                    3 => hit.kind == WatchKind::READ,
+                    _ => true,
+                }
+        };
+        let name = match self.watchpoints.iter().find(matches) {
+            Some((2, ..)) => "watch",
+            Some((3, ..)) => "rwatch",
+            _ => "awatch",
+        };
+        format!("T{:02X}{}:{:04x};", SIGTRAP, name, hit.addr)
+    }
+
+    // Z/z type,addr,kind: breakpoints (0, 1) and watchpoints (2, 3, 4)
+    fn set_point(&mut self, cpu: &mut CPU, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(addr), Some(len)) = (fields.next(), fields.next(), fields.next()) else {
+            return "E01".into();
+        };
+        let (Ok(kind), Ok(addr), Ok(len)) =
+            (kind.parse::<u8>(), u32::from_str_radix(addr, 16), u32::from_str_radix(len, 16))
+        else {
+            return "E01".into();
+        };
+        if addr > 0xFFFF {
+            return "E01".into();
+        }
+        let addr = addr as u16;
+        match kind {
+            0 | 1 => {
+                if insert {
+                    self.breakpoints.insert(addr);
+                } else {
+                    self.breakpoints.remove(&addr);
+                }
+            }
+            2..=4 => {
+                let last = (addr as u32).saturating_add(len.max(1) - 1).min(0xFFFF) as u16;
+                let name = watch_name(kind, addr, last);
+                cpu.bus.watches.remove_by_name(&name);
+                self.watchpoints.retain(|&w| w != (kind, addr, last));
+                if insert {
+                    let access = match kind {
+                        2 => WatchKind::WRITE,
+                        3 => WatchKind::READ,
+                        _ => WatchKind::READ | WatchKind::WRITE,
+                    };
+                    let hits = self.hits.clone();
+                    cpu.bus.watches.add(MemoryWatch::new(name, addr, last, access, move |hit| {
+                        hits.lock().unwrap().push(*hit);
+                        HookResult::Continue
+                    }));
+                    self.watchpoints.push((kind, addr, last));
+                }
+            }
+            _ => return String::new(),
+        }
+        "OK".into()
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |s, b| s.wrapping_add(b));
+        let packet = format!("${}#{:02x}", data, checksum);
+        self.link.write(packet.as_bytes());
+    }
+}
+
+impl DebugServer for GdbServer {
+    fn poll(&mut self, cpu: &mut CPU) {
+        if !self.link.is_attached() {
+            let Some(addr) = self.link.accept("gdb") else { return };
+            println!("gdb   {:>12} {:>8}    {}", "CLIENT", "ATTACHED", addr);
+            self.no_ack = false;
+            self.stopped = true;
+        }
+        if !self.link.receive() {
+            self.detach(cpu);
+            return;
+        }
+        self.process_input(cpu);
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    fn is_attached(&self) -> bool {
+        self.link.is_attached()
+    }
+
+    // Breakpoints, watchpoints and halts stop the CPU
+    fn check(&mut self, cpu: &CPU) -> bool {
+        if self.stopped || !self.link.is_attached() {
+            return false;
+        }
+        let hit = self.hits.lock().unwrap().drain(..).next();
+        let reply = if let Some(hit) = hit {
+            self.watch_reply(&hit)
+        } else if self.breakpoints.contains(&cpu.pc) {
+            format!("T{:02X}swbreak:;", SIGTRAP)
+        } else if cpu.bus.interrupts.halted {
+            format!("S{:02X}", SIGTRAP)
+        } else {
+            return false;
+        };
+        self.stop(&reply);
+        true
+    }
+}
+
+fn watch_name(kind: u8, first: u16, last: u16) -> String {
+    format!("gdb_watch_{}_{:04X}_{:04X}", kind, first, last)
+}
+
+fn registers(cpu: &CPU) -> Vec<u8> {
+    let [lo, hi] = cpu.pc.to_le_bytes();
+    vec![cpu.regs.a, cpu.regs.x, cpu.regs.y, cpu.p.bits(), cpu.regs.sp, lo, hi]
+}
+
+fn set_registers(cpu: &mut CPU, bytes: &[u8]) {
+    cpu.regs.a = bytes[0];
+    cpu.regs.x = bytes[1];
+    cpu.regs.y = bytes[2];
+    cpu.p = Flags::from_bits_truncate(bytes[3]);
+    cpu.regs.sp = bytes[4];
+    cpu.pc = u16::from_le_bytes([bytes[5], bytes[6]]);
+}
+
+fn register(cpu: &CPU, n: usize) -> Option<Vec<u8>> {
+    let regs = registers(cpu);
+    match n {
+        0..=4 => Some(vec![regs[n]]),
+        5 => Some(regs[5..7].to_vec()),
+        _ => None,
+    }
+}
+
+fn set_register(cpu: &mut CPU, n: usize, bytes: &[u8]) -> bool {
+    let mut regs = registers(cpu);
+    match (n, bytes) {
+        (0..=4, [v, ..]) => regs[n] = *v,
+        (5, [lo, hi, ..]) => regs[5..7].copy_from_slice(&[*lo, *hi]),
+        _ => return false,
+    }
+    set_registers(cpu, &regs);
+    true
+}
+
+fn set_resume_address(cpu: &mut CPU, args: &str) {
+    if let Ok(addr) = u32::from_str_radix(args, 16) {
+        cpu.pc = addr as u16;
+    }
+}
+
+// "addr,len" within the 64K address space
+pub fn parse_range(args: &str) -> Option<(u16, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    let addr = u32::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    (addr as usize + len <= 0x10000 && len <= PACKET_SIZE / 2).then_some((addr as u16, len))
+}
+
+// One qXfer chunk of a document: "offset,length"
+pub fn xfer_chunk(document: &str, range: &str) -> String {
+    let Some((offset, length)) = range
+        .split_once(',')
+        .and_then(|(o, l)| Some((usize::from_str_radix(o, 16).ok()?, usize::from_str_radix(l, 16).ok()?)))
+    else {
+        return "E00".into();
+    };
+    let bytes = document.as_bytes();
+    let start = offset.min(bytes.len());
+    let end = (start + length).min(bytes.len());
+    let marker = if end == bytes.len() { 'l' } else { 'm' };
+    format!("{}{}", marker, String::from_utf8_lossy(&bytes[start..end]))
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn decode(hex: &str) -> Option<Vec<u8>> {
+    // from_str_radix would take a sign, so check the digits first
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
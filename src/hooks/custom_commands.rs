@@ -9,13 +9,14 @@
 //!
 //! 2. **Memory Watch** - Observe game state by watching specific addresses.
 //!    For game modding (3D overlay, trainers, etc.), you watch health, position,
-//!    inventory, etc. and react to changes.
+//!    inventory, etc. and react to changes. Watches fire from the bus on the
+//!    write itself, see `hooks::watch`.
 
 #![allow(dead_code)]
 
 use crate::bus::Bus;
-use super::{HookFilter, HookManager, HookMode, HookResult, Hook};
-use std::sync::atomic::{AtomicU8, AtomicU16, Ordering};
+use super::{HookFilter, HookManager, HookMode, HookResult, Hook, MemoryWatch, WatchCondition, WatchKind};
+use std::sync::atomic::{AtomicU8, Ordering};
 
 // =============================================================================
 // ProDOS MLI Hook System
@@ -102,12 +103,6 @@ const EXAMPLE_PLAYER_X_ADDR: u16 = 0x1235;
 #[allow(dead_code)]
 const EXAMPLE_PLAYER_Y_ADDR: u16 = 0x1236;
 
-/// Tracks previous state for change detection
-#[allow(dead_code)]
-static WATCHED_VALUE: AtomicU8 = AtomicU8::new(0);
-#[allow(dead_code)]
-static WATCH_ADDR: AtomicU16 = AtomicU16::new(0);
-
 // =============================================================================
 // Public API
 // =============================================================================
@@ -123,8 +118,8 @@ pub fn register_prodos_hooks(hooks: &mut HookManager) {
         "prodos_mli_hook",
         |ctx| {
             // When JSR $BF00 is executed, the return address on stack points to
-            // the call_number byte. We can't read memory from here, but we can
-            // signal that an MLI call happened.
+            // the call_number byte. check_mli_calls decodes it; here we only
+            // note that an MLI call happened.
             log::trace!("MLI called at cycle {}", ctx.cycles);
             HookResult::Continue
         }
@@ -292,32 +287,21 @@ pub fn check_custom_command_mli(_bus: &mut Bus, _mli_info: &MliCallInfo) -> bool
 // =============================================================================
 
 /// Set up a memory watch at a specific address
-/// When the value changes, on_change callback is called with (old, new) values
+/// When a write changes the value, on_change is called with (old, new) values
 #[allow(dead_code)]
-pub fn watch_memory(addr: u16) {
-    WATCH_ADDR.store(addr, Ordering::Relaxed);
+pub fn watch_memory<F>(bus: &mut Bus, addr: u16, mut on_change: F)
+where
+    F: FnMut(u8, u8) + Send + 'static,
+{
+    let watch = MemoryWatch::new(format!("watch_{:04X}", addr), addr, addr, WatchKind::WRITE, move |hit| {
+        on_change(hit.old.unwrap_or(hit.value), hit.value);
+        HookResult::Continue
+    })
+    .with_condition(WatchCondition::Changed);
+    bus.watches.add(watch);
     println!("Memory watch set at ${:04X}", addr);
 }
 
-/// Check watched memory for changes - call this periodically
-/// Returns Some((old, new)) if the value changed
-#[allow(dead_code)]
-pub fn check_memory_watch(bus: &mut Bus) -> Option<(u8, u8)> {
-    let addr = WATCH_ADDR.load(Ordering::Relaxed);
-    if addr == 0 {
-        return None;  // No watch set
-    }
-    
-    let current = bus.peek_byte(addr);
-    let previous = WATCHED_VALUE.swap(current, Ordering::Relaxed);
-    
-    if current != previous {
-        Some((previous, current))
-    } else {
-        None
-    }
-}
-
 /// Example: Read player state from known game addresses
 /// Replace addresses with actual game-specific locations
 #[allow(dead_code)]
@@ -2,10 +2,16 @@
 // Hook Types:
 // - OneShot: Fires once, then automatically removes itself
 // - Persistent: Fires every time the PC hits the address
-// - Replace: Fires and skips the original instruction (for patching)
+// - Replace: Fires and, if the callback returns Skip, runs in place of the
+//   original instruction (for patching). The callback leaves PC where the
+//   patched routine would, e.g. with HookContext::rts, and sets any
+//   registers or memory it would have changed.
 
 use std::collections::HashMap;
 
+use crate::bus::Bus;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 // Hook execution mode
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[allow(dead_code)]
@@ -14,7 +20,7 @@ pub enum HookMode {
     OneShot,
     // Fire every time PC reaches this address  
     Persistent,
-    // Fire and skip the original instruction (advanced patching)
+    // Fire, and skip the original instruction if the callback returns Skip
     Replace,
 }
 
@@ -30,10 +36,11 @@ pub enum HookResult {
     Remove,
 }
 
-// Context passed to hook callbacks with CPU/system state
-#[derive(Debug)]
+// Context passed to hook callbacks: the CPU registers, which a callback may
+// change, and the bus for memory access. Register changes are written back
+// to the CPU once every hook at the address has run.
 #[allow(dead_code)]
-pub struct HookContext {
+pub struct HookContext<'a> {
     pub pc: u16,
     pub a: u8,
     pub x: u8,
@@ -41,13 +48,60 @@ pub struct HookContext {
     pub sp: u8,
     pub p: u8,
     pub cycles: u64,
+    // Cycles taken by a Replace hook's own emulation, charged in place of
+    // the skipped instruction
+    pub replace_cycles: u64,
+    pub bus: &'a mut Bus,
+}
+
+#[allow(dead_code)]
+impl HookContext<'_> {
+    // Read memory without soft-switch side effects
+    pub fn peek(&mut self, addr: u16) -> u8 {
+        self.bus.peek_byte(addr)
+    }
+
+    pub fn peek_word(&mut self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.peek(addr), self.peek(addr.wrapping_add(1))])
+    }
+
+    // Write memory as the CPU would
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        self.bus.write_byte(addr, value);
+    }
+
+    pub fn push(&mut self, value: u8) {
+        self.bus.write_byte(0x0100 | self.sp as u16, value);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    pub fn pull(&mut self) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        self.peek(0x0100 | self.sp as u16)
+    }
+
+    // Continue at `addr`, as JMP would. Return Skip from a Replace hook to
+    // jump instead of running the original instruction.
+    pub fn jmp(&mut self, addr: u16) {
+        self.pc = addr;
+        self.replace_cycles += 3;
+    }
+
+    // Return from the current subroutine, as RTS would
+    pub fn rts(&mut self) {
+        let lo = self.pull();
+        let hi = self.pull();
+        self.pc = u16::from_le_bytes([lo, hi]).wrapping_add(1);
+        self.replace_cycles += 6;
+    }
 }
 
 // Filter conditions for hooks - determines when a hook should be active
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 #[allow(dead_code)]
 pub enum HookFilter {
     // Always active (no filtering)
+    #[default]
     Always,
     // Only when ProDOS is detected (checks MLI signature at $BF00)
     ProDOS,
@@ -67,18 +121,12 @@ pub enum HookFilter {
     Not(Box<HookFilter>),
 }
 
-impl Default for HookFilter {
-    fn default() -> Self {
-        HookFilter::Always
-    }
-}
-
 // A registered hook
 pub struct Hook {
     pub address: u16,
     pub mode: HookMode,
     pub name: String,
-    pub callback: Box<dyn FnMut(&HookContext) -> HookResult + Send>,
+    pub callback: Box<dyn FnMut(&mut HookContext) -> HookResult + Send>,
     pub enabled: bool,
     pub filter: HookFilter,
 }
@@ -86,7 +134,7 @@ pub struct Hook {
 impl Hook {
     pub fn new<F>(address: u16, mode: HookMode, name: impl Into<String>, callback: F) -> Self
     where
-        F: FnMut(&HookContext) -> HookResult + Send + 'static,
+        F: FnMut(&mut HookContext) -> HookResult + Send + 'static,
     {
         Self {
             address,
@@ -170,7 +218,7 @@ impl HookManager {
     #[allow(dead_code)]
     pub fn add_oneshot<F>(&mut self, address: u16, name: impl Into<String>, callback: F)
     where
-        F: FnMut(&HookContext) -> HookResult + Send + 'static,
+        F: FnMut(&mut HookContext) -> HookResult + Send + 'static,
     {
         self.add_hook(Hook::new(address, HookMode::OneShot, name, callback));
     }
@@ -179,11 +227,20 @@ impl HookManager {
     #[allow(dead_code)]
     pub fn add_persistent<F>(&mut self, address: u16, name: impl Into<String>, callback: F)
     where
-        F: FnMut(&HookContext) -> HookResult + Send + 'static,
+        F: FnMut(&mut HookContext) -> HookResult + Send + 'static,
     {
         self.add_hook(Hook::new(address, HookMode::Persistent, name, callback));
     }
 
+    // Convenience method to add a hook that can replace the instruction at `address`
+    #[allow(dead_code)]
+    pub fn add_replace<F>(&mut self, address: u16, name: impl Into<String>, callback: F)
+    where
+        F: FnMut(&mut HookContext) -> HookResult + Send + 'static,
+    {
+        self.add_hook(Hook::new(address, HookMode::Replace, name, callback));
+    }
+
     // Remove all hooks at an address
     #[allow(dead_code)]
     pub fn remove_hooks_at(&mut self, address: u16) {
@@ -315,7 +372,7 @@ impl HookManager {
                 // Check $67-$68 (MEMSIZ) is reasonable for BASIC
                 let memsiz_lo = peek(0x67);
                 let memsiz_hi = peek(0x68);
-                memsiz_hi >= 0x08 && memsiz_hi <= 0xBF && memsiz_lo == 0x00
+                (0x08..=0xBF).contains(&memsiz_hi) && memsiz_lo == 0x00
             }
             
             HookFilter::MemorySignature(addr, bytes) => {
@@ -341,14 +398,13 @@ impl HookManager {
         }
     }
 
-    // Execute all hooks at the given address, with memory access for filter checking
-    // Returns true if instruction should be skipped (Replace mode)
-    // `peek` is a function that reads a byte from memory without side effects
-    pub fn execute_hooks_filtered<F>(&mut self, ctx: &HookContext, peek: &mut F) -> bool 
-    where
-        F: FnMut(u16) -> u8,
-    {
-        let Some(hooks) = self.hooks.get_mut(&ctx.pc) else {
+    // Execute all hooks at ctx.pc. Callbacks may change the registers and
+    // memory through `ctx`. Returns true if a Replace hook asked to skip the
+    // instruction at this address.
+    pub fn execute_hooks_filtered(&mut self, ctx: &mut HookContext) -> bool {
+        // Callbacks may move ctx.pc, so keep the address the hooks fired at
+        let address = ctx.pc;
+        let Some(hooks) = self.hooks.get_mut(&address) else {
             return false;
         };
 
@@ -361,13 +417,13 @@ impl HookManager {
             }
             
             // Check filter before firing
-            if !Self::check_filter(&hook.filter, peek) {
-                log::trace!("Hook '{}' at ${:04X} skipped (filter not matched)", hook.name, ctx.pc);
+            if !Self::check_filter(&hook.filter, &mut |addr| ctx.bus.peek_byte(addr)) {
+                log::trace!("Hook '{}' at ${:04X} skipped (filter not matched)", hook.name, address);
                 continue;
             }
 
             self.fire_count += 1;
-            log::trace!("Hook fired: '{}' at ${:04X}", hook.name, ctx.pc);
+            log::trace!("Hook fired: '{}' at ${:04X}", hook.name, address);
             
             // Check for special system hooks that need to set pending actions
             // (Note: address-based mockingboard_activate is deprecated, use timed hooks)
@@ -405,12 +461,12 @@ impl HookManager {
         hooks_to_remove.dedup();
         for idx in hooks_to_remove.into_iter().rev() {
             let removed = hooks.remove(idx);
-            log::debug!("Hook removed: '{}' at ${:04X}", removed.name, ctx.pc);
+            log::debug!("Hook removed: '{}' at ${:04X}", removed.name, address);
         }
 
         // Clean up empty hook lists
         if hooks.is_empty() {
-            self.hooks.remove(&ctx.pc);
+            self.hooks.remove(&address);
         }
 
         skip_instruction
@@ -436,6 +492,43 @@ impl HookManager {
     }
 }
 
+// Timed hooks are closures and can't be serialized in general. The only ones
+// that affect machine state are the Mockingboard activation timers, so those
+// are saved by name and re-registered on load; other timed hooks are left as-is.
+const MOCKINGBOARD_HOOK_NAMES: [&str; 2] = ["mockingboard_activate", "mockingboard2_activate"];
+
+impl SaveState for HookManager {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"HOOK");
+        w.bool(self.pending_mockingboard_activate);
+        w.bool(self.pending_mockingboard2_activate);
+
+        let pending: Vec<&TimedHook> = self.timed_hooks.iter()
+            .filter(|h| MOCKINGBOARD_HOOK_NAMES.contains(&h.name.as_str()))
+            .collect();
+        w.u8(pending.len() as u8);
+        for hook in pending {
+            w.u8(if hook.name == MOCKINGBOARD_HOOK_NAMES[0] { 0 } else { 1 });
+            w.u64(hook.trigger_cycle);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"HOOK")?;
+        self.pending_mockingboard_activate = r.bool()?;
+        self.pending_mockingboard2_activate = r.bool()?;
+
+        self.timed_hooks.retain(|h| !MOCKINGBOARD_HOOK_NAMES.contains(&h.name.as_str()));
+        let count = r.u8()?;
+        for _ in 0..count {
+            let slot = r.u8()?;
+            let trigger_cycle = r.u64()?;
+            self.register_mockingboard_hook(slot, trigger_cycle);
+        }
+        Ok(())
+    }
+}
+
 // Well-known Apple IIc ROM addresses for hooking
 #[allow(dead_code)]
 pub mod iic_addresses {
@@ -3,12 +3,14 @@
 //! This module provides:
 //! - PC-based hooks (fire when execution reaches a specific address)
 //! - Timer-based hooks (fire after N CPU cycles)
+//! - Memory watches (fire on reads, writes or execution in an address range)
 //! - Hook filters (ProDOS, DOS 3.3, memory signatures)
 //! - ProDOS MLI call interception and logging
 //! - Custom command support (RUSTIIC, DEBUG, etc.)
 
 mod manager;
 mod custom_commands;
+mod watch;
 
 // Re-export main types from manager
 #[allow(unused_imports)]
@@ -22,6 +24,16 @@ pub use manager::{
     TimedHook,
 };
 
+// Re-export memory watch types
+#[allow(unused_imports)]
+pub use watch::{
+    MemoryWatch,
+    WatchCondition,
+    WatchHit,
+    WatchKind,
+    WatchList,
+};
+
 // Re-export custom command functions
 #[allow(unused_imports)]
 pub use custom_commands::{
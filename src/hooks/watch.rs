@@ -0,0 +1,178 @@
+// Memory access hooks (watchpoints)
+//
+// A watch covers an address range and fires on reads, writes and/or
+// instruction fetches in it, optionally only in one MMU bank and only when
+// the value matches a condition. Reads and writes fire from the bus, so they
+// see every data access the CPU makes, the dummy reads and writes of indexed
+// and read-modify-write instructions included: a soft switch sees those too.
+// Instruction bytes, immediate operands included, are not reads; execute
+// watches fire from CPU::step on the opcode fetch alone. Debugger peeks
+// never fire.
+
+use bitflags::bitflags;
+
+use super::HookResult;
+use crate::mmu::Bank;
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct WatchKind: u8 {
+        const READ    = 0b001;
+        const WRITE   = 0b010;
+        const EXECUTE = 0b100;
+    }
+}
+
+// Condition on the value read, written or fetched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum WatchCondition {
+    Any,
+    Equal(u8),
+    NotEqual(u8),
+    // value & mask == expected
+    Masked { mask: u8, value: u8 },
+    // Inclusive range
+    Between(u8, u8),
+    // Writes only: the byte written differs from the one it replaces
+    Changed,
+}
+
+impl WatchCondition {
+    fn matches(self, value: u8, old: Option<u8>) -> bool {
+        match self {
+            WatchCondition::Any => true,
+            WatchCondition::Equal(v) => value == v,
+            WatchCondition::NotEqual(v) => value != v,
+            WatchCondition::Masked { mask, value: v } => value & mask == v,
+            WatchCondition::Between(lo, hi) => (lo..=hi).contains(&value),
+            WatchCondition::Changed => old.is_some_and(|old| old != value),
+        }
+    }
+}
+
+// One access that hit a watch
+#[derive(Clone, Copy, Debug)]
+pub struct WatchHit {
+    // A single kind: the access that fired
+    pub kind: WatchKind,
+    pub addr: u16,
+    pub value: u8,
+    // Byte replaced by a write, when the write reached memory
+    pub old: Option<u8>,
+    pub bank: Bank,
+    // Instruction making the access
+    pub pc: u16,
+}
+
+pub struct MemoryWatch {
+    pub name: String,
+    pub start: u16,
+    // Inclusive
+    pub end: u16,
+    pub kind: WatchKind,
+    // Only accesses resolving to this bank, or any bank
+    pub bank: Option<Bank>,
+    pub condition: WatchCondition,
+    pub enabled: bool,
+    pub callback: Box<dyn FnMut(&WatchHit) -> HookResult + Send>,
+}
+
+#[allow(dead_code)]
+impl MemoryWatch {
+    pub fn new<F>(name: impl Into<String>, start: u16, end: u16, kind: WatchKind, callback: F) -> Self
+    where
+        F: FnMut(&WatchHit) -> HookResult + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            start,
+            end,
+            kind,
+            bank: None,
+            condition: WatchCondition::Any,
+            enabled: true,
+            callback: Box::new(callback),
+        }
+    }
+
+    pub fn with_bank(mut self, bank: Bank) -> Self {
+        self.bank = Some(bank);
+        self
+    }
+
+    pub fn with_condition(mut self, condition: WatchCondition) -> Self {
+        self.condition = condition;
+        self
+    }
+
+    #[inline]
+    fn covers(&self, kind: WatchKind, addr: u16) -> bool {
+        self.enabled && self.kind.intersects(kind) && addr >= self.start && addr <= self.end
+    }
+}
+
+// All memory watches on a bus
+#[derive(Default)]
+pub struct WatchList {
+    watches: Vec<MemoryWatch>,
+    // Count of watch callbacks that have fired (for debugging)
+    pub fire_count: u64,
+}
+
+#[allow(dead_code)]
+impl WatchList {
+    pub fn add(&mut self, watch: MemoryWatch) {
+        log::debug!(
+            "Watch registered: '{}' at ${:04X}-${:04X} ({:?})",
+            watch.name, watch.start, watch.end, watch.kind
+        );
+        self.watches.push(watch);
+    }
+
+    pub fn remove_by_name(&mut self, name: &str) {
+        self.watches.retain(|w| w.name != name);
+    }
+
+    pub fn clear(&mut self) {
+        self.watches.clear();
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &MemoryWatch> {
+        self.watches.iter()
+    }
+
+    // Fast path for the bus: nothing to check at all
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    // Whether any watch covers this access, before the bank is resolved
+    #[inline]
+    pub fn covers(&self, kind: WatchKind, addr: u16) -> bool {
+        self.watches.iter().any(|w| w.covers(kind, addr))
+    }
+
+    // Run the callbacks of every watch the access matches
+    pub fn fire(&mut self, hit: &WatchHit) {
+        let mut to_remove: Vec<usize> = Vec::new();
+        for (idx, watch) in self.watches.iter_mut().enumerate() {
+            if !watch.covers(hit.kind, hit.addr)
+                || watch.bank.is_some_and(|bank| bank != hit.bank)
+                || !watch.condition.matches(hit.value, hit.old)
+            {
+                continue;
+            }
+            self.fire_count += 1;
+            log::trace!("Watch fired: '{}' {:?} ${:04X}={:02X}", watch.name, hit.kind, hit.addr, hit.value);
+            if (watch.callback)(hit) == HookResult::Remove {
+                to_remove.push(idx);
+            }
+        }
+        for idx in to_remove.into_iter().rev() {
+            let removed = self.watches.remove(idx);
+            log::debug!("Watch removed: '{}'", removed.name);
+        }
+    }
+}
@@ -1,16 +1,29 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum InterruptType {
     NMI,
     IRQ,
-    BRK,
     RST,
 }
 
+impl InterruptType {
+    // Where the CPU fetches the handler address from. BRK shares the IRQ
+    // vector.
+    pub fn vector(self) -> u16 {
+        match self {
+            InterruptType::NMI => 0xFFFA,
+            InterruptType::RST => 0xFFFC,
+            InterruptType::IRQ => 0xFFFE,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct InterruptController {
     pub nmi: bool,     // Non-Maskable Interrupt
     pub irq: bool,     // Maskable Interrupt
-    pub brk: bool,     // Software Interrupt (BRK)
     pub reset: bool,   // Reset Interrupt
     pub waiting: bool, // WAI: CPU waiting for interrupt
     pub halted: bool,  // STP: CPU halted indefinitely
@@ -35,10 +48,6 @@ impl InterruptController {
         self.waiting = false;
     }
 
-    pub fn request_brk(&mut self) {
-        self.brk = true;
-    }
-
     pub fn enter_wait(&mut self) {
         self.waiting = true;
     }
@@ -58,51 +67,57 @@ impl InterruptController {
     pub fn clear_all(&mut self) {
         self.nmi = false;
         self.irq = false;
-        self.brk = false;
         self.reset = false;
     }
 
-    pub fn handle_interrupt_with_vectors(
-        &mut self,
-        nmi_vector: u16,
-        reset_vector: u16,
-        irq_vector: u16,
-    ) -> Option<(InterruptType, u16)> {
+    // Highest-priority pending interrupt. A reset is consumed here; IRQ and
+    // NMI stay pending until the CPU has taken them.
+    pub fn take_pending(&mut self) -> Option<InterruptType> {
         if self.halted {
             return None;
         }
 
-        let (interrupt_type, resolved_vector) = if self.nmi {
-            (InterruptType::NMI, nmi_vector)
+        if self.nmi {
+            Some(InterruptType::NMI)
         } else if self.reset {
             self.reset = false;
-            (InterruptType::RST, reset_vector)
-        } else if self.brk {
-            self.brk = false;
-            (InterruptType::BRK, irq_vector)
+            Some(InterruptType::RST)
         } else if self.irq {
-            (InterruptType::IRQ, irq_vector)
+            Some(InterruptType::IRQ)
         } else {
-            return None;
-        };
-
-        // println!(
-        //     "Handling {:?} Interrupt: Jumping to {:#06X}",
-        //     interrupt_type, resolved_vector
-        // );
-
-        Some((interrupt_type, resolved_vector))
+            None
+        }
     }
 
     pub fn status_string(&self) -> String {
         format!(
-            "I:{}{}{}{}{}{}",
+            "I:{}{}{}{}{}",
             if self.nmi { "N" } else { "." },
             if self.irq { "I" } else { "." },
-            if self.brk { "B" } else { "." },
             if self.reset { "R" } else { "." },
             if self.waiting { "W" } else { "." },
             if self.halted { "H" } else { "." },
         )
     }
 }
+
+impl SaveState for InterruptController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"INTC");
+        w.bool(self.nmi);
+        w.bool(self.irq);
+        w.bool(self.reset);
+        w.bool(self.waiting);
+        w.bool(self.halted);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"INTC")?;
+        self.nmi = r.bool()?;
+        self.irq = r.bool()?;
+        self.reset = r.bool()?;
+        self.waiting = r.bool()?;
+        self.halted = r.bool()?;
+        Ok(())
+    }
+}
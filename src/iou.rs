@@ -1,6 +1,7 @@
 use std::cell::Cell;
 
-use crate::{device::{iwm::Iwm, paddle::Paddle, keyboard::Keyboard, memexp::MemoryExpansion, mockingboard::Mockingboard, mouse::Mouse, scc::Scc, speaker::{AudioProducer, Speaker}, zip::ZipChip}, mmu::{LcRamMode, MemStateMask, LCRAMMODEMASK}, timing, video::VideoModeMask};
+use crate::rom::RomRevision;
+use crate::{device::{iwm::Iwm, paddle::Paddle, keyboard::Keyboard, memexp::MemoryExpansion, mockingboard::Mockingboard, mouse::Mouse, scc::Scc, speaker::{AudioProducer, Speaker}, zip::ZipChip}, mmu::{LcRamMode, MemStateMask, LCRAMMODEMASK}, savestate::{SaveState, StateReader, StateWriter}, timing, video::VideoModeMask};
 
 /// Even $C08x access: apply mode (never includes WRITE).
 /// Also resets the consecutive-read tracking since any even access
@@ -39,6 +40,7 @@ macro_rules! set_lcram_mode_rr {
   }};
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct IOU {
   pub mem_state: Cell<u8>,
   pub last_read_addr: Cell<u16>,
@@ -59,6 +61,7 @@ pub struct IOU {
   pub mockingboard: Mockingboard, // Mockingboard sound card (Slot 5)
   pub mockingboard2: Mockingboard, // Second Mockingboard (Slot 4, conflicts with memexp)
   pub zip: ZipChip,  // ZIP Chip accelerator (optional)
+  pub rom_revision: RomRevision, // Installed firmware; gates ROMBANK and the memexp connector
   pub cycles: u64,
   pub scan_cycle: u64,  // Position within NTSC frame (resets every CYCLES_PER_FRAME cycles)
   pub floating_bus: u8,  // Last byte video hardware would read from RAM at current scan position
@@ -89,6 +92,7 @@ impl IOU {
           mockingboard: Mockingboard::new(),  // Disabled by default, enabled via --mockingboard
           mockingboard2: Mockingboard::new(), // Disabled by default, enabled via --mockingboard2
           zip: ZipChip::new(false),  // Disabled by default, enabled via --zip
+          rom_revision: RomRevision::Rom3,
           cycles: 0,
           scan_cycle: 0,
           floating_bus: 0,
@@ -118,6 +122,24 @@ impl IOU {
         }
     }
 
+    /// Match the hardware to the installed ROM. Machines before ROM 3 have
+    /// no memory expansion connector, and ROM 255 has no second ROM bank.
+    pub fn set_rom_revision(&mut self, revision: RomRevision) {
+        self.rom_revision = revision;
+        if !revision.has_memexp() {
+            self.memexp.set_enabled(false);
+        }
+    }
+
+    // $C028 ROMBANK: swap ROM banks (no second bank on ROM 255)
+    fn toggle_rom_bank(&self) -> u8 {
+        if self.rom_revision.banks() > 1 {
+            toggle_bits_cell!(self.mem_state, MemStateMask::ALTROM)
+        } else {
+            self.mem_state.get()
+        }
+    }
+
     pub fn reset(&mut self) {
         self.mem_state.set(MemStateMask::INIT);
         self.last_read_addr.set(0x0000);
@@ -197,7 +219,7 @@ impl IOU {
 
         0xC020 => ((self.cycles & 1) as u8) << 7, // TAPEOUT / Cassette input — bit 7 toggles, used as entropy source
 
-        0xC028 => { toggle_bits_cell!(self.mem_state, MemStateMask::ALTROM); 0x00 }, // ROMBANK
+        0xC028 => { self.toggle_rom_bank(); 0x00 }, // ROMBANK
 
         0xC030 => { self.speaker.toggle(self.cycles); 0x00 }, // C030 48200 SPKR         OECG  R   Toggle Speaker
 
@@ -205,7 +227,7 @@ impl IOU {
         // Bit 6: 0=5.25" drives, 1=3.5"/SmartPort mode
         // Bit 7: Read/Write head select (for double-sided 3.5")
         0xC031 => {
-            ((self.disk35_mode as u8) << 6) | ((self.iwm.get_head35() as u8) << 7)
+            ((self.disk35_mode as u8) << 6) | (self.iwm.get_head35() << 7)
         },
 
         // Zilog 8530 SCC — $C038: ChB Cmd, $C039: ChA Cmd, $C03A: ChB Data, $C03B: ChA Data
@@ -384,13 +406,11 @@ impl IOU {
           },
           0xC00C => {
               if self.debug { println!("IOU: 80COL OFF"); }
-            let result = clear_bits_cell!(self.video_mode, VideoModeMask::COL80);
-            result
+            clear_bits_cell!(self.video_mode, VideoModeMask::COL80)
           },
           0xC00D => {
               if self.debug { println!("IOU: 80COL ON"); }
-            let result = set_bits_cell!(self.video_mode, VideoModeMask::COL80);
-            result
+            set_bits_cell!(self.video_mode, VideoModeMask::COL80)
           },
           0xC00E => {
               if self.debug { println!("IOU: ALTCHAR OFF"); }
@@ -463,53 +483,45 @@ impl IOU {
           0xC004 => clear_bits_cell!(self.mem_state, MemStateMask::RAMWRT),
           0xC005 => set_bits_cell!(self.mem_state, MemStateMask::RAMWRT),
           
-          0xC028 => toggle_bits_cell!(self.mem_state, MemStateMask::ALTROM),
+          0xC028 => self.toggle_rom_bank(),
 
 
           0xC006 | 0xC007 | 0xC00A | 0xC00B => 0x00,
 
           0xC050 => {
               if self.debug { println!("IOU: TEXT OFF"); }
-              let result = clear_bits_cell!(self.video_mode, VideoModeMask::TEXT);
-              result
+              clear_bits_cell!(self.video_mode, VideoModeMask::TEXT)
           }, 
           0xC051 => {
               if self.debug { println!("IOU: TEXT ON"); }
-              let result = set_bits_cell!(self.video_mode, VideoModeMask::TEXT);
-              result
+              set_bits_cell!(self.video_mode, VideoModeMask::TEXT)
           },   
           0xC052 => {
               if self.debug { println!("IOU: MIXED OFF"); }
-              let result = clear_bits_cell!(self.video_mode, VideoModeMask::MIXED);
-              result
+              clear_bits_cell!(self.video_mode, VideoModeMask::MIXED)
           }, 
           0xC053 => {
               if self.debug { println!("IOU: MIXED ON"); }
-              let result = set_bits_cell!(self.video_mode, VideoModeMask::MIXED);
-              result
+              set_bits_cell!(self.video_mode, VideoModeMask::MIXED)
           },   
           0xC054 => {
               if self.debug { println!("IOU: PAGE2 OFF"); }
-              let result = clear_bits_cell!(self.video_mode, VideoModeMask::PAGE2);
-              result
+              clear_bits_cell!(self.video_mode, VideoModeMask::PAGE2)
           }, 
           0xC055 => {
               if self.debug { println!("IOU: PAGE2 ON"); }
-              let result = set_bits_cell!(self.video_mode, VideoModeMask::PAGE2);
-              result
+              set_bits_cell!(self.video_mode, VideoModeMask::PAGE2)
           },   
 
           0xC056 => {
             if self.debug { println!("IOU: LORES ON / HIRES OFF"); }
             clear_bits_cell!(self.video_mode, VideoModeMask::HIRES);
-            let result = set_bits_cell!(self.video_mode, VideoModeMask::LORES);
-            result
+            set_bits_cell!(self.video_mode, VideoModeMask::LORES)
           },
           0xC057 => {
             if self.debug { println!("IOU: HIRES ON / LORES OFF"); }
             clear_bits_cell!(self.video_mode, VideoModeMask::LORES);
-            let result = set_bits_cell!(self.video_mode, VideoModeMask::HIRES);
-            result
+            set_bits_cell!(self.video_mode, VideoModeMask::HIRES)
           },
 
           0xC062 => 0x00, // Ignore write to Button 1
@@ -616,3 +628,61 @@ impl IOU {
         mouse_irq || scc_irq || mockingboard_irq
     }
 }
+
+impl SaveState for IOU {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"IOU ");
+        w.u8(self.mem_state.get());
+        w.u16(self.last_read_addr.get());
+        w.u16(self.current_pc.get());
+        w.bool(self.is_80store.get());
+        w.bool(self.ioudis.get());
+        w.u8(self.video_mode.get());
+        w.u64(self.cycles);
+        w.u64(self.scan_cycle);
+        w.u8(self.floating_bus);
+        w.bool(self.col80_switch);
+        w.bool(self.disk35_mode);
+        w.string(self.rom_revision.name());
+
+        self.keyboard.save_state(w);
+        self.paddle.save_state(w);
+        self.scc.save_state(w);
+        self.iwm.save_state(w);
+        self.mouse.save_state(w);
+        self.speaker.save_state(w);
+        self.memexp.save_state(w);
+        self.mockingboard.save_state(w);
+        self.mockingboard2.save_state(w);
+        self.zip.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"IOU ")?;
+        self.mem_state.set(r.u8()?);
+        self.last_read_addr.set(r.u16()?);
+        self.current_pc.set(r.u16()?);
+        self.is_80store.set(r.bool()?);
+        self.ioudis.set(r.bool()?);
+        self.video_mode.set(r.u8()?);
+        self.cycles = r.u64()?;
+        self.scan_cycle = r.u64()?;
+        self.floating_bus = r.u8()?;
+        self.col80_switch = r.bool()?;
+        self.disk35_mode = r.bool()?;
+        let rom = r.string()?;
+        self.rom_revision = RomRevision::from_name(&rom)
+            .ok_or_else(|| anyhow::anyhow!("save state: unknown ROM revision '{}'", rom))?;
+
+        self.keyboard.load_state(r)?;
+        self.paddle.load_state(r)?;
+        self.scc.load_state(r)?;
+        self.iwm.load_state(r)?;
+        self.mouse.load_state(r)?;
+        self.speaker.load_state(r)?;
+        self.memexp.load_state(r)?;
+        self.mockingboard.load_state(r)?;
+        self.mockingboard2.load_state(r)?;
+        self.zip.load_state(r)
+    }
+}
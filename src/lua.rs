@@ -0,0 +1,465 @@
+// Lua automation scripts (--lua).
+//
+// The script runs once at startup to register callbacks on the global `emu`
+// table; after that the callbacks are called from CPU::tick with the machine
+// `m` as their first argument.
+//
+//   emu.on_pc(addr, fn(m))          call fn each time PC reaches addr
+//   emu.after(n, fn(m))             call fn once, n cycles from now
+//   emu.every(n, fn(m))             call fn every n cycles
+//   emu.watch(addr, fn(m, old, new)) call fn when a write changes the byte at addr
+//   emu.on_read(first, last, fn(m, hit) [, cond])   call fn on reads in first..last
+//   emu.on_write(first, last, fn(m, hit) [, cond])  ... on writes
+//   emu.on_exec(first, last, fn(m, hit) [, cond])   ... on opcode fetches
+//   emu.remove(id)                  remove a callback (each of the above returns its id)
+//   emu.type(text)                  type ASCII text through the keyboard, one key per frame
+//
+// `cond` is a table that narrows an access watch: bank = "main", "aux",
+// "lcmain", "lcaux", "rom" or "io", and one of equal = v, not_equal = v,
+// mask = m with value = v, or min = lo with max = hi. `hit` has addr, value,
+// old (writes), bank and pc. Memory watches fire on the bus, during the
+// instruction; their callbacks run once it has finished.
+//
+// A callback that returns false is removed. One that raises an error is
+// reported and removed. The machine:
+//
+//   m.a m.x m.y m.sp m.p m.pc       registers, read/write
+//   m.cycles                        CPU cycles since power on, read only
+//   m:peek(addr) m:peek_word(addr)  read memory as the CPU sees it, without soft-switch side effects
+//   m:poke(addr, v)                 write memory as the CPU does, soft switches included
+//   m:key(code)                     press a key now (Apple II code)
+//   m:insert(drive, path)           drives 1-2: 5.25", 3-4: 3.5"
+//   m:eject(drive)
+//   m:screen()                      the text screen as a string, one line per row
+//   m:reset()                       Control-Reset
+//   m:exit([code])                  flush disks and exit the emulator
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use mlua::{Function, Lua, RegistryKey, Table, UserData, UserDataFields, UserDataMethods, Value};
+
+use crate::cpu::{Flags, CPU};
+use crate::hooks::{HookResult, MemoryWatch, WatchCondition, WatchHit, WatchKind};
+use crate::mmu::Bank;
+use crate::script::SCRIPT_KEY_ID;
+use crate::timing;
+
+type Callback = Rc<RegistryKey>;
+
+struct Timed {
+    id: u32,
+    cycle: u64,
+    // Re-armed this many cycles later after firing (emu.every)
+    period: Option<u64>,
+    callback: Callback,
+}
+
+struct Watch {
+    callback: Callback,
+    // emu.watch: called with (old, new) instead of a hit table
+    changes: bool,
+}
+
+// Hits from the bus watches, waiting for the instruction to finish
+type Hits = Arc<Mutex<Vec<(u32, WatchHit)>>>;
+
+enum Args {
+    None,
+    Change(u8, u8),
+    Hit(WatchHit),
+}
+
+// Callbacks registered by the script. Shared with the `emu` functions, which
+// may be called from inside a callback, so no borrow is held across calls.
+#[derive(Default)]
+struct Registry {
+    next_id: u32,
+    // Cycle count at the last tick, base for emu.after and emu.every
+    now: u64,
+    pc_hooks: HashMap<u16, Vec<(u32, Callback)>>,
+    // Sorted by trigger cycle
+    timed: Vec<Timed>,
+    watches: HashMap<u32, Watch>,
+    // Bus watches to add and remove at the next tick, when the bus is at hand
+    new_watches: Vec<MemoryWatch>,
+    dropped_watches: Vec<String>,
+    // Keys from emu.type still to press, and the cycle the next one is due
+    typing: VecDeque<u8>,
+    next_key_cycle: u64,
+}
+
+impl Registry {
+    fn next_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn add_timed(&mut self, timed: Timed) {
+        let at = self.timed.partition_point(|t| t.cycle <= timed.cycle);
+        self.timed.insert(at, timed);
+    }
+
+    fn remove(&mut self, id: u32) {
+        for hooks in self.pc_hooks.values_mut() {
+            hooks.retain(|(i, _)| *i != id);
+        }
+        self.pc_hooks.retain(|_, v| !v.is_empty());
+        self.timed.retain(|t| t.id != id);
+        if self.watches.remove(&id).is_some() {
+            self.dropped_watches.push(watch_name(id));
+        }
+    }
+
+    fn is_idle_at(&self, pc: u16, cycles: u64) -> bool {
+        !self.pc_hooks.contains_key(&pc)
+            && self.timed.first().is_none_or(|t| t.cycle > cycles)
+            && self.new_watches.is_empty()
+            && self.dropped_watches.is_empty()
+            && self.typing.is_empty()
+    }
+
+    // Add a bus watch on first..=last that queues its hits for `callback`
+    fn add_watch(
+        &mut self,
+        hits: &Hits,
+        (first, last, kind): (u16, u16, WatchKind),
+        bank: Option<Bank>,
+        condition: WatchCondition,
+        callback: Callback,
+        changes: bool,
+    ) -> u32 {
+        let id = self.next_id();
+        let hits = hits.clone();
+        let mut watch = MemoryWatch::new(watch_name(id), first, last, kind, move |hit| {
+            hits.lock().unwrap().push((id, *hit));
+            HookResult::Continue
+        })
+        .with_condition(condition);
+        watch.bank = bank;
+        self.watches.insert(id, Watch { callback, changes });
+        self.new_watches.push(watch);
+        id
+    }
+}
+
+fn watch_name(id: u32) -> String {
+    format!("lua_watch_{}", id)
+}
+
+pub struct LuaScript {
+    lua: Lua,
+    registry: Rc<RefCell<Registry>>,
+    hits: Hits,
+}
+
+impl LuaScript {
+    // Run the script file to register its callbacks.
+    pub fn load(path: &str, cycles: u64) -> Result<Self> {
+        let src = fs::read_to_string(path).map_err(|e| anyhow!("{}: {}", path, e))?;
+        Self::from_source(&src, path, cycles)
+    }
+
+    // Run a script already in memory; name is used in error messages
+    pub fn from_source(src: &str, name: &str, cycles: u64) -> Result<Self> {
+        let lua = Lua::new();
+        let registry = Rc::new(RefCell::new(Registry { now: cycles, ..Default::default() }));
+        let hits = Hits::default();
+        register_api(&lua, &registry, &hits).map_err(|e| anyhow!("{}", e))?;
+        lua.load(src).set_name(name).exec().map_err(|e| anyhow!("{}", e))?;
+        Ok(Self { lua, registry, hits })
+    }
+
+    // Number of callbacks currently registered
+    pub fn callback_count(&self) -> usize {
+        let reg = self.registry.borrow();
+        reg.pc_hooks.values().map(Vec::len).sum::<usize>() + reg.timed.len() + reg.watches.len()
+    }
+
+    // Run the callbacks due at the CPU's current state. Called after every
+    // instruction, so PC hooks see the machine just before the instruction
+    // at their address executes.
+    pub fn tick(&mut self, cpu: &mut CPU) {
+        let hits = std::mem::take(&mut *self.hits.lock().unwrap());
+        {
+            let mut reg = self.registry.borrow_mut();
+            reg.now = cpu.cycles;
+            if hits.is_empty() && reg.is_idle_at(cpu.pc, cpu.cycles) {
+                return;
+            }
+        }
+
+        {
+            let mut reg = self.registry.borrow_mut();
+            for name in reg.dropped_watches.drain(..) {
+                cpu.bus.watches.remove_by_name(&name);
+            }
+            for watch in reg.new_watches.drain(..) {
+                cpu.bus.watches.add(watch);
+            }
+            if cpu.cycles >= reg.next_key_cycle {
+                if let Some(key) = reg.typing.pop_front() {
+                    press_key(cpu, key);
+                    reg.next_key_cycle = cpu.cycles + timing::CYCLES_PER_FRAME;
+                }
+            }
+        }
+
+        // Accesses made by the instruction that just finished
+        for (id, hit) in hits {
+            let Some((callback, changes)) = self.registry.borrow().watches.get(&id).map(|w| (w.callback.clone(), w.changes)) else {
+                continue;
+            };
+            let args = match (changes, hit.old) {
+                (true, Some(old)) => Args::Change(old, hit.value),
+                _ => Args::Hit(hit),
+            };
+            if !self.call(cpu, id, &callback, args) {
+                self.registry.borrow_mut().remove(id);
+            }
+        }
+
+        // Timed callbacks next: they are the only ones independent of PC
+        loop {
+            let due = {
+                let mut reg = self.registry.borrow_mut();
+                if reg.timed.first().is_none_or(|t| t.cycle > cpu.cycles) {
+                    break;
+                }
+                reg.timed.remove(0)
+            };
+            let keep = self.call(cpu, due.id, &due.callback, Args::None);
+            if let (true, Some(period)) = (keep, due.period) {
+                let cycle = due.cycle.max(cpu.cycles.saturating_sub(period)) + period;
+                self.registry.borrow_mut().add_timed(Timed { cycle, ..due });
+            }
+        }
+
+        let hooks = self.registry.borrow().pc_hooks.get(&cpu.pc).cloned().unwrap_or_default();
+        for (id, callback) in hooks {
+            if !self.call(cpu, id, &callback, Args::None) {
+                self.registry.borrow_mut().remove(id);
+            }
+        }
+    }
+
+    // Call one callback with the machine lent to Lua for the duration.
+    // Returns false if the callback asked to be removed or failed.
+    fn call(&self, cpu: &mut CPU, id: u32, callback: &RegistryKey, args: Args) -> bool {
+        let result = self.lua.scope(|scope| {
+            let machine = scope.create_userdata_ref_mut(cpu)?;
+            let function: Function = self.lua.registry_value(callback)?;
+            match args {
+                Args::None => function.call::<_, Value>(machine),
+                Args::Change(old, new) => function.call::<_, Value>((machine, old, new)),
+                Args::Hit(hit) => {
+                    let table = self.lua.create_table()?;
+                    table.set("addr", hit.addr)?;
+                    table.set("value", hit.value)?;
+                    table.set("old", hit.old)?;
+                    table.set("bank", hit.bank.name().to_ascii_lowercase())?;
+                    table.set("pc", hit.pc)?;
+                    function.call::<_, Value>((machine, table))
+                }
+            }
+        });
+        match result {
+            Ok(Value::Boolean(false)) => false,
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("lua   {:>12} {:>8}    callback {} removed: {}", "SCRIPT", "ERROR", id, e);
+                false
+            }
+        }
+    }
+}
+
+fn register_api(lua: &Lua, registry: &Rc<RefCell<Registry>>, hits: &Hits) -> mlua::Result<()> {
+    let emu = lua.create_table()?;
+
+    let reg = registry.clone();
+    emu.set("on_pc", lua.create_function(move |lua, (addr, f): (u16, Function)| {
+        let callback = Rc::new(lua.create_registry_value(f)?);
+        let mut reg = reg.borrow_mut();
+        let id = reg.next_id();
+        reg.pc_hooks.entry(addr).or_default().push((id, callback));
+        Ok(id)
+    })?)?;
+
+    let reg = registry.clone();
+    emu.set("after", lua.create_function(move |lua, (cycles, f): (u64, Function)| {
+        let callback = Rc::new(lua.create_registry_value(f)?);
+        let mut reg = reg.borrow_mut();
+        let id = reg.next_id();
+        let cycle = reg.now + cycles;
+        reg.add_timed(Timed { id, cycle, period: None, callback });
+        Ok(id)
+    })?)?;
+
+    let reg = registry.clone();
+    emu.set("every", lua.create_function(move |lua, (cycles, f): (u64, Function)| {
+        if cycles == 0 {
+            return Err(mlua::Error::runtime("emu.every needs a period of at least one cycle"));
+        }
+        let callback = Rc::new(lua.create_registry_value(f)?);
+        let mut reg = reg.borrow_mut();
+        let id = reg.next_id();
+        let cycle = reg.now + cycles;
+        reg.add_timed(Timed { id, cycle, period: Some(cycles), callback });
+        Ok(id)
+    })?)?;
+
+    let (reg, hits_) = (registry.clone(), hits.clone());
+    emu.set("watch", lua.create_function(move |lua, (addr, f): (u16, Function)| {
+        let callback = Rc::new(lua.create_registry_value(f)?);
+        let range = (addr, addr, WatchKind::WRITE);
+        Ok(reg.borrow_mut().add_watch(&hits_, range, None, WatchCondition::Changed, callback, true))
+    })?)?;
+
+    for (name, kind) in [("on_read", WatchKind::READ), ("on_write", WatchKind::WRITE), ("on_exec", WatchKind::EXECUTE)] {
+        let (reg, hits_) = (registry.clone(), hits.clone());
+        let function = lua.create_function(move |lua, (first, last, f, cond): (u16, u16, Function, Option<Table>)| {
+            let callback = Rc::new(lua.create_registry_value(f)?);
+            let (bank, condition) = match cond {
+                Some(cond) => parse_condition(&cond)?,
+                None => (None, WatchCondition::Any),
+            };
+            let range = (first.min(last), first.max(last), kind);
+            Ok(reg.borrow_mut().add_watch(&hits_, range, bank, condition, callback, false))
+        })?;
+        emu.set(name, function)?;
+    }
+
+    let reg = registry.clone();
+    emu.set("type", lua.create_function(move |_, text: String| {
+        if let Some(c) = text.chars().find(|c| !c.is_ascii()) {
+            return Err(mlua::Error::runtime(format!("emu.type: '{}' is not on the Apple II keyboard", c)));
+        }
+        // Return is the only line ending the Apple II keyboard knows
+        let keys = text.bytes().map(|b| if b == b'\n' { 0x0D } else { b });
+        reg.borrow_mut().typing.extend(keys);
+        Ok(())
+    })?)?;
+
+    let reg = registry.clone();
+    emu.set("remove", lua.create_function(move |_, id: u32| {
+        reg.borrow_mut().remove(id);
+        Ok(())
+    })?)?;
+
+    lua.globals().set("emu", emu)
+}
+
+// The bank and value condition of an on_read/on_write/on_exec `cond` table
+fn parse_condition(cond: &Table) -> mlua::Result<(Option<Bank>, WatchCondition)> {
+    let bank = match cond.get::<_, Option<String>>("bank")? {
+        Some(name) => Some(Bank::parse(&name).ok_or_else(|| mlua::Error::runtime(format!("unknown bank '{}'", name)))?),
+        None => None,
+    };
+    let get = |key: &str| cond.get::<_, Option<u8>>(key);
+    let condition = match (get("equal")?, get("not_equal")?, get("mask")?, get("min")?, get("max")?) {
+        (Some(v), ..) => WatchCondition::Equal(v),
+        (_, Some(v), ..) => WatchCondition::NotEqual(v),
+        (_, _, Some(mask), ..) => WatchCondition::Masked { mask, value: get("value")?.unwrap_or(mask) },
+        (_, _, _, lo, hi) if lo.is_some() || hi.is_some() => {
+            WatchCondition::Between(lo.unwrap_or(0x00), hi.unwrap_or(0xFF))
+        }
+        _ => WatchCondition::Any,
+    };
+    Ok((bank, condition))
+}
+
+fn press_key(cpu: &CPU, code: u8) {
+    cpu.bus.iou.keyboard.key_down(SCRIPT_KEY_ID, code, cpu.bus.iou.cycles);
+    cpu.bus.iou.keyboard.key_up(SCRIPT_KEY_ID, cpu.bus.iou.cycles);
+}
+
+impl UserData for CPU {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("a", |_, cpu| Ok(cpu.regs.a));
+        fields.add_field_method_get("x", |_, cpu| Ok(cpu.regs.x));
+        fields.add_field_method_get("y", |_, cpu| Ok(cpu.regs.y));
+        fields.add_field_method_get("sp", |_, cpu| Ok(cpu.regs.sp));
+        fields.add_field_method_get("p", |_, cpu| Ok(cpu.p.bits()));
+        fields.add_field_method_get("pc", |_, cpu| Ok(cpu.pc));
+        fields.add_field_method_get("cycles", |_, cpu| Ok(cpu.cycles));
+
+        fields.add_field_method_set("a", |_, cpu, v: u8| {
+            cpu.regs.a = v;
+            Ok(())
+        });
+        fields.add_field_method_set("x", |_, cpu, v: u8| {
+            cpu.regs.x = v;
+            Ok(())
+        });
+        fields.add_field_method_set("y", |_, cpu, v: u8| {
+            cpu.regs.y = v;
+            Ok(())
+        });
+        fields.add_field_method_set("sp", |_, cpu, v: u8| {
+            cpu.regs.sp = v;
+            Ok(())
+        });
+        fields.add_field_method_set("p", |_, cpu, v: u8| {
+            cpu.p = Flags::from_bits_truncate(v);
+            Ok(())
+        });
+        fields.add_field_method_set("pc", |_, cpu, v: u16| {
+            cpu.pc = v;
+            Ok(())
+        });
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("peek", |_, cpu, addr: u16| Ok(cpu.bus.peek_byte(addr)));
+        methods.add_method_mut("peek_word", |_, cpu, addr: u16| {
+            Ok(u16::from_le_bytes([cpu.bus.peek_byte(addr), cpu.bus.peek_byte(addr.wrapping_add(1))]))
+        });
+        methods.add_method_mut("poke", |_, cpu, (addr, value): (u16, u8)| {
+            cpu.bus.write_byte(addr, value);
+            Ok(())
+        });
+
+        methods.add_method("key", |_, cpu, code: u8| {
+            press_key(cpu, code & 0x7F);
+            Ok(())
+        });
+
+        methods.add_method_mut("insert", |_, cpu, (drive, path): (u8, String)| {
+            let iwm = &mut cpu.bus.iou.iwm;
+            let result = match drive {
+                1 => iwm.load_disk(&path),
+                2 => iwm.load_disk2(&path),
+                3 | 4 => iwm.load_disk35_drive(drive as usize - 3, &path),
+                _ => return Err(mlua::Error::runtime(format!("no drive {}", drive))),
+            };
+            result.map_err(|e| mlua::Error::runtime(format!("{}: {}", path, e)))
+        });
+        methods.add_method_mut("eject", |_, cpu, drive: u8| {
+            let iwm = &mut cpu.bus.iou.iwm;
+            match drive {
+                1 | 2 => iwm.eject_disk(drive as usize - 1),
+                3 | 4 => iwm.eject_disk_35(drive as usize - 3),
+                _ => return Err(mlua::Error::runtime(format!("no drive {}", drive))),
+            }
+            Ok(())
+        });
+
+        methods.add_method("screen", |_, cpu, ()| Ok(cpu.bus.text_screen().to_string()));
+        methods.add_method_mut("reset", |_, cpu, ()| {
+            cpu.reset();
+            Ok(())
+        });
+        methods.add_method_mut("exit", |_, cpu, code: Option<i32>| -> mlua::Result<()> {
+            cpu.bus.iou.iwm.eject_disk(0);
+            cpu.bus.iou.iwm.eject_disk(1);
+            cpu.bus.iou.iwm.smartport.flush_all();
+            cpu.bus.flush_switch_trace();
+            std::process::exit(code.unwrap_or(0));
+        });
+    }
+}
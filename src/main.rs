@@ -9,19 +9,38 @@ mod app;
 mod audio_mixer;
 mod bus;
 mod cli;
+mod coverage;
 mod cpu;
 mod cpu_monitor;
+mod dap;
+mod debug_info;
+mod debug_link;
 mod device;
 mod disassembler;
+mod gdb;
 mod hooks;
 mod interrupts;
 mod iou;
+mod lua;
 mod memory;
 mod mmu;
 mod monitor;
+mod movie;
+mod opcodes;
+mod profiler;
+mod recorder;
 mod render;
+mod rewind;
 mod rom;
+mod savestate;
+mod screenshot;
+mod script;
+mod softswitch;
+mod text_screen;
+#[cfg(test)]
+mod tests;
 mod timing;
+mod trace;
 mod util;
 mod video;
 
@@ -36,8 +55,21 @@ use winit::platform::pump_events::{EventLoopExtPumpEvents, PumpStatus};
 
 use crate::app::{run_monitor_mode, App};
 use crate::audio_mixer::AudioMixer;
-use crate::cli::{Args, ShaderType};
+use crate::cli::{Args, CpuArg, ShaderType, SystemArg};
+use crate::coverage::Coverage;
 use crate::cpu::{CpuType, SystemType, CPU};
+use crate::dap::DapServer;
+use crate::debug_link::DebugServer;
+use crate::gdb::GdbServer;
+use crate::lua::LuaScript;
+use crate::mmu::MemoryView;
+use crate::monitor::trace_trigger;
+use crate::movie::{Movie, MoviePlayer, MovieRecorder};
+use crate::profiler::Profiler;
+use crate::rom::RomRevision;
+use crate::rewind::RewindBuffer;
+use crate::softswitch::{Devices, SwitchTrace};
+use crate::trace::{TraceFilter, Tracer};
 
 const BANNER: &str = r#"*
      ██▀███   █    ██   ██████ ▄▄▄█████▓ ██▓ ██▓ ▄████▄  
@@ -79,15 +111,48 @@ fn main() -> Result<(), Error> {
         println!("audio {:>12} {:>8}    {} Hz", "MIXER", "ONLINE", sample_rate);
     }
 
+    let system_type = match args.system {
+        SystemArg::Iic => SystemType::AppleIIc,
+        SystemArg::Generic => SystemType::Generic,
+    };
+    let cpu_type = match (args.cpu, system_type) {
+        (Some(CpuArg::Nmos6502), SystemType::AppleIIc) => {
+            eprintln!("cpu   {:>12} {:>8}    the //c firmware needs a 65C02; use --system generic", "6502", "ERROR");
+            std::process::exit(2);
+        }
+        (Some(CpuArg::Nmos6502), _) | (None, SystemType::Generic) => CpuType::NMOS6502,
+        (Some(CpuArg::Cmos65c02), _) | (None, SystemType::AppleIIc) => CpuType::CMOS65C02,
+        (Some(CpuArg::R65c02), _) => CpuType::R65C02,
+        (Some(CpuArg::Wdc65c02s), _) => CpuType::WDC65C02S,
+    };
+
     let mut cpu = CPU::new(
-        SystemType::AppleIIc,
-        CpuType::CMOS65C02,
+        system_type,
+        cpu_type,
         (args.speed as f64 * timing::CYCLES_PER_SECOND) as u32,
         args.self_test,
         audio_producers.speaker,
         sample_rate,
     );
 
+    if system_type == SystemType::Generic {
+        cpu.debug = args.debug;
+        run_generic(cpu, &args);
+        return Ok(());
+    }
+
+    // Power-on RAM pattern: from the movie being replayed, --ram-seed, or random
+    let movie = args.play_movie.as_ref().and_then(|path| match Movie::load(path) {
+        Ok(movie) => Some(movie),
+        Err(e) => {
+            eprintln!("movie {:>12} {:>8}    {}: {}", "PLAYBACK", "ERROR", path, e);
+            None
+        }
+    });
+    if let Some(seed) = movie.as_ref().map(|m| m.ram_seed).or(args.ram_seed) {
+        cpu.bus.randomize_ram(seed);
+    }
+
     cpu.bus.iou.iwm.init_audio(audio_producers.drive_audio, sample_rate);
     println!("audio {:>12} {:>8}", "DRIVE_SYNTH", "ONLINE");
 
@@ -129,10 +194,6 @@ fn main() -> Result<(), Error> {
         println!("accel {:>12} {:>8}    8 MHz", "ZIP_II-8", "ONLINE");
     }
 
-    if !args.mockingboard2 {
-        println!("slot4 {:>12} {:>8}    1024 KB Slinky", "MEMEXP", "ONLINE");
-    }
-
     // Mockingboard sound card in slot 5
     if args.mockingboard {
         cpu.bus.iou.mockingboard = crate::device::mockingboard::Mockingboard::with_audio(audio_producers.mockingboard1, sample_rate);
@@ -165,10 +226,44 @@ fn main() -> Result<(), Error> {
         println!("input {:>12} {:>8}", "PADDLE", "ONLINE");
     }
 
-    // Load ROM
-    let iic_rom_file = include_bytes!("../assets/iic3.bin");
-    let iic_rom = rom::ROM::load_from_bytes(iic_rom_file, cpu.system_type).unwrap();
+    // Load ROM: built-in ROM 3 unless a file is given
+    let forced_rom = match args.rom_type.as_str() {
+        "auto" => None,
+        name => match RomRevision::parse(name) {
+            Some(rev) => Some(rev),
+            None => {
+                eprintln!("rom   {:>12} {:>8}    unknown --rom-type '{}' (auto, 255, 0, 3, 4, 4x)", "FIRMWARE", "ERROR", name);
+                std::process::exit(2);
+            }
+        },
+    };
+    let iic_rom_file = match &args.rom {
+        Some(path) => std::fs::read(path).unwrap_or_else(|e| {
+            eprintln!("rom   {:>12} {:>8}    {}: {}", "FIRMWARE", "ERROR", path, e);
+            std::process::exit(2);
+        }),
+        None => {
+            if forced_rom.is_some_and(|rev| rev != RomRevision::Rom3) {
+                eprintln!("rom   {:>12} {:>8}    only ROM 3 is built in; pass the image with --rom", "FIRMWARE", "ERROR");
+                std::process::exit(2);
+            }
+            include_bytes!("../assets/iic3.bin").to_vec()
+        }
+    };
+    let iic_rom = match rom::ROM::load_iic(&iic_rom_file, forced_rom) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("rom   {:>12} {:>8}    {}", "FIRMWARE", "ERROR", e);
+            std::process::exit(2);
+        }
+    };
     cpu.load_rom(iic_rom);
+
+    // Slot 4 RAM card needs the connector and firmware added in ROM 3
+    if !args.mockingboard2 && cpu.bus.iou.memexp.is_enabled() {
+        println!("slot4 {:>12} {:>8}    1024 KB Slinky", "MEMEXP", "ONLINE");
+    }
+
     cpu.init();
 
     // Load disks
@@ -239,35 +334,321 @@ fn main() -> Result<(), Error> {
         }
     }
 
+    // Restore save state last so it overrides everything set up above
+    if let Some(path) = &args.load_state {
+        match savestate::load_from_file(&mut cpu, path) {
+            Ok(()) => {
+                println!("state {:>12} {:>8}    {}", "SAVESTATE", "LOADED", path);
+            }
+            Err(e) => {
+                eprintln!("state {:>12} {:>8}    {}: {}", "SAVESTATE", "ERROR", path, e);
+            }
+        }
+    }
+
+    if let Some(path) = &args.trace_switches {
+        trace_switches(&mut cpu, path, &args.trace_devices);
+    }
+
+    load_symbols(&mut cpu, &args.symbols);
+    if let Some(path) = &args.profile {
+        cpu.profiler = Some(Box::new(Profiler::new(Some(path.into()))));
+    }
+    if let Some(stem) = &args.coverage {
+        cpu.bus.coverage = Some(Box::new(Coverage::new(Some(stem.into()))));
+    }
+    if let Some(path) = &args.trace {
+        start_trace(&mut cpu, path, &args);
+    }
+
+    if let Some(path) = &args.lua {
+        load_lua(&mut cpu, path);
+    }
+
+    let movie_player = movie.and_then(|movie| {
+        let events = movie.events.len();
+        match MoviePlayer::start(movie, &mut cpu) {
+            Ok(player) => {
+                println!("movie {:>12} {:>8}    {} events", "PLAYBACK", "ONLINE", events);
+                Some(player)
+            }
+            Err(e) => {
+                eprintln!("movie {:>12} {:>8}    {}", "PLAYBACK", "ERROR", e);
+                None
+            }
+        }
+    });
+
     // Monitor mode
     if args.monitor {
         run_monitor_mode(&mut cpu);
     }
 
+    // Scripted headless test run
+    if let Some(path) = &args.script {
+        let code = script::run_script(&mut cpu, path);
+        cpu.bus.iou.iwm.eject_disk(0);
+        cpu.bus.iou.iwm.eject_disk(1);
+        cpu.bus.iou.iwm.smartport.flush_all();
+        cpu.bus.flush_switch_trace();
+        cpu.stop_profiler();
+        cpu.stop_coverage();
+        cpu.stop_trace();
+        std::process::exit(code);
+    }
+
     // Headless mode
     if args.no_video {
-        run_headless(cpu);
+        if let Some(port) = args.gdb {
+            debug_link::serve(&mut cpu, &mut listen_gdb(port, false));
+            return Ok(());
+        }
+        if let Some(port) = args.dap {
+            debug_link::serve(&mut cpu, &mut listen_dap(port, false));
+            return Ok(());
+        }
+        run_headless(cpu, movie_player);
         return Ok(());
     }
 
     // GUI mode
-    run_gui(cpu, &args)
+    run_gui(cpu, movie_player, &args)
+}
+
+/// Run a memory image on the bare Generic system until the program traps
+/// (a jump or branch to itself), halts, or the monitor is quit.
+fn run_generic(mut cpu: CPU, args: &Args) {
+    let Some(path) = &args.rom else {
+        eprintln!("rom   {:>12} {:>8}    --system generic needs a memory image (--rom)", "IMAGE", "ERROR");
+        std::process::exit(2);
+    };
+    let image = if path.to_ascii_lowercase().ends_with(".hex") {
+        rom::ROM::load_from_intel(path, SystemType::Generic)
+    } else {
+        rom::ROM::load_from_file(path, SystemType::Generic)
+    };
+    match image {
+        Ok(image) => cpu.load_rom(image),
+        Err(e) => {
+            eprintln!("rom   {:>12} {:>8}    {}: {}", "IMAGE", "ERROR", path, e);
+            std::process::exit(2);
+        }
+    }
+
+    if let Some(entry) = &args.entry {
+        match u16::from_str_radix(entry.trim_start_matches("0x").trim_start_matches('$'), 16) {
+            Ok(addr) => cpu.entry_point_override = Some(addr),
+            Err(_) => {
+                eprintln!("cpu   {:>12} {:>8}    bad --entry '{}'", "ENTRY", "ERROR", entry);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    cpu.init();
+    load_symbols(&mut cpu, &args.symbols);
+    if let Some(path) = &args.profile {
+        cpu.profiler = Some(Box::new(Profiler::new(Some(path.into()))));
+    }
+    if let Some(stem) = &args.coverage {
+        cpu.bus.coverage = Some(Box::new(Coverage::new(Some(stem.into()))));
+    }
+    if let Some(path) = &args.trace {
+        start_trace(&mut cpu, path, args);
+    }
+
+    if let Some(path) = &args.lua {
+        load_lua(&mut cpu, path);
+    }
+
+    if args.monitor {
+        run_monitor_mode(&mut cpu);
+        return;
+    }
+
+    if let Some(port) = args.gdb {
+        debug_link::serve(&mut cpu, &mut listen_gdb(port, true));
+        return;
+    }
+
+    if let Some(port) = args.dap {
+        debug_link::serve(&mut cpu, &mut listen_dap(port, true));
+        return;
+    }
+
+    loop {
+        let pc = cpu.pc;
+        cpu.tick();
+        if cpu.bus.interrupts.halted || (cpu.pc == pc && !cpu.bus.interrupts.waiting) {
+            println!("cpu   {:>12} {:>8}    PC={:#06X} after {} cycles", cpu.cpu_type.name(), "TRAPPED", pc, cpu.cycles);
+            break;
+        }
+    }
+    cpu.stop_profiler();
+    cpu.stop_coverage();
+    cpu.stop_trace();
+}
+
+/// Load a Lua script and attach it to the CPU; exits on error.
+fn load_lua(cpu: &mut CPU, path: &str) {
+    match LuaScript::load(path, cpu.cycles) {
+        Ok(script) => {
+            println!("lua   {:>12} {:>8}    {} ({} callbacks)", "SCRIPT", "LOADED", path, script.callback_count());
+            cpu.lua = Some(Box::new(script));
+        }
+        Err(e) => {
+            eprintln!("lua   {:>12} {:>8}    {}", "SCRIPT", "ERROR", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Load --symbols files, each FILE or FILE@BANK; exits on error.
+fn load_symbols(cpu: &mut CPU, files: &[String]) {
+    for spec in files {
+        let (path, view) = match spec.rsplit_once('@') {
+            Some((path, bank)) => match MemoryView::parse(bank) {
+                Some(view) => (path, Some(view)),
+                None => {
+                    eprintln!("sym   {:>12} {:>8}    unknown bank '{}' in {}", "SYMBOLS", "ERROR", bank, spec);
+                    std::process::exit(2);
+                }
+            },
+            None => (spec.as_str(), None),
+        };
+        match cpu.bus.symbols.load_file(path, view) {
+            Ok(count) => {
+                let bank = view.map(|view| format!(" in {}", view.name())).unwrap_or_default();
+                println!("sym   {:>12} {:>8}    {} ({} symbols{})", "SYMBOLS", "LOADED", path, count, bank);
+            }
+            Err(e) => {
+                eprintln!("sym   {:>12} {:>8}    {}", "SYMBOLS", "ERROR", e);
+                std::process::exit(2);
+            }
+        }
+    }
+}
+
+/// Start the instruction trace given by the --trace options; exits on error.
+fn start_trace(cpu: &mut CPU, path: &str, args: &Args) {
+    let fail = |e: &dyn std::fmt::Display| -> ! {
+        eprintln!("trace {:>12} {:>8}    {}", "TRACE", "ERROR", e);
+        std::process::exit(2);
+    };
+    let trigger = |text: &Option<String>| {
+        text.as_ref().map(|text| trace_trigger(&cpu.bus, text).unwrap_or_else(|e| fail(&e)))
+    };
+    let (start, stop) = (trigger(&args.trace_start), trigger(&args.trace_stop));
+
+    let mut filter = TraceFilter::default();
+    if let Some(range) = &args.trace_range {
+        filter.range = Some(TraceFilter::parse_range(range).unwrap_or_else(|e| fail(&e)));
+    }
+    if let Some(banks) = &args.trace_bank {
+        filter.banks = TraceFilter::parse_banks(banks).unwrap_or_else(|e| fail(&e));
+    }
+    if let Some(cycles) = &args.trace_cycles {
+        filter.cycles = Some(TraceFilter::parse_cycles(cycles).unwrap_or_else(|e| fail(&e)));
+    }
+
+    let mut tracer = Tracer::create(std::path::Path::new(path), &cpu.trace_header(), args.trace_ring)
+        .unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+    tracer.filter = filter;
+    tracer.set_start(start);
+    tracer.stop = stop;
+    cpu.tracer = Some(Box::new(tracer));
+    println!("trace {:>12} {:>8}    {}", "TRACE", "ONLINE", path);
+}
+
+/// Listen for a GDB remote protocol client; exits on error.
+fn listen_gdb(port: u16, wait: bool) -> GdbServer {
+    match GdbServer::listen(port, wait) {
+        Ok(gdb) => {
+            // Port 0 picks a free port; show the one bound
+            let addr = gdb.local_addr().map_or(format!("127.0.0.1:{}", port), |a| a.to_string());
+            println!("gdb   {:>12} {:>8}    {}", "RSP", "LISTENING", addr);
+            gdb
+        }
+        Err(e) => {
+            eprintln!("gdb   {:>12} {:>8}    port {}: {}", "RSP", "ERROR", port, e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Listen for a Debug Adapter Protocol client; exits on error.
+fn listen_dap(port: u16, wait: bool) -> DapServer {
+    match DapServer::listen(port, wait) {
+        Ok(dap) => {
+            // Port 0 picks a free port; show the one bound
+            let addr = dap.local_addr().map_or(format!("127.0.0.1:{}", port), |a| a.to_string());
+            println!("dap   {:>12} {:>8}    {}", "DAP", "LISTENING", addr);
+            dap
+        }
+        Err(e) => {
+            eprintln!("dap   {:>12} {:>8}    port {}: {}", "DAP", "ERROR", port, e);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn trace_switches(cpu: &mut CPU, path: &str, devices: &str) {
+    let filter = match Devices::parse(devices) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("trace {:>12} {:>8}    {}", "SWITCHES", "ERROR", e);
+            std::process::exit(2);
+        }
+    };
+    match SwitchTrace::to_file(path, filter) {
+        Ok(trace) => {
+            println!("trace {:>12} {:>8}    {} ({})", "SWITCHES", "ONLINE", path, devices);
+            cpu.bus.switch_trace = Some(trace);
+        }
+        Err(e) => {
+            eprintln!("trace {:>12} {:>8}    {}: {}", "SWITCHES", "ERROR", path, e);
+            std::process::exit(2);
+        }
+    }
 }
 
 /// Run emulator in headless (no video) mode.
-fn run_headless(mut cpu: CPU) {
+fn run_headless(mut cpu: CPU, mut movie_player: Option<MoviePlayer>) {
     loop {
+        if let Some(player) = movie_player.as_mut() {
+            player.apply_due(&mut cpu);
+            if player.is_finished() {
+                println!("Movie finished at cycle {}", cpu.cycles);
+                movie_player = None;
+            }
+        }
         cpu.tick();
         if cpu.bus.interrupts.halted {
             println!("*");
             break;
         }
     }
+    cpu.stop_profiler();
+    cpu.stop_coverage();
+    cpu.stop_trace();
 }
 
-fn run_gui(cpu: CPU, args: &Args) -> Result<(), Error> {
+fn run_gui(cpu: CPU, movie_player: Option<MoviePlayer>, args: &Args) -> Result<(), Error> {
     let mut event_loop = EventLoop::new().unwrap();
     let mut app = App::new(cpu, args.shader, args.fullscreen);
+    app.movie_player = movie_player;
+    if let Some(path) = &args.record_movie {
+        match MovieRecorder::create(path, &app.cpu) {
+            Ok(rec) => {
+                println!("movie {:>12} {:>8}    {} (seed {:016X})", "RECORDER", "ONLINE", path, app.cpu.bus.ram_seed());
+                app.movie_recorder = Some(rec);
+            }
+            Err(e) => eprintln!("movie {:>12} {:>8}    {}: {}", "RECORDER", "ERROR", path, e),
+        }
+    }
+    if let Some(dir) = &args.record {
+        app.start_recording(std::path::Path::new(dir));
+    }
 
     let timeout = Some(Duration::ZERO);
     let target_frame_time = Duration::from_micros(timing::FRAME_DURATION_MICROS);
@@ -296,6 +677,10 @@ fn run_gui(cpu: CPU, args: &Args) -> Result<(), Error> {
     let mut perf_frames = 0u64;
     let mut perf_cycles_start = app.cpu.cycles;
 
+    let mut rewind = RewindBuffer::new(args.rewind_seconds);
+    let mut gdb = args.gdb.map(|port| listen_gdb(port, false));
+    let mut dap = args.dap.map(|port| listen_dap(port, false));
+
     // Ctrl-C
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
@@ -307,6 +692,7 @@ fn run_gui(cpu: CPU, args: &Args) -> Result<(), Error> {
 
     loop {
         if !running.load(Ordering::SeqCst) {
+            app.stop_recording();
             app.flush_disks();
             std::process::exit(0);
         }
@@ -327,7 +713,24 @@ fn run_gui(cpu: CPU, args: &Args) -> Result<(), Error> {
             cycles_per_frame * zip_multiplier
         };
 
-        if app.window.is_some() && !app.paused {
+        // Debuggers are answered even while paused or rewinding
+        if let Some(gdb) = gdb.as_mut() {
+            gdb.poll(&mut app.cpu);
+        }
+        if let Some(dap) = dap.as_mut() {
+            dap.poll(&mut app.cpu);
+        }
+
+        if app.rewind_held && app.window.is_some() && !app.paused {
+            // Rewind: restore snapshots instead of running the CPU
+            match rewind.hold(&mut app.cpu) {
+                Ok(true) => app.stop_movie("rewound"),
+                Ok(false) => {}
+                Err(e) => eprintln!("Rewind failed: {}", e),
+            }
+        } else if app.window.is_some() && !app.paused {
+            rewind.release();
+
             // Scanline-interleaved execution: run ~65 cycles per scanline (262 scanlines/frame)
             // for scanline-accurate VBL timing and floating bus values.
             let cycles_per_scanline = effective_cpf / timing::SCANLINES_PER_FRAME;
@@ -342,6 +745,11 @@ fn run_gui(cpu: CPU, args: &Args) -> Result<(), Error> {
                 target_cycles += cycles_per_scanline + if (scanline as u64) < remainder { 1 } else { 0 };
 
                 while cycles_run < target_cycles {
+                    // Stopped by the debugger: the frame is drawn but nothing runs
+                    if gdb.as_ref().is_some_and(|gdb| gdb.is_stopped()) || dap.as_ref().is_some_and(|dap| dap.is_stopped()) {
+                        break;
+                    }
+
                     if fast_mode && app.cpu.pc == fast_until_addr {
                         println!(
                             "Reached fast_until address {:04X}. Switching to normal speed.",
@@ -357,13 +765,27 @@ fn run_gui(cpu: CPU, args: &Args) -> Result<(), Error> {
                         std::process::exit(0);
                     }
 
+                    if let Some(player) = app.movie_player.as_mut() {
+                        player.apply_due(&mut app.cpu);
+                    }
+
                     cycles_run += app.cpu.tick();
+
+                    if let Some(gdb) = gdb.as_mut() {
+                        gdb.check(&app.cpu);
+                    }
+                    if let Some(dap) = dap.as_mut() {
+                        dap.check(&app.cpu);
+                    }
                 }
 
                 // Snapshot video mode at end of each visible scanline
                 if scanline < 192 {
                     app.cpu.video_snapshot_scanline(scanline);
                 }
+                // A recording takes a frame per frame of emulated time,
+                // however many this pass runs
+                app.record_frames();
             }
 
             cpu_time = frame_start.elapsed();
@@ -373,14 +795,22 @@ fn run_gui(cpu: CPU, args: &Args) -> Result<(), Error> {
             app.cpu.bus.iou.iwm.update_audio();
 
             // Poll host gamepad for paddle/button input
-            app.cpu.bus.iou.paddle.poll();
+            app.poll_paddle();
+
+            if app.movie_player.as_ref().is_some_and(|p| p.is_finished()) {
+                println!("Movie finished at cycle {}: live input resumed", app.cpu.cycles);
+                app.movie_player = None;
+            }
+
+            rewind.capture(&app.cpu);
         }
 
         let status = event_loop.pump_app_events(timeout, &mut app);
 
         if let PumpStatus::Exit(exit_code) = status {
+            app.stop_recording();
             app.flush_disks();
-            std::process::exit(exit_code as i32);
+            std::process::exit(exit_code);
         }
 
         // Snap window to aspect ratio after user finishes resizing
@@ -395,7 +825,7 @@ fn run_gui(cpu: CPU, args: &Args) -> Result<(), Error> {
         if perf_start.elapsed() >= Duration::from_secs(1) {
             if args.perf {
                 let elapsed = perf_start.elapsed().as_secs_f64();
-                let cycles_total = app.cpu.cycles - perf_cycles_start;
+                let cycles_total = app.cpu.cycles.saturating_sub(perf_cycles_start);
                 let mhz = cycles_total as f64 / elapsed / 1_000_000.0;
                 let fps = perf_frames as f64 / elapsed;
                 let cycles_per_frame_avg = cycles_total as f64 / perf_frames as f64;
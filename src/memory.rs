@@ -1,3 +1,5 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 pub struct Memory {
     data: Vec<u8>
 }
@@ -9,10 +11,10 @@ impl Memory {
         Self { data: vec![0x00; size] }
     }
 
-    pub fn randomize_power_on(&mut self) {
+    pub fn randomize_power_on(&mut self, rng: &mut fastrand::Rng) {
         for (i, byte) in self.data.iter_mut().enumerate() {
             let base = if (i / 128) & 1 == 0 { 0x00u8 } else { 0xFFu8 };
-            let noise = fastrand::u8(..) & fastrand::u8(..) & fastrand::u8(..);
+            let noise = rng.u8(..) & rng.u8(..) & rng.u8(..);
             *byte = base ^ noise;
         }
     }
@@ -48,3 +50,13 @@ impl Memory {
         }
     }
 }
+
+impl SaveState for Memory {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.data);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.bytes_into(&mut self.data)
+    }
+}
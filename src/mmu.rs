@@ -1,22 +1,10 @@
-use crate::{iou::IOU, memory::Memory, rom::ROM, video::VideoModeMask};
+use crate::{iou::IOU, memory::Memory, rom::ROM, savestate::{SaveState, StateReader, StateWriter}, video::VideoModeMask};
 
 const RAM_SIZE: usize = 64 * 1024;
 const ROM_SIZE: usize = 16 * 1024;
 const LCRAM_SIZE: usize = 4 * 1024;
 const LCRAM_HIGH_SIZE: usize = 8 * 1024;
 
-macro_rules! maybe_write_byte {
-    ($write:expr, $ram:expr, $bank:expr, $addr:expr, $value:expr) => {
-        if $write == 1 {
-            $ram[$bank as usize].write_byte($addr, $value);
-            0x00
-        } else {
-            println!("Attempted write to read-only memory at {:#06X}", $addr);
-            0x00
-        }
-    };
-}
-
 pub struct MemStateMask;
 #[rustfmt::skip]
 impl MemStateMask {
@@ -62,11 +50,156 @@ impl LcRamMode {
     pub const C08F: u8 = MemStateMask::LCRAM | MemStateMask::WRITE;
 }
 
+// Where a CPU access resolved to, for watchpoints. LC is the language card
+// RAM at $D000-$FFFF; Io is the soft switches and cards intercepting $C1xx-$CFxx.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bank {
+    Main,
+    Aux,
+    LcMain,
+    LcAux,
+    Rom,
+    Io,
+}
+
+impl Bank {
+    fn ram(aux: usize) -> Self {
+        if aux == 1 { Bank::Aux } else { Bank::Main }
+    }
+
+    fn lc(aux: usize) -> Self {
+        if aux == 1 { Bank::LcAux } else { Bank::LcMain }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Bank::Main => "MAIN",
+            Bank::Aux => "AUX",
+            Bank::LcMain => "LCMAIN",
+            Bank::LcAux => "LCAUX",
+            Bank::Rom => "ROM",
+            Bank::Io => "IO",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [Bank::Main, Bank::Aux, Bank::LcMain, Bank::LcAux, Bank::Rom, Bank::Io]
+            .into_iter()
+            .find(|bank| bank.name().eq_ignore_ascii_case(name))
+    }
+}
+
+// Where an access goes once the soft switches are applied: the index into
+// MMU::ram, MMU::lcram ([MAIN1, MAIN2, AUX1, AUX2]; $E000-$FFFF is in the
+// RAM of the same side) or MMU::rom.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Location {
+    Ram(usize),
+    LcRam(usize),
+    Rom(usize),
+    // Mockingboard VIA registers in the slot 4 or 5 ROM space
+    Card,
+    // $C000-$C0FF, handled by the bus before it gets here
+    Switch,
+}
+
+impl Location {
+    fn bank(self) -> Bank {
+        match self {
+            Location::Ram(aux) => Bank::ram(aux),
+            Location::LcRam(bank) => Bank::lc(bank >> 1),
+            Location::Rom(_) => Bank::Rom,
+            Location::Card | Location::Switch => Bank::Io,
+        }
+    }
+
+    // The RAM view holding the byte; None for ROM, cards and switches
+    fn ram_view(self) -> Option<MemoryView> {
+        match self {
+            Location::Ram(0) => Some(MemoryView::Main),
+            Location::Ram(_) => Some(MemoryView::Aux),
+            Location::LcRam(bank) => Some(MemoryView::lc(bank)),
+            _ => None,
+        }
+    }
+}
+
+// A bank as stored, whatever the soft switches select: the debugger's view
+// of memory the CPU may not see right now. Each view covers the addresses
+// the bank answers to. The LC views are $D000-$FFFF: their own 4K at $D000
+// and the 8K at $E000 they share with the other bank of the same side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryView {
+    Main,
+    Aux,
+    LcMain1,
+    LcMain2,
+    LcAux1,
+    LcAux2,
+    Rom1,
+    Rom2,
+}
+
+impl MemoryView {
+    pub const ALL: [MemoryView; 8] = [
+        MemoryView::Main,
+        MemoryView::Aux,
+        MemoryView::LcMain1,
+        MemoryView::LcMain2,
+        MemoryView::LcAux1,
+        MemoryView::LcAux2,
+        MemoryView::Rom1,
+        MemoryView::Rom2,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryView::Main => "main",
+            MemoryView::Aux => "aux",
+            MemoryView::LcMain1 => "lcmain1",
+            MemoryView::LcMain2 => "lcmain2",
+            MemoryView::LcAux1 => "lcaux1",
+            MemoryView::LcAux2 => "lcaux2",
+            MemoryView::Rom1 => "rom1",
+            MemoryView::Rom2 => "rom2",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|view| view.name().eq_ignore_ascii_case(name))
+    }
+
+    // First and last address (inclusive)
+    pub fn range(self) -> (u16, u16) {
+        match self {
+            MemoryView::Main | MemoryView::Aux => (0x0000, 0xBFFF),
+            MemoryView::Rom1 | MemoryView::Rom2 => (0xC000, 0xFFFF),
+            _ => (0xD000, 0xFFFF),
+        }
+    }
+
+    // Index into MMU::lcram of an LC view: [MAIN1, MAIN2, AUX1, AUX2]
+    fn lc_bank(self) -> usize {
+        match self {
+            MemoryView::LcMain1 => 0,
+            MemoryView::LcMain2 => 1,
+            MemoryView::LcAux1 => 2,
+            _ => 3,
+        }
+    }
+
+    fn lc(bank: usize) -> Self {
+        [MemoryView::LcMain1, MemoryView::LcMain2, MemoryView::LcAux1, MemoryView::LcAux2][bank]
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct MMU {
     rom: [Memory; 2],   // Two 16KB ROM banks | [ROM1, ROM2]
     ram: [Memory; 2],   // 64KB Main and Auxiliary RAM | [MAIN, AUX]
     lcram: [Memory; 4], // Four 4KB Language Card RAM banks | [MAIN1, MAIN2, AUX1, AUX2]
     lcram_high: [Memory; 2], // Two 8KB high LC RAM banks | [MAIN, AUX]
+    ram_seed: u64,      // Seed of the last power-on RAM pattern
 }
 
 impl MMU {
@@ -90,27 +223,41 @@ impl MMU {
                 Memory::new(LCRAM_HIGH_SIZE, "LCHIGHMAIN".into()),
                 Memory::new(LCRAM_HIGH_SIZE, "LCHIGHAUX".into()),
             ],
+            ram_seed: 0,
         };
-        mmu.randomize_ram();
+        mmu.randomize_ram(fastrand::u64(..));
         mmu
     }
 
-    pub fn randomize_ram(&mut self) {
+    // Fill all RAM with the power-on pattern; the same seed always gives the same contents
+    pub fn randomize_ram(&mut self, seed: u64) {
+        let mut rng = fastrand::Rng::with_seed(seed);
         for bank in &mut self.ram {
-            bank.randomize_power_on();
+            bank.randomize_power_on(&mut rng);
         }
         for bank in &mut self.lcram {
-            bank.randomize_power_on();
+            bank.randomize_power_on(&mut rng);
         }
         for bank in &mut self.lcram_high {
-            bank.randomize_power_on();
+            bank.randomize_power_on(&mut rng);
         }
+        self.ram_seed = seed;
+    }
+
+    pub fn ram_seed(&self) -> u64 {
+        self.ram_seed
     }
 
     pub fn load_rom(&mut self, rom: ROM) {
-        // v3 iic rom boundary $3fff
+        // 32K ROMs (0 and later) split at $3fff into main and alternate banks;
+        // ROM 255 has one 16K bank, mirrored so ALTROM reads the same firmware
+        let banks = rom.revision.map_or(2, |rev| rev.banks());
         self.rom[0].load_bytes(0, &rom.data[0..ROM_SIZE]);
-        self.rom[1].load_bytes(0, &rom.data[ROM_SIZE..(ROM_SIZE << 1)]);
+        if banks == 1 {
+            self.rom[1].load_bytes(0, &rom.data[0..ROM_SIZE]);
+        } else {
+            self.rom[1].load_bytes(0, &rom.data[ROM_SIZE..(ROM_SIZE << 1)]);
+        }
 
         // // Patch "Check Disk Drive" infinite loop to boot to BASIC prompt instead.
         // // At $C55D the ROM has BRA $C55D (80 FE) which hangs forever when no disk is inserted.
@@ -129,168 +276,203 @@ impl MMU {
         self.ram[1].read_byte(addr)
     }
 
-    pub fn read_byte(&self, iou: &mut IOU, addr: u16) -> u8 {
-        let mem_state = iou.mem_state.get();
-        let video_mode = iou.video_mode.get();
-        let is_page2 = check_bits_u8!(video_mode, VideoModeMask::PAGE2);
-        let is_80store = iou.is_80store.get();
-
-        let altzp = check_bits_u8!(mem_state, MemStateMask::ALTZP) as usize;
-        let altrom = check_bits_u8!(mem_state, MemStateMask::ALTROM) as usize;
-        let lcram = check_bits_u8!(mem_state, MemStateMask::LCRAM) as usize;
-        let bank = check_bits_u8!(mem_state, MemStateMask::RDBNK) as usize;
-        let ramrd = check_bits_u8!(mem_state, MemStateMask::RAMRD) as usize;
+    // Byte at addr in a view; None outside the addresses it covers
+    pub fn peek_view(&self, view: MemoryView, addr: u16) -> Option<u8> {
+        let (first, last) = view.range();
+        if addr < first || addr > last {
+            return None;
+        }
+        Some(match view {
+            MemoryView::Main => self.ram[0].read_byte(addr),
+            MemoryView::Aux => self.ram[1].read_byte(addr),
+            MemoryView::Rom1 => self.rom[0].read_byte(addr - 0xC000),
+            MemoryView::Rom2 => self.rom[1].read_byte(addr - 0xC000),
+            _ if addr >= 0xE000 => self.ram[view.lc_bank() >> 1].read_byte(addr),
+            _ => self.lcram[view.lc_bank()].read_byte(addr - 0xD000),
+        })
+    }
 
-        match addr {
-            // Zero Page & Stack (Main vs. Auxiliary)
-            0x0000..=0x01FF => self.ram[altzp].read_byte(addr),
+    // Store a byte in a view, ROM included; false outside its addresses
+    pub fn poke_view(&mut self, view: MemoryView, addr: u16, value: u8) -> bool {
+        let (first, last) = view.range();
+        if addr < first || addr > last {
+            return false;
+        }
+        match view {
+            MemoryView::Main => self.ram[0].write_byte(addr, value),
+            MemoryView::Aux => self.ram[1].write_byte(addr, value),
+            MemoryView::Rom1 => self.rom[0].write_byte(addr - 0xC000, value),
+            MemoryView::Rom2 => self.rom[1].write_byte(addr - 0xC000, value),
+            _ if addr >= 0xE000 => self.ram[view.lc_bank() >> 1].write_byte(addr, value),
+            _ => self.lcram[view.lc_bank()].write_byte(addr - 0xD000, value),
+        };
+        true
+    }
 
-            // 80STORE-affected Display Memory (Text & Graphics)
-            // Text page ($0400-$07FF): always redirected by 80STORE+PAGE2
-            0x0400..=0x07FF if is_80store => {
-                self.ram[is_page2 as usize].read_byte(addr)
+    pub fn read_byte(&self, iou: &mut IOU, addr: u16) -> u8 {
+        match self.read_location(iou, addr) {
+            Location::Ram(aux) => self.ram[aux].read_byte(addr),
+            Location::LcRam(bank) if addr >= 0xE000 => self.ram[bank >> 1].read_byte(addr),
+            Location::LcRam(bank) => self.lcram[bank].read_byte(addr - 0xD000),
+            // Mockingboard VIA registers in the slot 4 and 5 ROM space, once
+            // a write has woken the card up
+            Location::Card if addr < 0xC500 => iou.mockingboard2.read(addr as u8),
+            Location::Card => iou.mockingboard.read(addr as u8),
+            Location::Rom(altrom) => {
+                // Machine ID spoofing: When any Mockingboard is enabled, report as Apple IIe
+                // so games like Ultima V don't refuse to use Mockingboard on IIc.
+                // $FBB3 = machine ID ($00 = IIc, $06 = IIe)
+                // $FBC0 = version ($00 = IIc, $EA = IIe)
+                if iou.mockingboard.is_enabled() || iou.mockingboard2.is_enabled() {
+                    match addr {
+                        0xFBB3 => return 0x06, // Report as IIe
+                        0xFBC0 => return 0xEA, // IIe version byte
+                        _ => {}
+                    }
+                }
+                self.rom[altrom].read_byte(addr - 0xC000)
             }
-            // HiRes page ($2000-$3FFF): only redirected by 80STORE+PAGE2 when HIRES is active
-            0x2000..=0x3FFF if is_80store && check_bits_u8!(video_mode, VideoModeMask::HIRES) => {
-                self.ram[is_page2 as usize].read_byte(addr)
+            Location::Switch => {
+                println!("Unhandled Memory Read at {:#06X}", addr);
+                0x00
             }
+        }
+    }
 
-            // General 48K RAM ($0200 - $BFFF)
-            0x0200..=0xBFFF => self.ram[ramrd].read_byte(addr),
-
-            // // Soft Switches ($C000 - $C0FF)
-            // 0xC000..=0xC0FF => {
-            //     let result = self.handle_softswitch_read(addr);
-            //     println!(
-            //         "SoftSwitch Read at {:#06X} = {:#04X} {}",
-            //         addr,
-            //         result,
-            //         mem_state_to_string(mem_state)
-            //     );
-            //     self.last_rd_addr.set(addr);
-            //     return result;
-            // }
-
-            // Language Card (LC) RAM / ROM ($C100 - $CFFF)
-            0xC100..=0xCFFF => {
-                // Mockingboard2 slot 4 ROM space ($C400-$C4FF) - VIA register access
-                // ONLY intercept if Mockingboard is both enabled AND activated.
-                // Timer-based activation ensures this only happens after boot completes.
-                if iou.mockingboard2.is_activated() && (0xC400..=0xC4FF).contains(&addr) {
-                    let offset = (addr & 0xFF) as u8;
-                    let value = iou.mockingboard2.read(offset);
-                    return value;
-                }
-                // Mockingboard slot 5 ROM space ($C500-$C5FF)
-                if iou.mockingboard.is_activated() && (0xC500..=0xC5FF).contains(&addr) {
-                    let offset = (addr & 0xFF) as u8;
-                    let value = iou.mockingboard.read(offset);
-                    return value;
-                }
-                // Other slot ROM space (or $C4xx/$C5xx when MB not activated): read from ROM
-                self.rom[altrom].read_byte(addr.wrapping_sub(0xC000))
+    pub fn write_byte(&mut self, iou: &mut IOU, addr: u16, value: u8) -> u8 {
+        match self.write_location(iou, addr) {
+            Location::Ram(aux) => self.ram[aux].write_byte(addr, value),
+            Location::LcRam(bank) if addr >= 0xE000 => self.ram[bank >> 1].write_byte(addr, value),
+            Location::LcRam(bank) => self.lcram[bank].write_byte(addr - 0xD000, value),
+            // Any write to $C4xx/$C5xx wakes the Mockingboard up (mimicking
+            // MB4c hardware) and goes to its VIA registers
+            Location::Card if addr < 0xC500 => {
+                log::debug!("MMU: Mockingboard2 write intercepted at ${:04X} = ${:02X}", addr, value);
+                iou.mockingboard2.activate();
+                iou.mockingboard2.write(addr as u8, value);
+                0x00
             }
-
-            // Language Card RAM ($D000 - $DFFF)
-            0xD000..=0xDFFF => {
-                if lcram == 1 {
-                    self.lcram[bank + (altzp << 1)].read_byte(addr.wrapping_sub(0xD000))
-                } else {
-                    self.rom[altrom].read_byte(addr - 0xC000)
-                }
+            Location::Card => {
+                log::debug!("MMU: Mockingboard write intercepted at ${:04X} = ${:02X}", addr, value);
+                iou.mockingboard.activate();
+                iou.mockingboard.write(addr as u8, value);
+                0x00
             }
-
-            // High Memory RAM / ROM ($E000 - $FFFF)
-            0xE000..=0xFFFF => {
-                if lcram == 1 {
-                    self.ram[altzp].read_byte(addr)
-                } else {
-                    // Machine ID spoofing: When any Mockingboard is enabled, report as Apple IIe
-                    // so games like Ultima V don't refuse to use Mockingboard on IIc.
-                    // $FBB3 = machine ID ($00 = IIc, $06 = IIe)
-                    // $FBC0 = version ($00 = IIc, $EA = IIe)
-                    if iou.mockingboard.is_enabled() || iou.mockingboard2.is_enabled() {
-                        match addr {
-                            0xFBB3 => return 0x06, // Report as IIe
-                            0xFBC0 => return 0xEA, // IIe version byte
-                            _ => {}
-                        }
-                    }
-                    self.rom[altrom].read_byte(addr.wrapping_sub(0xC000))
-                }
+            Location::Rom(_) if addr >= 0xD000 => {
+                println!("Attempted write to read-only memory at {:#06X}", addr);
+                0x00
             }
-            _ => {
-                println!("Unhandled Memory Read at {:#06X}", addr);
+            // Slot ROM space is read-only, writes are ignored
+            Location::Rom(_) => 0x00,
+            Location::Switch => {
+                println!("Unhandled Memory Write at {:#06X}", addr);
                 0x00
             }
         }
     }
 
-    pub fn write_byte(
-        &mut self,
-        iou: &mut IOU,
-        addr: u16,
-        value: u8,
-        mem_state: u8,
-        is_80store: bool,
-        is_page2: bool,
-        is_hires: bool,
-    ) -> u8 {
+    // Where a read of `addr` goes under the current soft switches. The byte
+    // accessors, the watch banks and the debugger views all resolve here.
+    fn read_location(&self, iou: &IOU, addr: u16) -> Location {
+        let mem_state = iou.mem_state.get();
         let altzp = check_bits_u8!(mem_state, MemStateMask::ALTZP) as usize;
+        let altrom = check_bits_u8!(mem_state, MemStateMask::ALTROM) as usize;
+        let lcram = check_bits_u8!(mem_state, MemStateMask::LCRAM);
         let bank = check_bits_u8!(mem_state, MemStateMask::RDBNK) as usize;
-        let ramwrt = check_bits_u8!(mem_state, MemStateMask::RAMWRT) as usize;
-        let write = check_bits_u8!(mem_state, MemStateMask::WRITE) as usize;
+        let ramrd = check_bits_u8!(mem_state, MemStateMask::RAMRD) as usize;
 
         match addr {
             // Zero Page & Stack (Main vs. Auxiliary)
-            0x0000..=0x01FF => self.ram[altzp].write_byte(addr, value),
-
-            // 80STORE-affected Display Memory (Text & Graphics)
-            // Text page ($0400-$07FF): always redirected by 80STORE+PAGE2
-            0x0400..=0x07FF if is_80store => self.ram[is_page2 as usize].write_byte(addr, value),
-
-            // HiRes page ($2000-$3FFF): only redirected by 80STORE+PAGE2 when HIRES is active
-            0x2000..=0x3FFF if is_80store && is_hires => self.ram[is_page2 as usize].write_byte(addr, value),
-
-            // General 48K RAM ($0200 - $BFFF)
-            0x0200..=0xBFFF => self.ram[ramwrt].write_byte(addr, value),
-
-            // Soft Switches ($C000 - $C0FF)
-            0xC100..=0xCFFF => {
-                // Mockingboard2 slot 4 ROM space ($C400-$C4FF) - VIA register writes
-                // Any write to $C4xx activates the Mockingboard2 (mimicking MB4c hardware)
-                if iou.mockingboard2.is_enabled() && (0xC400..=0xC4FF).contains(&addr) {
-                    log::debug!("MMU: Mockingboard2 write intercepted at ${:04X} = ${:02X}", addr, value);
-                    iou.mockingboard2.activate();  // First write wakes up the Mockingboard
-                    let offset = (addr & 0xFF) as u8;
-                    iou.mockingboard2.write(offset, value);
-                }
-                // Mockingboard slot 5 ROM space ($C500-$C5FF)
-                if iou.mockingboard.is_enabled() && (0xC500..=0xC5FF).contains(&addr) {
-                    log::debug!("MMU: Mockingboard write intercepted at ${:04X} = ${:02X}", addr, value);
-                    iou.mockingboard.activate();  // First write wakes up the Mockingboard
-                    let offset = (addr & 0xFF) as u8;
-                    iou.mockingboard.write(offset, value);
-                }
-                // ROM space is read-only, writes are ignored
-                0x00
-            },
-
-            // Language Card RAM ($D000 - $DFFF)
-            0xD000..=0xDFFF => maybe_write_byte!(
-                write,
-                self.lcram,
-                bank + (altzp << 1),
-                addr - 0xD000,
-                value
-            ),
-
-            // High Memory RAM / ROM ($E000 - $FFFF)
-            0xE000..=0xFFFF => maybe_write_byte!(write, self.ram, altzp, addr, value),
-            _ => {
-                println!("Unhandled Memory Write at {:#06X}", addr);
-                0x00
-            }
+            0x0000..=0x01FF => Location::Ram(altzp),
+            // General 48K RAM, less the display pages 80STORE redirects
+            0x0200..=0xBFFF => Location::Ram(Self::display_page(iou, addr).unwrap_or(ramrd)),
+            0xC000..=0xC0FF => Location::Switch,
+            0xC400..=0xC4FF if iou.mockingboard2.is_activated() => Location::Card,
+            0xC500..=0xC5FF if iou.mockingboard.is_activated() => Location::Card,
+            0xD000..=0xFFFF if lcram => Location::LcRam(bank + (altzp << 1)),
+            _ => Location::Rom(altrom),
+        }
+    }
+
+    // Where a write of `addr` goes; Rom when it goes nowhere (ROM, or LC
+    // RAM write-protected)
+    fn write_location(&self, iou: &IOU, addr: u16) -> Location {
+        let mem_state = iou.mem_state.get();
+        let altzp = check_bits_u8!(mem_state, MemStateMask::ALTZP) as usize;
+        let altrom = check_bits_u8!(mem_state, MemStateMask::ALTROM) as usize;
+        let bank = check_bits_u8!(mem_state, MemStateMask::RDBNK) as usize;
+        let ramwrt = check_bits_u8!(mem_state, MemStateMask::RAMWRT) as usize;
+        let write = check_bits_u8!(mem_state, MemStateMask::WRITE);
+
+        match addr {
+            0x0000..=0x01FF => Location::Ram(altzp),
+            0x0200..=0xBFFF => Location::Ram(Self::display_page(iou, addr).unwrap_or(ramwrt)),
+            0xC000..=0xC0FF => Location::Switch,
+            0xC400..=0xC4FF if iou.mockingboard2.is_enabled() => Location::Card,
+            0xC500..=0xC5FF if iou.mockingboard.is_enabled() => Location::Card,
+            0xD000..=0xFFFF if write => Location::LcRam(bank + (altzp << 1)),
+            _ => Location::Rom(altrom),
+        }
+    }
+
+    // RAM bank of a display page address redirected by 80STORE: the text
+    // page always, the hi-res page only in HIRES. PAGE2 selects aux.
+    fn display_page(iou: &IOU, addr: u16) -> Option<usize> {
+        let video_mode = iou.video_mode.get();
+        let redirected = match addr {
+            0x0400..=0x07FF => true,
+            0x2000..=0x3FFF => check_bits_u8!(video_mode, VideoModeMask::HIRES),
+            _ => false,
+        };
+        (redirected && iou.is_80store.get()).then_some(check_bits_u8!(video_mode, VideoModeMask::PAGE2) as usize)
+    }
+
+    // Bank a read of `addr` resolves to
+    pub fn read_bank(&self, iou: &IOU, addr: u16) -> Bank {
+        self.read_location(iou, addr).bank()
+    }
+
+    // View a read of `addr` comes from, telling the LC and ROM banks apart;
+    // None for soft switches and cards
+    pub fn read_view(&self, iou: &IOU, addr: u16) -> Option<MemoryView> {
+        match self.read_location(iou, addr) {
+            Location::Rom(altrom) => Some(if altrom == 1 { MemoryView::Rom2 } else { MemoryView::Rom1 }),
+            location => location.ram_view(),
+        }
+    }
+
+    // View a write of `addr` lands in; None when it goes nowhere or to a
+    // soft switch or card
+    pub fn write_view(&self, iou: &IOU, addr: u16) -> Option<MemoryView> {
+        self.write_location(iou, addr).ram_view()
+    }
+
+    // Bank a write of `addr` lands in, and the byte there before the write;
+    // None when the write goes nowhere (ROM, or LC RAM write-protected)
+    pub fn write_target(&self, iou: &IOU, addr: u16) -> (Bank, Option<u8>) {
+        let location = self.write_location(iou, addr);
+        let old = location.ram_view().and_then(|view| self.peek_view(view, addr));
+        (location.bank(), old)
+    }
+}
+
+impl SaveState for MMU {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"MMU ");
+        for bank in self.rom.iter().chain(&self.ram).chain(&self.lcram).chain(&self.lcram_high) {
+            bank.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"MMU ")?;
+        for bank in self.rom.iter_mut()
+            .chain(&mut self.ram)
+            .chain(&mut self.lcram)
+            .chain(&mut self.lcram_high)
+        {
+            bank.load_state(r)?;
         }
+        Ok(())
     }
 }
@@ -1,13 +1,86 @@
-use crate::cpu::CPU;
+use crate::bus::Bus;
+use crate::coverage::Coverage;
+use crate::cpu::{Flags, CPU};
+use crate::disassembler::Disassembler;
+use crate::mmu::MemoryView;
+use crate::profiler::{self, Profiler};
 use crate::rom::ROM;
-use std::collections::HashSet;
+use crate::screenshot;
+use crate::softswitch::{Devices, SwitchTrace};
+use crate::trace::{self, TraceFilter, TraceRecord, Tracer, Trigger};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+// Cycles next, finish and until run before giving up
+const RUN_LIMIT: u64 = 20_000_000;
+
+// What a breakpoint condition can test: registers, then flags (0 or 1)
+const REGISTER_NAMES: [&str; 13] = ["A", "X", "Y", "SP", "P", "PC", "N", "V", "B", "D", "I", "Z", "C"];
+
+// Condition of a conditional breakpoint: a register or flag compared with
+// a hex value, as in `A==FF`, `SP<E0` or `Z==1`
+pub struct Condition {
+    register: String,
+    op: &'static str,
+    value: u16,
+}
+
+impl Condition {
+    const OPS: [&'static str; 7] = ["==", "!=", "<=", ">=", "<", ">", "="];
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (at, op) = Self::OPS
+            .iter()
+            .filter_map(|&op| text.find(op).map(|at| (at, op)))
+            .min_by_key(|&(at, op)| (at, std::cmp::Reverse(op.len())))
+            .ok_or_else(|| format!("No comparison in '{}'", text))?;
+        let register = text[..at].trim().to_ascii_uppercase();
+        if !REGISTER_NAMES.contains(&register.as_str()) {
+            return Err(format!("Unknown register or flag '{}'", register));
+        }
+        let value = parse_hex(text[at + op.len()..].trim()).ok_or_else(|| format!("Bad value in '{}'", text))?;
+        let op = if op == "=" { "==" } else { op };
+        Ok(Self { register, op, value })
+    }
+
+    pub fn holds(&self, cpu: &CPU) -> bool {
+        let regs = &cpu.regs;
+        self.compare(register_value(&self.register, [regs.a, regs.x, regs.y, regs.sp, cpu.p.bits()], cpu.pc))
+    }
+
+    // Tested on the registers a trace record holds, those before it ran
+    pub fn holds_before(&self, record: &TraceRecord) -> bool {
+        let r = record;
+        self.compare(register_value(&self.register, [r.a, r.x, r.y, r.sp, r.p], r.pc))
+    }
+
+    fn compare(&self, value: Option<u16>) -> bool {
+        let Some(value) = value else {
+            return false;
+        };
+        match self.op {
+            "==" => value == self.value,
+            "!=" => value != self.value,
+            "<=" => value <= self.value,
+            ">=" => value >= self.value,
+            "<" => value < self.value,
+            _ => value > self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}${:02X}", self.register, self.op, self.value)
+    }
+}
 
 pub struct Monitor<'a> {
     cpu: &'a mut CPU,
-    breakpoints: HashSet<u16>,
+    breakpoints: BTreeMap<u16, Option<Condition>>,
 }
 
 impl<'a> Monitor<'a> {
@@ -15,7 +88,7 @@ impl<'a> Monitor<'a> {
         cpu.bus.interrupts.enter_halt();
         Self {
             cpu,
-            breakpoints: HashSet::new(),
+            breakpoints: BTreeMap::new(),
         }
     }
 
@@ -41,7 +114,7 @@ impl<'a> Monitor<'a> {
         }
     }
 
-    fn execute_command(&mut self, input: &str) -> bool {
+    pub fn execute_command(&mut self, input: &str) -> bool {
         let args: Vec<&str> = input.split_whitespace().collect();
 
         if args.is_empty() {
@@ -52,8 +125,13 @@ impl<'a> Monitor<'a> {
             "help" => { self.show_help(); true },
             "reset" => { self.cpu.reset(); true },
             "step" | "s" => { self.step(); true },
+            "next" | "n" => { self.next(); true },
+            "finish" => { self.finish(); true },
+            "until" if args.len() == 2 => { self.until(args[1]); true },
             "continue" | "c" => { self.resume(); false },
-            "break" if args.len() == 2 => { self.set_breakpoint(args[1]); true },
+            "break" if args.len() == 1 => { self.list_breakpoints(); true },
+            "break" if args.len() == 2 => { self.set_breakpoint(args[1], None); true },
+            "break" if args.len() >= 4 && args[2] == "if" => { self.set_breakpoint(args[1], Some(&args[3..].join(""))); true },
             "delete" if args.len() == 2 => { self.remove_breakpoint(args[1]); true },
             "registers" | "r" => { self.show_registers(); true },
             "flags" => { self.show_flags(); true },
@@ -64,6 +142,21 @@ impl<'a> Monitor<'a> {
             "mem" if args.len() == 3 => { self.view_memory(args[1], Some(args[2])); true },
             "page" if args.len() == 2 => { self.view_memory_page(args[1]); true },
             "write" if args.len() == 3 => { self.write_memory(args[1], args[2]); true },
+            "dis" if args.len() <= 3 => { self.disassemble(args.get(1).copied(), args.get(2).copied()); true },
+            "a" if args.len() >= 3 => { self.assemble(args[1], &args[2..].join(" ")); true },
+            "fill" if args.len() == 4 => { self.fill(args[1], args[2], args[3]); true },
+            "move" if args.len() == 4 => { self.move_memory(args[1], args[2], args[3]); true },
+            "compare" if args.len() == 4 => { self.compare(args[1], args[2], args[3]); true },
+            "search" if args.len() >= 4 => { self.search(args[1], args[2], &args[3..]); true },
+            "screenshot" if args.len() == 1 => { self.screenshot(None); true },
+            "screenshot" if args.len() == 2 => { self.screenshot(Some(args[1])); true },
+            "text" if args.len() == 1 => { self.show_text(None); true },
+            "text" if args.len() == 2 => { self.show_text(Some(args[1])); true },
+            "switches" => { self.switches(&args[1..]); true },
+            "sym" => { self.symbols(&args[1..]); true },
+            "profile" => { self.profile(&args[1..]); true },
+            "coverage" => { self.coverage(&args[1..]); true },
+            "trace" => { self.trace(&args[1..]); true },
             "exit" | "quit" => {
                 println!("Exiting monitor. CPU remains halted.");
                 std::process::exit(0);
@@ -78,8 +171,14 @@ impl<'a> Monitor<'a> {
         println!("  load <file> [addr]  - Load a ROM file into memory at [addr] (default 0x0000)");
         println!("  reset          - Reset the CPU");
         println!("  step (s)       - Execute a single instruction");
+        println!("  next (n)       - Execute one instruction, running a JSR through to its return");
+        println!("  finish         - Run until the current subroutine returns (RTS/RTI)");
+        println!("  until <addr>   - Run until PC reaches <addr> (hex)");
         println!("  continue (c)   - Resume execution from halt/breakpoint");
+        println!("  break          - List breakpoints");
         println!("  break <addr>   - Set a breakpoint at <addr> (hex)");
+        println!("  break <addr> if <reg><op><value> - Conditional breakpoint, e.g. break C600 if A==FF");
+        println!("                   (reg: A X Y SP P PC or flag N V B D I Z C; op: == != < <= > >=)");
         println!("  delete <addr>  - Remove a breakpoint at <addr> (hex)");
         println!("  registers (r)  - Show CPU registers");
         println!("  flags          - Show CPU status flags");
@@ -88,6 +187,39 @@ impl<'a> Monitor<'a> {
         println!("  mem <start> <end> - View memory range (hex)");
         println!("  page <addr>    - View a full 256-byte memory page");
         println!("  write <addr> <value> - Write <value> (hex) to <addr> (hex)");
+        println!("  dis [addr] [n] - Disassemble n instructions (default 20) from <addr> (default PC)");
+        println!("  a <addr> <instruction> - Assemble one instruction at <addr>, e.g. a 0300 LDA #$01");
+        println!("  fill <start> <end> <value> - Fill memory with <value>");
+        println!("  move <start> <end> <dest>  - Copy memory to <dest>");
+        println!("  compare <start> <end> <other> - Show the bytes that differ from those at <other>");
+        println!("  search <start> <end> <bytes...> - Find a byte sequence, e.g. search 0800 BFFF A9 00");
+        println!("  text [1|2]     - Show the text screen (or text page 1/2)");
+        println!("  screenshot [file] - Save the display as PNG (default screenshot-NNNN.png)");
+        println!("  switches [n]   - Show the last n traced soft-switch accesses (default 20)");
+        println!("  switches on [devices] - Trace soft-switch accesses (e.g. iwm,scc; default all)");
+        println!("  switches off   - Stop tracing soft-switch accesses");
+        println!("  switches save <file> - Write the traced accesses to <file>");
+        println!("  sym            - Show how many symbols are loaded");
+        println!("  sym load <file> [bank] - Load a symbol file (AppleWin, ca65 .lbl, VICE, Merlin, Ophis),");
+        println!("                   optionally for one bank: main aux lcmain1 lcmain2 lcaux1 lcaux2 rom1 rom2");
+        println!("  sym <name>     - Show the address of a symbol");
+        println!("  profile on|off|reset - Count cycles per address and bank, and the JSR call graph");
+        println!("  profile [n]    - Show the n hottest addresses (default 20)");
+        println!("  profile calls [n] - Show the n busiest caller -> callee edges (default 20)");
+        println!("  profile save <file> - Write the call stacks as a collapsed-stack (flame graph) file");
+        println!("  coverage on|off|reset - Record bytes executed, read and written in each bank");
+        println!("  coverage       - Show how many bytes were executed, read and written, and in which banks");
+        println!("  coverage save <stem> - Write the map to <stem>.png and the code/data listing to <stem>.lst");
+        println!("  trace on <file> [n] - Write each instruction run to <file> (text if it ends in .txt),");
+        println!("                   or only the last n, written when the trace is turned off");
+        println!("  trace off      - Stop tracing and close the file");
+        println!("  trace          - Show the trace file, its filters and triggers");
+        println!("  trace range <lo>-<hi>|off - Only trace PCs in this range");
+        println!("  trace bank <banks>|off - Only trace these banks, e.g. main,lcmain1");
+        println!("  trace cycles <from>-<to>|off - Only trace this cycle window");
+        println!("  trace start|stop <addr> [if <condition>]|off - Start tracing at <addr>, or pause");
+        println!("                   after <addr>; the condition is written as for break");
+        println!("  Addresses for until, break, delete, dis, a and trace may also be symbol names.");
         println!("  quit | exit    - Exit the monitor (CPU remains halted)");
     }
 
@@ -127,30 +259,64 @@ impl<'a> Monitor<'a> {
     }
 
     fn step(&mut self) {
-        if self.cpu.bus.interrupts.halted {
-            println!("CPU is halted. Use 'continue' to resume execution.");
+        self.run_until(|_, _| true);
+    }
+
+    // Step over a JSR: run until it returns with the stack where it was
+    fn next(&mut self) {
+        let (pc, sp) = (self.cpu.pc, self.cpu.regs.sp);
+        if self.cpu.bus.peek_byte(pc) != 0x20 {
+            self.step();
             return;
         }
+        let ret = pc.wrapping_add(3);
+        self.run_until(|cpu, _| cpu.pc == ret && cpu.regs.sp == sp);
+    }
 
-        self.cpu.tick();
-        self.show_registers();
+    // Run until an RTS or RTI pops the stack above where it is now
+    fn finish(&mut self) {
+        let sp = self.cpu.regs.sp;
+        self.run_until(|cpu, opcode| matches!(opcode, 0x40 | 0x60) && cpu.regs.sp > sp);
+    }
 
-        if self.breakpoints.contains(&self.cpu.pc) {
-            println!("Hit breakpoint at {:04X}. Execution halted.", self.cpu.pc);
-            self.cpu.bus.interrupts.enter_halt();
+    fn until(&mut self, addr: &str) {
+        match self.address(addr) {
+            Some(addr) => self.run_until(|cpu, _| cpu.pc == addr),
+            None => println!("Usage: until <addr>"),
         }
     }
 
-    #[allow(dead_code)]
-    fn run(&mut self) {
-        while !self.cpu.bus.interrupts.halted {
-            if self.breakpoints.contains(&self.cpu.pc) {
-                println!("Hit breakpoint at {:04X}. Execution halted.", self.cpu.pc);
-                self.cpu.bus.interrupts.enter_halt();
+    // Run until `done` holds after an instruction (given the CPU and the
+    // opcode it ran), a breakpoint is reached, the CPU stops itself or
+    // RUN_LIMIT cycles pass. The monitor keeps the CPU halted between
+    // commands; the halt is lifted while it runs here.
+    fn run_until(&mut self, mut done: impl FnMut(&CPU, u8) -> bool) {
+        self.cpu.bus.interrupts.leave_halt();
+        let start = self.cpu.cycles;
+        loop {
+            let opcode = self.cpu.bus.peek_byte(self.cpu.pc);
+            self.cpu.tick();
+            if self.cpu.bus.interrupts.halted {
+                println!("CPU stopped at {:04X}.", self.cpu.pc);
+                break;
+            }
+            if let Some(condition) = self.breakpoints.get(&self.cpu.pc) {
+                if condition.as_ref().is_none_or(|c| c.holds(self.cpu)) {
+                    println!("Hit breakpoint at {:04X}. Execution halted.", self.cpu.pc);
+                    break;
+                }
+            }
+            if done(self.cpu, opcode) {
+                break;
+            }
+            if self.cpu.cycles - start >= RUN_LIMIT {
+                println!("Still running after {} cycles; stopped.", RUN_LIMIT);
                 break;
             }
-            self.cpu.tick();
         }
+        self.cpu.bus.interrupts.enter_halt();
+        self.show_registers();
+        self.show_instruction(self.cpu.pc);
     }
 
     // TODO: rework halt/wait in this context
@@ -171,20 +337,68 @@ impl<'a> Monitor<'a> {
         println!("CPU halted.");
     }
 
-    fn set_breakpoint(&mut self, addr: &str) {
-        if let Ok(addr) = u16::from_str_radix(addr, 16) {
-            self.breakpoints.insert(addr);
-            println!("Breakpoint set at ${:04X}", addr);
+    fn set_breakpoint(&mut self, addr: &str, condition: Option<&str>) {
+        let Some(addr) = self.address(addr) else {
+            println!("Usage: break <addr> [if <condition>]");
+            return;
+        };
+        let condition = match condition.map(Condition::parse) {
+            None => None,
+            Some(Ok(condition)) => Some(condition),
+            Some(Err(e)) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        match &condition {
+            Some(condition) => println!("Breakpoint set at ${:04X} if {}", addr, condition),
+            None => println!("Breakpoint set at ${:04X}", addr),
         }
+        self.breakpoints.insert(addr, condition);
     }
 
     fn remove_breakpoint(&mut self, addr: &str) {
-        if let Ok(addr) = u16::from_str_radix(addr, 16) {
+        if let Some(addr) = self.address(addr) {
             self.breakpoints.remove(&addr);
             println!("Breakpoint removed at ${:04X}", addr);
         }
     }
 
+    fn list_breakpoints(&self) {
+        if self.breakpoints.is_empty() {
+            println!("No breakpoints");
+        }
+        for (addr, condition) in &self.breakpoints {
+            match condition {
+                Some(condition) => println!("${:04X} if {}", addr, condition),
+                None => println!("${:04X}", addr),
+            }
+        }
+    }
+
+    fn show_text(&self, page: Option<&str>) {
+        let screen = match page {
+            None => self.cpu.bus.text_screen(),
+            Some("1") => self.cpu.bus.text_page(false),
+            Some("2") => self.cpu.bus.text_page(true),
+            Some(_) => {
+                println!("Usage: text [1|2]");
+                return;
+            }
+        };
+        print!("{}", screen.to_ansi_string());
+    }
+
+    fn screenshot(&mut self, file: Option<&str>) {
+        let path = file.map(PathBuf::from).unwrap_or_else(screenshot::next_path);
+        // Re-render so the image matches memory even when no frame has been drawn
+        self.cpu.video_update();
+        match screenshot::save_png(&self.cpu.bus.video, &path) {
+            Ok(()) => println!("Saved screenshot: {}", path.display()),
+            Err(e) => println!("Error saving screenshot: {}", e),
+        }
+    }
+
     fn show_registers(&self) {
         println!(
             "PC: {:04X}  A: {:02X}  X: {:02X}  Y: {:02X}  SP: {:02X}",
@@ -246,4 +460,441 @@ impl<'a> Monitor<'a> {
             println!("Wrote {:02X} to ${:04X}", value, addr);
         }
     }
+
+    fn show_instruction(&mut self, addr: u16) {
+        let line = Disassembler::disassemble(&mut self.cpu.bus, addr, self.cpu.cpu_type);
+        println!("{}", line.trim_end());
+    }
+
+    fn instruction_length(&mut self, addr: u16) -> u16 {
+        let bytes = [0, 1, 2].map(|i| self.cpu.bus.peek_byte(addr.wrapping_add(i)));
+        Disassembler::decode(addr, bytes, self.cpu.cpu_type).2 as u16
+    }
+
+    fn disassemble(&mut self, addr: Option<&str>, count: Option<&str>) {
+        let addr = match addr.map(|addr| self.address(addr)) {
+            None => Some(self.cpu.pc),
+            addr => addr.flatten(),
+        };
+        let count = match count.map(str::parse::<usize>) {
+            None => Some(20),
+            Some(count) => count.ok(),
+        };
+        let (Some(mut addr), Some(count)) = (addr, count) else {
+            println!("Usage: dis [addr] [n]");
+            return;
+        };
+        for _ in 0..count {
+            if let Some(label) = self.cpu.bus.symbols.lookup(addr, self.cpu.bus.read_view(addr)) {
+                println!("{}:", label);
+            }
+            self.show_instruction(addr);
+            addr = addr.wrapping_add(self.instruction_length(addr));
+        }
+    }
+
+    fn assemble(&mut self, addr: &str, instruction: &str) {
+        let Some(addr) = self.address(addr) else {
+            println!("Usage: a <addr> <instruction>");
+            return;
+        };
+        match Disassembler::assemble(addr, instruction, self.cpu.cpu_type) {
+            Ok(bytes) => {
+                self.cpu.bus.write_bytes(addr, &bytes);
+                self.show_instruction(addr);
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    fn fill(&mut self, start: &str, end: &str, value: &str) {
+        let (Some((start, end)), Some(value)) = (parse_range(start, end), parse_byte(value)) else {
+            println!("Usage: fill <start> <end> <value>");
+            return;
+        };
+        for addr in start..=end {
+            self.cpu.bus.write_byte(addr, value);
+        }
+        println!("Filled ${:04X}-${:04X} with {:02X}", start, end, value);
+    }
+
+    fn move_memory(&mut self, start: &str, end: &str, dest: &str) {
+        let (Some((start, end)), Some(dest)) = (parse_range(start, end), parse_hex(dest)) else {
+            println!("Usage: move <start> <end> <dest>");
+            return;
+        };
+        // Read it all first so overlapping ranges copy as they were
+        let bytes: Vec<u8> = (start..=end).map(|addr| self.cpu.bus.peek_byte(addr)).collect();
+        self.cpu.bus.write_bytes(dest, &bytes);
+        println!("Moved ${:04X}-${:04X} to ${:04X} ({} bytes)", start, end, dest, bytes.len());
+    }
+
+    fn compare(&mut self, start: &str, end: &str, other: &str) {
+        let (Some((start, end)), Some(other)) = (parse_range(start, end), parse_hex(other)) else {
+            println!("Usage: compare <start> <end> <other>");
+            return;
+        };
+        let differ = compare_memory(&mut self.cpu.bus, start, end, other);
+        for &addr in &differ {
+            let there = other.wrapping_add(addr - start);
+            let (a, b) = (self.cpu.bus.peek_byte(addr), self.cpu.bus.peek_byte(there));
+            println!("${:04X}: {:02X}  ${:04X}: {:02X}", addr, a, there, b);
+        }
+        println!("{} bytes differ", differ.len());
+    }
+
+    fn search(&mut self, start: &str, end: &str, pattern: &[&str]) {
+        let pattern: Option<Vec<u8>> = pattern.iter().map(|b| parse_byte(b)).collect();
+        let (Some((start, end)), Some(pattern)) = (parse_range(start, end), pattern) else {
+            println!("Usage: search <start> <end> <bytes...>");
+            return;
+        };
+        let found = search_memory(&mut self.cpu.bus, start, end, &pattern);
+        for addr in &found {
+            println!("${:04X}", addr);
+        }
+        println!("{} found", found.len());
+    }
+
+    // Hex address, or the name of a symbol
+    fn address(&self, text: &str) -> Option<u16> {
+        parse_hex(text).or_else(|| self.cpu.bus.symbols.address(text))
+    }
+
+    fn symbols(&mut self, args: &[&str]) {
+        match args {
+            [] => println!("{} symbols loaded", self.cpu.bus.symbols.len()),
+            ["load", file] | ["load", file, _] => {
+                let view = match args.get(2).map(|bank| MemoryView::parse(bank)) {
+                    None => None,
+                    Some(Some(view)) => Some(view),
+                    Some(None) => {
+                        println!("Unknown bank '{}'", args[2]);
+                        return;
+                    }
+                };
+                match self.cpu.bus.symbols.load_file(file, view) {
+                    Ok(count) => println!("Loaded {} symbols from {}", count, file),
+                    Err(e) => println!("Error loading symbols: {}", e),
+                }
+            }
+            [name] => match self.cpu.bus.symbols.address(name) {
+                Some(addr) => println!("{} = ${:04X}", name, addr),
+                None => println!("No symbol '{}'", name),
+            },
+            _ => println!("Usage: sym | sym load <file> [bank] | sym <name>"),
+        }
+    }
+
+    fn profile(&mut self, args: &[&str]) {
+        let count = |arg: Option<&&str>| match arg.map(|n| n.parse::<usize>()) {
+            None => Some(20),
+            Some(n) => n.ok(),
+        };
+        match (args, self.cpu.profiler.as_mut()) {
+            (["on"], Some(_)) => println!("Already profiling"),
+            (["on"], None) => {
+                self.cpu.profiler = Some(Box::new(Profiler::new(None)));
+                println!("Profiling on");
+            }
+            (["off"], _) => {
+                self.cpu.stop_profiler();
+                println!("Profiling off");
+            }
+            (["reset"], Some(profiler)) => {
+                profiler.reset();
+                println!("Profile cleared");
+            }
+            (["save", file], Some(profiler)) => match profiler.write_collapsed(Path::new(file), &self.cpu.bus.symbols) {
+                Ok(lines) => println!("Wrote {} stacks to {}", lines, file),
+                Err(e) => println!("Error writing {}: {}", file, e),
+            },
+            (["calls"] | ["calls", _], Some(profiler)) => {
+                let Some(count) = count(args.get(1)) else {
+                    println!("Usage: profile calls [n]");
+                    return;
+                };
+                let symbols = &self.cpu.bus.symbols;
+                for call in profiler.calls().iter().take(count) {
+                    let caller = call.caller.map_or("top".to_string(), |caller| profiler::routine_name(caller, symbols));
+                    println!(
+                        "{} -> {}  {} calls, {} cycles ({:.1}%)",
+                        caller,
+                        profiler::routine_name(call.callee, symbols),
+                        call.calls,
+                        call.cycles,
+                        profiler.percent(call.cycles),
+                    );
+                }
+            }
+            ([] | [_], Some(profiler)) => match count(args.first()) {
+                Some(count) => print!("{}", profiler.report(count, &self.cpu.bus.symbols)),
+                None => println!("Usage: profile on|off|reset | [n] | calls [n] | save <file>"),
+            },
+            (_, None) => println!("Not profiling; use 'profile on'"),
+            _ => println!("Usage: profile on|off|reset | [n] | calls [n] | save <file>"),
+        }
+    }
+
+    fn coverage(&mut self, args: &[&str]) {
+        match (args, self.cpu.bus.coverage.as_mut()) {
+            (["on"], Some(_)) => println!("Already recording coverage"),
+            (["on"], None) => {
+                self.cpu.bus.coverage = Some(Box::new(Coverage::new(None)));
+                println!("Coverage on");
+            }
+            (["off"], _) => {
+                self.cpu.stop_coverage();
+                println!("Coverage off");
+            }
+            (["reset"], Some(coverage)) => {
+                coverage.reset();
+                println!("Coverage cleared");
+            }
+            (["save", stem], Some(_)) => match self.cpu.save_coverage(Path::new(stem)) {
+                Ok(regions) => println!("Wrote {}.png and {}.lst ({} regions)", stem, stem, regions),
+                Err(e) => println!("Error writing {}: {}", stem, e),
+            },
+            ([], Some(coverage)) => {
+                let [executed, read, written] = coverage.counts();
+                let views: Vec<&str> = coverage.views().map(MemoryView::name).collect();
+                println!("{} bytes executed, {} read, {} written", executed, read, written);
+                println!("Banks: {}", if views.is_empty() { "none".to_string() } else { views.join(" ") });
+            }
+            (_, None) => println!("Not recording coverage; use 'coverage on'"),
+            _ => println!("Usage: coverage on|off|reset | save <stem>"),
+        }
+    }
+
+    fn trace(&mut self, args: &[&str]) {
+        match args {
+            ["on", file] | ["on", file, _] => {
+                let ring = match args.get(2).map(|n| n.parse::<usize>()) {
+                    None => None,
+                    Some(Ok(n)) if n > 0 => Some(n),
+                    Some(_) => {
+                        println!("Usage: trace on <file> [n]");
+                        return;
+                    }
+                };
+                self.cpu.stop_trace();
+                match Tracer::create(Path::new(file), &self.cpu.trace_header(), ring) {
+                    Ok(tracer) => {
+                        self.cpu.tracer = Some(Box::new(tracer));
+                        println!("Tracing to {}", file);
+                    }
+                    Err(e) => println!("Error creating {}: {}", file, e),
+                }
+                return;
+            }
+            ["off"] => {
+                if self.cpu.tracer.is_some() {
+                    self.cpu.stop_trace();
+                } else {
+                    println!("Not tracing");
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        // Triggers may name symbols, so resolve them before taking the tracer
+        let trigger = match args {
+            ["start" | "stop", "off"] => None,
+            ["start" | "stop", rest @ ..] if !rest.is_empty() => match trace_trigger(&self.cpu.bus, &rest.join(" ")) {
+                Ok(trigger) => Some(trigger),
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                }
+            },
+            _ => None,
+        };
+        let Some(tracer) = self.cpu.tracer.as_mut() else {
+            println!("Not tracing; use 'trace on <file>'");
+            return;
+        };
+        let result = match args {
+            [] => {
+                let filter = &tracer.filter;
+                println!("Tracing to {}, {} instructions written", tracer.path.display(), tracer.records);
+                if let Some((lo, hi)) = filter.range {
+                    println!("  range   ${:04X}-${:04X}", lo, hi);
+                }
+                if !filter.banks.is_empty() {
+                    let banks: Vec<&str> = filter.banks.iter().map(|&bank| trace::BANK_NAMES[bank as usize]).collect();
+                    println!("  banks   {}", banks.join(","));
+                }
+                if let Some((from, to)) = filter.cycles {
+                    println!("  cycles  {}-{}", from, if to == u64::MAX { String::new() } else { to.to_string() });
+                }
+                if let Some(start) = &tracer.start {
+                    println!("  start   {}", start);
+                }
+                if let Some(stop) = &tracer.stop {
+                    println!("  stop    {}", stop);
+                }
+                match (tracer.is_active(), &tracer.start) {
+                    (true, _) => println!("  active"),
+                    (false, Some(start)) => println!("  waiting for {}", start),
+                    (false, None) => println!("  stopped"),
+                }
+                Ok(())
+            }
+            ["range", "off"] => {
+                tracer.filter.range = None;
+                Ok(())
+            }
+            ["range", range] => TraceFilter::parse_range(range).map(|range| tracer.filter.range = Some(range)),
+            ["bank", "off"] => {
+                tracer.filter.banks.clear();
+                Ok(())
+            }
+            ["bank", banks] => TraceFilter::parse_banks(banks).map(|banks| tracer.filter.banks = banks),
+            ["cycles", "off"] => {
+                tracer.filter.cycles = None;
+                Ok(())
+            }
+            ["cycles", window] => TraceFilter::parse_cycles(window).map(|window| tracer.filter.cycles = Some(window)),
+            ["start", _, ..] => {
+                tracer.set_start(trigger);
+                Ok(())
+            }
+            ["stop", _, ..] => {
+                tracer.stop = trigger;
+                Ok(())
+            }
+            _ => {
+                println!("Usage: trace on <file> [n] | off | range|bank|cycles|start|stop <value>|off");
+                return;
+            }
+        };
+        if let Err(e) = result {
+            println!("{}", e);
+        }
+    }
+
+    fn switches(&mut self, args: &[&str]) {
+        match args {
+            ["on"] | ["on", _] => {
+                // Replacing a --trace-switches trace would silently end its file
+                if let Some(path) = self.cpu.bus.switch_trace.as_ref().and_then(SwitchTrace::path) {
+                    println!("Soft switches are already being traced to {}; 'switches off' ends that trace", path);
+                    return;
+                }
+                let filter = match Devices::parse(args.get(1).unwrap_or(&"all")) {
+                    Ok(filter) => filter,
+                    Err(e) => {
+                        println!("{}", e);
+                        return;
+                    }
+                };
+                self.cpu.bus.switch_trace = Some(SwitchTrace::new(filter));
+                println!("Tracing soft switches: {:?}", filter);
+            }
+            ["off"] => {
+                self.cpu.bus.flush_switch_trace();
+                self.cpu.bus.switch_trace = None;
+                println!("Soft-switch trace off");
+            }
+            ["save", file] => match self.cpu.bus.switch_trace.as_ref() {
+                Some(trace) => match trace.dump(Path::new(file)) {
+                    Ok(count) => println!("Wrote {} accesses to {}", count, file),
+                    Err(e) => println!("Error writing {}: {}", file, e),
+                },
+                None => println!("Soft switches are not being traced; use 'switches on'"),
+            },
+            [] | [_] => {
+                let Some(trace) = self.cpu.bus.switch_trace.as_ref() else {
+                    println!("Soft switches are not being traced; use 'switches on'");
+                    return;
+                };
+                let count = match args.first().map(|n| n.parse::<usize>()) {
+                    None => 20,
+                    Some(Ok(n)) => n,
+                    Some(Err(_)) => {
+                        println!("Usage: switches [n] | on [devices] | off | save <file>");
+                        return;
+                    }
+                };
+                let recent: Vec<_> = trace.recent().collect();
+                for access in &recent[recent.len().saturating_sub(count)..] {
+                    println!("{}", access);
+                }
+            }
+            _ => println!("Usage: switches [n] | on [devices] | off | save <file>"),
+        }
+    }
+}
+
+// A, X, Y, SP and P, then PC
+fn register_value(name: &str, [a, x, y, sp, p]: [u8; 5], pc: u16) -> Option<u16> {
+    let flag = |flag: Flags| Some((p & flag.bits() != 0) as u16);
+    match name {
+        "A" => Some(a as u16),
+        "X" => Some(x as u16),
+        "Y" => Some(y as u16),
+        "SP" => Some(sp as u16),
+        "P" => Some(p as u16),
+        "PC" => Some(pc),
+        "N" => flag(Flags::NEGATIVE),
+        "V" => flag(Flags::OVERFLOW),
+        "B" => flag(Flags::BREAK),
+        "D" => flag(Flags::DECIMAL),
+        "I" => flag(Flags::IRQ_DISABLE),
+        "Z" => flag(Flags::ZERO),
+        "C" => flag(Flags::CARRY),
+        _ => None,
+    }
+}
+
+// Trace start or stop trigger written as a breakpoint is, `ADDR [if COND]`,
+// for the monitor's `trace start|stop` and for --trace-start/--trace-stop.
+// ADDR is hex, with $ or 0x, or a symbol.
+pub fn trace_trigger(bus: &Bus, text: &str) -> Result<Trigger, String> {
+    let (addr, condition) = match text.split_once(" if ") {
+        Some((addr, condition)) => (addr.trim(), Some(condition)),
+        None => (text.trim(), None),
+    };
+    let addr = parse_hex(addr.strip_prefix("0x").unwrap_or(addr))
+        .or_else(|| bus.symbols.address(addr))
+        .ok_or_else(|| format!("Bad address '{}'", addr))?;
+    match condition {
+        Some(condition) => {
+            let condition = Condition::parse(&condition.replace(' ', ""))?;
+            Ok(Trigger::when(addr, condition.to_string(), move |record| condition.holds_before(record)))
+        }
+        None => Ok(Trigger::at(addr)),
+    }
+}
+
+// Addresses in start..=end whose byte differs from the one as far on from other
+pub fn compare_memory(bus: &mut Bus, start: u16, end: u16, other: u16) -> Vec<u16> {
+    (start..=end).filter(|&addr| bus.peek_byte(addr) != bus.peek_byte(other.wrapping_add(addr - start))).collect()
+}
+
+// Addresses in start..=end where the pattern starts and fits before end
+pub fn search_memory(bus: &mut Bus, start: u16, end: u16, pattern: &[u8]) -> Vec<u16> {
+    let memory: Vec<u8> = (start..=end).map(|addr| bus.peek_byte(addr)).collect();
+    memory
+        .windows(pattern.len().max(1))
+        .enumerate()
+        .filter(|(_, window)| *window == pattern)
+        .map(|(offset, _)| start + offset as u16)
+        .collect()
+}
+
+// Hex, with or without $
+fn parse_hex(text: &str) -> Option<u16> {
+    u16::from_str_radix(text.strip_prefix('$').unwrap_or(text), 16).ok()
+}
+
+fn parse_byte(text: &str) -> Option<u8> {
+    parse_hex(text).and_then(|value| u8::try_from(value).ok())
+}
+
+// start <= end
+fn parse_range(start: &str, end: &str) -> Option<(u16, u16)> {
+    let (start, end) = (parse_hex(start)?, parse_hex(end)?);
+    (start <= end).then_some((start, end))
 }
@@ -0,0 +1,310 @@
+// Input movies (--record-movie / --play-movie).
+//
+// A movie is the power-on RAM seed plus every host input that reaches the
+// machine, each stamped with the `CPU::cycles` value it was applied at.
+// Host input only ever lands between instructions, so applying the same
+// events at the same cycles from the same starting state replays a session
+// bit-exactly, independent of host speed and frame pacing.
+//
+// File layout (little-endian, appended as events happen so a crash keeps
+// everything up to the last input):
+//
+//   "IICMOVIE"        8-byte magic
+//   version           u16
+//   ram seed          u64
+//   start state       length-prefixed save state; empty for a power-on start
+//   events...         u64 cycle + u8 kind + payload
+//
+// Not captured: serial/modem traffic, memory edits made from the monitor,
+// and disk images modified on the host between recording and playback.
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::cpu::CPU;
+use crate::savestate::{self, StateReader, StateWriter};
+
+pub const MOVIE_MAGIC: &[u8; 8] = b"IICMOVIE";
+pub const MOVIE_VERSION: u16 = 1;
+
+// Drives are numbered like the toolbar: 0-1 are 5.25", 2-3 are 3.5".
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    KeyDown { key: u16, code: u8 },
+    KeyUp { key: u16 },
+    MouseMove { dx: f64, dy: f64 },
+    MouseButton { button: u8, pressed: bool },
+    Paddle { paddle: u8, position: Option<u8> },
+    PaddleButton { button: u8, pressed: bool },
+    InsertDisk { drive: u8, path: String },
+    EjectDisk { drive: u8 },
+    WriteProtect { drive: u8 },
+    Col80Switch(bool),
+    ZipToggle,
+    ZipBootEscape,
+    Reset { hard: bool },
+    PowerCycle { ram_seed: u64 },
+}
+
+impl InputEvent {
+    fn write(&self, w: &mut StateWriter) {
+        match self {
+            InputEvent::KeyDown { key, code } => {
+                w.u8(0);
+                w.u16(*key);
+                w.u8(*code);
+            }
+            InputEvent::KeyUp { key } => {
+                w.u8(1);
+                w.u16(*key);
+            }
+            InputEvent::MouseMove { dx, dy } => {
+                w.u8(2);
+                w.u64(dx.to_bits());
+                w.u64(dy.to_bits());
+            }
+            InputEvent::MouseButton { button, pressed } => {
+                w.u8(3);
+                w.u8(*button);
+                w.bool(*pressed);
+            }
+            InputEvent::Paddle { paddle, position } => {
+                w.u8(4);
+                w.u8(*paddle);
+                w.opt_u8(*position);
+            }
+            InputEvent::PaddleButton { button, pressed } => {
+                w.u8(5);
+                w.u8(*button);
+                w.bool(*pressed);
+            }
+            InputEvent::InsertDisk { drive, path } => {
+                w.u8(6);
+                w.u8(*drive);
+                w.string(path);
+            }
+            InputEvent::EjectDisk { drive } => {
+                w.u8(7);
+                w.u8(*drive);
+            }
+            InputEvent::WriteProtect { drive } => {
+                w.u8(8);
+                w.u8(*drive);
+            }
+            InputEvent::Col80Switch(on) => {
+                w.u8(9);
+                w.bool(*on);
+            }
+            InputEvent::ZipToggle => w.u8(10),
+            InputEvent::ZipBootEscape => w.u8(11),
+            InputEvent::Reset { hard } => {
+                w.u8(12);
+                w.bool(*hard);
+            }
+            InputEvent::PowerCycle { ram_seed } => {
+                w.u8(13);
+                w.u64(*ram_seed);
+            }
+        }
+    }
+
+    fn read(r: &mut StateReader) -> Result<Self> {
+        Ok(match r.u8()? {
+            0 => InputEvent::KeyDown { key: r.u16()?, code: r.u8()? },
+            1 => InputEvent::KeyUp { key: r.u16()? },
+            2 => InputEvent::MouseMove { dx: f64::from_bits(r.u64()?), dy: f64::from_bits(r.u64()?) },
+            3 => InputEvent::MouseButton { button: r.u8()?, pressed: r.bool()? },
+            4 => InputEvent::Paddle { paddle: r.u8()?, position: r.opt_u8()? },
+            5 => InputEvent::PaddleButton { button: r.u8()?, pressed: r.bool()? },
+            6 => InputEvent::InsertDisk { drive: r.u8()?, path: r.string()? },
+            7 => InputEvent::EjectDisk { drive: r.u8()? },
+            8 => InputEvent::WriteProtect { drive: r.u8()? },
+            9 => InputEvent::Col80Switch(r.bool()?),
+            10 => InputEvent::ZipToggle,
+            11 => InputEvent::ZipBootEscape,
+            12 => InputEvent::Reset { hard: r.bool()? },
+            13 => InputEvent::PowerCycle { ram_seed: r.u64()? },
+            kind => bail!("movie: unknown event kind {}", kind),
+        })
+    }
+}
+
+// Apply one input to the machine. Live input and playback both go through
+// here so a replayed event has exactly the effect the recorded one had.
+pub fn apply(cpu: &mut CPU, event: &InputEvent) {
+    let cycles = cpu.bus.iou.cycles;
+    match event {
+        InputEvent::KeyDown { key, code } => cpu.bus.iou.keyboard.key_down(*key, *code, cycles),
+        InputEvent::KeyUp { key } => cpu.bus.iou.keyboard.key_up(*key, cycles),
+        InputEvent::MouseMove { dx, dy } => cpu.bus.iou.mouse.add_delta(*dx, *dy),
+        InputEvent::MouseButton { button, pressed } => cpu.bus.iou.mouse.set_button(*button as usize, *pressed),
+        InputEvent::Paddle { paddle, position } => cpu.bus.iou.paddle.set_position(*paddle, *position),
+        InputEvent::PaddleButton { button, pressed } => match button {
+            0 => cpu.bus.iou.paddle.button0.set(*pressed),
+            1 => cpu.bus.iou.paddle.button1.set(*pressed),
+            _ => {}
+        },
+        InputEvent::InsertDisk { drive, path } => {
+            let iwm = &mut cpu.bus.iou.iwm;
+            let result = match drive {
+                0 => iwm.load_disk(path),
+                1 => iwm.load_disk2(path),
+                2 => iwm.load_disk35(path),
+                3 => iwm.load_disk35_drive(1, path),
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                println!("Error loading disk: {}", e);
+            }
+        }
+        InputEvent::EjectDisk { drive } => match drive {
+            0 | 1 => cpu.bus.iou.iwm.eject_disk(*drive as usize),
+            2 | 3 => cpu.bus.iou.iwm.eject_disk_35(*drive as usize - 2),
+            _ => {}
+        },
+        InputEvent::WriteProtect { drive } => match drive {
+            0 | 1 => cpu.bus.iou.iwm.toggle_write_protect(*drive as usize),
+            2 | 3 => cpu.bus.iou.iwm.toggle_write_protect_35(*drive as usize - 2),
+            _ => {}
+        },
+        InputEvent::Col80Switch(on) => cpu.bus.iou.col80_switch = *on,
+        InputEvent::ZipToggle => cpu.bus.iou.zip.toggle(),
+        InputEvent::ZipBootEscape => cpu.bus.iou.zip.check_boot_escape(),
+        InputEvent::Reset { hard } => {
+            // Control-Command-Reset: clobber the power-up byte so the ROM cold starts
+            if *hard {
+                cpu.bus.write_byte(0x03F4, 0x00);
+            }
+            cpu.reset();
+        }
+        InputEvent::PowerCycle { ram_seed } => cpu.power_cycle(*ram_seed),
+    }
+}
+
+pub struct MovieRecorder {
+    out: File,
+    events: u64,
+}
+
+impl MovieRecorder {
+    // Start a movie from the machine's current state. At power-on only the
+    // RAM seed is needed; anywhere else the full state is embedded.
+    pub fn create<P: AsRef<Path>>(path: P, cpu: &CPU) -> Result<Self> {
+        let mut w = StateWriter::new();
+        w.array(MOVIE_MAGIC);
+        w.u16(MOVIE_VERSION);
+        w.u64(cpu.bus.ram_seed());
+        if cpu.cycles == 0 {
+            w.bytes(&[]);
+        } else {
+            w.bytes(&savestate::save_machine(cpu));
+        }
+
+        let mut out = File::create(path)?;
+        out.write_all(&w.into_bytes())?;
+        let mut recorder = Self { out, events: 0 };
+
+        // Disks mounted from the command line are part of the starting setup
+        let iwm = &cpu.bus.iou.iwm;
+        let mounted = (0..2).map(|d| iwm.disk_path(d)).chain((0..2).map(|d| iwm.disk_path_35(d)));
+        for (drive, path) in mounted.enumerate() {
+            if let Some(path) = path {
+                let event = InputEvent::InsertDisk { drive: drive as u8, path: path.to_string() };
+                recorder.record(cpu.cycles, &event)?;
+            }
+        }
+        Ok(recorder)
+    }
+
+    // Append one event. Written straight through so nothing is lost if the
+    // emulator dies mid-session, which is usually why the movie exists.
+    pub fn record(&mut self, cycle: u64, event: &InputEvent) -> Result<()> {
+        let mut w = StateWriter::new();
+        w.u64(cycle);
+        event.write(&mut w);
+        self.out.write_all(&w.into_bytes())?;
+        self.events += 1;
+        Ok(())
+    }
+
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+}
+
+pub struct Movie {
+    pub ram_seed: u64,
+    pub start_state: Vec<u8>,
+    pub events: Vec<(u64, InputEvent)>,
+}
+
+impl Movie {
+    // Anything unreadable at the end (recording killed mid-write) is dropped.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = fs::read(path)?;
+        let mut r = StateReader::new(&data);
+        let mut magic = [0u8; 8];
+        r.array(&mut magic)?;
+        if &magic != MOVIE_MAGIC {
+            bail!("not an Apple IIc input movie");
+        }
+        let version = r.u16()?;
+        if version != MOVIE_VERSION {
+            bail!("unsupported movie version {} (expected {})", version, MOVIE_VERSION);
+        }
+        let ram_seed = r.u64()?;
+        let start_state = r.bytes()?;
+
+        let mut events = Vec::new();
+        while !r.is_empty() {
+            match r.u64().and_then(|cycle| Ok((cycle, InputEvent::read(&mut r)?))) {
+                Ok(event) => events.push(event),
+                Err(e) => {
+                    println!("movie: dropping unreadable data after {} events ({})", events.len(), e);
+                    break;
+                }
+            }
+        }
+        Ok(Self { ram_seed, start_state, events })
+    }
+}
+
+pub struct MoviePlayer {
+    events: VecDeque<(u64, InputEvent)>,
+}
+
+impl MoviePlayer {
+    // Put the machine in the movie's starting state. The RAM seed must
+    // already have been applied before the CPU was initialized.
+    pub fn start(movie: Movie, cpu: &mut CPU) -> Result<Self> {
+        if !movie.start_state.is_empty() {
+            savestate::load_machine(cpu, &movie.start_state)?;
+            cpu.bus.iou.keyboard.release_all();
+        }
+        Ok(Self { events: movie.events.into() })
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // Apply every event stamped at or before the current cycle. Call before
+    // each instruction.
+    pub fn apply_due(&mut self, cpu: &mut CPU) {
+        while let Some((cycle, _)) = self.events.front() {
+            if *cycle > cpu.cycles {
+                break;
+            }
+            let (_, event) = self.events.pop_front().unwrap();
+            apply(cpu, &event);
+        }
+    }
+}
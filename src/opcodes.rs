@@ -0,0 +1,668 @@
+// 6502/65C02 opcode tables and instruction decoding
+//
+// Kept free of the rest of the emulator so the trace tools in src/bin can
+// decode instructions and show P too. The NMOS 6502 has a table of its
+// own, the undocumented opcodes included; the 65C02 variants share one and
+// differ only in the Rockwell bit instructions and WDC's WAI and STP.
+
+// The opcode map an instruction is decoded with
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InstructionSet {
+    Nmos,
+    Cmos,
+    // RMB/SMB/BBR/BBS
+    Rockwell,
+    // Rockwell's, plus WAI and STP
+    Wdc,
+}
+
+impl InstructionSet {
+    pub fn has_bit_instructions(self) -> bool {
+        matches!(self, InstructionSet::Rockwell | InstructionSet::Wdc)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AddressingMode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    ZeroPageIndirect,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+    IndirectAbsolute,
+    ZeroPageRelative,
+}
+
+impl AddressingMode {
+    pub fn operand_bytes(&self) -> usize {
+        match self {
+            AddressingMode::Implied | AddressingMode::Accumulator => 0,
+            AddressingMode::Immediate
+            | AddressingMode::ZeroPage
+            | AddressingMode::ZeroPageX
+            | AddressingMode::ZeroPageY
+            | AddressingMode::ZeroPageIndirect
+            | AddressingMode::Relative
+            | AddressingMode::IndirectX
+            | AddressingMode::IndirectY => 1,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect
+            | AddressingMode::IndirectAbsolute
+            | AddressingMode::ZeroPageRelative => 2,
+        }
+    }
+}
+
+// The 65C02 with every extension. The opcodes the 6502 left undefined are
+// NOPs of one to three bytes; without the bit instructions columns 7 and F
+// are one-byte NOPs too, and only WDC has WAI and STP.
+const CMOS_OPCODES: [(u8, &str, AddressingMode); 256] = [
+    (0x00, "BRK", AddressingMode::Implied),
+    (0x01, "ORA", AddressingMode::IndirectX),
+    (0x02, "NOP", AddressingMode::Immediate),
+    (0x03, "NOP", AddressingMode::Implied),
+    (0x04, "TSB", AddressingMode::ZeroPage),
+    (0x05, "ORA", AddressingMode::ZeroPage),
+    (0x06, "ASL", AddressingMode::ZeroPage),
+    (0x07, "RMB0", AddressingMode::ZeroPage),
+    (0x08, "PHP", AddressingMode::Implied),
+    (0x09, "ORA", AddressingMode::Immediate),
+    (0x0A, "ASL", AddressingMode::Accumulator),
+    (0x0B, "NOP", AddressingMode::Implied),
+    (0x0C, "TSB", AddressingMode::Absolute),
+    (0x0D, "ORA", AddressingMode::Absolute),
+    (0x0E, "ASL", AddressingMode::Absolute),
+    (0x0F, "BBR0", AddressingMode::ZeroPageRelative),
+    (0x10, "BPL", AddressingMode::Relative),
+    (0x11, "ORA", AddressingMode::IndirectY),
+    (0x12, "ORA", AddressingMode::ZeroPageIndirect),
+    (0x13, "NOP", AddressingMode::Implied),
+    (0x14, "TRB", AddressingMode::ZeroPage),
+    (0x15, "ORA", AddressingMode::ZeroPageX),
+    (0x16, "ASL", AddressingMode::ZeroPageX),
+    (0x17, "RMB1", AddressingMode::ZeroPage),
+    (0x18, "CLC", AddressingMode::Implied),
+    (0x19, "ORA", AddressingMode::AbsoluteY),
+    (0x1A, "INA", AddressingMode::Implied),
+    (0x1B, "NOP", AddressingMode::Implied),
+    (0x1C, "TRB", AddressingMode::Absolute),
+    (0x1D, "ORA", AddressingMode::AbsoluteX),
+    (0x1E, "ASL", AddressingMode::AbsoluteX),
+    (0x1F, "BBR1", AddressingMode::ZeroPageRelative),
+    (0x20, "JSR", AddressingMode::Absolute),
+    (0x21, "AND", AddressingMode::IndirectX),
+    (0x22, "NOP", AddressingMode::Immediate),
+    (0x23, "NOP", AddressingMode::Implied),
+    (0x24, "BIT", AddressingMode::ZeroPage),
+    (0x25, "AND", AddressingMode::ZeroPage),
+    (0x26, "ROL", AddressingMode::ZeroPage),
+    (0x27, "RMB2", AddressingMode::ZeroPage),
+    (0x28, "PLP", AddressingMode::Implied),
+    (0x29, "AND", AddressingMode::Immediate),
+    (0x2A, "ROL", AddressingMode::Accumulator),
+    (0x2B, "NOP", AddressingMode::Implied),
+    (0x2C, "BIT", AddressingMode::Absolute),
+    (0x2D, "AND", AddressingMode::Absolute),
+    (0x2E, "ROL", AddressingMode::Absolute),
+    (0x2F, "BBR2", AddressingMode::ZeroPageRelative),
+    (0x30, "BMI", AddressingMode::Relative),
+    (0x31, "AND", AddressingMode::IndirectY),
+    (0x32, "AND", AddressingMode::ZeroPageIndirect),
+    (0x33, "NOP", AddressingMode::Implied),
+    (0x34, "BIT", AddressingMode::ZeroPageX),
+    (0x35, "AND", AddressingMode::ZeroPageX),
+    (0x36, "ROL", AddressingMode::ZeroPageX),
+    (0x37, "RMB3", AddressingMode::ZeroPage),
+    (0x38, "SEC", AddressingMode::Implied),
+    (0x39, "AND", AddressingMode::AbsoluteY),
+    (0x3A, "DEA", AddressingMode::Implied),
+    (0x3B, "NOP", AddressingMode::Implied),
+    (0x3C, "BIT", AddressingMode::AbsoluteX),
+    (0x3D, "AND", AddressingMode::AbsoluteX),
+    (0x3E, "ROL", AddressingMode::AbsoluteX),
+    (0x3F, "BBR3", AddressingMode::ZeroPageRelative),
+    (0x40, "RTI", AddressingMode::Implied),
+    (0x41, "EOR", AddressingMode::IndirectX),
+    (0x42, "NOP", AddressingMode::Immediate),
+    (0x43, "NOP", AddressingMode::Implied),
+    (0x44, "NOP", AddressingMode::ZeroPage),
+    (0x45, "EOR", AddressingMode::ZeroPage),
+    (0x46, "LSR", AddressingMode::ZeroPage),
+    (0x47, "RMB4", AddressingMode::ZeroPage),
+    (0x48, "PHA", AddressingMode::Implied),
+    (0x49, "EOR", AddressingMode::Immediate),
+    (0x4A, "LSR", AddressingMode::Accumulator),
+    (0x4B, "NOP", AddressingMode::Implied),
+    (0x4C, "JMP", AddressingMode::Absolute),
+    (0x4D, "EOR", AddressingMode::Absolute),
+    (0x4E, "LSR", AddressingMode::Absolute),
+    (0x4F, "BBR4", AddressingMode::ZeroPageRelative),
+    (0x50, "BVC", AddressingMode::Relative),
+    (0x51, "EOR", AddressingMode::IndirectY),
+    (0x52, "EOR", AddressingMode::ZeroPageIndirect),
+    (0x53, "NOP", AddressingMode::Implied),
+    (0x54, "NOP", AddressingMode::ZeroPageX),
+    (0x55, "EOR", AddressingMode::ZeroPageX),
+    (0x56, "LSR", AddressingMode::ZeroPageX),
+    (0x57, "RMB5", AddressingMode::ZeroPage),
+    (0x58, "CLI", AddressingMode::Implied),
+    (0x59, "EOR", AddressingMode::AbsoluteY),
+    (0x5A, "PHY", AddressingMode::Implied),
+    (0x5B, "NOP", AddressingMode::Implied),
+    (0x5C, "NOP", AddressingMode::Absolute),
+    (0x5D, "EOR", AddressingMode::AbsoluteX),
+    (0x5E, "LSR", AddressingMode::AbsoluteX),
+    (0x5F, "BBR5", AddressingMode::ZeroPageRelative),
+    (0x60, "RTS", AddressingMode::Implied),
+    (0x61, "ADC", AddressingMode::IndirectX),
+    (0x62, "NOP", AddressingMode::Immediate),
+    (0x63, "NOP", AddressingMode::Implied),
+    (0x64, "STZ", AddressingMode::ZeroPage),
+    (0x65, "ADC", AddressingMode::ZeroPage),
+    (0x66, "ROR", AddressingMode::ZeroPage),
+    (0x67, "RMB6", AddressingMode::ZeroPage),
+    (0x68, "PLA", AddressingMode::Implied),
+    (0x69, "ADC", AddressingMode::Immediate),
+    (0x6A, "ROR", AddressingMode::Accumulator),
+    (0x6B, "NOP", AddressingMode::Implied),
+    (0x6C, "JMP", AddressingMode::Indirect),
+    (0x6D, "ADC", AddressingMode::Absolute),
+    (0x6E, "ROR", AddressingMode::Absolute),
+    (0x6F, "BBR6", AddressingMode::ZeroPageRelative),
+    (0x70, "BVS", AddressingMode::Relative),
+    (0x71, "ADC", AddressingMode::IndirectY),
+    (0x72, "ADC", AddressingMode::ZeroPageIndirect),
+    (0x73, "NOP", AddressingMode::Implied),
+    (0x74, "STZ", AddressingMode::ZeroPageX),
+    (0x75, "ADC", AddressingMode::ZeroPageX),
+    (0x76, "ROR", AddressingMode::ZeroPageX),
+    (0x77, "RMB7", AddressingMode::ZeroPage),
+    (0x78, "SEI", AddressingMode::Implied),
+    (0x79, "ADC", AddressingMode::AbsoluteY),
+    (0x7A, "PLY", AddressingMode::Implied),
+    (0x7B, "NOP", AddressingMode::Implied),
+    (0x7C, "JMP", AddressingMode::IndirectAbsolute),
+    (0x7D, "ADC", AddressingMode::AbsoluteX),
+    (0x7E, "ROR", AddressingMode::AbsoluteX),
+    (0x7F, "BBR7", AddressingMode::ZeroPageRelative),
+    (0x80, "BRA", AddressingMode::Relative),
+    (0x81, "STA", AddressingMode::IndirectX),
+    (0x82, "NOP", AddressingMode::Immediate),
+    (0x83, "NOP", AddressingMode::Implied),
+    (0x84, "STY", AddressingMode::ZeroPage),
+    (0x85, "STA", AddressingMode::ZeroPage),
+    (0x86, "STX", AddressingMode::ZeroPage),
+    (0x87, "SMB0", AddressingMode::ZeroPage),
+    (0x88, "DEY", AddressingMode::Implied),
+    (0x89, "BIT", AddressingMode::Immediate),
+    (0x8A, "TXA", AddressingMode::Implied),
+    (0x8B, "NOP", AddressingMode::Implied),
+    (0x8C, "STY", AddressingMode::Absolute),
+    (0x8D, "STA", AddressingMode::Absolute),
+    (0x8E, "STX", AddressingMode::Absolute),
+    (0x8F, "BBS0", AddressingMode::ZeroPageRelative),
+    (0x90, "BCC", AddressingMode::Relative),
+    (0x91, "STA", AddressingMode::IndirectY),
+    (0x92, "STA", AddressingMode::ZeroPageIndirect),
+    (0x93, "NOP", AddressingMode::Implied),
+    (0x94, "STY", AddressingMode::ZeroPageX),
+    (0x95, "STA", AddressingMode::ZeroPageX),
+    (0x96, "STX", AddressingMode::ZeroPageY),
+    (0x97, "SMB1", AddressingMode::ZeroPage),
+    (0x98, "TYA", AddressingMode::Implied),
+    (0x99, "STA", AddressingMode::AbsoluteY),
+    (0x9A, "TXS", AddressingMode::Implied),
+    (0x9B, "NOP", AddressingMode::Implied),
+    (0x9C, "STZ", AddressingMode::Absolute),
+    (0x9D, "STA", AddressingMode::AbsoluteX),
+    (0x9E, "STZ", AddressingMode::AbsoluteX),
+    (0x9F, "BBS1", AddressingMode::ZeroPageRelative),
+    (0xA0, "LDY", AddressingMode::Immediate),
+    (0xA1, "LDA", AddressingMode::IndirectX),
+    (0xA2, "LDX", AddressingMode::Immediate),
+    (0xA3, "NOP", AddressingMode::Implied),
+    (0xA4, "LDY", AddressingMode::ZeroPage),
+    (0xA5, "LDA", AddressingMode::ZeroPage),
+    (0xA6, "LDX", AddressingMode::ZeroPage),
+    (0xA7, "SMB2", AddressingMode::ZeroPage),
+    (0xA8, "TAY", AddressingMode::Implied),
+    (0xA9, "LDA", AddressingMode::Immediate),
+    (0xAA, "TAX", AddressingMode::Implied),
+    (0xAB, "NOP", AddressingMode::Implied),
+    (0xAC, "LDY", AddressingMode::Absolute),
+    (0xAD, "LDA", AddressingMode::Absolute),
+    (0xAE, "LDX", AddressingMode::Absolute),
+    (0xAF, "BBS2", AddressingMode::ZeroPageRelative),
+    (0xB0, "BCS", AddressingMode::Relative),
+    (0xB1, "LDA", AddressingMode::IndirectY),
+    (0xB2, "LDA", AddressingMode::ZeroPageIndirect),
+    (0xB3, "NOP", AddressingMode::Implied),
+    (0xB4, "LDY", AddressingMode::ZeroPageX),
+    (0xB5, "LDA", AddressingMode::ZeroPageX),
+    (0xB6, "LDX", AddressingMode::ZeroPageY),
+    (0xB7, "SMB3", AddressingMode::ZeroPage),
+    (0xB8, "CLV", AddressingMode::Implied),
+    (0xB9, "LDA", AddressingMode::AbsoluteY),
+    (0xBA, "TSX", AddressingMode::Implied),
+    (0xBB, "NOP", AddressingMode::Implied),
+    (0xBC, "LDY", AddressingMode::AbsoluteX),
+    (0xBD, "LDA", AddressingMode::AbsoluteX),
+    (0xBE, "LDX", AddressingMode::AbsoluteY),
+    (0xBF, "BBS3", AddressingMode::ZeroPageRelative),
+    (0xC0, "CPY", AddressingMode::Immediate),
+    (0xC1, "CMP", AddressingMode::IndirectX),
+    (0xC2, "NOP", AddressingMode::Immediate),
+    (0xC3, "NOP", AddressingMode::Implied),
+    (0xC4, "CPY", AddressingMode::ZeroPage),
+    (0xC5, "CMP", AddressingMode::ZeroPage),
+    (0xC6, "DEC", AddressingMode::ZeroPage),
+    (0xC7, "SMB4", AddressingMode::ZeroPage),
+    (0xC8, "INY", AddressingMode::Implied),
+    (0xC9, "CMP", AddressingMode::Immediate),
+    (0xCA, "DEX", AddressingMode::Implied),
+    (0xCB, "WAI", AddressingMode::Implied),
+    (0xCC, "CPY", AddressingMode::Absolute),
+    (0xCD, "CMP", AddressingMode::Absolute),
+    (0xCE, "DEC", AddressingMode::Absolute),
+    (0xCF, "BBS4", AddressingMode::ZeroPageRelative),
+    (0xD0, "BNE", AddressingMode::Relative),
+    (0xD1, "CMP", AddressingMode::IndirectY),
+    (0xD2, "CMP", AddressingMode::ZeroPageIndirect),
+    (0xD3, "NOP", AddressingMode::Implied),
+    (0xD4, "NOP", AddressingMode::ZeroPageX),
+    (0xD5, "CMP", AddressingMode::ZeroPageX),
+    (0xD6, "DEC", AddressingMode::ZeroPageX),
+    (0xD7, "SMB5", AddressingMode::ZeroPage),
+    (0xD8, "CLD", AddressingMode::Implied),
+    (0xD9, "CMP", AddressingMode::AbsoluteY),
+    (0xDA, "PHX", AddressingMode::Implied),
+    (0xDB, "STP", AddressingMode::Implied),
+    (0xDC, "NOP", AddressingMode::Absolute),
+    (0xDD, "CMP", AddressingMode::AbsoluteX),
+    (0xDE, "DEC", AddressingMode::AbsoluteX),
+    (0xDF, "BBS5", AddressingMode::ZeroPageRelative),
+    (0xE0, "CPX", AddressingMode::Immediate),
+    (0xE1, "SBC", AddressingMode::IndirectX),
+    (0xE2, "NOP", AddressingMode::Immediate),
+    (0xE3, "NOP", AddressingMode::Implied),
+    (0xE4, "CPX", AddressingMode::ZeroPage),
+    (0xE5, "SBC", AddressingMode::ZeroPage),
+    (0xE6, "INC", AddressingMode::ZeroPage),
+    (0xE7, "SMB6", AddressingMode::ZeroPage),
+    (0xE8, "INX", AddressingMode::Implied),
+    (0xE9, "SBC", AddressingMode::Immediate),
+    (0xEA, "NOP", AddressingMode::Implied),
+    (0xEB, "NOP", AddressingMode::Implied),
+    (0xEC, "CPX", AddressingMode::Absolute),
+    (0xED, "SBC", AddressingMode::Absolute),
+    (0xEE, "INC", AddressingMode::Absolute),
+    (0xEF, "BBS6", AddressingMode::ZeroPageRelative),
+    (0xF0, "BEQ", AddressingMode::Relative),
+    (0xF1, "SBC", AddressingMode::IndirectY),
+    (0xF2, "SBC", AddressingMode::ZeroPageIndirect),
+    (0xF3, "NOP", AddressingMode::Implied),
+    (0xF4, "NOP", AddressingMode::ZeroPageX),
+    (0xF5, "SBC", AddressingMode::ZeroPageX),
+    (0xF6, "INC", AddressingMode::ZeroPageX),
+    (0xF7, "SMB7", AddressingMode::ZeroPage),
+    (0xF8, "SED", AddressingMode::Implied),
+    (0xF9, "SBC", AddressingMode::AbsoluteY),
+    (0xFA, "PLX", AddressingMode::Implied),
+    (0xFB, "NOP", AddressingMode::Implied),
+    (0xFC, "NOP", AddressingMode::Absolute),
+    (0xFD, "SBC", AddressingMode::AbsoluteX),
+    (0xFE, "INC", AddressingMode::AbsoluteX),
+    (0xFF, "BBS7", AddressingMode::ZeroPageRelative),
+];
+
+// The NMOS 6502, undocumented opcodes included, with the names in common
+// use for them. The JAMs lock the CPU up.
+const NMOS_OPCODES: [(u8, &str, AddressingMode); 256] = [
+    (0x00, "BRK", AddressingMode::Implied),
+    (0x01, "ORA", AddressingMode::IndirectX),
+    (0x02, "JAM", AddressingMode::Implied),
+    (0x03, "SLO", AddressingMode::IndirectX),
+    (0x04, "NOP", AddressingMode::ZeroPage),
+    (0x05, "ORA", AddressingMode::ZeroPage),
+    (0x06, "ASL", AddressingMode::ZeroPage),
+    (0x07, "SLO", AddressingMode::ZeroPage),
+    (0x08, "PHP", AddressingMode::Implied),
+    (0x09, "ORA", AddressingMode::Immediate),
+    (0x0A, "ASL", AddressingMode::Accumulator),
+    (0x0B, "ANC", AddressingMode::Immediate),
+    (0x0C, "NOP", AddressingMode::Absolute),
+    (0x0D, "ORA", AddressingMode::Absolute),
+    (0x0E, "ASL", AddressingMode::Absolute),
+    (0x0F, "SLO", AddressingMode::Absolute),
+    (0x10, "BPL", AddressingMode::Relative),
+    (0x11, "ORA", AddressingMode::IndirectY),
+    (0x12, "JAM", AddressingMode::Implied),
+    (0x13, "SLO", AddressingMode::IndirectY),
+    (0x14, "NOP", AddressingMode::ZeroPageX),
+    (0x15, "ORA", AddressingMode::ZeroPageX),
+    (0x16, "ASL", AddressingMode::ZeroPageX),
+    (0x17, "SLO", AddressingMode::ZeroPageX),
+    (0x18, "CLC", AddressingMode::Implied),
+    (0x19, "ORA", AddressingMode::AbsoluteY),
+    (0x1A, "NOP", AddressingMode::Implied),
+    (0x1B, "SLO", AddressingMode::AbsoluteY),
+    (0x1C, "NOP", AddressingMode::AbsoluteX),
+    (0x1D, "ORA", AddressingMode::AbsoluteX),
+    (0x1E, "ASL", AddressingMode::AbsoluteX),
+    (0x1F, "SLO", AddressingMode::AbsoluteX),
+    (0x20, "JSR", AddressingMode::Absolute),
+    (0x21, "AND", AddressingMode::IndirectX),
+    (0x22, "JAM", AddressingMode::Implied),
+    (0x23, "RLA", AddressingMode::IndirectX),
+    (0x24, "BIT", AddressingMode::ZeroPage),
+    (0x25, "AND", AddressingMode::ZeroPage),
+    (0x26, "ROL", AddressingMode::ZeroPage),
+    (0x27, "RLA", AddressingMode::ZeroPage),
+    (0x28, "PLP", AddressingMode::Implied),
+    (0x29, "AND", AddressingMode::Immediate),
+    (0x2A, "ROL", AddressingMode::Accumulator),
+    (0x2B, "ANC", AddressingMode::Immediate),
+    (0x2C, "BIT", AddressingMode::Absolute),
+    (0x2D, "AND", AddressingMode::Absolute),
+    (0x2E, "ROL", AddressingMode::Absolute),
+    (0x2F, "RLA", AddressingMode::Absolute),
+    (0x30, "BMI", AddressingMode::Relative),
+    (0x31, "AND", AddressingMode::IndirectY),
+    (0x32, "JAM", AddressingMode::Implied),
+    (0x33, "RLA", AddressingMode::IndirectY),
+    (0x34, "NOP", AddressingMode::ZeroPageX),
+    (0x35, "AND", AddressingMode::ZeroPageX),
+    (0x36, "ROL", AddressingMode::ZeroPageX),
+    (0x37, "RLA", AddressingMode::ZeroPageX),
+    (0x38, "SEC", AddressingMode::Implied),
+    (0x39, "AND", AddressingMode::AbsoluteY),
+    (0x3A, "NOP", AddressingMode::Implied),
+    (0x3B, "RLA", AddressingMode::AbsoluteY),
+    (0x3C, "NOP", AddressingMode::AbsoluteX),
+    (0x3D, "AND", AddressingMode::AbsoluteX),
+    (0x3E, "ROL", AddressingMode::AbsoluteX),
+    (0x3F, "RLA", AddressingMode::AbsoluteX),
+    (0x40, "RTI", AddressingMode::Implied),
+    (0x41, "EOR", AddressingMode::IndirectX),
+    (0x42, "JAM", AddressingMode::Implied),
+    (0x43, "SRE", AddressingMode::IndirectX),
+    (0x44, "NOP", AddressingMode::ZeroPage),
+    (0x45, "EOR", AddressingMode::ZeroPage),
+    (0x46, "LSR", AddressingMode::ZeroPage),
+    (0x47, "SRE", AddressingMode::ZeroPage),
+    (0x48, "PHA", AddressingMode::Implied),
+    (0x49, "EOR", AddressingMode::Immediate),
+    (0x4A, "LSR", AddressingMode::Accumulator),
+    (0x4B, "ALR", AddressingMode::Immediate),
+    (0x4C, "JMP", AddressingMode::Absolute),
+    (0x4D, "EOR", AddressingMode::Absolute),
+    (0x4E, "LSR", AddressingMode::Absolute),
+    (0x4F, "SRE", AddressingMode::Absolute),
+    (0x50, "BVC", AddressingMode::Relative),
+    (0x51, "EOR", AddressingMode::IndirectY),
+    (0x52, "JAM", AddressingMode::Implied),
+    (0x53, "SRE", AddressingMode::IndirectY),
+    (0x54, "NOP", AddressingMode::ZeroPageX),
+    (0x55, "EOR", AddressingMode::ZeroPageX),
+    (0x56, "LSR", AddressingMode::ZeroPageX),
+    (0x57, "SRE", AddressingMode::ZeroPageX),
+    (0x58, "CLI", AddressingMode::Implied),
+    (0x59, "EOR", AddressingMode::AbsoluteY),
+    (0x5A, "NOP", AddressingMode::Implied),
+    (0x5B, "SRE", AddressingMode::AbsoluteY),
+    (0x5C, "NOP", AddressingMode::AbsoluteX),
+    (0x5D, "EOR", AddressingMode::AbsoluteX),
+    (0x5E, "LSR", AddressingMode::AbsoluteX),
+    (0x5F, "SRE", AddressingMode::AbsoluteX),
+    (0x60, "RTS", AddressingMode::Implied),
+    (0x61, "ADC", AddressingMode::IndirectX),
+    (0x62, "JAM", AddressingMode::Implied),
+    (0x63, "RRA", AddressingMode::IndirectX),
+    (0x64, "NOP", AddressingMode::ZeroPage),
+    (0x65, "ADC", AddressingMode::ZeroPage),
+    (0x66, "ROR", AddressingMode::ZeroPage),
+    (0x67, "RRA", AddressingMode::ZeroPage),
+    (0x68, "PLA", AddressingMode::Implied),
+    (0x69, "ADC", AddressingMode::Immediate),
+    (0x6A, "ROR", AddressingMode::Accumulator),
+    (0x6B, "ARR", AddressingMode::Immediate),
+    (0x6C, "JMP", AddressingMode::Indirect),
+    (0x6D, "ADC", AddressingMode::Absolute),
+    (0x6E, "ROR", AddressingMode::Absolute),
+    (0x6F, "RRA", AddressingMode::Absolute),
+    (0x70, "BVS", AddressingMode::Relative),
+    (0x71, "ADC", AddressingMode::IndirectY),
+    (0x72, "JAM", AddressingMode::Implied),
+    (0x73, "RRA", AddressingMode::IndirectY),
+    (0x74, "NOP", AddressingMode::ZeroPageX),
+    (0x75, "ADC", AddressingMode::ZeroPageX),
+    (0x76, "ROR", AddressingMode::ZeroPageX),
+    (0x77, "RRA", AddressingMode::ZeroPageX),
+    (0x78, "SEI", AddressingMode::Implied),
+    (0x79, "ADC", AddressingMode::AbsoluteY),
+    (0x7A, "NOP", AddressingMode::Implied),
+    (0x7B, "RRA", AddressingMode::AbsoluteY),
+    (0x7C, "NOP", AddressingMode::AbsoluteX),
+    (0x7D, "ADC", AddressingMode::AbsoluteX),
+    (0x7E, "ROR", AddressingMode::AbsoluteX),
+    (0x7F, "RRA", AddressingMode::AbsoluteX),
+    (0x80, "NOP", AddressingMode::Immediate),
+    (0x81, "STA", AddressingMode::IndirectX),
+    (0x82, "NOP", AddressingMode::Immediate),
+    (0x83, "SAX", AddressingMode::IndirectX),
+    (0x84, "STY", AddressingMode::ZeroPage),
+    (0x85, "STA", AddressingMode::ZeroPage),
+    (0x86, "STX", AddressingMode::ZeroPage),
+    (0x87, "SAX", AddressingMode::ZeroPage),
+    (0x88, "DEY", AddressingMode::Implied),
+    (0x89, "NOP", AddressingMode::Immediate),
+    (0x8A, "TXA", AddressingMode::Implied),
+    (0x8B, "ANE", AddressingMode::Immediate),
+    (0x8C, "STY", AddressingMode::Absolute),
+    (0x8D, "STA", AddressingMode::Absolute),
+    (0x8E, "STX", AddressingMode::Absolute),
+    (0x8F, "SAX", AddressingMode::Absolute),
+    (0x90, "BCC", AddressingMode::Relative),
+    (0x91, "STA", AddressingMode::IndirectY),
+    (0x92, "JAM", AddressingMode::Implied),
+    (0x93, "SHA", AddressingMode::IndirectY),
+    (0x94, "STY", AddressingMode::ZeroPageX),
+    (0x95, "STA", AddressingMode::ZeroPageX),
+    (0x96, "STX", AddressingMode::ZeroPageY),
+    (0x97, "SAX", AddressingMode::ZeroPageY),
+    (0x98, "TYA", AddressingMode::Implied),
+    (0x99, "STA", AddressingMode::AbsoluteY),
+    (0x9A, "TXS", AddressingMode::Implied),
+    (0x9B, "TAS", AddressingMode::AbsoluteY),
+    (0x9C, "SHY", AddressingMode::AbsoluteX),
+    (0x9D, "STA", AddressingMode::AbsoluteX),
+    (0x9E, "SHX", AddressingMode::AbsoluteY),
+    (0x9F, "SHA", AddressingMode::AbsoluteY),
+    (0xA0, "LDY", AddressingMode::Immediate),
+    (0xA1, "LDA", AddressingMode::IndirectX),
+    (0xA2, "LDX", AddressingMode::Immediate),
+    (0xA3, "LAX", AddressingMode::IndirectX),
+    (0xA4, "LDY", AddressingMode::ZeroPage),
+    (0xA5, "LDA", AddressingMode::ZeroPage),
+    (0xA6, "LDX", AddressingMode::ZeroPage),
+    (0xA7, "LAX", AddressingMode::ZeroPage),
+    (0xA8, "TAY", AddressingMode::Implied),
+    (0xA9, "LDA", AddressingMode::Immediate),
+    (0xAA, "TAX", AddressingMode::Implied),
+    (0xAB, "LXA", AddressingMode::Immediate),
+    (0xAC, "LDY", AddressingMode::Absolute),
+    (0xAD, "LDA", AddressingMode::Absolute),
+    (0xAE, "LDX", AddressingMode::Absolute),
+    (0xAF, "LAX", AddressingMode::Absolute),
+    (0xB0, "BCS", AddressingMode::Relative),
+    (0xB1, "LDA", AddressingMode::IndirectY),
+    (0xB2, "JAM", AddressingMode::Implied),
+    (0xB3, "LAX", AddressingMode::IndirectY),
+    (0xB4, "LDY", AddressingMode::ZeroPageX),
+    (0xB5, "LDA", AddressingMode::ZeroPageX),
+    (0xB6, "LDX", AddressingMode::ZeroPageY),
+    (0xB7, "LAX", AddressingMode::ZeroPageY),
+    (0xB8, "CLV", AddressingMode::Implied),
+    (0xB9, "LDA", AddressingMode::AbsoluteY),
+    (0xBA, "TSX", AddressingMode::Implied),
+    (0xBB, "LAS", AddressingMode::AbsoluteY),
+    (0xBC, "LDY", AddressingMode::AbsoluteX),
+    (0xBD, "LDA", AddressingMode::AbsoluteX),
+    (0xBE, "LDX", AddressingMode::AbsoluteY),
+    (0xBF, "LAX", AddressingMode::AbsoluteY),
+    (0xC0, "CPY", AddressingMode::Immediate),
+    (0xC1, "CMP", AddressingMode::IndirectX),
+    (0xC2, "NOP", AddressingMode::Immediate),
+    (0xC3, "DCP", AddressingMode::IndirectX),
+    (0xC4, "CPY", AddressingMode::ZeroPage),
+    (0xC5, "CMP", AddressingMode::ZeroPage),
+    (0xC6, "DEC", AddressingMode::ZeroPage),
+    (0xC7, "DCP", AddressingMode::ZeroPage),
+    (0xC8, "INY", AddressingMode::Implied),
+    (0xC9, "CMP", AddressingMode::Immediate),
+    (0xCA, "DEX", AddressingMode::Implied),
+    (0xCB, "SBX", AddressingMode::Immediate),
+    (0xCC, "CPY", AddressingMode::Absolute),
+    (0xCD, "CMP", AddressingMode::Absolute),
+    (0xCE, "DEC", AddressingMode::Absolute),
+    (0xCF, "DCP", AddressingMode::Absolute),
+    (0xD0, "BNE", AddressingMode::Relative),
+    (0xD1, "CMP", AddressingMode::IndirectY),
+    (0xD2, "JAM", AddressingMode::Implied),
+    (0xD3, "DCP", AddressingMode::IndirectY),
+    (0xD4, "NOP", AddressingMode::ZeroPageX),
+    (0xD5, "CMP", AddressingMode::ZeroPageX),
+    (0xD6, "DEC", AddressingMode::ZeroPageX),
+    (0xD7, "DCP", AddressingMode::ZeroPageX),
+    (0xD8, "CLD", AddressingMode::Implied),
+    (0xD9, "CMP", AddressingMode::AbsoluteY),
+    (0xDA, "NOP", AddressingMode::Implied),
+    (0xDB, "DCP", AddressingMode::AbsoluteY),
+    (0xDC, "NOP", AddressingMode::AbsoluteX),
+    (0xDD, "CMP", AddressingMode::AbsoluteX),
+    (0xDE, "DEC", AddressingMode::AbsoluteX),
+    (0xDF, "DCP", AddressingMode::AbsoluteX),
+    (0xE0, "CPX", AddressingMode::Immediate),
+    (0xE1, "SBC", AddressingMode::IndirectX),
+    (0xE2, "NOP", AddressingMode::Immediate),
+    (0xE3, "ISC", AddressingMode::IndirectX),
+    (0xE4, "CPX", AddressingMode::ZeroPage),
+    (0xE5, "SBC", AddressingMode::ZeroPage),
+    (0xE6, "INC", AddressingMode::ZeroPage),
+    (0xE7, "ISC", AddressingMode::ZeroPage),
+    (0xE8, "INX", AddressingMode::Implied),
+    (0xE9, "SBC", AddressingMode::Immediate),
+    (0xEA, "NOP", AddressingMode::Implied),
+    (0xEB, "SBC", AddressingMode::Immediate),
+    (0xEC, "CPX", AddressingMode::Absolute),
+    (0xED, "SBC", AddressingMode::Absolute),
+    (0xEE, "INC", AddressingMode::Absolute),
+    (0xEF, "ISC", AddressingMode::Absolute),
+    (0xF0, "BEQ", AddressingMode::Relative),
+    (0xF1, "SBC", AddressingMode::IndirectY),
+    (0xF2, "JAM", AddressingMode::Implied),
+    (0xF3, "ISC", AddressingMode::IndirectY),
+    (0xF4, "NOP", AddressingMode::ZeroPageX),
+    (0xF5, "SBC", AddressingMode::ZeroPageX),
+    (0xF6, "INC", AddressingMode::ZeroPageX),
+    (0xF7, "ISC", AddressingMode::ZeroPageX),
+    (0xF8, "SED", AddressingMode::Implied),
+    (0xF9, "SBC", AddressingMode::AbsoluteY),
+    (0xFA, "NOP", AddressingMode::Implied),
+    (0xFB, "ISC", AddressingMode::AbsoluteY),
+    (0xFC, "NOP", AddressingMode::AbsoluteX),
+    (0xFD, "SBC", AddressingMode::AbsoluteX),
+    (0xFE, "INC", AddressingMode::AbsoluteX),
+    (0xFF, "ISC", AddressingMode::AbsoluteX),
+];
+
+// Mnemonic and addressing mode of an opcode
+pub fn lookup(opcode: u8, set: InstructionSet) -> (&'static str, AddressingMode) {
+    if set == InstructionSet::Nmos {
+        let (_, mnemonic, mode) = NMOS_OPCODES[opcode as usize];
+        return (mnemonic, mode);
+    }
+    if opcode & 0x07 == 0x07 && !set.has_bit_instructions() {
+        return ("NOP", AddressingMode::Implied);
+    }
+    if matches!(opcode, 0xCB | 0xDB) && set != InstructionSet::Wdc {
+        return ("NOP", AddressingMode::Implied);
+    }
+    let (_, mnemonic, mode) = CMOS_OPCODES[opcode as usize];
+    (mnemonic, mode)
+}
+
+// Mnemonic, formatted operand and length of the instruction whose
+// bytes start at addr
+pub fn decode(addr: u16, bytes: [u8; 3], set: InstructionSet) -> (&'static str, String, usize) {
+    let (mnemonic, mode) = lookup(bytes[0], set);
+    let operand = match mode.operand_bytes() {
+        0 => String::new(),
+        1 => format_operands(addr, mode, bytes[1], 0x00),
+        _ => format_operands(addr, mode, bytes[1], bytes[2]),
+    };
+    (mnemonic, operand, 1 + mode.operand_bytes())
+}
+
+pub fn format_operands(addr: u16, mode: AddressingMode, operand1: u8, operand2: u8) -> String {
+    match mode {
+        AddressingMode::Implied => String::new(),
+        AddressingMode::Accumulator => "A".to_string(),
+        AddressingMode::Immediate => format!("#${:02X}", operand1),
+        AddressingMode::ZeroPage => format!("${:02X}", operand1),
+        AddressingMode::ZeroPageX => format!("${:02X},X", operand1),
+        AddressingMode::ZeroPageY => format!("${:02X},Y", operand1),
+        AddressingMode::ZeroPageIndirect => format!("(${:02X})", operand1),
+        AddressingMode::Absolute => {
+            format!("${:04X}", (operand2 as u16) << 8 | operand1 as u16)
+        }
+        AddressingMode::AbsoluteX => {
+            format!("${:04X},X", (operand2 as u16) << 8 | operand1 as u16)
+        }
+        AddressingMode::AbsoluteY => {
+            format!("${:04X},Y", (operand2 as u16) << 8 | operand1 as u16)
+        }
+        AddressingMode::Indirect => {
+            format!("(${:04X})", (operand2 as u16) << 8 | operand1 as u16)
+        }
+        AddressingMode::IndirectX => format!("(${:02X},X)", operand1),
+        AddressingMode::IndirectY => format!("(${:02X}),Y", operand1),
+        AddressingMode::IndirectAbsolute => {
+            format!("(${:04X},X)", (operand2 as u16) << 8 | operand1 as u16)
+        }
+        AddressingMode::Relative => {
+            let offset = operand1 as i8;
+            let target = addr.wrapping_add(2).wrapping_add(offset as u16);
+            format!("${:04X}", target)
+        }
+        AddressingMode::ZeroPageRelative => {
+            let offset = operand2 as i8;
+            let target = addr.wrapping_add(3).wrapping_add(offset as u16);
+            format!("${:02X},${:04X}", operand1, target)
+        }
+    }
+}
+
+// P as "NV-BDIZC", upper case for the flags that are set
+pub fn format_flags(p: u8) -> String {
+    "NV-BDIZC"
+        .chars()
+        .enumerate()
+        .map(|(i, name)| match (name, p & (0x80 >> i) != 0) {
+            ('-', _) => '-',
+            (name, true) => name,
+            (name, false) => name.to_ascii_lowercase(),
+        })
+        .collect()
+}
@@ -0,0 +1,252 @@
+// Execution profiler
+//
+// Counts the instructions run and the cycles spent at each PC, kept apart
+// for each memory bank so the same address in main RAM, aux RAM, an LC bank
+// or a ROM bank counts as different code. Cycles also go to the call stack
+// they were spent under, built from JSR and interrupt entry: that gives the
+// call graph and a collapsed-stack file for flame graph tools
+// (`flamegraph.pl`, `inferno-flamegraph`), one `top;caller;callee cycles`
+// line per stack.
+//
+// Returns are found from the stack pointer rather than by matching RTS to
+// JSR: a call is over once SP is back up to where it was before the call.
+// RTS, RTI, and code that drops a return address and jumps away all unwind
+// the same way.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::disassembler::SymbolTable;
+use crate::mmu::MemoryView;
+
+// A bank and address: where an instruction or a called routine is
+pub type CodeAddr = (Option<MemoryView>, u16);
+
+// Nested calls tracked; deeper ones are counted in the deepest frame
+const MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PcStats {
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+// A routine as reached through one chain of calls
+struct Node {
+    parent: usize,
+    routine: CodeAddr,
+    children: HashMap<CodeAddr, usize>,
+    calls: u64,
+    // Cycles spent in the routine itself, not in what it called
+    cycles: u64,
+}
+
+// One edge of the call graph
+pub struct Call {
+    pub caller: Option<CodeAddr>,
+    pub callee: CodeAddr,
+    pub calls: u64,
+    // Cycles in the callee and everything it called
+    pub cycles: u64,
+}
+
+pub struct Profiler {
+    stats: HashMap<CodeAddr, PcStats>,
+    // Call tree; node 0 is the code outside any call
+    nodes: Vec<Node>,
+    // Calls in progress: tree node, and SP before the call
+    frames: Vec<(usize, u8)>,
+    total: PcStats,
+    // Collapsed stacks written here by CPU::stop_profiler (--profile)
+    pub output: Option<PathBuf>,
+}
+
+impl Profiler {
+    pub fn new(output: Option<PathBuf>) -> Self {
+        let mut profiler = Self {
+            stats: HashMap::new(),
+            nodes: Vec::new(),
+            frames: Vec::new(),
+            total: PcStats::default(),
+            output,
+        };
+        profiler.reset();
+        profiler
+    }
+
+    // Forget everything counted so far
+    pub fn reset(&mut self) {
+        self.stats.clear();
+        self.nodes.clear();
+        self.nodes.push(Node { parent: 0, routine: (None, 0), children: HashMap::new(), calls: 0, cycles: 0 });
+        self.frames.clear();
+        self.total = PcStats::default();
+    }
+
+    pub fn total(&self) -> PcStats {
+        self.total
+    }
+
+    // An instruction at `at` took `cycles` and left SP at `sp`. A JSR
+    // names the routine it called, and the SP it was made with.
+    #[inline]
+    pub fn record(&mut self, at: CodeAddr, cycles: u64, sp: u8, call: Option<(CodeAddr, u8)>) {
+        let stats = self.stats.entry(at).or_default();
+        stats.instructions += 1;
+        stats.cycles += cycles;
+        self.total.instructions += 1;
+        self.total.cycles += cycles;
+        let node = self.current();
+        self.nodes[node].cycles += cycles;
+
+        match call {
+            Some((routine, caller_sp)) => self.enter(routine, caller_sp),
+            None => self.unwind(sp),
+        }
+    }
+
+    // The CPU took an interrupt, entering the handler at `handler` with
+    // SP at `sp` before the return address and status were pushed
+    pub fn interrupt(&mut self, handler: CodeAddr, cycles: u64, sp: u8) {
+        self.total.cycles += cycles;
+        self.enter(handler, sp);
+        let node = self.current();
+        self.nodes[node].cycles += cycles;
+    }
+
+    fn current(&self) -> usize {
+        self.frames.last().map_or(0, |&(node, _)| node)
+    }
+
+    fn enter(&mut self, routine: CodeAddr, caller_sp: u8) {
+        self.unwind(caller_sp);
+        if self.frames.len() >= MAX_DEPTH {
+            return;
+        }
+        let parent = self.current();
+        let node = match self.nodes[parent].children.get(&routine) {
+            Some(&node) => node,
+            None => {
+                let node = self.nodes.len();
+                self.nodes.push(Node { parent, routine, children: HashMap::new(), calls: 0, cycles: 0 });
+                self.nodes[parent].children.insert(routine, node);
+                node
+            }
+        };
+        self.nodes[node].calls += 1;
+        self.frames.push((node, caller_sp));
+    }
+
+    // End the calls SP has come back up past
+    fn unwind(&mut self, sp: u8) {
+        while self.frames.last().is_some_and(|&(_, caller_sp)| caller_sp <= sp) {
+            self.frames.pop();
+        }
+    }
+
+    // Addresses by cycles spent there, most first
+    pub fn hot_spots(&self, count: usize) -> Vec<(CodeAddr, PcStats)> {
+        let mut spots: Vec<_> = self.stats.iter().map(|(&at, &stats)| (at, stats)).collect();
+        spots.sort_unstable_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0 .1.cmp(&b.0 .1)));
+        spots.truncate(count);
+        spots
+    }
+
+    // Call graph edges by caller and callee, most cycles first; the caller
+    // is None for calls made outside any other call
+    pub fn calls(&self) -> Vec<Call> {
+        let mut inclusive = vec![0u64; self.nodes.len()];
+        // Children always come after their parent
+        for index in (1..self.nodes.len()).rev() {
+            inclusive[index] += self.nodes[index].cycles;
+            let parent = self.nodes[index].parent;
+            inclusive[parent] += inclusive[index];
+        }
+
+        let mut edges: HashMap<(Option<CodeAddr>, CodeAddr), (u64, u64)> = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate().skip(1) {
+            let caller = (node.parent != 0).then(|| self.nodes[node.parent].routine);
+            let edge = edges.entry((caller, node.routine)).or_default();
+            edge.0 += node.calls;
+            edge.1 += inclusive[index];
+        }
+        let mut calls: Vec<Call> = edges
+            .into_iter()
+            .map(|((caller, callee), (calls, cycles))| Call { caller, callee, calls, cycles })
+            .collect();
+        calls.sort_unstable_by_key(|call| std::cmp::Reverse(call.cycles));
+        calls
+    }
+
+    // Write one `top;caller;callee cycles` line for each call stack cycles
+    // were spent in; returns the number of lines
+    pub fn write_collapsed(&self, path: &Path, symbols: &SymbolTable) -> io::Result<usize> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let mut lines = 0;
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.cycles == 0 {
+                continue;
+            }
+            let mut stack = Vec::new();
+            let mut at = index;
+            while at != 0 {
+                stack.push(routine_name(self.nodes[at].routine, symbols));
+                at = self.nodes[at].parent;
+            }
+            stack.push("top".to_string());
+            stack.reverse();
+            writeln!(out, "{} {}", stack.join(";"), node.cycles)?;
+            lines += 1;
+        }
+        out.flush()?;
+        Ok(lines)
+    }
+
+    // The hot spot table as text, for the monitor
+    pub fn report(&self, count: usize, symbols: &SymbolTable) -> String {
+        let mut text = format!(
+            "{} instructions, {} cycles\n{:<8} {:>5}  {:<16} {:>10} {:>12} {:>6}\n",
+            self.total.instructions, self.total.cycles, "BANK", "ADDR", "LABEL", "INSTR", "CYCLES", "%"
+        );
+        for (at, stats) in self.hot_spots(count) {
+            text.push_str(&format!(
+                "{:<8} ${:04X}  {:<16} {:>10} {:>12} {:>5.1}%\n",
+                bank_name(at.0),
+                at.1,
+                symbols.lookup(at.1, at.0).unwrap_or(""),
+                stats.instructions,
+                stats.cycles,
+                self.percent(stats.cycles),
+            ));
+        }
+        text
+    }
+
+    // Share of all cycles counted
+    pub fn percent(&self, cycles: u64) -> f64 {
+        if self.total.cycles == 0 {
+            0.0
+        } else {
+            cycles as f64 * 100.0 / self.total.cycles as f64
+        }
+    }
+}
+
+pub fn bank_name(view: Option<MemoryView>) -> &'static str {
+    view.map_or("-", MemoryView::name)
+}
+
+// Label of a routine, or its address; qualified with the bank outside main RAM
+pub fn routine_name(routine: CodeAddr, symbols: &SymbolTable) -> String {
+    let (view, addr) = routine;
+    let name = match symbols.lookup(addr, view) {
+        Some(label) => label.to_string(),
+        None => format!("${:04X}", addr),
+    };
+    match view {
+        None | Some(MemoryView::Main) => name,
+        Some(view) => format!("{}:{}", view.name(), name),
+    }
+}
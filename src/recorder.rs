@@ -0,0 +1,220 @@
+// Audio/video recording in lockstep with emulated time.
+//
+// Every CYCLES_PER_FRAME of emulated time the recorder writes the rendered
+// framebuffer as a PNG (frame-NNNNNN.png) and appends to audio.wav exactly
+// as many samples as the frame's cycles span at the output rate. Frames are
+// checked for from the scanline loop, so fast disk, the ZIP chip and
+// --speed, which run several frames per host pass, still get every one of
+// them and the video stays as long as the audio. Audio comes from the
+// SampleTaps on the speaker, both Mockingboards and the drive synth, mixed
+// the same way AudioMixer mixes for playback. Because nothing depends on
+// wall time, a slow host makes the emulator run slower but never drops or
+// duplicates frames in the capture.
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::audio_mixer::soft_clip;
+use crate::cpu::CPU;
+use crate::screenshot;
+use crate::timing;
+
+// Source samples allowed to queue ahead of the output clock before the
+// oldest are dropped (sources round their per-frame sample counts up)
+const MAX_SOURCE_LAG: usize = 32;
+
+// First unused recording-NNNN directory in the working directory
+pub fn next_dir() -> PathBuf {
+    (1..)
+        .map(|n| PathBuf::from(format!("recording-{:04}", n)))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+// 16-bit stereo PCM WAV writer; sizes are patched in on finish
+struct WavWriter {
+    out: BufWriter<File>,
+    frames: u32,
+}
+
+impl WavWriter {
+    fn create(path: &Path, sample_rate: u32) -> Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let block_align = 4u16; // 2 channels * 16 bits
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&2u16.to_le_bytes())?;
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        out.write_all(&block_align.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        Ok(Self { out, frames: 0 })
+    }
+
+    fn write_frame(&mut self, left: f32, right: f32) -> Result<()> {
+        for s in [left, right] {
+            let v = (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.out.write_all(&v.to_le_bytes())?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        let data_len = self.frames * 4;
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + data_len).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&data_len.to_le_bytes())?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+pub struct Recorder {
+    dir: PathBuf,
+    wav: WavWriter,
+    sample_rate: u32,
+    video_frames: u64,
+    next_frame_cycle: u64,
+    last_cycle: u64,
+    cycles_recorded: u64,
+    samples_written: u64,
+
+    speaker: VecDeque<f32>,
+    mb1: VecDeque<(f32, f32)>,
+    mb2: VecDeque<(f32, f32)>,
+    drive: VecDeque<f32>,
+    mb_last: [f32; 4], // mb1 L/R, mb2 L/R
+}
+
+impl Recorder {
+    pub fn start(dir: &Path, cpu: &mut CPU) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let sample_rate = cpu.bus.iou.speaker.sample_rate();
+        let wav = WavWriter::create(&dir.join("audio.wav"), sample_rate)?;
+        set_taps(cpu, true);
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            wav,
+            sample_rate,
+            video_frames: 0,
+            next_frame_cycle: cpu.bus.iou.cycles + timing::CYCLES_PER_FRAME,
+            last_cycle: cpu.bus.iou.cycles,
+            cycles_recorded: 0,
+            samples_written: 0,
+            speaker: VecDeque::new(),
+            mb1: VecDeque::new(),
+            mb2: VecDeque::new(),
+            drive: VecDeque::new(),
+            mb_last: [0.0; 4],
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Record each frame whose emulated time has run out since the last call;
+    // call after every scanline. Returns the frames recorded.
+    pub fn capture_due(&mut self, cpu: &mut CPU) -> Result<u64> {
+        let cycle = cpu.bus.iou.cycles;
+        // A rewind or state load moved the clock back: frames count from there
+        if cycle < self.last_cycle {
+            self.last_cycle = cycle;
+            self.next_frame_cycle = cycle + timing::CYCLES_PER_FRAME;
+        }
+        let mut frames = 0;
+        while cycle >= self.next_frame_cycle {
+            let iou = &mut cpu.bus.iou;
+            iou.speaker.update(cycle);
+            iou.mockingboard.update(cycle);
+            iou.mockingboard2.update(cycle);
+            iou.iwm.update_drive_audio();
+            cpu.video_update();
+            self.capture_frame(cpu)?;
+            self.next_frame_cycle += timing::CYCLES_PER_FRAME;
+            frames += 1;
+        }
+        Ok(frames)
+    }
+
+    // Record one emulated frame. The framebuffer must already be rendered
+    // and the audio sources updated up to the current cycle.
+    fn capture_frame(&mut self, cpu: &mut CPU) -> Result<()> {
+        let path = self.dir.join(format!("frame-{:06}.png", self.video_frames));
+        screenshot::save_png(&cpu.bus.video, path)?;
+        self.video_frames += 1;
+
+        // Cycles can go backwards after a rewind or state load; count forward progress only
+        let cycle = cpu.bus.iou.cycles;
+        self.cycles_recorded += cycle.saturating_sub(self.last_cycle);
+        self.last_cycle = cycle;
+
+        let iou = &mut cpu.bus.iou;
+        self.speaker.extend(iou.speaker.tap.take());
+        self.mb1.extend(pairs(iou.mockingboard.tap.take()));
+        self.mb2.extend(pairs(iou.mockingboard2.tap.take()));
+        self.drive.extend(iou.iwm.drive_audio.tap.take());
+
+        let target = (self.cycles_recorded as f64 * self.sample_rate as f64 / timing::CYCLES_PER_SECOND) as u64;
+        while self.samples_written < target {
+            // Like the mixer: speaker and drive go silent on underrun,
+            // Mockingboards hold their last value to avoid clicks
+            let speaker = self.speaker.pop_front().unwrap_or(0.0);
+            let drive = self.drive.pop_front().unwrap_or(0.0);
+            if let Some((l, r)) = self.mb1.pop_front() {
+                self.mb_last[0] = l;
+                self.mb_last[1] = r;
+            }
+            if let Some((l, r)) = self.mb2.pop_front() {
+                self.mb_last[2] = l;
+                self.mb_last[3] = r;
+            }
+            let left = speaker + self.mb_last[0] + self.mb_last[2] + drive;
+            let right = speaker + self.mb_last[1] + self.mb_last[3] + drive;
+            self.wav.write_frame(soft_clip(left), soft_clip(right))?;
+            self.samples_written += 1;
+        }
+
+        trim(&mut self.speaker);
+        trim(&mut self.mb1);
+        trim(&mut self.mb2);
+        trim(&mut self.drive);
+        Ok(())
+    }
+
+    // Stop recording and finalize the WAV header. Returns frames written.
+    pub fn finish(self, cpu: &mut CPU) -> Result<u64> {
+        set_taps(cpu, false);
+        self.wav.finish()?;
+        Ok(self.video_frames)
+    }
+}
+
+fn set_taps(cpu: &mut CPU, enabled: bool) {
+    let iou = &mut cpu.bus.iou;
+    iou.speaker.tap.set_enabled(enabled);
+    iou.mockingboard.tap.set_enabled(enabled);
+    iou.mockingboard2.tap.set_enabled(enabled);
+    iou.iwm.drive_audio.tap.set_enabled(enabled);
+}
+
+fn pairs(samples: Vec<f32>) -> Vec<(f32, f32)> {
+    samples.chunks_exact(2).map(|c| (c[0], c[1])).collect()
+}
+
+fn trim<T>(queue: &mut VecDeque<T>) {
+    if queue.len() > MAX_SOURCE_LAG {
+        queue.drain(..queue.len() - MAX_SOURCE_LAG);
+    }
+}
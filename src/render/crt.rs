@@ -124,6 +124,7 @@ impl CrtRenderer {
     /// Scale factor: 420/192 = 2.1875, so correction = 2.1875/2.0 = 1.09375
     pub const CRT_ASPECT_CORRECTION: f32 = 1.09375;
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &wgpu::Device,
         surface_width: u32,
@@ -975,6 +976,7 @@ impl CrtRenderer {
         (texture, view, render_view)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn create_bind_group(
         device: &wgpu::Device,
         layout: &wgpu::BindGroupLayout,
@@ -1352,6 +1354,7 @@ impl CrtRenderer {
 
     /// Update the content rect and source dimensions based on actual blit geometry.
     /// Call each frame with the real position/size of the emulator content in the surface.
+    #[allow(clippy::too_many_arguments)]
     pub fn update_content_rect(
         &self, queue: &wgpu::Queue,
         surface_w: u32, surface_h: u32,
@@ -1458,6 +1461,7 @@ impl CrtRenderer {
 
     /// Get a reference to the intermediate texture view.
     /// The scaling renderer should render into this instead of the final surface.
+    #[allow(clippy::misnamed_getters)]
     pub fn intermediate_view(&self) -> &wgpu::TextureView {
         &self.intermediate_render_view
     }
@@ -21,7 +21,7 @@ fn rasterize_apple_label(text: &str) -> (usize, usize, Vec<u8>) {
     for (ci, ch) in text.chars().enumerate() {
         // Map ASCII to font ROM offset: uppercase A-Z at 0x40-0x5A, numbers/symbols at 0x20-0x3F
         let code = ch as u8;
-        let font_index = if code >= 0x20 && code <= 0x7F {
+        let font_index = if (0x20..=0x7F).contains(&code) {
             code as usize
         } else {
             0x20
@@ -60,6 +60,8 @@ pub struct ToolbarLabels {
     pub pwr: egui::TextureHandle,
     pub col80: egui::TextureHandle,
     pub col40: egui::TextureHandle,
+    pub sav: egui::TextureHandle,
+    pub lod: egui::TextureHandle,
 }
 
 impl ToolbarLabels {
@@ -76,6 +78,8 @@ impl ToolbarLabels {
             pwr: make_label(ctx, "lbl_pwr", "PWR"),
             col80: make_label(ctx, "lbl_80", "80"),
             col40: make_label(ctx, "lbl_40", "40"),
+            sav: make_label(ctx, "lbl_sav", "SAV"),
+            lod: make_label(ctx, "lbl_lod", "LOD"),
         }
     }
 }
@@ -125,6 +129,7 @@ pub fn blit_direct(frame: &mut [u8], src: &[u8]) {
 // Nearest-neighbor blit from src into frame at (dst_x, dst_y) scaled to (dst_w × dst_h).
 // Preserves pixel-perfect sharpness, each source pixel maps to an integer number of
 // destination pixels. Caller should ensure dst_w/dst_h are integer multiples of src_w/src_h.
+#[allow(clippy::too_many_arguments)]
 pub fn blit_nearest(
     frame: &mut [u8],
     frame_w: u32,
@@ -166,6 +171,8 @@ pub struct ToolbarAction {
     pub toggle_write_protect: Option<usize>,
     pub eject_disk: Option<usize>,
     pub toggle_pause: bool,
+    pub save_state: bool,
+    pub load_state: bool,
 }
 
 // Render the toolbar as an egui bottom panel overlay.
@@ -241,6 +248,28 @@ pub fn render_toolbar_ui(
                     action.toggle_col80 = true;
                 }
 
+                // Save state button
+                let sav_img = egui::Image::new(&labels.sav)
+                    .fit_to_exact_size(egui::vec2(labels.sav.size()[0] as f32, labels.sav.size()[1] as f32))
+                    .tint(egui::Color32::WHITE);
+                if ui
+                    .add(egui::Button::image(sav_img).min_size(egui::vec2(32.0, 32.0)))
+                    .clicked()
+                {
+                    action.save_state = true;
+                }
+
+                // Load state button
+                let lod_img = egui::Image::new(&labels.lod)
+                    .fit_to_exact_size(egui::vec2(labels.lod.size()[0] as f32, labels.lod.size()[1] as f32))
+                    .tint(egui::Color32::WHITE);
+                if ui
+                    .add(egui::Button::image(lod_img).min_size(egui::vec2(32.0, 32.0)))
+                    .clicked()
+                {
+                    action.load_state = true;
+                }
+
                 // push drive icons to the right
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     ui.spacing_mut().item_spacing.x = 4.0;
@@ -42,6 +42,7 @@ impl LcdRenderer {
     /// which gives us 16/25 = 0.64
     pub const LCD_ASPECT_CORRECTION: f32 = 0.64;
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &wgpu::Device,
         surface_width: u32,
@@ -312,6 +313,8 @@ impl LcdRenderer {
 }
 
 impl PostProcessor for LcdRenderer {
+    // The sampled view feeds the passes; callers render into the target view
+    #[allow(clippy::misnamed_getters)]
     fn intermediate_view(&self) -> &wgpu::TextureView {
         &self.intermediate_render_view
     }
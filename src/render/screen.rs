@@ -12,6 +12,7 @@ pub trait PostProcessor {
     );
 
     /// Update the content rect and source dimensions based on actual blit geometry.
+    #[allow(clippy::too_many_arguments)]
     fn update_content_rect(
         &self,
         queue: &wgpu::Queue,
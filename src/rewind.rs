@@ -0,0 +1,93 @@
+// Rewind buffer.
+//
+// Keeps a ring of deflate-compressed save states, one per second of
+// emulated time, captured at the end of a frame in the GUI loop. While
+// the rewind key is held the newest snapshot is popped and restored every
+// few frames, so the machine steps backwards through the last N seconds.
+// Releasing the key resumes emulation from the restored point and new
+// snapshots start filling the ring again.
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, Result};
+
+use crate::cpu::CPU;
+use crate::savestate;
+use crate::timing;
+
+// Emulated cycles between snapshots
+const SNAPSHOT_INTERVAL_CYCLES: u64 = timing::CYCLES_PER_SECOND as u64;
+
+// Host frames between restores while the key is held (~6 seconds per second)
+const REWIND_STEP_FRAMES: u32 = 10;
+
+// Fast compression level; snapshots are taken while the emulator runs
+const COMPRESSION_LEVEL: u8 = 1;
+
+pub struct RewindBuffer {
+    snapshots: VecDeque<Vec<u8>>,
+    capacity: usize,
+    next_capture_cycle: u64,
+    hold_frames: u32,
+}
+
+impl RewindBuffer {
+    // `seconds` is the rewind depth; 0 disables capture entirely.
+    pub fn new(seconds: usize) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(seconds),
+            capacity: seconds,
+            next_capture_cycle: 0,
+            hold_frames: 0,
+        }
+    }
+
+    // Take a snapshot if a full interval has passed since the last one.
+    // Call once per frame after the frame's cycles have run.
+    pub fn capture(&mut self, cpu: &CPU) {
+        if self.capacity == 0 {
+            return;
+        }
+        // Clock moved backwards (quick-load, power cycle): restart the interval
+        if cpu.cycles + SNAPSHOT_INTERVAL_CYCLES < self.next_capture_cycle {
+            self.next_capture_cycle = cpu.cycles;
+        }
+        if cpu.cycles < self.next_capture_cycle {
+            return;
+        }
+        let state = savestate::save_machine(cpu);
+        let packed = miniz_oxide::deflate::compress_to_vec(&state, COMPRESSION_LEVEL);
+        if self.snapshots.len() >= self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(packed);
+        self.next_capture_cycle = cpu.cycles + SNAPSHOT_INTERVAL_CYCLES;
+    }
+
+    // Call once per frame while the rewind key is held instead of running
+    // the CPU. Returns true when a snapshot was restored this frame.
+    pub fn hold(&mut self, cpu: &mut CPU) -> Result<bool> {
+        let step = self.hold_frames.is_multiple_of(REWIND_STEP_FRAMES);
+        self.hold_frames = self.hold_frames.wrapping_add(1);
+        if !step {
+            return Ok(false);
+        }
+        // Keep the oldest snapshot so holding the key parks the machine there
+        let packed = match self.snapshots.len() {
+            0 => return Ok(false),
+            1 => self.snapshots[0].clone(),
+            _ => self.snapshots.pop_back().unwrap(),
+        };
+        let state = miniz_oxide::inflate::decompress_to_vec(&packed)
+            .map_err(|e| anyhow!("rewind snapshot corrupt: {:?}", e))?;
+        savestate::load_machine(cpu, &state)?;
+        cpu.bus.iou.keyboard.release_all();
+        self.next_capture_cycle = cpu.cycles + SNAPSHOT_INTERVAL_CYCLES;
+        Ok(true)
+    }
+
+    // Rewind key released: the next hold() restores immediately again.
+    pub fn release(&mut self) {
+        self.hold_frames = 0;
+    }
+}
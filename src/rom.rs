@@ -4,8 +4,119 @@ use std::io::{self, BufRead, BufReader, Read};
 use crate::cpu::SystemType;
 use crate::util::hexdump;
 
+// Offset of the $FBBF version byte in a //c ROM image (main bank first)
+const ID_BYTE_OFFSET: usize = 0xFBBF - 0xC000;
+
+// CRC32 of known-good dumps of every shipping //c ROM
+const KNOWN_ROMS: [(u32, RomRevision); 4] = [
+    (0xF0ED_AA1B, RomRevision::Rom255),
+    (0xC8B9_79B3, RomRevision::Rom0),
+    (0xBC5A_79FF, RomRevision::Rom3),
+    (0x06F5_3328, RomRevision::Rom4),
+];
+
+// Apple //c firmware revisions, named after the $FBBF version byte.
+//
+//   255  original 1984 ROM, 16K, no 3.5" support
+//   0    UniDisk 3.5 ROM, 32K (second bank via $C028)
+//   3    memory expansion ROM, adds the slot 4 RAM card firmware
+//   4    revised memory expansion ROM
+//   4X   community update of ROM 4 (same version byte, different checksum)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RomRevision {
+    Rom255,
+    Rom0,
+    Rom3,
+    Rom4,
+    Rom4X,
+}
+
+impl RomRevision {
+    pub const ALL: [RomRevision; 5] = [Self::Rom255, Self::Rom0, Self::Rom3, Self::Rom4, Self::Rom4X];
+
+    // Parse a --rom-type value; "auto" is handled by the caller
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "255" | "ff" => Some(Self::Rom255),
+            "0" => Some(Self::Rom0),
+            "3" => Some(Self::Rom3),
+            "4" => Some(Self::Rom4),
+            "4x" => Some(Self::Rom4X),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Rom255 => "ROM 255",
+            Self::Rom0 => "ROM 0",
+            Self::Rom3 => "ROM 3",
+            Self::Rom4 => "ROM 4",
+            Self::Rom4X => "ROM 4X",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|rev| rev.name() == name)
+    }
+
+    pub fn id_byte(&self) -> u8 {
+        match self {
+            Self::Rom255 => 0xFF,
+            Self::Rom0 => 0x00,
+            Self::Rom3 => 0x03,
+            Self::Rom4 | Self::Rom4X => 0x04,
+        }
+    }
+
+    pub fn from_id_byte(id: u8) -> Option<Self> {
+        match id {
+            0xFF => Some(Self::Rom255),
+            0x00 => Some(Self::Rom0),
+            0x03 => Some(Self::Rom3),
+            0x04 => Some(Self::Rom4),
+            _ => None,
+        }
+    }
+
+    // Image size in bytes. ROM 255 has a single 16K bank, so $C028
+    // (ROMBANK) does nothing on that machine.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Rom255 => 0x4000,
+            _ => 0x8000,
+        }
+    }
+
+    pub fn banks(&self) -> usize {
+        self.size() / 0x4000
+    }
+
+    // The memory expansion connector and its slot 4 firmware arrived with ROM 3
+    pub fn has_memexp(&self) -> bool {
+        matches!(self, Self::Rom3 | Self::Rom4 | Self::Rom4X)
+    }
+
+    // Identify an image by checksum, falling back to the version byte for
+    // dumps we have no checksum for. An unknown image claiming to be
+    // ROM 4 is taken to be 4X, the only modified ROM 4 in circulation.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        let crc = crc32fast::hash(bytes);
+        if let Some((_, rev)) = KNOWN_ROMS.iter().find(|(known, _)| *known == crc) {
+            return Some(*rev);
+        }
+        match Self::from_id_byte(*bytes.get(ID_BYTE_OFFSET)?)? {
+            Self::Rom4 => Some(Self::Rom4X),
+            rev => Some(rev),
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct ROM {
     pub data: Vec<u8>,
+    // Firmware revision; None for Generic system images
+    pub revision: Option<RomRevision>,
 }
 
 impl ROM {
@@ -44,10 +155,38 @@ impl ROM {
 
         hexdump(&data, Some(0), Some(bytes.len().min(0x100)));
 
-        Ok(Self { data })
+        Ok(Self { data, revision: None })
+    }
+
+    // Load an Apple //c firmware image. The revision is detected from the
+    // image unless `forced` (--rom-type) names one.
+    pub fn load_iic(bytes: &[u8], forced: Option<RomRevision>) -> io::Result<Self> {
+        let detected = RomRevision::detect(bytes);
+        let revision = forced.or(detected).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "unrecognized Apple //c ROM; pass --rom-type")
+        })?;
+        if bytes.len() != revision.size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} image must be {} bytes, got {}", revision.name(), revision.size(), bytes.len()),
+            ));
+        }
+
+        let mut rom = Self::load_from_bytes(bytes, SystemType::AppleIIc)?;
+        rom.revision = Some(revision);
+
+        match detected {
+            Some(rev) if rev == revision => {
+                println!("rom   {:>12} {:>8}    {}", "REVISION", "DETECTED", revision.name());
+            }
+            _ => println!("rom   {:>12} {:>8}    {}", "REVISION", "FORCED", revision.name()),
+        }
+        if bytes.get(ID_BYTE_OFFSET) != Some(&revision.id_byte()) {
+            println!("rom   {:>12} {:>8}    $FBBF does not match {}", "REVISION", "WARNING", revision.name());
+        }
+        Ok(rom)
     }
 
-    #[allow(dead_code)]
     pub fn load_from_intel(filename: &str, system_type: SystemType) -> io::Result<Self> {
         let file = File::open(filename)?;
         let reader = BufReader::new(file);
@@ -124,6 +263,6 @@ impl ROM {
 
         hexdump(&data, Some(0), Some(0x100));
 
-        Ok(Self { data })
+        Ok(Self { data, revision: None })
     }
 }
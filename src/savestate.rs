@@ -0,0 +1,279 @@
+// Machine save states.
+//
+// A save state is a flat little-endian byte stream:
+//
+//   "IICSTATE"        8-byte magic
+//   version           u16
+//   sections...       4-byte tag + component payload
+//
+// Every component that carries emulated state implements `SaveState` next
+// to its own definition, so private fields stay private. Section tags are
+// checked on load so a truncated or reordered file fails loudly instead of
+// silently restoring garbage. Host-side resources (audio producers, TCP
+// streams, gamepads, block-device file handles) are not part of the state.
+//
+// Bump STATE_VERSION whenever the layout of any section changes.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::cpu::CPU;
+
+pub const STATE_MAGIC: &[u8; 8] = b"IICSTATE";
+pub const STATE_VERSION: u16 = 3;
+
+// Default file used by the quick-save / quick-load hotkeys.
+pub const QUICKSAVE_PATH: &str = "quicksave.iicstate";
+
+pub trait SaveState {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()>;
+}
+
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self { buf: Vec::with_capacity(512 * 1024) }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn section(&mut self, tag: &[u8; 4]) {
+        self.buf.extend_from_slice(tag);
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.buf.push(v as u8);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn i16(&mut self, v: i16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, v: f32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, v: usize) {
+        self.u64(v as u64);
+    }
+
+    // Length-prefixed byte blob
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.u32(v.len() as u32);
+        self.buf.extend_from_slice(v);
+    }
+
+    // Fixed-size array, no length prefix
+    pub fn array(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+
+    pub fn opt_u8(&mut self, v: Option<u8>) {
+        self.bool(v.is_some());
+        self.u8(v.unwrap_or(0));
+    }
+
+    pub fn string(&mut self, v: &str) {
+        self.bytes(v.as_bytes());
+    }
+
+    pub fn opt_string(&mut self, v: Option<&str>) {
+        self.bool(v.is_some());
+        self.string(v.unwrap_or(""));
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.data.len())
+            .ok_or_else(|| anyhow!("save state truncated at offset {}", self.pos))?;
+        let slice = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub fn section(&mut self, tag: &[u8; 4]) -> Result<()> {
+        let offset = self.pos;
+        let found = self.take(4)?;
+        if found != tag {
+            bail!(
+                "save state: expected section '{}' at offset {}, found '{}'",
+                String::from_utf8_lossy(tag),
+                offset,
+                String::from_utf8_lossy(found)
+            );
+        }
+        Ok(())
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    pub fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    pub fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub fn usize(&mut self) -> Result<usize> {
+        Ok(self.u64()? as usize)
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub fn array(&mut self, out: &mut [u8]) -> Result<()> {
+        out.copy_from_slice(self.take(out.len())?);
+        Ok(())
+    }
+
+    // Read a length-prefixed blob into a fixed-size buffer, rejecting size mismatches
+    pub fn bytes_into(&mut self, out: &mut [u8]) -> Result<()> {
+        let len = self.u32()? as usize;
+        if len != out.len() {
+            bail!("save state: expected {} byte block, found {}", out.len(), len);
+        }
+        self.array(out)
+    }
+
+    pub fn opt_u8(&mut self) -> Result<Option<u8>> {
+        let some = self.bool()?;
+        let v = self.u8()?;
+        Ok(if some { Some(v) } else { None })
+    }
+
+    pub fn string(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.bytes()?)?)
+    }
+
+    pub fn opt_string(&mut self) -> Result<Option<String>> {
+        let some = self.bool()?;
+        let s = self.string()?;
+        Ok(if some { Some(s) } else { None })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+// Serialize the whole machine into a versioned save-state blob.
+pub fn save_machine(cpu: &CPU) -> Vec<u8> {
+    let mut w = StateWriter::new();
+    w.array(STATE_MAGIC);
+    w.u16(STATE_VERSION);
+    cpu.save_state(&mut w);
+    w.section(b"END ");
+    w.into_bytes()
+}
+
+// Restore the whole machine from a save-state blob produced by `save_machine`.
+//
+// Loading is all or nothing: components validate as they go, so a file that
+// fails part-way is rolled back to a snapshot taken before the first write
+// and the machine is left exactly as it was.
+pub fn load_machine(cpu: &mut CPU, data: &[u8]) -> Result<()> {
+    // Catch truncated files before touching any machine state
+    if !data.ends_with(b"END ") {
+        bail!("save state is truncated");
+    }
+
+    let mut r = StateReader::new(data);
+    let mut magic = [0u8; 8];
+    r.array(&mut magic)?;
+    if &magic != STATE_MAGIC {
+        bail!("not an Apple IIc save state");
+    }
+    let version = r.u16()?;
+    if version != STATE_VERSION {
+        bail!("unsupported save state version {} (expected {})", version, STATE_VERSION);
+    }
+
+    let backup = save_machine(cpu);
+    let result = load_body(cpu, &mut r);
+    if result.is_err() {
+        // Our own snapshot always parses
+        let mut r = StateReader::new(&backup[STATE_MAGIC.len() + 2..]);
+        load_body(cpu, &mut r).expect("restoring the pre-load snapshot");
+    }
+    result
+}
+
+fn load_body(cpu: &mut CPU, r: &mut StateReader) -> Result<()> {
+    cpu.load_state(r)?;
+    r.section(b"END ")?;
+    if !r.is_empty() {
+        bail!("save state has trailing data");
+    }
+    Ok(())
+}
+
+pub fn save_to_file<P: AsRef<Path>>(cpu: &CPU, path: P) -> Result<()> {
+    fs::write(path, save_machine(cpu))?;
+    Ok(())
+}
+
+pub fn load_from_file<P: AsRef<Path>>(cpu: &mut CPU, path: P) -> Result<()> {
+    let data = fs::read(path)?;
+    load_machine(cpu, &data)
+}
@@ -0,0 +1,26 @@
+// PNG screenshots of the emulated display.
+//
+// Captures the native 560x384 video framebuffer (border cropped) before any
+// CRT/LCD post-processing, i.e. exactly what the emulated machine drew.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::video::Video;
+
+pub fn save_png<P: AsRef<Path>>(video: &Video, path: P) -> Result<()> {
+    let (width, height) = video.get_active_dimensions();
+    let image = image::RgbaImage::from_raw(width, height, video.get_active_pixels())
+        .ok_or_else(|| anyhow!("framebuffer size mismatch"))?;
+    image.save_with_format(path, image::ImageFormat::Png)?;
+    Ok(())
+}
+
+// First unused screenshot-NNNN.png in the working directory
+pub fn next_path() -> PathBuf {
+    (1..)
+        .map(|n| PathBuf::from(format!("screenshot-{:04}.png", n)))
+        .find(|p| !p.exists())
+        .unwrap()
+}
@@ -0,0 +1,246 @@
+// Headless test scripts (--script).
+//
+// A script is a plain text file with one command per line. Blank lines and
+// lines starting with '#' are ignored. Strings are double-quoted and accept
+// \r, \n, \t, \", \\ and \xNN escapes. Typed text must be ASCII, as the
+// Apple II keyboard is.
+//
+//   cycles N              run N CPU cycles
+//   frames N              run N video frames (17030 cycles each)
+//   type "RUN\r"          type text through the keyboard, one key per frame
+//   key 1B                press a single key (Apple II code in hex)
+//   reset                 Control-Reset
+//   wait "text" [N]       run until text is on the text screen (timeout N frames, default 600)
+//   expect "text"         fail unless text is on the text screen right now
+//   screen                print the text screen
+//   screenshot "file.png" save the display as PNG
+//   pass / fail ["msg"]   stop with the given result
+//
+// Reaching the end of the script is a pass. The process exit code is
+// 0 for pass, 1 for fail and 2 for a script error.
+
+use std::fs;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::cpu::CPU;
+use crate::screenshot;
+use crate::text_screen::TEXT_ROWS;
+use crate::timing;
+
+pub const EXIT_PASS: i32 = 0;
+pub const EXIT_FAIL: i32 = 1;
+pub const EXIT_ERROR: i32 = 2;
+
+const DEFAULT_WAIT_FRAMES: u64 = 600;
+
+// Physical key id used for injected keys; never produced by winit
+pub const SCRIPT_KEY_ID: u16 = 0xFFFF;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Cycles(u64),
+    Frames(u64),
+    Type(Vec<u8>),
+    Wait(String, u64),
+    Expect(String),
+    Reset,
+    Screen,
+    Screenshot(String),
+    Pass,
+    Fail(String),
+}
+
+enum Outcome {
+    Continue,
+    Pass,
+    Fail(String),
+}
+
+// Run a script file against the machine and return the process exit code.
+pub fn run_script(cpu: &mut CPU, path: &str) -> i32 {
+    let commands = match fs::read_to_string(path)
+        .map_err(|e| anyhow!("{}: {}", path, e))
+        .and_then(|src| parse(&src))
+    {
+        Ok(commands) => commands,
+        Err(e) => {
+            eprintln!("script {:>11} {:>8}    {}", "PARSE", "ERROR", e);
+            return EXIT_ERROR;
+        }
+    };
+
+    println!("script {:>11} {:>8}    {}", "RUNNER", "ONLINE", path);
+
+    for (line, cmd) in &commands {
+        match execute(cpu, cmd) {
+            Outcome::Continue => {}
+            Outcome::Pass => break,
+            Outcome::Fail(msg) => {
+                println!("script {:>11} {:>8}    line {}: {}", "RESULT", "FAIL", line, msg);
+                return EXIT_FAIL;
+            }
+        }
+    }
+
+    println!("script {:>11} {:>8}    {} cycles", "RESULT", "PASS", cpu.cycles);
+    EXIT_PASS
+}
+
+fn execute(cpu: &mut CPU, cmd: &Command) -> Outcome {
+    match cmd {
+        Command::Cycles(n) => run_cycles(cpu, *n),
+        Command::Frames(n) => run_cycles(cpu, n * timing::CYCLES_PER_FRAME),
+        Command::Type(keys) => {
+            for &key in keys {
+                cpu.bus.iou.keyboard.key_down(SCRIPT_KEY_ID, key, cpu.bus.iou.cycles);
+                cpu.bus.iou.keyboard.key_up(SCRIPT_KEY_ID, cpu.bus.iou.cycles);
+                if let Outcome::Fail(msg) = run_cycles(cpu, timing::CYCLES_PER_FRAME) {
+                    return Outcome::Fail(msg);
+                }
+            }
+            Outcome::Continue
+        }
+        Command::Wait(text, frames) => {
+            for _ in 0..*frames {
+                if cpu.bus.text_screen().contains(text) {
+                    return Outcome::Continue;
+                }
+                if let Outcome::Fail(msg) = run_cycles(cpu, timing::CYCLES_PER_FRAME) {
+                    return Outcome::Fail(msg);
+                }
+            }
+            if cpu.bus.text_screen().contains(text) {
+                return Outcome::Continue;
+            }
+            print_screen(cpu);
+            Outcome::Fail(format!("timed out after {} frames waiting for \"{}\"", frames, text))
+        }
+        Command::Expect(text) => {
+            if cpu.bus.text_screen().contains(text) {
+                Outcome::Continue
+            } else {
+                print_screen(cpu);
+                Outcome::Fail(format!("\"{}\" not on screen", text))
+            }
+        }
+        Command::Reset => {
+            cpu.reset();
+            Outcome::Continue
+        }
+        Command::Screen => {
+            print_screen(cpu);
+            Outcome::Continue
+        }
+        Command::Screenshot(path) => {
+            cpu.video_update();
+            match screenshot::save_png(&cpu.bus.video, path) {
+                Ok(()) => Outcome::Continue,
+                Err(e) => Outcome::Fail(format!("screenshot {}: {}", path, e)),
+            }
+        }
+        Command::Pass => Outcome::Pass,
+        Command::Fail(msg) => Outcome::Fail(msg.clone()),
+    }
+}
+
+fn run_cycles(cpu: &mut CPU, n: u64) -> Outcome {
+    let target = cpu.cycles + n;
+    while cpu.cycles < target {
+        if cpu.bus.interrupts.halted {
+            return Outcome::Fail(format!("CPU halted at {:04X}", cpu.pc));
+        }
+        cpu.tick();
+    }
+    Outcome::Continue
+}
+
+fn print_screen(cpu: &CPU) {
+    let screen = cpu.bus.text_screen();
+    for row in 0..TEXT_ROWS {
+        println!("| {}", screen.row_string(row).trim_end());
+    }
+}
+
+pub fn parse(src: &str) -> Result<Vec<(usize, Command)>> {
+    let mut commands = Vec::new();
+    for (i, raw) in src.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_no = i + 1;
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let cmd = parse_command(word, rest).map_err(|e| anyhow!("line {}: {}", line_no, e))?;
+        commands.push((line_no, cmd));
+    }
+    Ok(commands)
+}
+
+fn parse_command(word: &str, rest: &str) -> Result<Command> {
+    Ok(match word {
+        "cycles" => Command::Cycles(parse_number(rest)?),
+        "frames" => Command::Frames(parse_number(rest)?),
+        "type" => {
+            let (text, _) = parse_string(rest)?;
+            if let Some(c) = text.chars().find(|c| !c.is_ascii()) {
+                bail!("'{}' is not on the Apple II keyboard", c);
+            }
+            // Return is the only line ending the Apple II keyboard knows
+            Command::Type(text.bytes().map(|b| if b == b'\n' { 0x0D } else { b }).collect())
+        }
+        "key" => {
+            let code = u8::from_str_radix(rest.trim_start_matches('$'), 16)
+                .map_err(|_| anyhow!("bad key code '{}'", rest))?;
+            Command::Type(vec![code & 0x7F])
+        }
+        "wait" => {
+            let (text, tail) = parse_string(rest)?;
+            let frames = if tail.trim().is_empty() { DEFAULT_WAIT_FRAMES } else { parse_number(tail)? };
+            Command::Wait(text, frames)
+        }
+        "expect" => Command::Expect(parse_string(rest)?.0),
+        "reset" => Command::Reset,
+        "screen" => Command::Screen,
+        "screenshot" => Command::Screenshot(parse_string(rest)?.0),
+        "pass" => Command::Pass,
+        "fail" => {
+            let msg = if rest.is_empty() { "fail".to_string() } else { parse_string(rest)?.0 };
+            Command::Fail(msg)
+        }
+        _ => bail!("unknown command '{}'", word),
+    })
+}
+
+fn parse_number(s: &str) -> Result<u64> {
+    let s = s.trim().replace('_', "");
+    s.parse().map_err(|_| anyhow!("bad number '{}'", s))
+}
+
+// Parse a leading double-quoted string; returns it and the remaining input.
+pub fn parse_string(s: &str) -> Result<(String, &str)> {
+    let body = s.strip_prefix('"').ok_or_else(|| anyhow!("expected a quoted string"))?;
+    let mut out = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((out, &body[i + 1..])),
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('r') => out.push('\r'),
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('"') => out.push('"'),
+                Some('\\') => out.push('\\'),
+                Some('x') => {
+                    let hex: String = (0..2).filter_map(|_| chars.next().map(|(_, c)| c)).collect();
+                    let v = u8::from_str_radix(&hex, 16).map_err(|_| anyhow!("bad escape \\x{}", hex))?;
+                    out.push(v as char);
+                }
+                other => bail!("bad escape \\{}", other.map(String::from).unwrap_or_default()),
+            },
+            _ => out.push(c),
+        }
+    }
+    bail!("unterminated string")
+}
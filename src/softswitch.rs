@@ -0,0 +1,296 @@
+// Soft-switch names and access trace
+//
+// Every CPU access to $C000-$C0FF on the //c can be recorded with the
+// switch's name, the PC and cycle of the access, and the memory and video
+// state it left behind. Names are the //c ones from docs/softswitches.txt;
+// slot 6 is the IWM, and the SCC command ports are named by the register
+// the access reaches (SCCAREG WR9). $C058-$C05F are named by the IOUDIS
+// state at the time: mouse switches with the IOU enabled, annunciators and
+// DHIRES with it disabled.
+//
+// The trace keeps the most recent accesses in memory for the monitor's
+// `switches` command, and can stream every access to a file as it happens
+// (--trace-switches). Both are limited to the devices in the filter.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use bitflags::bitflags;
+
+use crate::util::{mem_state_to_string, video_mode_to_string};
+
+// Accesses kept for the monitor
+const RECENT_ACCESSES: usize = 4096;
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Devices: u16 {
+        const KEYBOARD = 1 << 0;
+        const MEMORY   = 1 << 1;
+        const LANGCARD = 1 << 2;
+        const VIDEO    = 1 << 3;
+        const MOUSE    = 1 << 4;
+        const PADDLE   = 1 << 5;
+        const SPEAKER  = 1 << 6;
+        const SCC      = 1 << 7;
+        const IWM      = 1 << 8;
+        const SLOT     = 1 << 9;
+        const SYSTEM   = 1 << 10;
+    }
+}
+
+impl Devices {
+    // A comma-separated list of device names, or "all"
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let mut devices = Devices::empty();
+        for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if name.eq_ignore_ascii_case("all") {
+                devices = Devices::all();
+                continue;
+            }
+            devices |= Devices::from_name(&name.to_ascii_uppercase()).ok_or_else(|| {
+                let known: Vec<String> = Devices::all().iter_names().map(|(n, _)| n.to_ascii_lowercase()).collect();
+                format!("unknown device '{}' ({}, all)", name, known.join(", "))
+            })?;
+        }
+        Ok(devices)
+    }
+
+    fn name(self) -> &'static str {
+        self.iter_names().next().map_or("?", |(name, _)| name)
+    }
+}
+
+// Device and name of a switch access; ioudis is the IOU disable state
+// before the access
+#[rustfmt::skip]
+pub fn lookup(addr: u16, write: bool, ioudis: bool) -> (Devices, &'static str) {
+    use Devices as D;
+    match (addr, write) {
+        (0xC000, true)  => (D::MEMORY, "80STOREOFF"),
+        (0xC001, true)  => (D::MEMORY, "80STOREON"),
+        (0xC002, true)  => (D::MEMORY, "RDMAINRAM"),
+        (0xC003, true)  => (D::MEMORY, "RDCARDRAM"),
+        (0xC004, true)  => (D::MEMORY, "WRMAINRAM"),
+        (0xC005, true)  => (D::MEMORY, "WRCARDRAM"),
+        (0xC006, true)  => (D::MEMORY, "SETSLOTCXROM"),
+        (0xC007, true)  => (D::MEMORY, "SETINTCXROM"),
+        (0xC008, true)  => (D::MEMORY, "SETSTDZP"),
+        (0xC009, true)  => (D::MEMORY, "SETALTZP"),
+        (0xC00A, true)  => (D::MEMORY, "SETINTC3ROM"),
+        (0xC00B, true)  => (D::MEMORY, "SETSLOTC3ROM"),
+        (0xC00C, true)  => (D::VIDEO, "CLR80VID"),
+        (0xC00D, true)  => (D::VIDEO, "SET80VID"),
+        (0xC00E, true)  => (D::VIDEO, "CLRALTCHAR"),
+        (0xC00F, true)  => (D::VIDEO, "SETALTCHAR"),
+        (0xC000..=0xC00F, false) => (D::KEYBOARD, "KBD"),
+        (0xC010, _)     => (D::KEYBOARD, "KBDSTRB"),
+        (0xC011, false) => (D::LANGCARD, "RDLCBNK2"),
+        (0xC012, false) => (D::LANGCARD, "RDLCRAM"),
+        (0xC013, false) => (D::MEMORY, "RDRAMRD"),
+        (0xC014, false) => (D::MEMORY, "RDRAMWRT"),
+        (0xC015, false) => (D::MOUSE, "RSTXINT"),
+        (0xC016, false) => (D::MEMORY, "RDALTZP"),
+        (0xC017, false) => (D::MOUSE, "RSTYINT"),
+        (0xC018, false) => (D::MEMORY, "RD80STORE"),
+        (0xC019, false) => (D::MOUSE, "RSTVBL"),
+        (0xC01A, false) => (D::VIDEO, "RDTEXT"),
+        (0xC01B, false) => (D::VIDEO, "RDMIXED"),
+        (0xC01C, false) => (D::VIDEO, "RDPAGE2"),
+        (0xC01D, false) => (D::VIDEO, "RDHIRES"),
+        (0xC01E, false) => (D::VIDEO, "RDALTCHAR"),
+        (0xC01F, false) => (D::VIDEO, "RD80VID"),
+        (0xC011..=0xC01F, true) => (D::KEYBOARD, "KBDSTRB"),
+        (0xC020, _)     => (D::SYSTEM, "TAPEOUT"),
+        (0xC028, _)     => (D::SYSTEM, "ROMBANK"),
+        (0xC030, _)     => (D::SPEAKER, "SPKR"),
+        (0xC031, _)     => (D::IWM, "DISKREG"),
+        (0xC038, _)     => (D::SCC, "SCCBREG"),
+        (0xC039, _)     => (D::SCC, "SCCAREG"),
+        (0xC03A, _)     => (D::SCC, "SCCBDATA"),
+        (0xC03B, _)     => (D::SCC, "SCCADATA"),
+        (0xC040, _)     => (D::MOUSE, "RDXYMSK"),
+        (0xC041, _)     => (D::MOUSE, "RDVBLMSK"),
+        (0xC042, _)     => (D::MOUSE, "RDX0EDGE"),
+        (0xC043, _)     => (D::MOUSE, "RDY0EDGE"),
+        (0xC048, _)     => (D::MOUSE, "RSTXY"),
+        (0xC050, _)     => (D::VIDEO, "TXTCLR"),
+        (0xC051, _)     => (D::VIDEO, "TXTSET"),
+        (0xC052, _)     => (D::VIDEO, "MIXCLR"),
+        (0xC053, _)     => (D::VIDEO, "MIXSET"),
+        (0xC054, _)     => (D::VIDEO, "TXTPAGE1"),
+        (0xC055, _)     => (D::VIDEO, "TXTPAGE2"),
+        (0xC056, _)     => (D::VIDEO, "LORES"),
+        (0xC057, _)     => (D::VIDEO, "HIRES"),
+        (0xC058, _) if !ioudis => (D::MOUSE, "DISXY"),
+        (0xC059, _) if !ioudis => (D::MOUSE, "ENBXY"),
+        (0xC05A, _) if !ioudis => (D::MOUSE, "DISVBL"),
+        (0xC05B, _) if !ioudis => (D::MOUSE, "ENVBL"),
+        (0xC05C, _) if !ioudis => (D::MOUSE, "X0EDGERISE"),
+        (0xC05D, _) if !ioudis => (D::MOUSE, "X0EDGEFALL"),
+        (0xC05E, _) if !ioudis => (D::MOUSE, "Y0EDGERISE"),
+        (0xC05F, _) if !ioudis => (D::MOUSE, "Y0EDGEFALL"),
+        (0xC058, _)     => (D::SYSTEM, "CLRAN0"),
+        (0xC059, _)     => (D::SYSTEM, "SETAN0"),
+        (0xC05A, _)     => (D::SYSTEM, "CLRAN1"),
+        (0xC05B, _)     => (D::SYSTEM, "SETAN1"),
+        (0xC05C, _)     => (D::SYSTEM, "CLRAN2"),
+        (0xC05D, _)     => (D::SYSTEM, "SETAN2"),
+        (0xC05E, _)     => (D::VIDEO, "DHIRESON"),
+        (0xC05F, _)     => (D::VIDEO, "DHIRESOFF"),
+        (0xC060, _)     => (D::SYSTEM, "RD80SW"),
+        (0xC061, _)     => (D::KEYBOARD, "RDBTN0"),
+        (0xC062, _)     => (D::KEYBOARD, "RDBTN1"),
+        (0xC063, _)     => (D::MOUSE, "RD63"),
+        (0xC064, _)     => (D::PADDLE, "PADDL0"),
+        (0xC065, _)     => (D::PADDLE, "PADDL1"),
+        (0xC066, _)     => (D::MOUSE, "RDMOUX1"),
+        (0xC067, _)     => (D::MOUSE, "RDMOUY1"),
+        (0xC068, _)     => (D::SYSTEM, "STATEREG"),
+        (0xC070, _)     => (D::PADDLE, "PTRIG"),
+        (0xC078, true)  => (D::VIDEO, "IOUDISON"),
+        (0xC079, true)  => (D::VIDEO, "IOUDISOFF"),
+        (0xC07E, false) => (D::VIDEO, "RDIOUDIS"),
+        (0xC07E, true)  => (D::VIDEO, "IOUDISON"),
+        (0xC07F, false) => (D::VIDEO, "RDDHIRES"),
+        (0xC07F, true)  => (D::VIDEO, "IOUDISOFF"),
+        (0xC071..=0xC07F, _) => (D::PADDLE, "PTRIG"),
+        (0xC080 | 0xC084, _) => (D::LANGCARD, "RDRAM2"),
+        (0xC081 | 0xC085, _) => (D::LANGCARD, "ROMIN2"),
+        (0xC082 | 0xC086, _) => (D::LANGCARD, "RDROM2"),
+        (0xC083 | 0xC087, _) => (D::LANGCARD, "LCBANK2"),
+        (0xC088 | 0xC08C, _) => (D::LANGCARD, "RDRAM1"),
+        (0xC089 | 0xC08D, _) => (D::LANGCARD, "ROMIN1"),
+        (0xC08A | 0xC08E, _) => (D::LANGCARD, "RDROM1"),
+        (0xC08B | 0xC08F, _) => (D::LANGCARD, "LCBANK1"),
+        // The serial ports' ACIA-compatible registers
+        (0xC098 | 0xC0A8, _) => (D::SCC, "ACIADATA"),
+        (0xC099 | 0xC0A9, _) => (D::SCC, "ACIASTATUS"),
+        (0xC09A | 0xC0AA, _) => (D::SCC, "ACIACMD"),
+        (0xC09B | 0xC0AB, _) => (D::SCC, "ACIACTRL"),
+        (0xC09C..=0xC09F | 0xC0AC..=0xC0AF, _) => (D::SCC, "ACIA"),
+        (0xC0E0, _)     => (D::IWM, "PH0OFF"),
+        (0xC0E1, _)     => (D::IWM, "PH0ON"),
+        (0xC0E2, _)     => (D::IWM, "PH1OFF"),
+        (0xC0E3, _)     => (D::IWM, "PH1ON"),
+        (0xC0E4, _)     => (D::IWM, "PH2OFF"),
+        (0xC0E5, _)     => (D::IWM, "PH2ON"),
+        (0xC0E6, _)     => (D::IWM, "PH3OFF"),
+        (0xC0E7, _)     => (D::IWM, "PH3ON"),
+        (0xC0E8, _)     => (D::IWM, "MOTOROFF"),
+        (0xC0E9, _)     => (D::IWM, "MOTORON"),
+        (0xC0EA, _)     => (D::IWM, "DRV1"),
+        (0xC0EB, _)     => (D::IWM, "DRV2"),
+        (0xC0EC, _)     => (D::IWM, "Q6L"),
+        (0xC0ED, _)     => (D::IWM, "Q6H"),
+        (0xC0EE, _)     => (D::IWM, "Q7L"),
+        (0xC0EF, _)     => (D::IWM, "Q7H"),
+        (0xC0C0..=0xC0CF, _) => (D::SLOT, "SLOT4"),
+        (0xC0D0..=0xC0DF, _) => (D::SLOT, "SLOT5"),
+        (0xC090..=0xC0FF, _) => (D::SLOT, "SLOT"),
+        _ => (D::SYSTEM, "-"),
+    }
+}
+
+// One soft-switch access
+#[derive(Clone, Copy, Debug)]
+pub struct SwitchAccess {
+    pub cycle: u64,
+    pub pc: u16,
+    pub addr: u16,
+    pub value: u8,
+    pub write: bool,
+    pub device: Devices,
+    pub name: &'static str,
+    // SCC register reached through a command port
+    pub scc_reg: Option<u8>,
+    // State after the access
+    pub mem_state: u8,
+    pub video_mode: u8,
+}
+
+impl fmt::Display for SwitchAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.scc_reg {
+            Some(reg) => format!("{} {}{}", self.name, if self.write { "WR" } else { "RR" }, reg),
+            None => self.name.to_string(),
+        };
+        write!(
+            f,
+            "{:>12} PC={:04X} {} {:04X}={:02X} {:<8} {:<16} {} {}",
+            self.cycle,
+            self.pc,
+            if self.write { 'W' } else { 'R' },
+            self.addr,
+            self.value,
+            self.device.name(),
+            name,
+            mem_state_to_string(self.mem_state),
+            video_mode_to_string(self.video_mode)
+        )
+    }
+}
+
+pub struct SwitchTrace {
+    pub filter: Devices,
+    recent: VecDeque<SwitchAccess>,
+    // Every access is written here as it happens
+    out: Option<(String, BufWriter<File>)>,
+}
+
+impl SwitchTrace {
+    pub fn new(filter: Devices) -> Self {
+        Self { filter, recent: VecDeque::with_capacity(RECENT_ACCESSES), out: None }
+    }
+
+    // Trace that also streams every access to path
+    pub fn to_file(path: &str, filter: Devices) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self { out: Some((path.to_string(), BufWriter::new(file))), ..Self::new(filter) })
+    }
+
+    // File every access is streamed to, if any
+    pub fn path(&self) -> Option<&str> {
+        self.out.as_ref().map(|(path, _)| path.as_str())
+    }
+
+    pub fn record(&mut self, access: SwitchAccess) {
+        if !self.filter.intersects(access.device) {
+            return;
+        }
+        if let Some((path, out)) = self.out.as_mut() {
+            if let Err(e) = writeln!(out, "{}", access) {
+                eprintln!("trace {:>12} {:>8}    {}: {}", "SWITCHES", "ERROR", path, e);
+                self.out = None;
+            }
+        }
+        if self.recent.len() == RECENT_ACCESSES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(access);
+    }
+
+    // Most recent accesses, oldest first
+    pub fn recent(&self) -> impl Iterator<Item = &SwitchAccess> {
+        self.recent.iter()
+    }
+
+    // Write the recent accesses to path. Returns how many were written.
+    pub fn dump(&self, path: &Path) -> io::Result<usize> {
+        let mut out = BufWriter::new(File::create(path)?);
+        for access in &self.recent {
+            writeln!(out, "{}", access)?;
+        }
+        out.flush()?;
+        Ok(self.recent.len())
+    }
+
+    pub fn flush(&mut self) {
+        if let Some((_, out)) = self.out.as_mut() {
+            let _ = out.flush();
+        }
+    }
+}
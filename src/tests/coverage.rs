@@ -0,0 +1,109 @@
+// Code/data coverage marks and the listing written from them.
+
+use std::env;
+use std::fs;
+
+use super::generic_cpu;
+use crate::coverage::{Access, Coverage};
+use crate::cpu::{CpuType, CPU};
+use crate::mmu::MemoryView;
+
+#[test]
+fn coverage_mark() {
+    let mut coverage = Coverage::new(None);
+    assert_eq!(coverage.views().count(), 0);
+
+    coverage.mark(MemoryView::Main, 0x0300, Access::EXECUTE);
+    coverage.mark(MemoryView::Main, 0x0300, Access::READ);
+    coverage.mark(MemoryView::Aux, 0x0300, Access::WRITE);
+    // $E000 up in LC bank 2 is kept with bank 1; below that it is its own
+    coverage.mark(MemoryView::LcMain2, 0xE000, Access::READ);
+    coverage.mark(MemoryView::LcAux2, 0xFFFF, Access::WRITE);
+    coverage.mark(MemoryView::LcMain2, 0xD000, Access::EXECUTE);
+
+    assert_eq!(coverage.get(MemoryView::Main, 0x0300), Access::EXECUTE | Access::READ);
+    assert_eq!(coverage.get(MemoryView::Aux, 0x0300), Access::WRITE);
+    assert_eq!(coverage.get(MemoryView::Main, 0x0301), Access::empty());
+    assert_eq!(coverage.get(MemoryView::LcMain1, 0xE000), Access::READ);
+    assert_eq!(coverage.get(MemoryView::LcMain2, 0xE000), Access::empty());
+    assert_eq!(coverage.get(MemoryView::LcAux1, 0xFFFF), Access::WRITE);
+    assert_eq!(coverage.get(MemoryView::LcMain2, 0xD000), Access::EXECUTE);
+    // Untouched banks have no map at all
+    assert_eq!(coverage.get(MemoryView::Rom1, 0xC000), Access::empty());
+
+    let views: Vec<_> = coverage.views().collect();
+    assert_eq!(views, [MemoryView::Main, MemoryView::Aux, MemoryView::LcMain1, MemoryView::LcMain2, MemoryView::LcAux1]);
+    assert_eq!(coverage.counts(), [2, 2, 2]);
+    coverage.reset();
+    assert_eq!(coverage.counts(), [0, 0, 0]);
+}
+
+// LDA #$01 / LAX $10 / STA $0400 / LDA $0400,X run with coverage on
+fn covered_cpu(cpu_type: CpuType) -> CPU {
+    let mut cpu = generic_cpu(cpu_type);
+    cpu.bus.write_bytes(0x0300, &[0xA9, 0x01, 0xA7, 0x10, 0x8D, 0x00, 0x04, 0xBD, 0x00, 0x04]);
+    cpu.bus.write_bytes(0x0010, &[0x42]);
+    cpu.pc = 0x0300;
+    cpu.bus.coverage = Some(Box::new(Coverage::new(None)));
+    for _ in 0..4 {
+        cpu.tick();
+    }
+    cpu
+}
+
+fn listing(cpu: &mut CPU) -> String {
+    let coverage = cpu.bus.coverage.take().unwrap();
+    let path = env::temp_dir().join(format!("rust-iic-coverage-{:?}-{}.lst", cpu.cpu_type, std::process::id()));
+    coverage.write_listing(&path, &mut cpu.bus, cpu.cpu_type).unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    text
+}
+
+#[test]
+fn coverage_marks_cpu_accesses() {
+    let cpu = covered_cpu(CpuType::NMOS6502);
+    let coverage = cpu.bus.coverage.as_ref().unwrap();
+    let get = |addr| coverage.get(MemoryView::Main, addr);
+    // Immediate operands are instruction bytes, not data
+    assert_eq!(get(0x0300), Access::EXECUTE);
+    assert_eq!(get(0x0301), Access::EXECUTE);
+    assert_eq!(get(0x0309), Access::EXECUTE);
+    assert_eq!(get(0x030A), Access::empty());
+    assert_eq!(get(0x0010), Access::READ);
+    assert_eq!(get(0x0400), Access::WRITE);
+    // LDA abs,X with X=$42 reads $0442 and no dummy cycle
+    assert_eq!(get(0x0442), Access::READ);
+    assert_eq!(coverage.counts(), [10, 2, 1]);
+}
+
+#[test]
+fn coverage_listing() {
+    let mut cpu = covered_cpu(CpuType::NMOS6502);
+    assert_eq!(
+        listing(&mut cpu),
+        "; ===== main $0000-$BFFF\n\
+         \n\
+         ; data $0010-$0010 (read)\n\
+         $0010  .byte $42\n\
+         \n\
+         ; code $0300-$0309\n\
+         $0300  A9 01     LDA  #$01\n\
+         $0302  A7 10     LAX  $10\n\
+         $0304  8D 00 04  STA  $0400\n\
+         $0307  BD 00 04  LDA  $0400,X\n\
+         \n\
+         ; data $0400-$0400 (written)\n\
+         $0400  .byte $42\n\
+         \n\
+         ; data $0442-$0442 (read)\n\
+         $0442  .byte $00\n\
+         \n"
+    );
+
+    // The same bytes on a 65C02: $A7 is a one-byte NOP there
+    let mut cpu = covered_cpu(CpuType::CMOS65C02);
+    let text = listing(&mut cpu);
+    assert!(text.contains("$0302  A7        NOP  \n"), "{}", text);
+    assert!(!text.contains("LAX"), "{}", text);
+}
@@ -0,0 +1,278 @@
+// The Debug Adapter Protocol server's helpers, the debug info it loads, and
+// a session over a socket.
+
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use json::{object, JsonValue};
+
+use super::generic_cpu;
+use crate::cpu::{CpuType, CPU};
+use crate::dap::{base64_decode, base64_encode, instruction_starts, DapServer};
+use crate::debug_info::DebugInfo;
+use crate::debug_link::DebugServer;
+
+#[test]
+fn dap_base64() {
+    let cases: &[(&[u8], &str)] = &[
+        (b"", ""),
+        (b"f", "Zg=="),
+        (b"fo", "Zm8="),
+        (b"foo", "Zm9v"),
+        (b"foob", "Zm9vYg=="),
+        (&[0x00, 0xFF, 0xFE], "AP/+"),
+        (&[0xFB, 0xEF, 0xBE], "++++"),
+    ];
+    for &(bytes, text) in cases {
+        assert_eq!(base64_encode(bytes), text);
+        assert_eq!(base64_decode(text).as_deref(), Some(bytes), "{}", text);
+    }
+    // Unpadded and wrapped text decode too
+    assert_eq!(base64_decode("Zm8").as_deref(), Some(&b"fo"[..]));
+    assert_eq!(base64_decode("Zm9v\r\nYg==").as_deref(), Some(&b"foob"[..]));
+    assert_eq!(base64_decode("Zm9v!"), None);
+}
+
+#[test]
+fn dap_instruction_starts() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    // LDA #$01 / STA $0400 / NOP / RTS at $0300
+    cpu.bus.write_bytes(0x300, &[0xA9, 0x01, 0x8D, 0x00, 0x04, 0xEA, 0x60]);
+
+    let starts = instruction_starts(&mut cpu, 0x300, 0, 4);
+    assert_eq!(starts, [Some(0x300), Some(0x302), Some(0x305), Some(0x306)]);
+    assert_eq!(instruction_starts(&mut cpu, 0x300, 2, 2), [Some(0x305), Some(0x306)]);
+    assert_eq!(instruction_starts(&mut cpu, 0x306, -2, 2), [Some(0x302), Some(0x305)]);
+
+    // Before the bottom and past the top of memory
+    let starts = instruction_starts(&mut cpu, 0x0001, -3, 4);
+    assert_eq!(starts[..2], [None, None]);
+    assert_eq!(starts[3], Some(0x0001));
+    assert_eq!(instruction_starts(&mut cpu, 0xFFFF, 1, 2), [None, None]);
+
+    // Offsets and counts far outside memory are cut down, not allocated
+    assert_eq!(instruction_starts(&mut cpu, 0x300, i64::MIN, 3), [None, None, None]);
+    assert_eq!(instruction_starts(&mut cpu, 0x300, i64::MAX, 1), [None]);
+    let starts = instruction_starts(&mut cpu, 0x300, -1, usize::MAX);
+    assert_eq!(starts.len(), 0x10000);
+}
+
+fn load_dbg(name: &str, text: &str) -> anyhow::Result<DebugInfo> {
+    let path = env::temp_dir().join(format!("rust-iic-{}-{}.dbg", name, std::process::id()));
+    fs::write(&path, text).unwrap();
+    let mut info = DebugInfo::default();
+    let result = info.load(path.to_str().unwrap());
+    fs::remove_file(&path).unwrap();
+    result.map(|_| info)
+}
+
+const DBG: &str = "version\tmajor=2,minor=0
+file\tid=0,name=\"main.s\",size=100,mtime=0x00000000,mod=0
+seg\tid=0,name=\"CODE\",start=0x000800,size=0x0010,addrsize=absolute,type=rw
+span\tid=0,seg=0,start=0,size=2
+span\tid=1,seg=0,start=2,size=3
+line\tid=0,file=0,line=4,span=0
+line\tid=1,file=0,line=5,span=1
+sym\tid=0,name=\"main\",addrsize=absolute,scope=0,def=0,val=0x800,seg=0,type=lab
+sym\tid=1,name=\"@loop\",addrsize=absolute,scope=0,def=0,val=0x802,seg=0,type=lab,parent=0
+sym\tid=2,name=\"COUNT\",addrsize=absolute,scope=0,def=0,val=0x10,type=equ
+";
+
+#[test]
+fn debug_info_dbg_lines_and_labels() {
+    let info = load_dbg("lines", DBG).unwrap();
+    assert_eq!(info.label("main"), Some(0x800));
+    // Cheap locals and equates are not labels
+    assert_eq!(info.label("@loop"), None);
+    assert_eq!(info.label("COUNT"), None);
+    assert_eq!(info.nearest_label(0x804), Some(("main", 4)));
+
+    let (file, line) = info.line_at(0x803).unwrap();
+    assert_eq!((file.file_name(), line), (Path::new("main.s").file_name(), 5));
+    assert!(info.line_at(0x805).is_none());
+    assert_eq!(info.line_addresses("main.s", 5), Some((5, &[0x802][..])));
+    assert_eq!(info.line_addresses("main.s", 1), Some((4, &[0x800][..])));
+}
+
+#[test]
+fn debug_info_dbg_rejects_spans_outside_memory() {
+    // A segment near the top with a span running past $FFFF
+    let past_top = DBG.replace("start=0x000800", "start=0x00FFFE");
+    let e = load_dbg("top", &past_top).err().unwrap();
+    assert!(e.to_string().ends_with("span 1: outside the address space"), "{}", e);
+    // Offsets too big to add
+    let huge = DBG.replace("span\tid=1,seg=0,start=2", "span\tid=1,seg=0,start=0xFFFFFFFF");
+    let e = load_dbg("huge", &huge).err().unwrap();
+    assert!(e.to_string().ends_with("span 1: outside the address space"), "{}", e);
+    // A span ending exactly at the top is fine
+    assert!(load_dbg("end", &DBG.replace("start=0x000800", "start=0x00FFFB")).is_ok());
+
+    let e = load_dbg("span", &DBG.replace("line=5,span=1", "line=5,span=9")).err().unwrap();
+    assert!(e.to_string().ends_with("line 7: no span 9"), "{}", e);
+    let e = load_dbg("seg", &DBG.replace("span\tid=1,seg=0", "span\tid=1,seg=3")).err().unwrap();
+    assert!(e.to_string().ends_with("span 1: no segment 3"), "{}", e);
+}
+
+#[test]
+fn debug_info_vice_labels() {
+    let path = env::temp_dir().join(format!("rust-iic-labels-{}.lbl", std::process::id()));
+    fs::write(&path, "al C:0803 .main\nal 000810 .loop\nal C:zz .bad\n").unwrap();
+    let mut info = DebugInfo::default();
+    info.load(path.to_str().unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(info.label_count(), 2);
+    assert_eq!(info.label("main"), Some(0x803));
+    assert_eq!(info.label("loop"), Some(0x810));
+}
+
+// A client connection: bytes received but not yet framed, and the next seq
+struct Client {
+    stream: TcpStream,
+    input: Vec<u8>,
+    seq: i64,
+}
+
+impl Client {
+    // One whole Content-Length framed message, if one has arrived
+    fn message(&mut self) -> Option<JsonValue> {
+        let header_end = self.input.windows(4).position(|w| w == b"\r\n\r\n")?;
+        let header = String::from_utf8_lossy(&self.input[..header_end]).into_owned();
+        let length: usize = header.strip_prefix("Content-Length: ").unwrap().parse().unwrap();
+        let body_start = header_end + 4;
+        if self.input.len() < body_start + length {
+            return None;
+        }
+        let body: Vec<u8> = self.input.drain(..body_start + length).skip(body_start).collect();
+        Some(json::parse(&String::from_utf8_lossy(&body)).unwrap())
+    }
+}
+
+// Run the server as the emulator loop does, polling it and running the CPU
+// while it is not stopped, until a message `wanted` arrives; messages before
+// it are passed over
+fn wait_for(dap: &mut DapServer, cpu: &mut CPU, client: &mut Client, wanted: impl Fn(&JsonValue) -> bool) -> JsonValue {
+    let mut buf = [0u8; 4096];
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        while let Some(message) = client.message() {
+            if wanted(&message) {
+                return message;
+            }
+        }
+        assert!(Instant::now() < deadline, "nothing wanted arrived");
+        dap.poll(cpu);
+        for _ in 0..100 {
+            if dap.is_stopped() {
+                break;
+            }
+            cpu.tick();
+            dap.check(cpu);
+        }
+        match client.stream.read(&mut buf) {
+            Ok(0) => panic!("server closed the connection"),
+            Ok(n) => client.input.extend_from_slice(&buf[..n]),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+// Send a request and wait for its response, which must succeed
+fn request(dap: &mut DapServer, cpu: &mut CPU, client: &mut Client, command: &str, arguments: JsonValue) -> JsonValue {
+    client.seq += 1;
+    let seq = client.seq;
+    let body = object! { seq: seq, type: "request", command: command, arguments: arguments }.dump();
+    write!(client.stream, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    let response = wait_for(dap, cpu, client, |m| m["type"] == "response" && m["request_seq"] == seq);
+    assert_eq!(response["success"], true, "{}: {}", command, response["message"]);
+    response["body"].clone()
+}
+
+// Step with a request and wait for the stop it ends in; returns the reason
+fn step(dap: &mut DapServer, cpu: &mut CPU, client: &mut Client, command: &str, granularity: Option<&str>) -> String {
+    let mut arguments = object! { threadId: 1 };
+    if let Some(granularity) = granularity {
+        arguments["granularity"] = granularity.into();
+    }
+    request(dap, cpu, client, command, arguments);
+    let stopped = wait_for(dap, cpu, client, |m| m["event"] == "stopped");
+    stopped["body"]["reason"].to_string()
+}
+
+#[test]
+fn dap_session_over_tcp() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    #[rustfmt::skip]
+    let program = [
+        0xA2, 0x03,         // $0800 LDX #$03     line 4
+        0x20, 0x10, 0x08,   //       JSR $0810    line 5
+        0xCA,               // $0805 DEX          line 6
+        0xEA,               //       NOP          line 6
+        0xD0, 0xF9,         // $0807 BNE $0802    line 7
+        0x4C, 0x09, 0x08,   //       JMP $0809    line 8
+    ];
+    cpu.bus.write_bytes(0x0800, &program);
+    cpu.bus.write_bytes(0x0810, &[0xA9, 0x42, 0x60]); // LDA #$42 / RTS, lines 10 and 11
+    cpu.regs.sp = 0xFF;
+    cpu.pc = 0x0800;
+
+    let dbg = env::temp_dir().join(format!("rust-iic-session-{}.dbg", std::process::id()));
+    let spans = [(0x00, 2, 4), (0x02, 3, 5), (0x05, 2, 6), (0x07, 2, 7), (0x09, 3, 8), (0x10, 2, 10), (0x12, 1, 11)];
+    let mut text = "version\tmajor=2,minor=0\nfile\tid=0,name=\"main.s\",size=100,mtime=0x00000000,mod=0\n".to_string();
+    text += "seg\tid=0,name=\"CODE\",start=0x000800,size=0x0013,addrsize=absolute,type=rw\n";
+    for (id, (start, size, line)) in spans.iter().enumerate() {
+        text += &format!("span\tid={},seg=0,start={},size={}\nline\tid={},file=0,line={},span={}\n", id, start, size, id, line, id);
+    }
+    fs::write(&dbg, text).unwrap();
+
+    let mut dap = DapServer::listen(0, false).unwrap();
+    let stream = TcpStream::connect(dap.local_addr().unwrap()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    let mut client = Client { stream, input: Vec::new(), seq: 0 };
+    let (dap, cpu, client) = (&mut dap, &mut cpu, &mut client);
+
+    let capabilities = request(dap, cpu, client, "initialize", object! { linesStartAt1: true });
+    assert_eq!(capabilities["supportsSteppingGranularity"], true);
+    wait_for(dap, cpu, client, |m| m["event"] == "initialized");
+    request(dap, cpu, client, "launch", object! { symbols: dbg.to_str().unwrap() });
+    fs::remove_file(&dbg).unwrap();
+    let source = object! { path: "main.s" };
+    let set = request(dap, cpu, client, "setBreakpoints", object! { source: source.clone(), breakpoints: [{ line: 5 }] });
+    assert_eq!(set["breakpoints"][0]["verified"], true);
+    assert_eq!(set["breakpoints"][0]["instructionReference"], "0x0802");
+    request(dap, cpu, client, "configurationDone", JsonValue::Null);
+
+    let stopped = wait_for(dap, cpu, client, |m| m["event"] == "stopped");
+    assert_eq!(stopped["body"]["reason"], "breakpoint");
+    assert_eq!(cpu.pc, 0x0802);
+    request(dap, cpu, client, "setBreakpoints", object! { source: source, breakpoints: [] });
+
+    // By line: over the call, across a two-instruction line, round the loop
+    assert_eq!(step(dap, cpu, client, "next", None), "step");
+    assert_eq!((cpu.pc, cpu.regs.a), (0x0805, 0x42));
+    assert_eq!(step(dap, cpu, client, "next", Some("statement")), "step");
+    assert_eq!(cpu.pc, 0x0807);
+    assert_eq!(step(dap, cpu, client, "next", Some("line")), "step");
+    assert_eq!(cpu.pc, 0x0802);
+
+    // Into the call, and back out to the caller's next line
+    assert_eq!(step(dap, cpu, client, "stepIn", None), "step");
+    assert_eq!(cpu.pc, 0x0810);
+    assert_eq!(step(dap, cpu, client, "next", None), "step");
+    assert_eq!(cpu.pc, 0x0812);
+    assert_eq!(step(dap, cpu, client, "next", None), "step");
+    assert_eq!(cpu.pc, 0x0805);
+
+    // By instruction, within a line
+    assert_eq!(step(dap, cpu, client, "next", Some("instruction")), "step");
+    assert_eq!(cpu.pc, 0x0806);
+    assert_eq!(step(dap, cpu, client, "stepIn", Some("instruction")), "step");
+    assert_eq!(cpu.pc, 0x0807);
+
+    let trace = request(dap, cpu, client, "stackTrace", object! { threadId: 1 });
+    assert_eq!(trace["stackFrames"][0]["line"], 7);
+}
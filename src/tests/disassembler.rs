@@ -0,0 +1,244 @@
+// Opcode tables, the monitor's disassembler and assembler, and symbol files.
+
+use std::fs;
+
+use super::generic_cpu;
+use crate::cpu::CpuType;
+use crate::disassembler::{Disassembler, SymbolFormat, SymbolTable};
+use crate::mmu::MemoryView;
+
+fn decode(cpu_type: CpuType, bytes: [u8; 3]) -> (String, usize) {
+    let (mnemonic, operand, length) = Disassembler::decode(0x0300, bytes, cpu_type);
+    (format!("{} {}", mnemonic, operand).trim_end().to_string(), length)
+}
+
+#[test]
+fn disassemble_nmos_undocumented_opcodes() {
+    let cases: &[([u8; 3], &str, usize)] = &[
+        ([0x03, 0x10, 0x00], "SLO ($10,X)", 2),
+        ([0x07, 0x10, 0x00], "SLO $10", 2),
+        ([0x0B, 0x7F, 0x00], "ANC #$7F", 2),
+        ([0x0F, 0x34, 0x12], "SLO $1234", 3),
+        ([0x02, 0x00, 0x00], "JAM", 1),
+        ([0x1C, 0x34, 0x12], "NOP $1234,X", 3),
+        ([0x6B, 0xC0, 0x00], "ARR #$C0", 2),
+        ([0x8B, 0x01, 0x00], "ANE #$01", 2),
+        ([0x97, 0x10, 0x00], "SAX $10,Y", 2),
+        ([0x9C, 0x34, 0x12], "SHY $1234,X", 3),
+        ([0xA3, 0x10, 0x00], "LAX ($10,X)", 2),
+        ([0xB7, 0x10, 0x00], "LAX $10,Y", 2),
+        ([0xCB, 0x02, 0x00], "SBX #$02", 2),
+        ([0xEB, 0x01, 0x00], "SBC #$01", 2),
+        ([0xFF, 0x34, 0x12], "ISC $1234,X", 3),
+    ];
+    for &(bytes, text, length) in cases {
+        assert_eq!(decode(CpuType::NMOS6502, bytes), (text.to_string(), length), "{:02X?}", bytes);
+    }
+}
+
+#[test]
+fn disassemble_depends_on_cpu_type() {
+    // $A7 is LAX on the NMOS part, a NOP or SMB2 on the 65C02s
+    let bytes = [0xA7, 0x10, 0x00];
+    assert_eq!(decode(CpuType::NMOS6502, bytes), ("LAX $10".to_string(), 2));
+    assert_eq!(decode(CpuType::CMOS65C02, bytes), ("NOP".to_string(), 1));
+    assert_eq!(decode(CpuType::R65C02, bytes), ("SMB2 $10".to_string(), 2));
+
+    // $1A is INA on the 65C02 only
+    assert_eq!(decode(CpuType::NMOS6502, [0x1A, 0, 0]), ("NOP".to_string(), 1));
+    assert_eq!(decode(CpuType::CMOS65C02, [0x1A, 0, 0]), ("INA".to_string(), 1));
+}
+
+#[test]
+fn disassemble_jmp_indirect_at_page_end() {
+    let mut cpu = generic_cpu(CpuType::NMOS6502);
+    cpu.bus.write_bytes(0x0300, &[0x6C, 0xFF, 0x10]);
+    for cpu_type in [CpuType::NMOS6502, CpuType::CMOS65C02] {
+        let line = Disassembler::disassemble(&mut cpu.bus, 0x0300, cpu_type);
+        assert!(line.starts_with("$0300  6C FF 10  -  JMP  ($10FF)"), "{:?}: {}", cpu_type, line);
+    }
+}
+
+#[test]
+fn disassemble_bit_instructions_per_cpu_type() {
+    let cases: &[([u8; 3], &str, usize)] = &[
+        ([0x07, 0x10, 0x00], "RMB0 $10", 2),
+        ([0xF7, 0x10, 0x00], "SMB7 $10", 2),
+        ([0x0F, 0x10, 0x05], "BBR0 $10,$0308", 3),
+        ([0xFF, 0x10, 0xFD], "BBS7 $10,$0300", 3),
+    ];
+    for &(bytes, text, length) in cases {
+        for cpu_type in [CpuType::R65C02, CpuType::WDC65C02S] {
+            assert_eq!(decode(cpu_type, bytes), (text.to_string(), length), "{:?} {:02X?}", cpu_type, bytes);
+        }
+        assert_eq!(decode(CpuType::CMOS65C02, bytes), ("NOP".to_string(), 1), "{:02X?}", bytes);
+    }
+}
+
+#[test]
+fn disassemble_cmos_undefined_opcodes_as_nops() {
+    let cases: &[([u8; 3], &str, usize)] = &[
+        ([0x02, 0x10, 0x00], "NOP #$10", 2),
+        ([0xE2, 0x10, 0x00], "NOP #$10", 2),
+        ([0x03, 0x10, 0x00], "NOP", 1),
+        ([0x44, 0x10, 0x00], "NOP $10", 2),
+        ([0xF4, 0x10, 0x00], "NOP $10,X", 2),
+        ([0x5C, 0x34, 0x12], "NOP $1234", 3),
+        ([0xDC, 0x34, 0x12], "NOP $1234", 3),
+        ([0xFC, 0x34, 0x12], "NOP $1234", 3),
+    ];
+    for &(bytes, text, length) in cases {
+        assert_eq!(decode(CpuType::CMOS65C02, bytes), (text.to_string(), length), "{:02X?}", bytes);
+    }
+
+    // WAI and STP are WDC additions
+    assert_eq!(decode(CpuType::CMOS65C02, [0xCB, 0, 0]), ("NOP".to_string(), 1));
+    assert_eq!(decode(CpuType::R65C02, [0xDB, 0, 0]), ("NOP".to_string(), 1));
+    assert_eq!(decode(CpuType::WDC65C02S, [0xCB, 0, 0]), ("WAI".to_string(), 1));
+    assert_eq!(decode(CpuType::WDC65C02S, [0xDB, 0, 0]), ("STP".to_string(), 1));
+}
+
+const CPU_TYPES: [CpuType; 4] = [CpuType::NMOS6502, CpuType::CMOS65C02, CpuType::R65C02, CpuType::WDC65C02S];
+
+#[test]
+fn assemble_round_trips_every_opcode() {
+    // Operands that branch forward and back
+    for cpu_type in CPU_TYPES {
+        for opcode in 0x00..=0xFF {
+            for operands in [[0x12, 0x10], [0xF2, 0xF0]] {
+                let bytes = [opcode, operands[0], operands[1]];
+                let (text, length) = decode(cpu_type, bytes);
+                let encoded = Disassembler::assemble(0x0300, &text, cpu_type)
+                    .unwrap_or_else(|e| panic!("{:?} {:02X} '{}': {}", cpu_type, opcode, text, e));
+                // Aliases may pick another opcode, but it must read back the same
+                let mut again = [0; 3];
+                again[..encoded.len()].copy_from_slice(&encoded);
+                assert_eq!(decode(cpu_type, again), (text.clone(), length), "{:?} {:02X}", cpu_type, opcode);
+                assert_eq!(encoded.len(), length, "{:?} '{}'", cpu_type, text);
+            }
+        }
+    }
+}
+
+#[test]
+fn assemble_uses_the_cpu_types_instructions() {
+    let assemble = |cpu_type, text| Disassembler::assemble(0x0300, text, cpu_type);
+    assert_eq!(assemble(CpuType::NMOS6502, "LAX $10"), Ok(vec![0xA7, 0x10]));
+    assert_eq!(assemble(CpuType::NMOS6502, "NOP"), Ok(vec![0xEA]));
+    assert_eq!(assemble(CpuType::NMOS6502, "STZ $10"), Err("unknown instruction STZ".into()));
+    assert_eq!(assemble(CpuType::NMOS6502, "JMP ($1234,X)"), Err("JMP does not take operand '($1234,X)'".into()));
+    assert_eq!(assemble(CpuType::CMOS65C02, "LAX $10"), Err("unknown instruction LAX".into()));
+    assert_eq!(assemble(CpuType::CMOS65C02, "JMP ($1234,X)"), Ok(vec![0x7C, 0x34, 0x12]));
+    assert_eq!(assemble(CpuType::CMOS65C02, "RMB0 $10"), Err("unknown instruction RMB0".into()));
+    assert_eq!(assemble(CpuType::R65C02, "RMB0 $10"), Ok(vec![0x07, 0x10]));
+    assert_eq!(assemble(CpuType::R65C02, "WAI"), Err("unknown instruction WAI".into()));
+    assert_eq!(assemble(CpuType::WDC65C02S, "WAI"), Ok(vec![0xCB]));
+}
+
+#[test]
+fn assemble_operand_errors() {
+    let assemble = |text| Disassembler::assemble(0x0300, text, CpuType::WDC65C02S);
+    assert_eq!(assemble("BBR0 $12,$0310"), Ok(vec![0x0F, 0x12, 0x0D]));
+    assert_eq!(assemble("BBR0 $1234,$0310"), Err("'$1234,$0310' needs a zero page address".into()));
+    assert_eq!(assemble("BBR0 $12,$0400"), Err("branch to $0400 out of range".into()));
+    assert_eq!(assemble("BNE $0381"), Ok(vec![0xD0, 0x7F]));
+    assert_eq!(assemble("BNE $0282"), Ok(vec![0xD0, 0x80]));
+    assert_eq!(assemble("BNE $0382"), Err("branch to $0382 out of range".into()));
+    assert_eq!(assemble("LDA #$100"), Err("immediate '#$100' is more than a byte".into()));
+    assert_eq!(assemble("LDA ($1234),Y"), Err("'($1234),Y' needs a zero page address".into()));
+    assert_eq!(assemble("LDA $12G"), Err("bad operand '$12G'".into()));
+    assert_eq!(assemble("LDA (12"), Err("bad operand '(12'".into()));
+}
+
+// A fixture file, its layout and symbols it must give
+type SymbolFileCase = (&'static str, SymbolFormat, &'static [(&'static str, u16)]);
+
+// A line, the layout it is read as and the symbol it gives
+type SymbolLineCase = (SymbolFormat, &'static str, Option<(u16, &'static str)>);
+
+fn fixture(name: &str) -> String {
+    format!("{}/src/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+#[test]
+fn symbol_files_of_each_format() {
+    let cases: &[SymbolFileCase] = &[
+        ("applewin.sym", SymbolFormat::AppleWin, &[("COUT", 0xFDED), ("RDKEY", 0xFD0C), ("START", 0x0800)]),
+        ("vice.lbl", SymbolFormat::Vice, &[("start", 0x0800), ("loop", 0x0810), ("COUT", 0xFDED)]),
+        // Code before the first equate
+        ("merlin.s", SymbolFormat::Merlin, &[("COUT", 0xFDED), ("HOME", 0xFC58), ("KBD", 0xC000)]),
+        ("ophis.map", SymbolFormat::Ophis, &[("start", 0x0800), ("loop", 0x0810), ("COUT", 0xFDED)]),
+    ];
+    for &(name, format, symbols) in cases {
+        let path = fixture(name);
+        assert_eq!(SymbolFormat::detect(&fs::read_to_string(&path).unwrap()), format, "{}", name);
+        let mut table = SymbolTable::new();
+        assert_eq!(table.load_file(&path, None).unwrap(), symbols.len() + (name == "applewin.sym") as usize);
+        for &(symbol, addr) in symbols {
+            assert_eq!(table.address(symbol), Some(addr), "{} {}", name, symbol);
+            assert_eq!(table.lookup(addr, None), Some(symbol), "{} {}", name, symbol);
+        }
+    }
+}
+
+#[test]
+fn symbol_format_lines() {
+    let cases: &[SymbolLineCase] = &[
+        (SymbolFormat::AppleWin, "FDED COUT", Some((0xFDED, "COUT"))),
+        (SymbolFormat::AppleWin, "$FDED COUT", Some((0xFDED, "COUT"))),
+        (SymbolFormat::AppleWin, "10000 BIG", None),
+        (SymbolFormat::AppleWin, "FDED", None),
+        (SymbolFormat::Vice, "al C:0803 .main", Some((0x0803, "main"))),
+        (SymbolFormat::Vice, "al 000803 .main", Some((0x0803, "main"))),
+        (SymbolFormat::Vice, "al 010000 .far", None),
+        (SymbolFormat::Vice, "break C:0803", None),
+        (SymbolFormat::Merlin, "COUT = $FDED", Some((0xFDED, "COUT"))),
+        (SymbolFormat::Merlin, "COUT equ $FDED", Some((0xFDED, "COUT"))),
+        (SymbolFormat::Merlin, "COUT =$FDED", Some((0xFDED, "COUT"))),
+        (SymbolFormat::Merlin, "COUT: = FDED", Some((0xFDED, "COUT"))),
+        (SymbolFormat::Merlin, "START JSR HOME", None),
+        (SymbolFormat::Ophis, "$0803 | main | game.oph:12", Some((0x0803, "main"))),
+        (SymbolFormat::Ophis, "$0803 |  | game.oph:12", None),
+    ];
+    for &(format, line, expected) in cases {
+        assert_eq!(format.parse_line(line), expected, "{:?} '{}'", format, line);
+    }
+
+    // No symbols at all is AppleWin, and an error to load
+    assert_eq!(SymbolFormat::detect("; nothing\n\n"), SymbolFormat::AppleWin);
+    assert_eq!(SymbolFormat::detect("$0800 | start | a.oph:1\n"), SymbolFormat::Ophis);
+    let mut table = SymbolTable::new();
+    let e = table.load_file(&fixture("merlin.s").replace("merlin.s", "missing.s"), None).unwrap_err();
+    assert!(e.to_string().contains("missing.s"), "{}", e);
+}
+
+#[test]
+fn symbol_table_banked_lookup() {
+    let mut table = SymbolTable::new();
+    table.insert(0xD000, "ANY_D000", None);
+    table.insert(0xE000, "ANY_E000", None);
+    table.insert(0xD000, "LC1_D000", Some(MemoryView::LcMain1));
+    table.insert(0xD000, "LC2_D000", Some(MemoryView::LcMain2));
+    table.insert(0xE000, "LC1_E000", Some(MemoryView::LcMain1));
+    table.insert(0xE000, "AUX2_E000", Some(MemoryView::LcAux2));
+    table.insert(0x0400, "AUX_0400", Some(MemoryView::Aux));
+    assert_eq!(table.len(), 7);
+
+    let cases: &[(u16, Option<MemoryView>, Option<&str>)] = &[
+        // Each bank's own name first, then the unbanked one
+        (0xD000, Some(MemoryView::LcMain1), Some("LC1_D000")),
+        (0xD000, Some(MemoryView::LcMain2), Some("LC2_D000")),
+        (0xD000, Some(MemoryView::LcAux1), Some("ANY_D000")),
+        (0xD000, None, Some("ANY_D000")),
+        // $E000 up is shared by the two LC banks of one side, not across sides
+        (0xE000, Some(MemoryView::LcMain2), Some("LC1_E000")),
+        (0xE000, Some(MemoryView::LcAux1), Some("AUX2_E000")),
+        (0xE000, Some(MemoryView::Rom1), Some("ANY_E000")),
+        (0x0400, Some(MemoryView::Aux), Some("AUX_0400")),
+        (0x0400, Some(MemoryView::Main), None),
+        (0x0400, None, None),
+    ];
+    for &(addr, view, name) in cases {
+        assert_eq!(table.lookup(addr, view), name, "{:04X} {:?}", addr, view);
+    }
+}
@@ -0,0 +1,7 @@
+* Merlin source: equates among the code
+         ORG   $0800
+COUT     EQU   $FDED
+HOME     =     $FC58
+KBD      =$C000
+START    JSR   HOME
+         RTS
@@ -0,0 +1,111 @@
+// The GDB remote protocol stub: packet helpers, and packets over a socket.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use super::generic_cpu;
+use crate::cpu::{CpuType, CPU};
+use crate::debug_link::DebugServer;
+use crate::gdb::{decode, encode, parse_range, xfer_chunk, GdbServer, PACKET_SIZE};
+
+#[test]
+fn gdb_hex_encoding() {
+    assert_eq!(encode(&[]), "");
+    assert_eq!(encode(&[0x00, 0x0A, 0xA9, 0xFF]), "000aa9ff");
+    assert_eq!(decode("000aa9ff"), Some(vec![0x00, 0x0A, 0xA9, 0xFF]));
+    assert_eq!(decode("A9Ff"), Some(vec![0xA9, 0xFF]));
+    assert_eq!(decode(""), Some(vec![]));
+    // Odd length and non-hex digits
+    assert_eq!(decode("a9f"), None);
+    assert_eq!(decode("zz"), None);
+    assert_eq!(decode("+1"), None);
+    // Multi-byte characters must not split a pair
+    assert_eq!(decode("é0"), None);
+}
+
+#[test]
+fn gdb_parse_range() {
+    assert_eq!(parse_range("300,10"), Some((0x300, 0x10)));
+    assert_eq!(parse_range("0,0"), Some((0, 0)));
+    // Up to the top of memory, not past it
+    assert_eq!(parse_range("fff0,10"), Some((0xFFF0, 0x10)));
+    assert_eq!(parse_range("fff0,11"), None);
+    assert_eq!(parse_range("10000,1"), None);
+    // No bigger than a reply packet can carry
+    assert_eq!(parse_range(&format!("0,{:x}", PACKET_SIZE / 2)), Some((0, PACKET_SIZE / 2)));
+    assert_eq!(parse_range(&format!("0,{:x}", PACKET_SIZE / 2 + 1)), None);
+    assert_eq!(parse_range("300"), None);
+    assert_eq!(parse_range("300,"), None);
+    assert_eq!(parse_range("x,1"), None);
+}
+
+#[test]
+fn gdb_xfer_chunk() {
+    let doc = "<target/>";
+    assert_eq!(xfer_chunk(doc, "0,4"), "m<tar");
+    assert_eq!(xfer_chunk(doc, "4,100"), "lget/>");
+    // Exactly to the end is the last chunk
+    assert_eq!(xfer_chunk(doc, "0,9"), "l<target/>");
+    // Past the end is an empty last chunk
+    assert_eq!(xfer_chunk(doc, "20,4"), "l");
+    assert_eq!(xfer_chunk(doc, "0"), "E00");
+    assert_eq!(xfer_chunk(doc, "x,4"), "E00");
+}
+
+fn packet(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |s, b| s.wrapping_add(b));
+    format!("${}#{:02x}", data, checksum)
+}
+
+// Send one packet and poll the server until its reply has arrived
+fn exchange(gdb: &mut GdbServer, cpu: &mut CPU, client: &mut TcpStream, data: &str) -> String {
+    client.write_all(packet(data).as_bytes()).unwrap();
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 256];
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        assert!(Instant::now() < deadline, "no reply to {}", data);
+        gdb.poll(cpu);
+        match client.read(&mut buf) {
+            Ok(0) => panic!("server closed the connection"),
+            Ok(n) => reply.extend_from_slice(&buf[..n]),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => panic!("{}", e),
+        }
+        let text = String::from_utf8_lossy(&reply);
+        if let Some(end) = text.find('#').filter(|&end| text.len() >= end + 3) {
+            let start = text.find('$').unwrap();
+            assert_eq!(&text[..start], "+", "ack for {}", data);
+            return text[start + 1..end].to_string();
+        }
+    }
+}
+
+#[test]
+fn gdb_packets_over_tcp() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    cpu.bus.write_bytes(0x300, &[0xA9, 0x42]);
+    cpu.pc = 0x300;
+
+    let mut gdb = GdbServer::listen(0, false).unwrap();
+    let mut client = TcpStream::connect(gdb.local_addr().unwrap()).unwrap();
+    client.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+
+    assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "?"), "S05");
+    assert!(gdb.is_attached() && gdb.is_stopped());
+    assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "m300,2"), "a942");
+    assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "m10000,1"), "E01");
+
+    // A watchpoint running to the top of memory is clamped, not overflowed
+    assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "Z2,ffff,10000"), "OK");
+    assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "Z3,fff0,ffffffff"), "OK");
+    assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "z2,ffff,10000"), "OK");
+    assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "Z2,10000,1"), "E01");
+
+    // Register 5 is PC, little-endian
+    assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "p5"), "0003");
+    assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "s"), "T05");
+    assert_eq!(cpu.regs.a, 0x42);
+    assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "p0"), "42");
+}
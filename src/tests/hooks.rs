@@ -0,0 +1,67 @@
+// PC hooks run from CPU::step: Replace hooks standing in for an instruction.
+
+use super::generic_cpu;
+use crate::cpu::{CpuType, CPU};
+use crate::hooks::HookResult;
+
+// JSR $0400; LDA #$11 at $0300, and LDA #$55; RTS at $0400
+fn cpu_with_subroutine() -> CPU {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    cpu.bus.write_bytes(0x0300, &[0x20, 0x00, 0x04, 0xA9, 0x11]);
+    cpu.bus.write_bytes(0x0400, &[0xA9, 0x55, 0x60]);
+    cpu.pc = 0x0300;
+    cpu.regs.sp = 0xFD;
+    cpu
+}
+
+#[test]
+fn replace_hook_rts_skips_the_subroutine() {
+    let mut cpu = cpu_with_subroutine();
+    cpu.hooks.add_replace(0x0400, "stub", |ctx| {
+        ctx.a = 0x77;
+        ctx.rts();
+        HookResult::Skip
+    });
+
+    assert_eq!(cpu.step(), 6); // JSR
+    assert_eq!((cpu.pc, cpu.regs.sp), (0x0400, 0xFB));
+
+    let before = cpu.bus.iou.cycles;
+    assert_eq!(cpu.step(), 6, "RTS's cycles are charged");
+    assert_eq!(cpu.bus.iou.cycles - before, 6, "and the bus is ticked for them");
+    assert_eq!((cpu.pc, cpu.regs.sp, cpu.regs.a), (0x0303, 0xFD, 0x77));
+
+    cpu.step();
+    assert_eq!(cpu.regs.a, 0x11, "execution carries on after the JSR");
+}
+
+#[test]
+fn replace_hook_jmp_and_continue() {
+    let mut cpu = cpu_with_subroutine();
+    cpu.hooks.add_replace(0x0300, "jump", |ctx| {
+        ctx.jmp(0x0400);
+        HookResult::Skip
+    });
+    assert_eq!(cpu.step(), 3);
+    assert_eq!((cpu.pc, cpu.regs.sp), (0x0400, 0xFD));
+
+    // Without Skip the instruction at the hook still runs
+    cpu.hooks.add_replace(0x0400, "observe", |_| HookResult::Continue);
+    assert_eq!(cpu.step(), 2);
+    assert_eq!((cpu.pc, cpu.regs.a), (0x0402, 0x55));
+}
+
+#[test]
+fn only_replace_hooks_skip() {
+    let mut cpu = cpu_with_subroutine();
+    cpu.pc = 0x0400;
+    cpu.hooks.add_persistent(0x0400, "watch", |_| HookResult::Skip);
+    cpu.step();
+    assert_eq!((cpu.pc, cpu.regs.a), (0x0402, 0x55));
+
+    // A Skip that leaves PC alone still costs a cycle, so time moves on
+    let mut cpu = cpu_with_subroutine();
+    cpu.hooks.add_replace(0x0300, "stall", |_| HookResult::Skip);
+    assert_eq!(cpu.step(), 1);
+    assert_eq!(cpu.pc, 0x0300);
+}
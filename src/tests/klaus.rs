@@ -0,0 +1,183 @@
+// Klaus Dormann's 6502/65C02 test suites
+// (https://github.com/Klaus2m5/6502_65C02_functional_tests).
+//
+// Each image is a full 64K memory map run on the Generic system from its
+// start address until the CPU traps: a `jmp *` or branch-to-self that
+// leaves the PC unchanged. Reaching the suite's success trap passes; any
+// other trap is a failure and reports where it stopped. The interrupt test
+// drives IRQ and NMI through the $BFFC feedback port on the Bus.
+//
+// Images are looked up as <name>.bin or <name>.hex (Intel HEX, as AS65
+// writes it) in KLAUS_TEST_DIR, then in OUT_DIR, then in build/asm.
+// build.rs assembles asm/6502_interrupt_test.a65 into OUT_DIR with as65,
+// once as is and once with D_clear = 1 for the 65C02; when as65 is missing
+// the interrupt tests are ignored. It links the ca65 programs in asm/ into
+// build/asm, and as ca65 is required to build at all, the BRK test built
+// from asm/test_klauss_37c9.s always runs. The other suites have no source
+// in this repository, so they are ignored unless asked for and fail if
+// their image cannot be found:
+//
+//   KLAUS_TEST_DIR=~/src/6502_65C02_functional_tests/bin_files \
+//       cargo test klaus -- --include-ignored --nocapture
+//
+// The success addresses are those of the images in that repository's
+// bin_files for the functional and extended suites, and of the interrupt
+// test as build.rs configures it. Rebuilding with another configuration
+// moves them; take the new address from the listing.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use super::generic_cpu;
+use crate::cpu::{CpuType, SystemType, CPU};
+use crate::rom::ROM;
+
+// Where build.rs puts the images it assembles with as65, and with ca65
+const BUILT_DIR: &str = env!("OUT_DIR");
+const ASM_BUILD_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/build/asm");
+
+// Generous bound; the functional test needs about 96M cycles
+const MAX_CYCLES: u64 = 200_000_000;
+
+// Bruce Clark's decimal test ends on STP (or a trap) and leaves its
+// verdict in ERROR: 0 for pass
+const DECIMAL_ERROR_ADDR: u16 = 0x000B;
+
+enum Success {
+    // Trap address reached when every test passed
+    Trap(u16),
+    // Run until the program stops, then this byte must be zero
+    ZeroAt(u16),
+}
+
+struct Suite {
+    image: &'static str,
+    start: u16,
+    success: Success,
+}
+
+fn find_image(name: &str) -> Option<PathBuf> {
+    let dirs = env::var_os("KLAUS_TEST_DIR").map(PathBuf::from).into_iter().chain([BUILT_DIR, ASM_BUILD_DIR].map(PathBuf::from));
+    dirs.flat_map(|dir| ["bin", "hex"].map(|ext| dir.join(format!("{}.{}", name, ext))))
+        .find(|p| p.metadata().map(|m| m.len() > 0).unwrap_or(false))
+}
+
+fn load_image(cpu: &mut CPU, path: &Path) {
+    let file = path.to_str().expect("image path is UTF-8");
+    let rom = if path.extension().is_some_and(|e| e == "hex") {
+        ROM::load_from_intel(file, SystemType::Generic)
+    } else {
+        ROM::load_from_file(file, SystemType::Generic)
+    };
+    cpu.bus.load_rom(rom.unwrap_or_else(|e| panic!("{}: {}", path.display(), e)));
+}
+
+// Run until the PC stops moving or the CPU halts. Returns the trap address.
+fn run_to_trap(cpu: &mut CPU) -> Result<u16, String> {
+    while cpu.cycles < MAX_CYCLES {
+        let pc = cpu.pc;
+        cpu.tick();
+        if cpu.bus.interrupts.halted {
+            return Ok(pc);
+        }
+        if cpu.pc == pc && !cpu.bus.interrupts.waiting {
+            return Ok(pc);
+        }
+    }
+    Err(format!("no trap after {} cycles, PC={:04X}", MAX_CYCLES, cpu.pc))
+}
+
+fn run_suite(cpu_type: CpuType, suite: &Suite) {
+    let Some(path) = find_image(suite.image) else {
+        panic!("klaus {}: image not found in KLAUS_TEST_DIR, {} or {}", suite.image, BUILT_DIR, ASM_BUILD_DIR);
+    };
+
+    let mut cpu = generic_cpu(cpu_type);
+    load_image(&mut cpu, &path);
+    cpu.pc = suite.start;
+
+    let trap = run_to_trap(&mut cpu).unwrap_or_else(|e| panic!("{}: {}", suite.image, e));
+    let regs = format!(
+        "A={:02X} X={:02X} Y={:02X} SP={:02X} P={:02X} after {} cycles",
+        cpu.regs.a,
+        cpu.regs.x,
+        cpu.regs.y,
+        cpu.regs.sp,
+        cpu.p.bits(),
+        cpu.cycles
+    );
+    match suite.success {
+        Success::Trap(addr) => {
+            assert_eq!(trap, addr, "{}: trapped at {:04X}, success is {:04X}; {}", suite.image, trap, addr, regs);
+        }
+        Success::ZeroAt(addr) => {
+            let error = cpu.bus.read_byte(addr);
+            assert_eq!(error, 0, "{}: stopped at {:04X} with ${:04X}={:02X}; {}", suite.image, trap, addr, error, regs);
+        }
+    }
+    println!("klaus {}: passed at {:04X}, {}", suite.image, trap, regs);
+}
+
+// The BRK frame check in asm/, linked by build.rs on every build
+const BRK_FRAME: Suite = Suite { image: "test_klauss_37c9", start: 0x0400, success: Success::Trap(0x9040) };
+
+#[test]
+fn klaus_brk_frame_nmos6502() {
+    run_suite(CpuType::NMOS6502, &BRK_FRAME);
+}
+
+#[test]
+fn klaus_brk_frame_65c02() {
+    run_suite(CpuType::CMOS65C02, &BRK_FRAME);
+}
+
+const FUNCTIONAL: Suite = Suite { image: "6502_functional_test", start: 0x0400, success: Success::Trap(0x3469) };
+
+#[test]
+#[ignore = "needs the image in KLAUS_TEST_DIR"]
+fn klaus_functional_nmos6502() {
+    run_suite(CpuType::NMOS6502, &FUNCTIONAL);
+}
+
+#[test]
+#[ignore = "needs the image in KLAUS_TEST_DIR"]
+fn klaus_functional_65c02() {
+    run_suite(CpuType::CMOS65C02, &FUNCTIONAL);
+}
+
+// Built with the Rockwell/WDC bit instructions and WAI/STP enabled
+#[test]
+#[ignore = "needs the image in KLAUS_TEST_DIR"]
+fn klaus_extended_opcodes_wdc65c02s() {
+    let suite = Suite { image: "65C02_extended_opcodes_test", start: 0x0400, success: Success::Trap(0x24F1) };
+    run_suite(CpuType::WDC65C02S, &suite);
+}
+
+#[test]
+#[ignore = "needs the image in KLAUS_TEST_DIR"]
+fn klaus_decimal_nmos6502() {
+    let suite = Suite { image: "6502_decimal_test", start: 0x0200, success: Success::ZeroAt(DECIMAL_ERROR_ADDR) };
+    run_suite(CpuType::NMOS6502, &suite);
+}
+
+#[test]
+#[ignore = "needs the image in KLAUS_TEST_DIR"]
+fn klaus_decimal_65c02() {
+    let suite = Suite { image: "65C02_decimal_test", start: 0x0200, success: Success::ZeroAt(DECIMAL_ERROR_ADDR) };
+    run_suite(CpuType::CMOS65C02, &suite);
+}
+
+#[test]
+#[cfg_attr(not(klaus_interrupt_images), ignore = "as65 was not found to assemble asm/6502_interrupt_test.a65")]
+fn klaus_interrupt_nmos6502() {
+    let suite = Suite { image: "6502_interrupt_test", start: 0x0400, success: Success::Trap(0x0701) };
+    run_suite(CpuType::NMOS6502, &suite);
+}
+
+// D_clear = 1: the 65C02 clears D when taking an interrupt
+#[test]
+#[cfg_attr(not(klaus_interrupt_images), ignore = "as65 was not found to assemble asm/6502_interrupt_test.a65")]
+fn klaus_interrupt_65c02() {
+    let suite = Suite { image: "65C02_interrupt_test", start: 0x0400, success: Success::Trap(0x072B) };
+    run_suite(CpuType::CMOS65C02, &suite);
+}
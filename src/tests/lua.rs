@@ -0,0 +1,91 @@
+// The Lua scripting API: callback registration and removal, and access
+// watches dispatched from the bus.
+
+use super::generic_cpu;
+use crate::cpu::{CpuType, CPU};
+use crate::lua::LuaScript;
+
+fn script(src: &str) -> Box<LuaScript> {
+    Box::new(LuaScript::from_source(src, "test", 0).unwrap_or_else(|e| panic!("{}", e)))
+}
+
+// NOP; LDA $2000; STA $2001; JMP * at $0300, with $42 at $2000
+fn cpu_with(src: &str) -> CPU {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    cpu.bus.write_bytes(0x0300, &[0xEA, 0xAD, 0x00, 0x20, 0x8D, 0x01, 0x20, 0x4C, 0x07, 0x03]);
+    cpu.bus.write_byte(0x2000, 0x42);
+    cpu.pc = 0x0300;
+    cpu.lua = Some(script(src));
+    cpu
+}
+
+#[test]
+fn lua_registry_ids_and_remove() {
+    let lua = script(
+        r#"
+        local f = function(m) end
+        local ids = {
+            emu.on_pc(0x0300, f), emu.after(10, f), emu.every(10, f),
+            emu.watch(0x2000, f), emu.on_read(0x2000, 0x20FF, f), emu.on_write(0x2000, 0x2000, f, { equal = 1 }),
+        }
+        for i = 2, #ids do assert(ids[i] > ids[i - 1], "ids are not increasing") end
+        emu.remove(ids[2])
+        emu.remove(ids[5])
+        emu.remove(9999)
+        "#,
+    );
+    assert_eq!(lua.callback_count(), 4);
+
+    assert!(LuaScript::from_source("emu.every(0, function() end)", "test", 0).is_err());
+    assert!(LuaScript::from_source("emu.on_read(0, 1, function() end, { bank = 'nowhere' })", "test", 0).is_err());
+}
+
+#[test]
+fn lua_access_watches_dispatch() {
+    let mut cpu = cpu_with(
+        r#"
+        emu.on_read(0x2000, 0x2000, function(m, hit)
+            m:poke(0x4000, hit.value)
+            m:poke(0x4001, hit.pc - 0x0300)
+        end)
+        emu.on_write(0x2001, 0x2001, function(m, hit) m:poke(0x4002, hit.value) end, { equal = 0x42 })
+        emu.on_write(0x2001, 0x2001, function(m, hit) m:poke(0x4003, 1) end, { equal = 0x99 })
+        emu.on_read(0x2000, 0x2000, function(m, hit) m:poke(0x4004, m:peek(0x4004) + 1); return false end)
+        "#,
+    );
+    // The watches reach the bus on the first tick, after the NOP
+    for _ in 0..4 {
+        cpu.tick();
+    }
+    assert_eq!(cpu.bus.read_byte(0x4000), 0x42);
+    assert_eq!(cpu.bus.read_byte(0x4001), 0x01);
+    assert_eq!(cpu.bus.read_byte(0x4002), 0x42);
+    assert_eq!(cpu.bus.read_byte(0x4003), 0x00, "condition not met");
+    assert_eq!(cpu.bus.read_byte(0x4004), 0x01);
+    assert_eq!(cpu.lua.as_ref().unwrap().callback_count(), 3, "callback returning false is removed");
+}
+
+#[test]
+fn lua_pc_hook_and_removal_from_callback() {
+    let mut cpu = cpu_with(
+        r#"
+        local id
+        id = emu.on_pc(0x0307, function(m)
+            m:poke(0x4000, m:peek(0x4000) + 1)
+            if m:peek(0x4000) == 3 then emu.remove(id) end
+        end)
+        "#,
+    );
+    for _ in 0..10 {
+        cpu.tick();
+    }
+    assert_eq!(cpu.bus.read_byte(0x4000), 3);
+    assert_eq!(cpu.lua.as_ref().unwrap().callback_count(), 0);
+}
+
+#[test]
+fn lua_type_rejects_non_ascii() {
+    assert!(LuaScript::from_source("emu.type('RUN\\n')", "test", 0).is_ok());
+    let err = LuaScript::from_source("emu.type('caf\u{E9}')", "test", 0).err().expect("non-ASCII is rejected");
+    assert!(err.to_string().contains("not on the Apple II keyboard"), "{}", err);
+}
@@ -0,0 +1,42 @@
+// Test suites.
+//
+// The emulator is a single binary crate, so suites that drive `CPU` and
+// `Bus` directly live here instead of under tests/. Run with `cargo test`.
+// tracediff.rs belongs to the tracediff tool in src/bin, which includes it.
+
+mod coverage;
+mod dap;
+mod disassembler;
+mod gdb;
+mod hooks;
+mod klaus;
+mod lua;
+mod monitor;
+mod movie;
+mod profiler;
+mod recorder;
+mod rewind;
+mod rom;
+mod savestate;
+mod script;
+mod single_step;
+mod softswitch;
+mod text_screen;
+mod trace;
+mod watch;
+
+use crate::audio_mixer::DummyAudioMixer;
+use crate::cpu::{CpuType, SystemType, CPU};
+
+// A bare CPU on the flat 64K Generic bus, no Apple IIc hardware mapped in.
+pub fn generic_cpu(cpu_type: CpuType) -> CPU {
+    let (_mixer, producers) = DummyAudioMixer::new();
+    CPU::new(SystemType::Generic, cpu_type, 1_000_000, false, producers.speaker, 44100)
+}
+
+// A //c with no ROM loaded: RAM, MMU and soft switches, for tests that
+// poke memory and switches rather than run firmware.
+pub fn iic_cpu() -> CPU {
+    let (_mixer, producers) = DummyAudioMixer::new();
+    CPU::new(SystemType::AppleIIc, CpuType::CMOS65C02, 1_000_000, false, producers.speaker, 44100)
+}
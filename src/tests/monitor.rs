@@ -0,0 +1,101 @@
+// The monitor: breakpoint conditions, memory commands and running to a stop.
+
+use super::generic_cpu;
+use crate::cpu::{CpuType, Flags};
+use crate::monitor::{compare_memory, search_memory, Condition, Monitor};
+
+#[test]
+fn monitor_condition_parse() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    cpu.regs.a = 0xFF;
+    cpu.regs.sp = 0xE0;
+    cpu.pc = 0x0300;
+    cpu.p = Flags::ZERO;
+
+    let cases: &[(&str, bool)] = &[
+        ("A==FF", true),
+        ("a = $ff", true),
+        ("A!=FF", false),
+        ("SP<E0", false),
+        ("SP<=E0", true),
+        ("SP>=E1", false),
+        ("SP>DF", true),
+        ("PC==300", true),
+        ("Z==1", true),
+        ("C==1", false),
+        ("X!=0", false),
+    ];
+    for &(text, holds) in cases {
+        let condition = Condition::parse(text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+        assert_eq!(condition.holds(&cpu), holds, "{}", text);
+    }
+    // The longest operator wins where two start together
+    assert_eq!(Condition::parse("A<=10").unwrap().to_string(), "A<=$10");
+    assert_eq!(Condition::parse("A=10").unwrap().to_string(), "A==$10");
+
+    for (text, error) in [
+        ("A", "No comparison in 'A'"),
+        ("Q==1", "Unknown register or flag 'Q'"),
+        ("A==", "Bad value in 'A=='"),
+        ("A==XYZ", "Bad value in 'A==XYZ'"),
+    ] {
+        assert_eq!(Condition::parse(text).err().as_deref(), Some(error));
+    }
+}
+
+#[test]
+fn monitor_memory_commands() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    let mut monitor = Monitor::new(&mut cpu);
+    monitor.execute_command("fill 1000 100F AA");
+    monitor.execute_command("fill 1004 1005 $55");
+    // Bad ranges and values change nothing
+    monitor.execute_command("fill 100F 1000 00");
+    monitor.execute_command("fill 1000 100F 100");
+    // Overlapping moves copy what was there
+    monitor.execute_command("move 1000 1007 1002");
+    drop(monitor);
+
+    let memory: Vec<u8> = (0x1000..0x1010).map(|addr| cpu.bus.peek_byte(addr)).collect();
+    assert_eq!(memory, [0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0x55, 0x55, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA]);
+
+    assert_eq!(compare_memory(&mut cpu.bus, 0x1000, 0x1007, 0x1008), [0x1006, 0x1007]);
+    assert!(compare_memory(&mut cpu.bus, 0x1000, 0x1005, 0x1008).is_empty());
+    assert_eq!(search_memory(&mut cpu.bus, 0x1000, 0x100F, &[0x55]), [0x1006, 0x1007]);
+    assert_eq!(search_memory(&mut cpu.bus, 0x1000, 0x100F, &[0xAA, 0x55]), [0x1005]);
+    // Only where the whole pattern fits before the end
+    assert!(search_memory(&mut cpu.bus, 0x1000, 0x1006, &[0x55, 0x55]).is_empty());
+    assert_eq!(search_memory(&mut cpu.bus, 0x1000, 0x1007, &[0x55, 0x55]), [0x1006]);
+    assert_eq!(search_memory(&mut cpu.bus, 0xFFFE, 0xFFFF, &[0x00]), [0xFFFE, 0xFFFF]);
+}
+
+#[test]
+fn monitor_runs_to_breakpoints() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    // $0300: LDX #$00 / INX / JSR $0310 / BRA $0302; $0310: RTS
+    cpu.bus.write_bytes(0x0300, &[0xA2, 0x00, 0xE8, 0x20, 0x10, 0x03, 0x80, 0xFA]);
+    cpu.bus.write_bytes(0x0310, &[0x60]);
+    cpu.pc = 0x0300;
+    cpu.regs.sp = 0xFF;
+
+    let mut monitor = Monitor::new(&mut cpu);
+    monitor.execute_command("until 0303");
+    drop(monitor);
+    assert_eq!((cpu.pc, cpu.regs.x), (0x0303, 1));
+    // The monitor keeps the CPU halted between commands
+    assert!(cpu.bus.interrupts.halted);
+
+    let mut monitor = Monitor::new(&mut cpu);
+    monitor.execute_command("break 0310 if X==4");
+    monitor.execute_command("until FFFF");
+    drop(monitor);
+    assert_eq!((cpu.pc, cpu.regs.x), (0x0310, 4));
+
+    // next runs over the JSR and stops after it
+    let mut monitor = Monitor::new(&mut cpu);
+    monitor.execute_command("delete 0310");
+    monitor.execute_command("until 0303");
+    monitor.execute_command("next");
+    drop(monitor);
+    assert_eq!((cpu.pc, cpu.regs.x, cpu.regs.sp), (0x0306, 5, 0xFF));
+}
@@ -0,0 +1,197 @@
+// Input movies: a recorded session replays to the same machine state, and
+// damaged movie files are rejected or cut back to their last whole event.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::iic_cpu;
+use crate::cpu::CPU;
+use crate::movie::{self, InputEvent, Movie, MoviePlayer, MovieRecorder, MOVIE_MAGIC, MOVIE_VERSION};
+use crate::savestate::{save_machine, StateWriter};
+
+const RUN_CYCLES: u64 = 60_000;
+
+fn temp_path(name: &str, ext: &str) -> PathBuf {
+    env::temp_dir().join(format!("rust-iic-movie-{}-{}.{}", name, std::process::id(), ext))
+}
+
+// Load a //c with a loop that samples every input a movie carries: the
+// keyboard, paddle 0's timer, button 0 and the disk data latch, keeping
+// the last of each and a running sum of what it read
+fn input_loop(cpu: &mut CPU) {
+    #[rustfmt::skip]
+    let program = [
+        0xAD, 0x00, 0xC0,   // $0300 LDA $C000
+        0x8D, 0x00, 0x04,   //       STA $0400
+        0x2C, 0x10, 0xC0,   //       BIT $C010
+        0xAD, 0x61, 0xC0,   //       LDA $C061
+        0x8D, 0x01, 0x04,   //       STA $0401
+        0xAD, 0x70, 0xC0,   //       LDA $C070
+        0xA2, 0x00,         //       LDX #$00
+        0xAD, 0x64, 0xC0,   // $0314 LDA $C064
+        0x10, 0x03,         //       BPL $031C
+        0xE8,               //       INX
+        0xD0, 0xF8,         //       BNE $0314
+        0x8E, 0x02, 0x04,   // $031C STX $0402
+        0xAD, 0xE9, 0xC0,   //       LDA $C0E9
+        0xAD, 0xEC, 0xC0,   //       LDA $C0EC
+        0x8D, 0x03, 0x04,   //       STA $0403
+        0x18,               //       CLC
+        0x6D, 0x04, 0x04,   //       ADC $0404
+        0x8D, 0x04, 0x04,   //       STA $0404
+        0x4C, 0x00, 0x03,   //       JMP $0300
+    ];
+    cpu.bus.write_bytes(0x0300, &program);
+    cpu.pc = 0x0300;
+}
+
+fn run_to(cpu: &mut CPU, cycles: u64) {
+    while cpu.cycles < cycles {
+        cpu.tick();
+    }
+}
+
+// Record a session as App::send_input does, stamping each event with the
+// cycle it lands on before applying it; returns the state it ends in
+fn record(cpu: &mut CPU, path: &Path, disk: &Path) -> Vec<u8> {
+    let start = cpu.cycles;
+    let events = [
+        (1_000, InputEvent::KeyDown { key: 4, code: 0xC1 }),
+        (3_000, InputEvent::KeyUp { key: 4 }),
+        (5_000, InputEvent::Paddle { paddle: 0, position: Some(200) }),
+        (9_000, InputEvent::PaddleButton { button: 0, pressed: true }),
+        (12_000, InputEvent::InsertDisk { drive: 0, path: disk.to_str().unwrap().to_string() }),
+        (20_000, InputEvent::WriteProtect { drive: 0 }),
+        (25_000, InputEvent::PaddleButton { button: 0, pressed: false }),
+        (30_000, InputEvent::Paddle { paddle: 0, position: Some(16) }),
+        (35_000, InputEvent::KeyDown { key: 5, code: 0xC2 }),
+        (36_000, InputEvent::KeyUp { key: 5 }),
+        (40_000, InputEvent::EjectDisk { drive: 0 }),
+    ];
+
+    let mut recorder = MovieRecorder::create(path, cpu).unwrap();
+    for (offset, event) in &events {
+        run_to(cpu, start + offset);
+        recorder.record(cpu.cycles, event).unwrap();
+        movie::apply(cpu, event);
+    }
+    assert_eq!(recorder.events(), events.len() as u64);
+    run_to(cpu, start + RUN_CYCLES);
+    save_machine(cpu)
+}
+
+// Replay a movie on a fresh machine the way main does: the RAM seed, then
+// the start state, then each event before the instruction it is due at
+fn replay(path: &Path, end: u64) -> CPU {
+    let movie = Movie::load(path).unwrap();
+    let mut cpu = iic_cpu();
+    cpu.bus.randomize_ram(movie.ram_seed);
+    if movie.start_state.is_empty() {
+        input_loop(&mut cpu);
+    }
+    let mut player = MoviePlayer::start(movie, &mut cpu).unwrap();
+    while cpu.cycles < end {
+        player.apply_due(&mut cpu);
+        cpu.tick();
+    }
+    assert!(player.is_finished());
+    cpu
+}
+
+#[test]
+fn movie_replays_bit_exact() {
+    let disk = temp_path("disk", "dsk");
+    fs::write(&disk, vec![0u8; 143_360]).unwrap();
+
+    // From power-on, where only the RAM seed is stored, and from mid-run,
+    // where the whole state is embedded
+    for (name, warm_up) in [("power-on", 0), ("mid-run", 7_000)] {
+        let path = temp_path(name, "iicmovie");
+        let mut cpu = iic_cpu();
+        cpu.bus.randomize_ram(0x1234_5678);
+        input_loop(&mut cpu);
+        run_to(&mut cpu, warm_up);
+        let expected = record(&mut cpu, &path, &disk);
+
+        let mut replayed = replay(&path, cpu.cycles);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replayed.cycles, cpu.cycles, "{}", name);
+        assert!(save_machine(&replayed) == expected, "{}: replay diverged", name);
+        // The inputs reached the program, so the comparison means something
+        assert_eq!(replayed.bus.peek_byte(0x0400) & 0x7F, b'B', "{}", name);
+    }
+    fs::remove_file(&disk).unwrap();
+}
+
+// A movie file from parts: header fields, then raw event bytes
+fn movie_bytes(magic: &[u8; 8], version: u16, events: &[(u64, &[u8])]) -> Vec<u8> {
+    let mut w = StateWriter::new();
+    w.array(magic);
+    w.u16(version);
+    w.u64(42);
+    w.bytes(&[]);
+    for (cycle, event) in events {
+        w.u64(*cycle);
+        w.array(event);
+    }
+    w.into_bytes()
+}
+
+fn load(name: &str, data: &[u8]) -> anyhow::Result<Movie> {
+    let path = temp_path(name, "iicmovie");
+    fs::write(&path, data).unwrap();
+    let movie = Movie::load(&path);
+    fs::remove_file(&path).unwrap();
+    movie
+}
+
+#[test]
+fn movie_load_rejects_bad_headers() {
+    let key_down: &[u8] = &[0, 4, 0, 0xC1];
+    let good = movie_bytes(MOVIE_MAGIC, MOVIE_VERSION, &[(100, key_down)]);
+    let movie = load("good", &good).unwrap();
+    assert_eq!(movie.ram_seed, 42);
+    assert!(movie.start_state.is_empty());
+    assert_eq!(movie.events, [(100, InputEvent::KeyDown { key: 4, code: 0xC1 })]);
+
+    let magic = load("magic", &movie_bytes(b"IICSTATE", MOVIE_VERSION, &[]));
+    assert_eq!(magic.err().unwrap().to_string(), "not an Apple IIc input movie");
+    let version = load("version", &movie_bytes(MOVIE_MAGIC, MOVIE_VERSION + 1, &[]));
+    assert_eq!(version.err().unwrap().to_string(), format!("unsupported movie version {} (expected {})", MOVIE_VERSION + 1, MOVIE_VERSION));
+
+    // Cut anywhere inside the header, the movie is unusable
+    let header = movie_bytes(MOVIE_MAGIC, MOVIE_VERSION, &[]).len();
+    for len in [0, 4, 9, 14, header - 1] {
+        assert!(load("short", &good[..len]).is_err(), "{} bytes", len);
+    }
+
+    // A start state longer than the file is too
+    let mut w = StateWriter::new();
+    w.array(MOVIE_MAGIC);
+    w.u16(MOVIE_VERSION);
+    w.u64(42);
+    w.bytes(&[0; 64]);
+    let mut state = w.into_bytes();
+    state.truncate(state.len() - 10);
+    assert!(load("state", &state).is_err());
+}
+
+#[test]
+fn movie_load_keeps_events_up_to_the_damage() {
+    let key_down: &[u8] = &[0, 4, 0, 0xC1];
+    let key_up: &[u8] = &[1, 4, 0];
+    let expected = [(100, InputEvent::KeyDown { key: 4, code: 0xC1 }), (200, InputEvent::KeyUp { key: 4 })];
+
+    // A recording killed mid-write: every cut inside the third event keeps
+    // the first two
+    let full = movie_bytes(MOVIE_MAGIC, MOVIE_VERSION, &[(100, key_down), (200, key_up), (300, key_down)]);
+    for cut in 1..8 + key_down.len() {
+        let movie = load("cut", &full[..full.len() - cut]).unwrap();
+        assert_eq!(movie.events, expected, "{} bytes cut", cut);
+    }
+
+    // An unknown event kind ends the movie there, whatever follows it
+    let corrupt = movie_bytes(MOVIE_MAGIC, MOVIE_VERSION, &[(100, key_down), (200, key_up), (300, &[99]), (400, key_up)]);
+    assert_eq!(load("corrupt", &corrupt).unwrap().events, expected);
+}
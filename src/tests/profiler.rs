@@ -0,0 +1,115 @@
+// The profiler's call stack, unwound from SP, and its collapsed-stack output.
+
+use std::env;
+use std::fs;
+
+use super::generic_cpu;
+use crate::cpu::CpuType;
+use crate::disassembler::SymbolTable;
+use crate::mmu::MemoryView;
+use crate::profiler::{CodeAddr, Profiler};
+
+const MAIN: CodeAddr = (None, 0x0400);
+const SUB: CodeAddr = (None, 0x0500);
+const HANDLER: CodeAddr = (Some(MemoryView::Rom1), 0xFA00);
+const RESET: CodeAddr = (Some(MemoryView::Main), 0x0600);
+
+// Calls, returns, an interrupt, a dropped return address and a stack reset
+fn profile() -> Profiler {
+    let mut profiler = Profiler::new(None);
+    let top = |addr| (None, addr);
+
+    profiler.record(top(0x0300), 2, 0xFF, None);
+    // JSR MAIN, which calls SUB, then both return
+    profiler.record(top(0x0302), 6, 0xFD, Some((MAIN, 0xFF)));
+    profiler.record((None, 0x0400), 2, 0xFD, None);
+    profiler.record((None, 0x0402), 6, 0xFB, Some((SUB, 0xFD)));
+    profiler.record((None, 0x0500), 3, 0xFB, None);
+    profiler.record((None, 0x0501), 6, 0xFD, None);
+    profiler.record((None, 0x0405), 6, 0xFF, None);
+    profiler.record(top(0x0305), 2, 0xFF, None);
+
+    // An interrupt, returning with RTI
+    profiler.interrupt(HANDLER, 7, 0xFF);
+    profiler.record(HANDLER, 2, 0xFC, None);
+    profiler.record((HANDLER.0, 0xFA02), 6, 0xFF, None);
+
+    // MAIN again, pulling its return address instead of returning
+    profiler.record(top(0x0306), 6, 0xFD, Some((MAIN, 0xFF)));
+    profiler.record((None, 0x0400), 4, 0xFE, None);
+    profiler.record((None, 0x0401), 4, 0xFF, None);
+
+    // A call two deep, ended by LDX #$FF / TXS
+    profiler.record(top(0x0309), 6, 0xFD, Some((RESET, 0xFF)));
+    profiler.record(RESET, 6, 0xFB, Some((SUB, 0xFD)));
+    profiler.record((None, 0x0500), 2, 0xFB, None);
+    profiler.record((None, 0x0502), 2, 0xFF, None);
+    profiler.record(top(0x030C), 2, 0xFF, None);
+    profiler
+}
+
+#[test]
+fn profiler_unwinds_by_stack_pointer() {
+    let profiler = profile();
+    assert_eq!(profiler.total().instructions, 18);
+    assert_eq!(profiler.total().cycles, 80);
+
+    let calls: Vec<_> = profiler.calls().iter().map(|call| (call.caller, call.callee, call.calls, call.cycles)).collect();
+    assert_eq!(
+        calls,
+        [
+            (None, MAIN, 2, 31),
+            (None, HANDLER, 1, 15),
+            (None, RESET, 1, 10),
+            (Some(MAIN), SUB, 1, 9),
+            (Some(RESET), SUB, 1, 4),
+        ]
+    );
+
+    // Nine addresses took 6 cycles; ties go to the lower address
+    let spots: Vec<_> = profiler.hot_spots(4).iter().map(|(at, stats)| (at.1, stats.cycles)).collect();
+    assert_eq!(spots, [(0x0302, 6), (0x0306, 6), (0x0309, 6), (0x0400, 6)]);
+}
+
+#[test]
+fn profiler_write_collapsed() {
+    let mut symbols = SymbolTable::new();
+    symbols.insert(MAIN.1, "MAIN", None);
+    symbols.insert(SUB.1, "SUB", None);
+    let path = env::temp_dir().join(format!("rust-iic-collapsed-{}.txt", std::process::id()));
+    let lines = profile().write_collapsed(&path, &symbols).unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(lines, 6);
+    assert_eq!(
+        text,
+        "top 24\n\
+         top;MAIN 22\n\
+         top;MAIN;SUB 9\n\
+         top;rom1:$FA00 15\n\
+         top;$0600 6\n\
+         top;$0600;SUB 4\n"
+    );
+}
+
+#[test]
+fn profiler_follows_the_cpu() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    // $0300: JSR $0310 / JSR $0310 / NOP; $0310: NOP / RTS
+    cpu.bus.write_bytes(0x0300, &[0x20, 0x10, 0x03, 0x20, 0x10, 0x03, 0xEA]);
+    cpu.bus.write_bytes(0x0310, &[0xEA, 0x60]);
+    cpu.pc = 0x0300;
+    cpu.regs.sp = 0xFF;
+    cpu.profiler = Some(Box::new(Profiler::new(None)));
+    for _ in 0..7 {
+        cpu.tick();
+    }
+
+    let profiler = cpu.profiler.take().unwrap();
+    assert_eq!(profiler.total().instructions, 7);
+    assert_eq!(profiler.total().cycles, 6 + 2 + 6 + 6 + 2 + 6 + 2);
+    let calls = profiler.calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!((calls[0].caller, calls[0].callee.1, calls[0].calls, calls[0].cycles), (None, 0x0310, 2, 16));
+}
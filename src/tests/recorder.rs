@@ -0,0 +1,56 @@
+// Audio/video recording: one video frame per frame of emulated time, and
+// as much audio as those frames last, however the host loop runs them.
+
+use std::env;
+use std::fs;
+
+use super::iic_cpu;
+use crate::recorder::Recorder;
+use crate::timing;
+
+// Record a //c clicking its speaker for a number of host passes of
+// `speed` frames each, checking for due frames after every scanline as the
+// run loop does; returns the video frames and audio samples written
+fn record(name: &str, passes: u64, speed: u64) -> (u64, u64) {
+    let dir = env::temp_dir().join(format!("rust-iic-recording-{}-{}", name, std::process::id()));
+    let mut cpu = iic_cpu();
+    // $0300: LDA $C030 / JMP $0300
+    cpu.bus.write_bytes(0x0300, &[0xAD, 0x30, 0xC0, 0x4C, 0x00, 0x03]);
+    cpu.pc = 0x0300;
+
+    let mut recorder = Recorder::start(&dir, &mut cpu).unwrap();
+    let scanline = timing::CYCLES_PER_FRAME * speed / timing::SCANLINES_PER_FRAME;
+    for _ in 0..passes * timing::SCANLINES_PER_FRAME {
+        let target = cpu.cycles + scanline;
+        while cpu.cycles < target {
+            cpu.tick();
+        }
+        recorder.capture_due(&mut cpu).unwrap();
+    }
+    let frames = recorder.finish(&mut cpu).unwrap();
+
+    let wav = fs::read(dir.join("audio.wav")).unwrap();
+    let data_len = u32::from_le_bytes(wav[40..44].try_into().unwrap()) as u64;
+    let pngs = fs::read_dir(&dir)
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension().is_some_and(|ext| ext == "png"))
+        .count();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(pngs as u64, frames);
+    (frames, data_len / 4)
+}
+
+#[test]
+fn recorder_keeps_video_and_audio_in_step() {
+    let rate = iic_cpu().bus.iou.speaker.sample_rate() as f64;
+    let samples_per_frame = rate * timing::CYCLES_PER_FRAME as f64 / timing::CYCLES_PER_SECOND;
+
+    // At normal speed, and eight frames to a pass as fast disk runs
+    for (name, passes, speed, frames) in [("normal", 4, 1, 4), ("fast", 2, 8, 16)] {
+        let (video, samples) = record(name, passes, speed);
+        // The last frame may fall a scanline short of being due
+        assert!(video == frames || video == frames - 1, "{}: {} frames", name, video);
+        let expected = video as f64 * samples_per_frame;
+        assert!((samples as f64 - expected).abs() < samples_per_frame / 4.0, "{}: {} samples for {} frames", name, samples, video);
+    }
+}
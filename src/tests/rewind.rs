@@ -0,0 +1,102 @@
+// The rewind ring: when snapshots are taken, which ones are kept, and how
+// holding and releasing the rewind key steps back through them.
+
+use super::generic_cpu;
+use crate::cpu::{CpuType, CPU};
+use crate::rewind::RewindBuffer;
+use crate::savestate::save_machine;
+use crate::timing;
+
+const SECOND: u64 = timing::CYCLES_PER_SECOND as u64;
+
+// Move the machine to a cycle count, marking it so each moment saves
+// differently, and offer the ring a snapshot; returns the machine's state
+fn frame_at(cpu: &mut CPU, rewind: &mut RewindBuffer, cycles: u64) -> Vec<u8> {
+    cpu.cycles = cycles;
+    cpu.bus.write_bytes(0x0300, &cycles.to_le_bytes());
+    rewind.capture(cpu);
+    save_machine(cpu)
+}
+
+// Hold the key for a number of frames; returns the frames that restored
+fn hold(cpu: &mut CPU, rewind: &mut RewindBuffer, frames: usize) -> Vec<usize> {
+    (0..frames).filter(|_| rewind.hold(cpu).unwrap()).collect()
+}
+
+#[test]
+fn rewind_captures_once_per_emulated_second() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    let mut rewind = RewindBuffer::new(10);
+    let first = frame_at(&mut cpu, &mut rewind, 0);
+    frame_at(&mut cpu, &mut rewind, SECOND / 2);
+    frame_at(&mut cpu, &mut rewind, SECOND - 1);
+    let second = frame_at(&mut cpu, &mut rewind, SECOND + 5);
+    // The next interval counts from the snapshot, not the second boundary
+    frame_at(&mut cpu, &mut rewind, 2 * SECOND);
+    let third = frame_at(&mut cpu, &mut rewind, 2 * SECOND + 5);
+
+    assert_eq!(hold(&mut cpu, &mut rewind, 1), [0]);
+    assert!(save_machine(&cpu) == third);
+    assert_eq!(hold(&mut cpu, &mut rewind, 10), [9]);
+    assert!(save_machine(&cpu) == second);
+    assert_eq!(hold(&mut cpu, &mut rewind, 10), [9]);
+    assert!(save_machine(&cpu) == first);
+}
+
+#[test]
+fn rewind_ring_drops_the_oldest() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    let mut rewind = RewindBuffer::new(3);
+    let states: Vec<Vec<u8>> = (0..5).map(|second| frame_at(&mut cpu, &mut rewind, second * SECOND)).collect();
+
+    // Restores every 10 frames, newest first, then stays on the oldest kept
+    assert_eq!(hold(&mut cpu, &mut rewind, 45), [0, 10, 20, 30, 40]);
+    assert!(save_machine(&cpu) == states[2]);
+
+    // Seconds 0 and 1 are gone: holding on never reaches them
+    assert_eq!(hold(&mut cpu, &mut rewind, 20).len(), 2);
+    assert!(save_machine(&cpu) == states[2]);
+}
+
+#[test]
+fn rewind_release_restores_at_once_on_the_next_hold() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    let mut rewind = RewindBuffer::new(5);
+    let states: Vec<Vec<u8>> = (0..3).map(|second| frame_at(&mut cpu, &mut rewind, second * SECOND)).collect();
+
+    assert_eq!(hold(&mut cpu, &mut rewind, 5), [0]);
+    assert!(save_machine(&cpu) == states[2]);
+    // Without a release the count carries on, to frame 10
+    assert_eq!(hold(&mut cpu, &mut rewind, 6), [5]);
+    assert!(save_machine(&cpu) == states[1]);
+    rewind.release();
+    assert_eq!(hold(&mut cpu, &mut rewind, 1), [0]);
+    assert!(save_machine(&cpu) == states[0]);
+
+    // Running on after a restore captures a second later, not at once
+    rewind.release();
+    frame_at(&mut cpu, &mut rewind, SECOND - 1);
+    let resumed = frame_at(&mut cpu, &mut rewind, SECOND);
+    assert_eq!(hold(&mut cpu, &mut rewind, 1), [0]);
+    assert!(save_machine(&cpu) == resumed);
+}
+
+#[test]
+fn rewind_restarts_the_interval_when_the_clock_goes_back() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    let mut rewind = RewindBuffer::new(5);
+    frame_at(&mut cpu, &mut rewind, 10 * SECOND);
+    // A power cycle or quick-load puts the clock back to 0
+    let reset = frame_at(&mut cpu, &mut rewind, 0);
+    assert_eq!(hold(&mut cpu, &mut rewind, 1), [0]);
+    assert!(save_machine(&cpu) == reset);
+}
+
+#[test]
+fn rewind_with_no_depth_captures_nothing() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    let mut rewind = RewindBuffer::new(0);
+    frame_at(&mut cpu, &mut rewind, 0);
+    frame_at(&mut cpu, &mut rewind, SECOND);
+    assert!(hold(&mut cpu, &mut rewind, 30).is_empty());
+}
@@ -0,0 +1,166 @@
+// //c firmware revisions: telling them apart, the image sizes each takes,
+// and the hardware differences that follow from the revision.
+
+use super::iic_cpu;
+use crate::mmu::MemoryView;
+use crate::rom::{RomRevision, ROM};
+use crate::savestate::{load_machine, save_machine};
+
+const ID_BYTE: usize = 0xFBBF - 0xC000;
+
+// A blank image of `size` bytes with the $FBBF version byte set
+fn image(size: usize, id: u8) -> Vec<u8> {
+    let mut bytes = vec![0x11; size];
+    if size > 0x4000 {
+        bytes[0x4000..].fill(0x22);
+    }
+    bytes[ID_BYTE] = id;
+    bytes
+}
+
+// Rewrite the last four bytes so the image has the given CRC32, standing
+// in for the real dumps, which cannot ship with the tests
+fn forge_crc(bytes: &mut [u8], crc: u32) {
+    let table: Vec<u32> = (0..256u32)
+        .map(|n| (0..8).fold(n, |c, _| if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 }))
+        .collect();
+    let len = bytes.len();
+    let prefix = !crc32fast::hash(&bytes[..len - 4]);
+    // Run the register back from the target over four unknown bytes
+    let mut reg = !crc;
+    for _ in 0..4 {
+        let j = table.iter().position(|t| t >> 24 == reg >> 24).unwrap() as u32;
+        reg = ((reg ^ table[j as usize]) << 8) | j;
+    }
+    bytes[len - 4..].copy_from_slice(&(reg ^ prefix).to_le_bytes());
+    assert_eq!(crc32fast::hash(bytes), crc);
+}
+
+#[test]
+fn rom_detects_known_dumps_by_checksum() {
+    let known = [
+        (0xF0ED_AA1B, RomRevision::Rom255),
+        (0xC8B9_79B3, RomRevision::Rom0),
+        (0xBC5A_79FF, RomRevision::Rom3),
+        (0x06F5_3328, RomRevision::Rom4),
+    ];
+    for (crc, rev) in known {
+        // A version byte no //c uses, so only the checksum can match
+        let mut bytes = image(rev.size(), 0x77);
+        forge_crc(&mut bytes, crc);
+        assert_eq!(RomRevision::detect(&bytes), Some(rev), "{:08X}", crc);
+    }
+}
+
+#[test]
+fn rom_falls_back_to_the_version_byte() {
+    let cases = [
+        (0xFF, Some(RomRevision::Rom255)),
+        (0x00, Some(RomRevision::Rom0)),
+        (0x03, Some(RomRevision::Rom3)),
+        // A ROM 4 that is not the known dump is the modified 4X
+        (0x04, Some(RomRevision::Rom4X)),
+        (0x77, None),
+    ];
+    for (id, rev) in cases {
+        assert_eq!(RomRevision::detect(&image(0x8000, id)), rev, "${:02X}", id);
+    }
+    // Too short to reach $FBBF at all
+    assert_eq!(RomRevision::detect(&[0xFF; 0x100]), None);
+}
+
+#[test]
+fn rom_parses_rom_type_names() {
+    let cases = [
+        ("255", Some(RomRevision::Rom255)),
+        ("FF", Some(RomRevision::Rom255)),
+        ("0", Some(RomRevision::Rom0)),
+        ("3", Some(RomRevision::Rom3)),
+        ("4", Some(RomRevision::Rom4)),
+        ("4X", Some(RomRevision::Rom4X)),
+        ("4x", Some(RomRevision::Rom4X)),
+        ("5", None),
+        ("auto", None),
+        ("", None),
+    ];
+    for (text, rev) in cases {
+        assert_eq!(RomRevision::parse(text), rev, "{}", text);
+    }
+    for rev in RomRevision::ALL {
+        assert_eq!(RomRevision::from_name(rev.name()), Some(rev));
+    }
+}
+
+#[test]
+fn rom_load_iic_checks_the_image_size() {
+    let rom = ROM::load_iic(&image(0x4000, 0xFF), None).unwrap();
+    assert_eq!(rom.revision, Some(RomRevision::Rom255));
+    let rom = ROM::load_iic(&image(0x8000, 0x03), None).unwrap();
+    assert_eq!(rom.revision, Some(RomRevision::Rom3));
+    // --rom-type wins over the version byte
+    let rom = ROM::load_iic(&image(0x8000, 0x00), Some(RomRevision::Rom4)).unwrap();
+    assert_eq!(rom.revision, Some(RomRevision::Rom4));
+
+    let errors = [
+        (image(0x8000, 0xFF), None, "ROM 255 image must be 16384 bytes, got 32768"),
+        (image(0x4000, 0x00), None, "ROM 0 image must be 32768 bytes, got 16384"),
+        (image(0x8000, 0x00), Some(RomRevision::Rom255), "ROM 255 image must be 16384 bytes, got 32768"),
+        (image(0x6000, 0x04), None, "ROM 4X image must be 32768 bytes, got 24576"),
+        (image(0x8000, 0x77), None, "unrecognized Apple //c ROM; pass --rom-type"),
+    ];
+    for (bytes, forced, error) in errors {
+        assert_eq!(ROM::load_iic(&bytes, forced).err().unwrap().to_string(), error);
+    }
+}
+
+#[test]
+fn rom_255_mirrors_its_single_bank() {
+    // $E000 is firmware in either bank; ROMBANK ($C028) swaps banks only
+    // when there is a second one
+    for (rev, size, after_switch) in [(RomRevision::Rom255, 0x4000, 0x11), (RomRevision::Rom0, 0x8000, 0x22)] {
+        let mut cpu = iic_cpu();
+        cpu.bus.load_rom(ROM::load_iic(&image(size, rev.id_byte()), None).unwrap());
+        assert_eq!(cpu.bus.read_byte(0xE000), 0x11, "{}", rev.name());
+        cpu.bus.read_byte(0xC028);
+        assert_eq!(cpu.bus.read_byte(0xE000), after_switch, "{}", rev.name());
+    }
+
+    // The alternate bank holds a copy of the one 16K image, not padding
+    let mut cpu = iic_cpu();
+    let bytes = image(0x4000, 0xFF);
+    cpu.bus.load_rom(ROM::load_iic(&bytes, None).unwrap());
+    for addr in [0xC100, 0xE000, 0xFBBF, 0xFFFC] {
+        assert_eq!(cpu.bus.peek_view(MemoryView::Rom2, addr), Some(bytes[addr as usize - 0xC000]));
+        assert_eq!(cpu.bus.peek_view(MemoryView::Rom1, addr), cpu.bus.peek_view(MemoryView::Rom2, addr));
+    }
+}
+
+#[test]
+fn rom_before_3_has_no_memory_expansion() {
+    for rev in RomRevision::ALL {
+        let mut cpu = iic_cpu();
+        cpu.bus.iou.memexp.set_enabled(true);
+        cpu.bus.iou.set_rom_revision(rev);
+        assert_eq!(cpu.bus.iou.memexp.is_enabled(), rev.has_memexp(), "{}", rev.name());
+    }
+    assert!(!RomRevision::Rom255.has_memexp() && !RomRevision::Rom0.has_memexp());
+
+    // Loading an older image switches it off too
+    let mut cpu = iic_cpu();
+    cpu.bus.iou.memexp.set_enabled(true);
+    cpu.bus.load_rom(ROM::load_iic(&image(0x8000, 0x00), None).unwrap());
+    assert!(!cpu.bus.iou.memexp.is_enabled());
+}
+
+#[test]
+fn rom_revision_survives_a_save_state() {
+    for rev in RomRevision::ALL {
+        let mut cpu = iic_cpu();
+        cpu.bus.iou.set_rom_revision(rev);
+        let state = save_machine(&cpu);
+
+        let mut restored = iic_cpu();
+        load_machine(&mut restored, &state).unwrap();
+        assert_eq!(restored.bus.iou.rom_revision, rev);
+    }
+}
@@ -0,0 +1,207 @@
+// Save-state round trips on the Generic system and the //c.
+//
+// A machine saved, disturbed and loaded again must serialize to the same
+// bytes it did before, and run on exactly as it would have; a file that
+// fails to load must leave the machine untouched.
+
+use super::{generic_cpu, iic_cpu};
+use crate::cpu::{CpuType, Flags, CPU};
+use crate::mmu::MemoryView;
+use crate::savestate::{load_machine, save_machine, STATE_MAGIC};
+
+fn busy_cpu() -> CPU {
+    let mut cpu = generic_cpu(CpuType::WDC65C02S);
+    cpu.pc = 0x1234;
+    cpu.regs.a = 0xA5;
+    cpu.regs.x = 0x5A;
+    cpu.regs.y = 0x0F;
+    cpu.regs.sp = 0xE0;
+    cpu.p = Flags::from_bits_truncate(0xC3);
+    cpu.cycles = 123_456_789;
+    cpu.bus.write_bytes(0x0300, b"ROUND TRIP");
+    cpu.bus.write_byte(0xFFFE, 0x00);
+    cpu.bus.write_byte(0xFFFF, 0x03);
+    cpu
+}
+
+fn disturb(cpu: &mut CPU) {
+    cpu.pc = 0;
+    cpu.regs.a = 0;
+    cpu.regs.sp = 0xFF;
+    cpu.cycles = 1;
+    cpu.bus.write_bytes(0x0300, b"----------");
+}
+
+#[test]
+fn savestate_round_trip() {
+    let mut cpu = busy_cpu();
+    let saved = save_machine(&cpu);
+
+    disturb(&mut cpu);
+    load_machine(&mut cpu, &saved).expect("loading our own save state");
+
+    assert_eq!(cpu.pc, 0x1234);
+    assert_eq!((cpu.regs.a, cpu.regs.x, cpu.regs.y, cpu.regs.sp), (0xA5, 0x5A, 0x0F, 0xE0));
+    assert_eq!(cpu.p.bits(), 0xC3);
+    assert_eq!(cpu.cycles, 123_456_789);
+    assert_eq!(cpu.cpu_type, CpuType::WDC65C02S);
+    let text: Vec<u8> = (0x0300..0x030A).map(|addr| cpu.bus.read_byte(addr)).collect();
+    assert_eq!(text, b"ROUND TRIP");
+    assert!(save_machine(&cpu) == saved, "state changed across a save/load round trip");
+}
+
+#[test]
+fn savestate_rejects_unknown_cpu_type() {
+    let mut cpu = busy_cpu();
+    let mut saved = save_machine(&cpu);
+    // magic, version, "CPU ", system type, then the CPU type
+    let cpu_type_at = STATE_MAGIC.len() + 2 + 4 + 1;
+    saved[cpu_type_at] = 0x7F;
+
+    disturb(&mut cpu);
+    let before = save_machine(&cpu);
+    let err = load_machine(&mut cpu, &saved).unwrap_err();
+    assert!(err.to_string().contains("unknown CPU type"), "{}", err);
+    assert!(save_machine(&cpu) == before, "failed load changed the machine");
+}
+
+#[test]
+fn savestate_failed_load_rolls_back() {
+    let mut cpu = busy_cpu();
+    let mut saved = save_machine(&cpu);
+    // Break a section tag after the CPU registers have already been read
+    let bus_at = saved.windows(4).position(|w| w == b"BUS ").expect("BUS section");
+    saved[bus_at] = b'X';
+
+    disturb(&mut cpu);
+    let before = save_machine(&cpu);
+    assert!(load_machine(&mut cpu, &saved).is_err());
+    assert_eq!(cpu.pc, 0);
+    assert!(save_machine(&cpu) == before, "failed load left a half-restored machine");
+}
+
+// A //c with its soft switches away from their power-on settings and
+// something in every RAM bank
+fn busy_iic() -> CPU {
+    let mut cpu = iic_cpu();
+    cpu.pc = 0x0300;
+    cpu.regs.sp = 0xF0;
+    // 80STORE, 80COL, ALTCHAR and ALTZP on; page 2 and hires
+    for switch in [0xC001, 0xC00D, 0xC00F, 0xC009] {
+        cpu.bus.write_byte(switch, 0);
+    }
+    cpu.bus.read_byte(0xC055);
+    cpu.bus.read_byte(0xC057);
+    // LC bank 1, read and write enabled
+    cpu.bus.read_byte(0xC08B);
+    cpu.bus.read_byte(0xC08B);
+    for (view, addr, value) in [
+        (MemoryView::Main, 0x0800, 0x11),
+        (MemoryView::Aux, 0x0800, 0x22),
+        (MemoryView::LcMain1, 0xD000, 0x33),
+        (MemoryView::LcMain2, 0xD000, 0x44),
+        (MemoryView::LcAux1, 0xD000, 0x55),
+        (MemoryView::LcAux2, 0xD000, 0x66),
+        (MemoryView::LcAux1, 0xE000, 0x77),
+    ] {
+        assert!(cpu.bus.poke_view(view, addr, value), "{:?}", view);
+    }
+    // Devices: memory expansion RAM and address, the ZIP chip, a Mockingboard
+    let memexp = &mut cpu.bus.iou.memexp;
+    for (register, value) in [(0, 0x34), (1, 0x12), (2, 0x05), (3, 0xAB), (3, 0xCD)] {
+        memexp.write(register, value);
+    }
+    cpu.bus.iou.set_zip_enabled(true);
+    cpu.bus.iou.mockingboard.set_enabled(true);
+    cpu.bus.iou.mockingboard.write(0x02, 0xFF);
+    cpu.cycles = 1_000_000;
+    cpu
+}
+
+#[test]
+fn savestate_iic_round_trip() {
+    let mut cpu = busy_iic();
+    let saved = save_machine(&cpu);
+
+    // Turn the switches back and overwrite the banks
+    for switch in [0xC000, 0xC00C, 0xC00E, 0xC008] {
+        cpu.bus.write_byte(switch, 0);
+    }
+    cpu.bus.read_byte(0xC054);
+    cpu.bus.read_byte(0xC056);
+    cpu.bus.read_byte(0xC082);
+    for view in [MemoryView::Main, MemoryView::Aux] {
+        cpu.bus.poke_view(view, 0x0800, 0);
+    }
+    for view in [MemoryView::LcMain1, MemoryView::LcMain2, MemoryView::LcAux1, MemoryView::LcAux2] {
+        cpu.bus.poke_view(view, 0xD000, 0);
+    }
+    cpu.bus.iou.memexp.write(3, 0);
+    cpu.bus.iou.set_zip_enabled(false);
+    assert!(save_machine(&cpu) != saved);
+
+    load_machine(&mut cpu, &saved).expect("loading our own save state");
+    assert!(save_machine(&cpu) == saved, "state changed across a save/load round trip");
+    assert!(cpu.bus.iou.is_80store.get());
+    assert!(cpu.bus.iou.zip.present);
+    let banks = [
+        (MemoryView::Main, 0x0800, 0x11),
+        (MemoryView::Aux, 0x0800, 0x22),
+        (MemoryView::LcMain1, 0xD000, 0x33),
+        (MemoryView::LcMain2, 0xD000, 0x44),
+        (MemoryView::LcAux1, 0xD000, 0x55),
+        (MemoryView::LcAux2, 0xD000, 0x66),
+        (MemoryView::LcAux1, 0xE000, 0x77),
+    ];
+    for (view, addr, value) in banks {
+        assert_eq!(cpu.bus.peek_view(view, addr), Some(value), "{:?}", view);
+    }
+    // ALTZP and LC bank 1 reads are back in force
+    assert_eq!(cpu.bus.peek_byte(0xD000), 0x55);
+    // The expansion RAM address carried on past the two bytes written
+    cpu.bus.iou.memexp.write(0, 0x34);
+    cpu.bus.iou.memexp.write(1, 0x12);
+    assert_eq!((cpu.bus.iou.memexp.read(3), cpu.bus.iou.memexp.read(3)), (0xAB, 0xCD));
+}
+
+// Run a loop that flips display switches, writes main, aux and LC RAM and
+// clicks the speaker, for a number of instructions
+fn run(cpu: &mut CPU, instructions: usize) {
+    for _ in 0..instructions {
+        cpu.tick();
+    }
+}
+
+#[test]
+fn savestate_continues_cycle_for_cycle() {
+    let mut cpu = busy_iic();
+    // ALTZP off again so the loop's stack is main RAM
+    cpu.bus.write_byte(0xC008, 0);
+    #[rustfmt::skip]
+    let program = [
+        0xEE, 0x00, 0x04,   // $0300 INC $0400
+        0xAD, 0x55, 0xC0,   //       LDA $C055
+        0xAD, 0x54, 0xC0,   //       LDA $C054
+        0x8D, 0x05, 0xC0,   //       STA $C005   aux writes
+        0xEE, 0x00, 0x08,   //       INC $0800
+        0x8D, 0x04, 0xC0,   //       STA $C004
+        0xAD, 0x8B, 0xC0,   //       LDA $C08B
+        0xEE, 0x00, 0xD0,   //       INC $D000
+        0xAD, 0x30, 0xC0,   //       LDA $C030
+        0x20, 0x40, 0x03,   //       JSR $0340
+        0x4C, 0x00, 0x03,   //       JMP $0300
+    ];
+    cpu.bus.write_bytes(0x0300, &program);
+    cpu.bus.write_bytes(0x0340, &[0xE8, 0x60]); // INX / RTS
+    run(&mut cpu, 500);
+
+    let saved = save_machine(&cpu);
+    run(&mut cpu, 2000);
+    let (after, cycles) = (save_machine(&cpu), cpu.cycles);
+    assert!(after != saved && cpu.bus.peek_view(MemoryView::Aux, 0x0800) != Some(0x22), "the loop did not run");
+
+    load_machine(&mut cpu, &saved).unwrap();
+    run(&mut cpu, 2000);
+    assert_eq!(cpu.cycles, cycles);
+    assert!(save_machine(&cpu) == after, "the reloaded machine ran differently");
+}
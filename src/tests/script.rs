@@ -0,0 +1,147 @@
+// The --script runner: parsing commands and strings, and the exit code a
+// script run ends with.
+
+use std::env;
+use std::fs;
+
+use super::iic_cpu;
+use crate::cpu::CPU;
+use crate::script::{parse, parse_string, run_script, Command, EXIT_ERROR, EXIT_FAIL, EXIT_PASS};
+
+#[test]
+fn script_string_escapes() {
+    let cases: &[(&str, &str, &str)] = &[
+        (r#""plain""#, "plain", ""),
+        (r#""RUN\r" 10"#, "RUN\r", " 10"),
+        (r#""a\nb\tc""#, "a\nb\tc", ""),
+        (r#""say \"hi\"""#, "say \"hi\"", ""),
+        (r#""back\\slash""#, "back\\slash", ""),
+        (r#""\x41\x7e""#, "A~", ""),
+        (r#""""#, "", ""),
+    ];
+    for &(input, text, rest) in cases {
+        assert_eq!(parse_string(input).unwrap(), (text.to_string(), rest), "{}", input);
+    }
+
+    let errors: &[(&str, &str)] = &[
+        ("bare", "expected a quoted string"),
+        (r#""open"#, "unterminated string"),
+        (r#""\q""#, "bad escape \\q"),
+        (r#""\xG1""#, "bad escape \\xG1"),
+        // Two digits are always taken, even the closing quote
+        (r#""\x4""#, "bad escape \\x4\""),
+    ];
+    for &(input, error) in errors {
+        assert_eq!(parse_string(input).unwrap_err().to_string(), error, "{}", input);
+    }
+}
+
+#[test]
+fn script_parses_commands() {
+    let src = "\
+# boot and check
+cycles 1_000
+frames 2
+
+type \"RUN\\n\"
+key $9B
+wait \"READY\"
+wait \"]\" 30
+expect \"OK\"
+reset
+screen
+screenshot \"out.png\"
+fail
+fail \"why\"
+pass
+";
+    let commands = parse(src).unwrap();
+    let expected = [
+        (2, Command::Cycles(1000)),
+        (3, Command::Frames(2)),
+        (5, Command::Type(b"RUN\r".to_vec())),
+        (6, Command::Type(vec![0x1B])),
+        (7, Command::Wait("READY".to_string(), 600)),
+        (8, Command::Wait("]".to_string(), 30)),
+        (9, Command::Expect("OK".to_string())),
+        (10, Command::Reset),
+        (11, Command::Screen),
+        (12, Command::Screenshot("out.png".to_string())),
+        (13, Command::Fail("fail".to_string())),
+        (14, Command::Fail("why".to_string())),
+        (15, Command::Pass),
+    ];
+    assert_eq!(commands, expected);
+}
+
+#[test]
+fn script_command_errors() {
+    let cases: &[(&str, &str)] = &[
+        ("jump 300", "line 1: unknown command 'jump'"),
+        ("cycles lots", "line 1: bad number 'lots'"),
+        ("frames", "line 1: bad number ''"),
+        ("key ZZ", "line 1: bad key code 'ZZ'"),
+        ("wait \"READY\" soon", "line 1: bad number 'soon'"),
+        ("expect READY", "line 1: expected a quoted string"),
+        ("\n\ntype \"café\"", "line 3: 'é' is not on the Apple II keyboard"),
+        ("type \"\\xE9\"", "line 1: 'é' is not on the Apple II keyboard"),
+    ];
+    for &(src, error) in cases {
+        assert_eq!(parse(src).err().map(|e| e.to_string()).as_deref(), Some(error), "{}", src);
+    }
+}
+
+// A //c running a loop from RAM: after a short delay it shows "OK" at the
+// top left, then echoes each key pressed at the third column
+fn echo_cpu() -> CPU {
+    let mut cpu = iic_cpu();
+    #[rustfmt::skip]
+    let program = [
+        0xA2, 0x40,         // $0300 LDX #$40
+        0x88,               //       DEY
+        0xD0, 0xFD,         //       BNE $0302
+        0xCA,               //       DEX
+        0xD0, 0xFA,         //       BNE $0302
+        0xA9, 0xCF,         //       LDA #'O'
+        0x8D, 0x00, 0x04,   //       STA $0400
+        0xA9, 0xCB,         //       LDA #'K'
+        0x8D, 0x01, 0x04,   //       STA $0401
+        0xAD, 0x00, 0xC0,   // $0312 LDA $C000
+        0x10, 0xFB,         //       BPL $0312
+        0x8D, 0x02, 0x04,   //       STA $0402
+        0x2C, 0x10, 0xC0,   //       BIT $C010
+        0x4C, 0x12, 0x03,   //       JMP $0312
+    ];
+    cpu.bus.write_bytes(0x0300, &program);
+    cpu.bus.write_bytes(0x0400, &[0xA0; 3]);
+    cpu.pc = 0x0300;
+    cpu
+}
+
+fn run(name: &str, src: Option<&str>) -> i32 {
+    let path = env::temp_dir().join(format!("rust-iic-script-{}-{}.txt", name, std::process::id()));
+    if let Some(src) = src {
+        fs::write(&path, src).unwrap();
+    }
+    let code = run_script(&mut echo_cpu(), path.to_str().unwrap());
+    if src.is_some() {
+        fs::remove_file(&path).unwrap();
+    }
+    code
+}
+
+#[test]
+fn script_exit_codes() {
+    // Reaching the end, or a pass command, passes
+    assert_eq!(run("end", Some("wait \"OK\" 60\ntype \"Z\"\nexpect \"OKZ\"\n")), EXIT_PASS);
+    assert_eq!(run("pass", Some("pass\nfail \"not reached\"\n")), EXIT_PASS);
+
+    // A fail command, a missing expectation and a wait that times out fail
+    assert_eq!(run("fail", Some("frames 1\nfail \"broken\"\n")), EXIT_FAIL);
+    assert_eq!(run("expect", Some("expect \"OK\"\n")), EXIT_FAIL);
+    assert_eq!(run("timeout", Some("wait \"NEVER\" 3\npass\n")), EXIT_FAIL);
+
+    // A script that does not parse, or is not there, is an error
+    assert_eq!(run("parse", Some("frames 1\nbogus\n")), EXIT_ERROR);
+    assert_eq!(run("missing", None), EXIT_ERROR);
+}
@@ -0,0 +1,488 @@
+// SingleStepTests conformance (https://github.com/SingleStepTests/65x02).
+//
+// Each opcode file holds 10,000 vectors: the CPU registers and RAM before
+// and after one instruction, plus the bus access made on every cycle. A
+// vector is loaded into a Generic CPU, run for one `CPU::step`, and the
+// result compared. The vectors are several GB and not checked in, so the
+// suites are ignored by default; point SST_65X02_DIR at a checkout of the
+// repository and run them with
+//
+//   SST_65X02_DIR=~/src/65x02 cargo test single_step -- --ignored --nocapture
+//
+// An ignored suite run without the directory fails rather than passing
+// vacuously. SST_LIMIT=N checks only the first N vectors per opcode. The
+// built-in vectors at the bottom of this file always run.
+//
+// Registers, RAM and cycle counts must match exactly, and so must the bus:
+// every cycle is a bus access, dummy reads included, and the sequence of
+// addresses, values and directions must be the expected one.
+// SST_LOOSE_BUS=1 relaxes that to "every access made appears among the
+// expected cycles", for chasing a register bug without the noise.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use json::JsonValue;
+
+use super::{generic_cpu, iic_cpu};
+use crate::bus::BusAccess;
+use crate::cpu::{CpuType, Flags, CPU};
+use crate::softswitch::{Devices, SwitchTrace};
+
+const DATA_DIR_VAR: &str = "SST_65X02_DIR";
+
+// B and bit 5 are not latched in the 6502 status register; they only exist
+// when P is pushed, and pushes are checked through RAM
+const P_IGNORED: u8 = 0x30;
+
+// Opcodes defined on the NMOS 6502
+const NMOS_DOCUMENTED: &[u8] = &[
+    0x00, 0x01, 0x05, 0x06, 0x08, 0x09, 0x0A, 0x0D, 0x0E,
+    0x10, 0x11, 0x15, 0x16, 0x18, 0x19, 0x1D, 0x1E,
+    0x20, 0x21, 0x24, 0x25, 0x26, 0x28, 0x29, 0x2A, 0x2C, 0x2D, 0x2E,
+    0x30, 0x31, 0x35, 0x36, 0x38, 0x39, 0x3D, 0x3E,
+    0x40, 0x41, 0x45, 0x46, 0x48, 0x49, 0x4A, 0x4C, 0x4D, 0x4E,
+    0x50, 0x51, 0x55, 0x56, 0x58, 0x59, 0x5D, 0x5E,
+    0x60, 0x61, 0x65, 0x66, 0x68, 0x69, 0x6A, 0x6C, 0x6D, 0x6E,
+    0x70, 0x71, 0x75, 0x76, 0x78, 0x79, 0x7D, 0x7E,
+    0x81, 0x84, 0x85, 0x86, 0x88, 0x8A, 0x8C, 0x8D, 0x8E,
+    0x90, 0x91, 0x94, 0x95, 0x96, 0x98, 0x99, 0x9A, 0x9D,
+    0xA0, 0xA1, 0xA2, 0xA4, 0xA5, 0xA6, 0xA8, 0xA9, 0xAA, 0xAC, 0xAD, 0xAE,
+    0xB0, 0xB1, 0xB4, 0xB5, 0xB6, 0xB8, 0xB9, 0xBA, 0xBC, 0xBD, 0xBE,
+    0xC0, 0xC1, 0xC4, 0xC5, 0xC6, 0xC8, 0xC9, 0xCA, 0xCC, 0xCD, 0xCE,
+    0xD0, 0xD1, 0xD5, 0xD6, 0xD8, 0xD9, 0xDD, 0xDE,
+    0xE0, 0xE1, 0xE4, 0xE5, 0xE6, 0xE8, 0xE9, 0xEA, 0xEC, 0xED, 0xEE,
+    0xF0, 0xF1, 0xF5, 0xF6, 0xF8, 0xF9, 0xFD, 0xFE,
+];
+
+// Undocumented NMOS opcodes with stable behaviour: the combined
+// read-modify-write/ALU ops, LAX/SAX, the immediate oddities and the NOPs.
+// The JAMs and the unstable SHA/SHX/SHY/TAS/LAS/ANE/LXA are not checked.
+const NMOS_UNDOCUMENTED: &[u8] = &[
+    0x03, 0x07, 0x0F, 0x13, 0x17, 0x1B, 0x1F, // SLO
+    0x23, 0x27, 0x2F, 0x33, 0x37, 0x3B, 0x3F, // RLA
+    0x43, 0x47, 0x4F, 0x53, 0x57, 0x5B, 0x5F, // SRE
+    0x63, 0x67, 0x6F, 0x73, 0x77, 0x7B, 0x7F, // RRA
+    0xC3, 0xC7, 0xCF, 0xD3, 0xD7, 0xDB, 0xDF, // DCP
+    0xE3, 0xE7, 0xEF, 0xF3, 0xF7, 0xFB, 0xFF, // ISC
+    0xA3, 0xA7, 0xAF, 0xB3, 0xB7, 0xBF, // LAX
+    0x83, 0x87, 0x8F, 0x97, // SAX
+    0x0B, 0x2B, 0x4B, 0x6B, 0xCB, 0xEB, // ANC ANC ALR ARR SBX SBC
+    0x1A, 0x3A, 0x5A, 0x7A, 0xDA, 0xFA, // NOP
+    0x80, 0x82, 0x89, 0xC2, 0xE2, // NOP #imm
+    0x04, 0x44, 0x64, 0x0C, // NOP zp/abs
+    0x14, 0x34, 0x54, 0x74, 0xD4, 0xF4, // NOP zp,X
+    0x1C, 0x3C, 0x5C, 0x7C, 0xDC, 0xFC, // NOP abs,X
+];
+
+struct CpuState {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+struct Vector {
+    name: String,
+    initial: CpuState,
+    expected: CpuState,
+    cycles: Vec<BusAccess>,
+}
+
+fn field<T>(v: &JsonValue, key: &str, conv: fn(&JsonValue) -> Option<T>) -> Result<T, String> {
+    conv(&v[key]).ok_or_else(|| format!("bad or missing '{}'", key))
+}
+
+fn parse_state(v: &JsonValue) -> Result<CpuState, String> {
+    let mut ram = Vec::new();
+    for entry in v["ram"].members() {
+        let addr = entry[0].as_u16().ok_or("bad ram address")?;
+        let value = entry[1].as_u8().ok_or("bad ram value")?;
+        ram.push((addr, value));
+    }
+    Ok(CpuState {
+        pc: field(v, "pc", JsonValue::as_u16)?,
+        s: field(v, "s", JsonValue::as_u8)?,
+        a: field(v, "a", JsonValue::as_u8)?,
+        x: field(v, "x", JsonValue::as_u8)?,
+        y: field(v, "y", JsonValue::as_u8)?,
+        p: field(v, "p", JsonValue::as_u8)?,
+        ram,
+    })
+}
+
+fn parse_vectors(src: &str) -> Result<Vec<Vector>, String> {
+    let root = json::parse(src).map_err(|e| e.to_string())?;
+    let mut vectors = Vec::new();
+    for v in root.members() {
+        let mut cycles = Vec::new();
+        for c in v["cycles"].members() {
+            let addr = c[0].as_u16().ok_or("bad cycle address")?;
+            let value = c[1].as_u8().ok_or("bad cycle value")?;
+            let write = match c[2].as_str() {
+                Some("read") => false,
+                Some("write") => true,
+                other => return Err(format!("bad cycle direction {:?}", other)),
+            };
+            cycles.push(BusAccess { addr, value, write });
+        }
+        vectors.push(Vector {
+            name: v["name"].as_str().unwrap_or("?").to_string(),
+            initial: parse_state(&v["initial"])?,
+            expected: parse_state(&v["final"])?,
+            cycles,
+        });
+    }
+    Ok(vectors)
+}
+
+fn format_access(access: &BusAccess) -> String {
+    let dir = if access.write { "write" } else { "read" };
+    format!("{} {:04X}={:02X}", dir, access.addr, access.value)
+}
+
+fn check_bus(expected: &[BusAccess], actual: &[BusAccess], strict: bool) -> Result<(), String> {
+    if strict {
+        if expected != actual {
+            let show = |list: &[BusAccess]| list.iter().map(format_access).collect::<Vec<_>>().join(", ");
+            return Err(format!("bus: expected [{}] got [{}]", show(expected), show(actual)));
+        }
+        return Ok(());
+    }
+    let mut remaining = expected.to_vec();
+    for access in actual {
+        match remaining.iter().position(|e| e == access) {
+            Some(i) => {
+                remaining.remove(i);
+            }
+            None => return Err(format!("bus: unexpected {}", format_access(access))),
+        }
+    }
+    Ok(())
+}
+
+// Run one vector; returns a description of every mismatch.
+fn run_vector(cpu: &mut CPU, t: &Vector, strict_bus: bool) -> Result<(), String> {
+    load_vector(cpu, t);
+    check_step(cpu, t, strict_bus)
+}
+
+// Registers and RAM from the vector's initial state, nothing pending
+fn load_vector(cpu: &mut CPU, t: &Vector) {
+    cpu.bus.access_log = None;
+    cpu.bus.interrupts.clear_all();
+    cpu.bus.interrupts.waiting = false;
+    cpu.bus.interrupts.halted = false;
+
+    let init = &t.initial;
+    cpu.pc = init.pc;
+    cpu.regs.sp = init.s;
+    cpu.regs.a = init.a;
+    cpu.regs.x = init.x;
+    cpu.regs.y = init.y;
+    cpu.p = Flags::from_bits_truncate(init.p);
+    for &(addr, value) in &init.ram {
+        cpu.bus.write_byte(addr, value);
+    }
+}
+
+// One CPU::step from the loaded state, checked against the vector
+fn check_step(cpu: &mut CPU, t: &Vector, strict_bus: bool) -> Result<(), String> {
+    cpu.bus.access_log = Some(Vec::new());
+    let cycles = cpu.step();
+    let log = cpu.bus.access_log.take().unwrap_or_default();
+
+    let want = &t.expected;
+    let mut errors = Vec::new();
+    let mut check = |what: &str, got: u16, expected: u16| {
+        if got != expected {
+            errors.push(format!("{} {:02X} != {:02X}", what, got, expected));
+        }
+    };
+    check("pc", cpu.pc, want.pc);
+    check("s", cpu.regs.sp as u16, want.s as u16);
+    check("a", cpu.regs.a as u16, want.a as u16);
+    check("x", cpu.regs.x as u16, want.x as u16);
+    check("y", cpu.regs.y as u16, want.y as u16);
+    check("p", (cpu.p.bits() | P_IGNORED) as u16, (want.p | P_IGNORED) as u16);
+    check("cycles", cycles as u16, t.cycles.len() as u16);
+    for &(addr, value) in &want.ram {
+        let got = cpu.bus.read_byte(addr);
+        if got != value {
+            errors.push(format!("ram {:04X} {:02X} != {:02X}", addr, got, value));
+        }
+    }
+    if let Err(e) = check_bus(&t.cycles, &log, strict_bus) {
+        errors.push(e);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+fn data_dir(variant: &str) -> Option<PathBuf> {
+    let root = env::var_os(DATA_DIR_VAR)?;
+    let dir = Path::new(&root).join(variant).join("v1");
+    dir.is_dir().then_some(dir)
+}
+
+fn env_flag(name: &str) -> bool {
+    env::var(name).map(|v| v != "0" && !v.is_empty()).unwrap_or(false)
+}
+
+// Check every opcode file for one CPU variant. Panics with a per-opcode
+// summary (first failure of each) if anything mismatches.
+fn run_suite(cpu_type: CpuType, variant: &str, opcodes: &[u8]) {
+    let Some(dir) = data_dir(variant) else {
+        panic!("single_step {}: set {} to a SingleStepTests/65x02 checkout", variant, DATA_DIR_VAR);
+    };
+    let limit = env::var("SST_LIMIT").ok().and_then(|v| v.parse().ok()).unwrap_or(usize::MAX);
+    let strict_bus = !env_flag("SST_LOOSE_BUS");
+
+    let mut cpu = generic_cpu(cpu_type);
+    cpu.bus.feedback_port = false;
+
+    let mut failures = Vec::new();
+    let mut passed = 0usize;
+    for &opcode in opcodes {
+        let path = dir.join(format!("{:02x}.json", opcode));
+        let vectors = match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|s| parse_vectors(&s)) {
+            Ok(v) => v,
+            Err(e) => {
+                failures.push(format!("{:02X}: {}: {}", opcode, path.display(), e));
+                continue;
+            }
+        };
+        let total = vectors.len().min(limit);
+        let mut failed = 0;
+        let mut first = None;
+        for t in vectors.iter().take(limit) {
+            if let Err(e) = run_vector(&mut cpu, t, strict_bus) {
+                failed += 1;
+                first.get_or_insert_with(|| format!("\"{}\": {}", t.name, e));
+            }
+        }
+        passed += total - failed;
+        if let Some(first) = first {
+            failures.push(format!("{:02X}: {}/{} failed, first {}", opcode, failed, total, first));
+        }
+    }
+
+    println!("single_step {}: {} vectors passed, {} opcodes failing", variant, passed, failures.len());
+    if !failures.is_empty() {
+        panic!("{} opcodes failing:\n{}", failures.len(), failures.join("\n"));
+    }
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests data in SST_65X02_DIR"]
+fn single_step_nmos6502() {
+    let opcodes = [NMOS_DOCUMENTED, NMOS_UNDOCUMENTED].concat();
+    run_suite(CpuType::NMOS6502, "6502", &opcodes);
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests data in SST_65X02_DIR"]
+fn single_step_65c02() {
+    let all: Vec<u8> = (0..=255).collect();
+    run_suite(CpuType::CMOS65C02, "synertek65c02", &all);
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests data in SST_65X02_DIR"]
+fn single_step_r65c02() {
+    let all: Vec<u8> = (0..=255).collect();
+    run_suite(CpuType::R65C02, "rockwell65c02", &all);
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests data in SST_65X02_DIR"]
+fn single_step_wdc65c02s() {
+    let all: Vec<u8> = (0..=255).collect();
+    run_suite(CpuType::WDC65C02S, "wdc65c02", &all);
+}
+
+// Hand-checked vectors in the SingleStepTests format, so the harness itself
+// is exercised on every `cargo test` even without the external data. The
+// bus sequence is checked exactly: STA abs,X reads its target before the
+// write on both cores, which is what makes STA $C0xx,X hit a soft switch
+// twice.
+const BUILTIN_VECTORS: &str = r#"[
+  { "name": "a9 42 00",
+    "initial": { "pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 38,
+                 "ram": [[4096, 169], [4097, 66]] },
+    "final":   { "pc": 4098, "s": 253, "a": 66, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4096, 169], [4097, 66]] },
+    "cycles": [[4096, 169, "read"], [4097, 66, "read"]] },
+  { "name": "8d 34 12",
+    "initial": { "pc": 4096, "s": 253, "a": 153, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4096, 141], [4097, 52], [4098, 18], [4660, 0]] },
+    "final":   { "pc": 4099, "s": 253, "a": 153, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4096, 141], [4097, 52], [4098, 18], [4660, 153]] },
+    "cycles": [[4096, 141, "read"], [4097, 52, "read"], [4098, 18, "read"], [4660, 153, "write"]] },
+  { "name": "20 00 20",
+    "initial": { "pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4096, 32], [4097, 0], [4098, 32], [509, 0], [508, 0]] },
+    "final":   { "pc": 8192, "s": 251, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4096, 32], [4097, 0], [4098, 32], [509, 16], [508, 2]] },
+    "cycles": [[4096, 32, "read"], [4097, 0, "read"], [509, 0, "read"],
+               [509, 16, "write"], [508, 2, "write"], [4098, 32, "read"]] },
+  { "name": "d0 fe",
+    "initial": { "pc": 4351, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4351, 208], [4352, 254], [4353, 0], [4607, 0]] },
+    "final":   { "pc": 4351, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4351, 208], [4352, 254]] },
+    "cycles": [[4351, 208, "read"], [4352, 254, "read"], [4353, 0, "read"], [4607, 0, "read"]] },
+  { "name": "9d 80 c0",
+    "initial": { "pc": 4096, "s": 253, "a": 85, "x": 15, "y": 0, "p": 36,
+                 "ram": [[4096, 157], [4097, 128], [4098, 192], [49295, 0]] },
+    "final":   { "pc": 4099, "s": 253, "a": 85, "x": 15, "y": 0, "p": 36,
+                 "ram": [[4096, 157], [4097, 128], [4098, 192], [49295, 85]] },
+    "cycles": [[4096, 157, "read"], [4097, 128, "read"], [4098, 192, "read"],
+               [49295, 0, "read"], [49295, 85, "write"]] }
+]"#;
+
+// Where the cores' dummy cycles differ. The NMOS part writes the old value
+// back during a read-modify-write, reads the half-formed address when an
+// index crosses a page and takes JMP ($xxFF)'s high byte from the start of
+// the page. BRK leaves D alone.
+const NMOS_VECTORS: &str = r#"[
+  { "name": "ee 34 12",
+    "initial": { "pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4096, 238], [4097, 52], [4098, 18], [4660, 65]] },
+    "final":   { "pc": 4099, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4660, 66]] },
+    "cycles": [[4096, 238, "read"], [4097, 52, "read"], [4098, 18, "read"],
+               [4660, 65, "read"], [4660, 65, "write"], [4660, 66, "write"]] },
+  { "name": "bd ff 12",
+    "initial": { "pc": 4096, "s": 253, "a": 0, "x": 1, "y": 0, "p": 36,
+                 "ram": [[4096, 189], [4097, 255], [4098, 18], [4608, 0], [4864, 128]] },
+    "final":   { "pc": 4099, "s": 253, "a": 128, "x": 1, "y": 0, "p": 164,
+                 "ram": [] },
+    "cycles": [[4096, 189, "read"], [4097, 255, "read"], [4098, 18, "read"],
+               [4608, 0, "read"], [4864, 128, "read"]] },
+  { "name": "6c ff 20",
+    "initial": { "pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4096, 108], [4097, 255], [4098, 32], [8447, 52], [8448, 18], [8192, 86]] },
+    "final":   { "pc": 22068, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [] },
+    "cycles": [[4096, 108, "read"], [4097, 255, "read"], [4098, 32, "read"],
+               [8447, 52, "read"], [8192, 86, "read"]] },
+  { "name": "00 ea",
+    "initial": { "pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 40,
+                 "ram": [[4096, 0], [4097, 234], [65534, 0], [65535, 48]] },
+    "final":   { "pc": 12288, "s": 250, "a": 0, "x": 0, "y": 0, "p": 44,
+                 "ram": [[509, 16], [508, 2], [507, 56]] },
+    "cycles": [[4096, 0, "read"], [4097, 234, "read"], [509, 16, "write"], [508, 2, "write"],
+               [507, 56, "write"], [65534, 0, "read"], [65535, 48, "read"]] }
+]"#;
+
+// The same instructions on the 65C02: a second read instead of the
+// write-back, the last instruction byte re-read for a page crossing and
+// for JMP (abs)'s extra cycle, and D cleared on entering BRK.
+const CMOS_VECTORS: &str = r#"[
+  { "name": "ee 34 12",
+    "initial": { "pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4096, 238], [4097, 52], [4098, 18], [4660, 65]] },
+    "final":   { "pc": 4099, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4660, 66]] },
+    "cycles": [[4096, 238, "read"], [4097, 52, "read"], [4098, 18, "read"],
+               [4660, 65, "read"], [4660, 65, "read"], [4660, 66, "write"]] },
+  { "name": "bd ff 12",
+    "initial": { "pc": 4096, "s": 253, "a": 0, "x": 1, "y": 0, "p": 36,
+                 "ram": [[4096, 189], [4097, 255], [4098, 18], [4608, 0], [4864, 128]] },
+    "final":   { "pc": 4099, "s": 253, "a": 128, "x": 1, "y": 0, "p": 164,
+                 "ram": [] },
+    "cycles": [[4096, 189, "read"], [4097, 255, "read"], [4098, 18, "read"],
+               [4098, 18, "read"], [4864, 128, "read"]] },
+  { "name": "6c ff 20",
+    "initial": { "pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[4096, 108], [4097, 255], [4098, 32], [8447, 52], [8448, 18], [8192, 86]] },
+    "final":   { "pc": 4660, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [] },
+    "cycles": [[4096, 108, "read"], [4097, 255, "read"], [4098, 32, "read"],
+               [4098, 32, "read"], [8447, 52, "read"], [8448, 18, "read"]] },
+  { "name": "00 ea",
+    "initial": { "pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 40,
+                 "ram": [[4096, 0], [4097, 234], [65534, 0], [65535, 48]] },
+    "final":   { "pc": 12288, "s": 250, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[509, 16], [508, 2], [507, 56]] },
+    "cycles": [[4096, 0, "read"], [4097, 234, "read"], [509, 16, "write"], [508, 2, "write"],
+               [507, 56, "write"], [65534, 0, "read"], [65535, 48, "read"]] }
+]"#;
+
+// An IRQ taken before a NOP at $1000, run on into the NOP at the handler:
+// the due opcode is read twice, the pushed P has B clear, and the step
+// counts the entry's seven cycles with the handler's instruction. Final P
+// is for the 65C02, which clears D; the NMOS run checks it with D set.
+const IRQ_VECTOR: &str = r#"[
+  { "name": "irq ea",
+    "initial": { "pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 40,
+                 "ram": [[4096, 234], [4097, 0], [12288, 234], [12289, 0], [65534, 0], [65535, 48]] },
+    "final":   { "pc": 12289, "s": 250, "a": 0, "x": 0, "y": 0, "p": 36,
+                 "ram": [[509, 16], [508, 0], [507, 40]] },
+    "cycles": [[4096, 234, "read"], [4096, 234, "read"], [509, 16, "write"], [508, 0, "write"],
+               [507, 40, "write"], [65534, 0, "read"], [65535, 48, "read"],
+               [12288, 234, "read"], [12289, 0, "read"]] }
+]"#;
+
+#[test]
+fn single_step_builtin_vectors() {
+    let common = parse_vectors(BUILTIN_VECTORS).expect("built-in vectors parse");
+    for (cpu_type, own) in [(CpuType::NMOS6502, NMOS_VECTORS), (CpuType::CMOS65C02, CMOS_VECTORS)] {
+        let own = parse_vectors(own).expect("built-in vectors parse");
+        let mut cpu = generic_cpu(cpu_type);
+        for t in common.iter().chain(&own) {
+            if let Err(e) = run_vector(&mut cpu, t, true) {
+                panic!("{:?} \"{}\": {}", cpu_type, t.name, e);
+            }
+        }
+    }
+}
+
+#[test]
+fn single_step_irq_entry() {
+    let mut vectors = parse_vectors(IRQ_VECTOR).expect("built-in vectors parse");
+    let t = &mut vectors[0];
+    for cpu_type in [CpuType::CMOS65C02, CpuType::NMOS6502] {
+        if cpu_type == CpuType::NMOS6502 {
+            t.expected.p |= Flags::DECIMAL.bits();
+        }
+        let mut cpu = generic_cpu(cpu_type);
+        load_vector(&mut cpu, t);
+        cpu.bus.interrupts.request_irq();
+        if let Err(e) = check_step(&mut cpu, t, true) {
+            panic!("{:?} \"{}\": {}", cpu_type, t.name, e);
+        }
+    }
+}
+
+// The soft switches see each access on the cycle it is made, the dummy
+// ones included: STA abs,X hits its switch on cycles 4 and 5, and LDA
+// abs,X crossing into the I/O page reaches it only on cycle 5.
+#[test]
+fn single_step_switch_timing() {
+    let mut cpu = iic_cpu();
+    cpu.bus.write_bytes(0x0300, &[0x9D, 0x20, 0xC0, 0xBD, 0xF0, 0xBF]);
+    cpu.regs.x = 0x10;
+    cpu.pc = 0x0300;
+    cpu.bus.switch_trace = Some(SwitchTrace::new(Devices::all()));
+
+    let start = cpu.bus.iou.cycles;
+    cpu.step();
+    cpu.regs.x = 0x40;
+    cpu.step();
+    assert_eq!(cpu.bus.iou.cycles - start, 10);
+
+    let trace = cpu.bus.switch_trace.take().unwrap();
+    let seen: Vec<(u64, u16, bool)> =
+        trace.recent().map(|a| (a.cycle - start, a.addr, a.write)).collect();
+    assert_eq!(seen, [(4, 0xC030, false), (5, 0xC030, true), (10, 0xC030, false)]);
+}
@@ -0,0 +1,86 @@
+// The soft-switch name table and the monitor's switch trace.
+
+use std::env;
+use std::fs;
+
+use super::iic_cpu;
+use crate::monitor::Monitor;
+use crate::softswitch::{lookup, Devices, SwitchTrace};
+
+#[test]
+fn softswitch_lookup() {
+    let cases: &[(u16, bool, bool, Devices, &str)] = &[
+        // $C000-$C00F: switches when written, the keyboard when read
+        (0xC000, true, false, Devices::MEMORY, "80STOREOFF"),
+        (0xC005, true, false, Devices::MEMORY, "WRCARDRAM"),
+        (0xC00F, true, false, Devices::VIDEO, "SETALTCHAR"),
+        (0xC005, false, false, Devices::KEYBOARD, "KBD"),
+        // $C011-$C01F: status when read, the strobe when written
+        (0xC010, false, false, Devices::KEYBOARD, "KBDSTRB"),
+        (0xC011, false, false, Devices::LANGCARD, "RDLCBNK2"),
+        (0xC01F, false, false, Devices::VIDEO, "RD80VID"),
+        (0xC01F, true, false, Devices::KEYBOARD, "KBDSTRB"),
+        (0xC030, false, false, Devices::SPEAKER, "SPKR"),
+        (0xC039, true, false, Devices::SCC, "SCCAREG"),
+        (0xC055, false, false, Devices::VIDEO, "TXTPAGE2"),
+        // $C058-$C05F belong to the mouse unless the IOU is disabled
+        (0xC058, true, false, Devices::MOUSE, "DISXY"),
+        (0xC058, true, true, Devices::SYSTEM, "CLRAN0"),
+        (0xC05E, false, false, Devices::MOUSE, "Y0EDGERISE"),
+        (0xC05E, false, true, Devices::VIDEO, "DHIRESON"),
+        (0xC07E, false, false, Devices::VIDEO, "RDIOUDIS"),
+        (0xC07E, true, false, Devices::VIDEO, "IOUDISON"),
+        (0xC07F, true, false, Devices::VIDEO, "IOUDISOFF"),
+        (0xC075, false, false, Devices::PADDLE, "PTRIG"),
+        (0xC083, false, false, Devices::LANGCARD, "LCBANK2"),
+        (0xC08F, false, false, Devices::LANGCARD, "LCBANK1"),
+        (0xC0AA, true, false, Devices::SCC, "ACIACMD"),
+        (0xC0C3, false, false, Devices::SLOT, "SLOT4"),
+        (0xC0E9, false, false, Devices::IWM, "MOTORON"),
+        (0xC0EF, true, false, Devices::IWM, "Q7H"),
+        (0xC0F0, false, false, Devices::SLOT, "SLOT"),
+        (0xC02F, false, false, Devices::SYSTEM, "-"),
+    ];
+    for &(addr, write, ioudis, device, name) in cases {
+        assert_eq!(lookup(addr, write, ioudis), (device, name), "${:04X} write={} ioudis={}", addr, write, ioudis);
+    }
+
+    // Every switch belongs to exactly one device
+    for addr in 0xC000..=0xC0FF {
+        for (write, ioudis) in [(false, false), (true, false), (false, true), (true, true)] {
+            let (device, _) = lookup(addr, write, ioudis);
+            assert_eq!(device.bits().count_ones(), 1, "${:04X}", addr);
+        }
+    }
+}
+
+#[test]
+fn softswitch_device_list() {
+    assert_eq!(Devices::parse("iwm, SCC"), Ok(Devices::IWM | Devices::SCC));
+    assert_eq!(Devices::parse("all"), Ok(Devices::all()));
+    assert!(Devices::parse("iwm,floppy").unwrap_err().contains("unknown device 'floppy'"));
+}
+
+#[test]
+fn monitor_keeps_a_file_trace() {
+    let path = env::temp_dir().join(format!("rust-iic-switches-{}.log", std::process::id()));
+    let path_str = path.to_str().unwrap().to_string();
+    let mut cpu = iic_cpu();
+    cpu.bus.switch_trace = Some(SwitchTrace::to_file(&path_str, Devices::all()).unwrap());
+
+    let mut monitor = Monitor::new(&mut cpu);
+    monitor.execute_command("switches on iwm");
+    drop(monitor);
+    let trace = cpu.bus.switch_trace.as_ref().unwrap();
+    assert_eq!(trace.path(), Some(path_str.as_str()));
+    assert_eq!(trace.filter, Devices::all());
+
+    // Once it is off, the monitor starts its own in-memory trace
+    let mut monitor = Monitor::new(&mut cpu);
+    monitor.execute_command("switches off");
+    monitor.execute_command("switches on iwm");
+    drop(monitor);
+    let trace = cpu.bus.switch_trace.as_ref().unwrap();
+    assert_eq!((trace.path(), trace.filter), (None, Devices::IWM));
+    let _ = fs::remove_file(&path);
+}
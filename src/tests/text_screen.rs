@@ -0,0 +1,66 @@
+// Text-screen scraping through the same layout the renderer uses.
+
+use std::collections::HashSet;
+
+use super::iic_cpu;
+use crate::cpu::CPU;
+use crate::mmu::MemoryView;
+use crate::text_screen::decode_char;
+use crate::video::VideoModeMask;
+
+// Normal (high-bit) screen codes for an ASCII string
+fn poke_text(cpu: &mut CPU, view: MemoryView, addr: u16, text: &str) {
+    for (i, b) in text.bytes().enumerate() {
+        cpu.bus.poke_view(view, addr + i as u16, b | 0x80);
+    }
+}
+
+fn set_mode(cpu: &CPU, mode: u8, store80: bool) {
+    cpu.bus.iou.video_mode.set(VideoModeMask::TEXT | mode);
+    cpu.bus.iou.is_80store.set(store80);
+}
+
+#[test]
+fn text_screen_follows_page_and_column_switches() {
+    let mut cpu = iic_cpu();
+    poke_text(&mut cpu, MemoryView::Main, 0x0400, "MAIN1");
+    poke_text(&mut cpu, MemoryView::Aux, 0x0400, "AUX1!");
+    poke_text(&mut cpu, MemoryView::Main, 0x0800, "MAIN2");
+    // Row 1 starts at $0480
+    poke_text(&mut cpu, MemoryView::Main, 0x0480, "ROW1");
+
+    set_mode(&cpu, 0, false);
+    let screen = cpu.bus.text_screen();
+    assert_eq!(screen.columns, 40);
+    assert!(screen.row_string(0).starts_with("MAIN1"));
+    assert!(screen.row_string(1).starts_with("ROW1"));
+
+    set_mode(&cpu, VideoModeMask::PAGE2, false);
+    assert!(cpu.bus.text_screen().row_string(0).starts_with("MAIN2"));
+
+    // 80STORE turns PAGE2 into the aux half of page 1
+    set_mode(&cpu, VideoModeMask::PAGE2, true);
+    assert!(cpu.bus.text_screen().row_string(0).starts_with("AUX1!"));
+
+    // 80 columns interleave aux (even) and main (odd)
+    set_mode(&cpu, VideoModeMask::COL80, false);
+    let screen = cpu.bus.text_screen();
+    assert_eq!(screen.columns, 80);
+    assert!(screen.row_string(0).starts_with("AMUAXI1N!1"), "{}", screen.row_string(0));
+}
+
+#[test]
+fn text_screen_decodes_inverse_flash_and_mousetext() {
+    let cell = decode_char(0x08, false);
+    assert_eq!((cell.ch, cell.inverse, cell.flash), ('H', true, false));
+
+    let cell = decode_char(0x48, false);
+    assert_eq!((cell.ch, cell.inverse, cell.flash), ('H', false, true));
+
+    // The same codes are MouseText under ALTCHAR
+    let cells: Vec<_> = (0x40..=0x5F).map(|code| decode_char(code, true)).collect();
+    assert!(cells.iter().all(|cell| cell.mousetext));
+    let glyphs: Vec<char> = cells.iter().map(|cell| cell.ch).collect();
+    let distinct: HashSet<char> = glyphs.iter().copied().collect();
+    assert_eq!(distinct.len(), 32, "MouseText glyphs must be distinct: {:?}", glyphs);
+}
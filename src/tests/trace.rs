@@ -0,0 +1,180 @@
+// The binary trace format, the tracer's ring and triggers, and the
+// instruction lengths the CPU records.
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use super::generic_cpu;
+use crate::cpu::CpuType;
+use crate::monitor::trace_trigger;
+use crate::opcodes::InstructionSet;
+use crate::trace::{TraceHeader, TraceReader, TraceRecord, TraceWriter, Tracer, Trigger, NO_BANK};
+
+fn record(pc: u16, cycles: u64) -> TraceRecord {
+    TraceRecord { pc, bank: NO_BANK, length: 1, bytes: [0xEA, 0, 0], a: 0, x: 0, y: 0, sp: 0xFF, p: 0x30, cycles }
+}
+
+fn header() -> TraceHeader {
+    TraceHeader { cpu: "65C02".to_string(), set: InstructionSet::Cmos }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("rust-iic-{}-{}.trace", name, std::process::id()))
+}
+
+fn read_all(bytes: &[u8]) -> (TraceHeader, Vec<TraceRecord>) {
+    let reader = TraceReader::new(Cursor::new(bytes)).unwrap();
+    let header = reader.header.clone();
+    (header, reader.map(Result::unwrap).collect())
+}
+
+#[test]
+fn trace_round_trip() {
+    let records = [
+        TraceRecord { pc: 0xC600, bank: 6, length: 3, bytes: [0xAD, 0x30, 0xC0], a: 0x12, x: 0x34, y: 0x56, sp: 0xF0, p: 0xB1, cycles: 100 },
+        TraceRecord { pc: 0x0300, bank: 0, length: 2, bytes: [0xA9, 0xFF, 0x00], a: 1, x: 2, y: 3, sp: 0xEF, p: 0x30, cycles: 104 },
+        // A gap too long for 32 bits, then time running backwards: each
+        // needs a SYNC
+        record(0x0302, 104 + (1 << 32) + 5),
+        record(0x0303, 7),
+        record(0x0304, 9),
+    ];
+    let mut bytes = Vec::new();
+    let mut writer = TraceWriter::new(&mut bytes, &header()).unwrap();
+    for record in &records {
+        writer.write(record).unwrap();
+    }
+    writer.flush().unwrap();
+    // Header, five records and two SYNCs
+    assert_eq!(bytes.len(), 16 + 7 * 16);
+    assert_eq!(&bytes[..8], b"IICTRACE");
+
+    let (header, read) = read_all(&bytes);
+    assert_eq!(header, self::header());
+    assert_eq!(read, records);
+
+    // Every instruction set and a CPU name cut to 6 bytes
+    for set in [InstructionSet::Cmos, InstructionSet::Rockwell, InstructionSet::Nmos, InstructionSet::Wdc] {
+        let mut bytes = Vec::new();
+        TraceWriter::new(&mut bytes, &TraceHeader { cpu: "65C02-long".to_string(), set }).unwrap();
+        assert_eq!(read_all(&bytes).0, TraceHeader { cpu: "65C02-".to_string(), set });
+    }
+}
+
+#[test]
+fn trace_reader_rejects_bad_files() {
+    assert!(TraceReader::new(Cursor::new(b"NOTTRACE\x01\x00\x00\x00\x00\x00\x00\x00")).is_err());
+    let mut bytes = Vec::new();
+    TraceWriter::new(&mut bytes, &header()).unwrap().write(&record(0x300, 1)).unwrap();
+    bytes[9] = 9;
+    assert!(TraceReader::new(Cursor::new(&bytes)).is_err());
+    // A record cut short
+    bytes[9] = 0;
+    bytes.truncate(16 + 10);
+    let mut reader = TraceReader::new(Cursor::new(&bytes)).unwrap();
+    assert!(reader.next().unwrap().is_err());
+}
+
+// Records the tracer writes to a temporary file, reading it back
+fn run_tracer(name: &str, ring: Option<usize>, setup: impl FnOnce(&mut Tracer), pcs: &[u16]) -> Vec<u16> {
+    let path = temp_path(name);
+    let mut tracer = Tracer::create(&path, &header(), ring).unwrap();
+    setup(&mut tracer);
+    for (i, &pc) in pcs.iter().enumerate() {
+        tracer.record(record(pc, i as u64)).unwrap();
+    }
+    let written = tracer.finish().unwrap();
+    let bytes = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let records = read_all(&bytes).1;
+    assert_eq!(written, records.len() as u64);
+    records.iter().map(|record| record.pc).collect()
+}
+
+#[test]
+fn tracer_ring_keeps_the_last_records() {
+    let pcs: Vec<u16> = (0x300..0x310).collect();
+    assert_eq!(run_tracer("ring", Some(4), |_| {}, &pcs), [0x30C, 0x30D, 0x30E, 0x30F]);
+    assert_eq!(run_tracer("ring-short", Some(40), |_| {}, &pcs[..3]), [0x300, 0x301, 0x302]);
+    assert_eq!(run_tracer("no-ring", None, |_| {}, &pcs).len(), 16);
+}
+
+#[test]
+fn tracer_start_and_stop_triggers() {
+    let pcs = [0x300, 0x301, 0x302, 0x303, 0x301, 0x302, 0x303];
+    let start_stop = |tracer: &mut Tracer| {
+        tracer.set_start(Some(Trigger::at(0x301)));
+        tracer.stop = Some(Trigger::at(0x302));
+    };
+    // The stop instruction is kept; the start fires again after it
+    assert_eq!(run_tracer("start-stop", None, start_stop, &pcs), [0x301, 0x302, 0x301, 0x302]);
+
+    // A condition is tested on the registers before the instruction ran
+    let on_fifth = |tracer: &mut Tracer| {
+        tracer.set_start(Some(Trigger::when(0x301, "cycle 4".to_string(), |record| record.cycles == 4)));
+    };
+    assert_eq!(run_tracer("when", None, on_fifth, &pcs), [0x301, 0x302, 0x303]);
+}
+
+#[test]
+fn trace_trigger_from_breakpoint_syntax() {
+    let mut cpu = generic_cpu(CpuType::CMOS65C02);
+    cpu.bus.symbols.insert(0x0803, "MAIN", None);
+    let at = |pc: u16, a: u8, p: u8| TraceRecord { pc, a, p, ..record(pc, 0) };
+
+    let trigger = trace_trigger(&cpu.bus, "C600").unwrap();
+    assert_eq!(trigger.to_string(), "$C600");
+    assert!(trigger.fires(&at(0xC600, 0, 0)));
+    assert!(!trigger.fires(&at(0xC601, 0, 0)));
+    for text in ["$C600", "0xC600"] {
+        assert_eq!(trace_trigger(&cpu.bus, text).unwrap().addr, 0xC600);
+    }
+    assert_eq!(trace_trigger(&cpu.bus, "MAIN").unwrap().addr, 0x0803);
+
+    let trigger = trace_trigger(&cpu.bus, "MAIN if A == FF").unwrap();
+    assert_eq!(trigger.to_string(), "$0803 if A==$FF");
+    assert!(trigger.fires(&at(0x0803, 0xFF, 0)));
+    assert!(!trigger.fires(&at(0x0803, 0xFE, 0)));
+    assert!(!trigger.fires(&at(0x0804, 0xFF, 0)));
+
+    // Flags come from the record's P
+    let trigger = trace_trigger(&cpu.bus, "300 if C=1").unwrap();
+    assert!(trigger.fires(&at(0x300, 0, 0x01)));
+    assert!(!trigger.fires(&at(0x300, 0, 0x80)));
+
+    for (text, error) in [
+        ("NOWHERE", "Bad address 'NOWHERE'"),
+        ("300 if Q==1", "Unknown register or flag 'Q'"),
+        ("300 if A", "No comparison in 'A'"),
+    ] {
+        assert_eq!(trace_trigger(&cpu.bus, text).err().as_deref(), Some(error));
+    }
+}
+
+#[test]
+fn instruction_lengths_follow_the_opcode_table() {
+    let cases: &[(CpuType, u8, u8)] = &[
+        (CpuType::NMOS6502, 0x03, 2),
+        (CpuType::NMOS6502, 0x0B, 2),
+        (CpuType::NMOS6502, 0x0C, 3),
+        (CpuType::NMOS6502, 0x1A, 1),
+        (CpuType::NMOS6502, 0xA3, 2),
+        (CpuType::NMOS6502, 0xBF, 3),
+        (CpuType::CMOS65C02, 0x03, 1),
+        (CpuType::CMOS65C02, 0x44, 2),
+        (CpuType::CMOS65C02, 0x5C, 3),
+        (CpuType::CMOS65C02, 0xDC, 3),
+        (CpuType::CMOS65C02, 0xFC, 3),
+        (CpuType::CMOS65C02, 0xB2, 2),
+    ];
+    for &(cpu_type, opcode, length) in cases {
+        let mut cpu = generic_cpu(cpu_type);
+        cpu.bus.write_bytes(0x0300, &[opcode, 0x10, 0x20]);
+        cpu.pc = 0x0300;
+        cpu.capture_trace = true;
+        cpu.tick();
+        assert_eq!(cpu.last_trace.instruction_len, length, "{:?} ${:02X}", cpu_type, opcode);
+    }
+}
@@ -0,0 +1,170 @@
+// The tracediff tool's log parsing and comparison. Built into the tool
+// itself, by #[path], rather than listed in mod.rs.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::{parse_line, parse_p, run, Cli, Compare, Step};
+use crate::opcodes::InstructionSet;
+use crate::trace::{TraceRecord, NO_BANK};
+
+fn parse(line: &str) -> Step {
+    parse_line(line).unwrap().unwrap_or_else(|| panic!("no PC in '{}'", line))
+}
+
+// (pc, a, x, y, sp, p, cycles) of a step
+type Fields = (u16, Option<u8>, Option<u8>, Option<u8>, Option<u8>, Option<u8>, Option<u64>);
+
+fn fields(step: &Step) -> Fields {
+    (step.pc, step.a, step.x, step.y, step.sp, step.p, step.cycles)
+}
+
+#[test]
+fn tracediff_parses_each_log_format() {
+    let cases: &[(&str, Fields)] = &[
+        // rust-iic text trace
+        (
+            "      100007  rom1    $FCAC  D0 FC     BNE  $FCAA      A=00 X=36 Y=00 SP=F0 P=nv-bdIZC",
+            (0xFCAC, Some(0x00), Some(0x36), Some(0x00), Some(0xF0), Some(0x27), Some(100007)),
+        ),
+        // MAME, with a tracelog prefix
+        (
+            "A=00 X=00 Y=00 P=24 S=F7 FF59: cld",
+            (0xFF59, Some(0x00), Some(0x00), Some(0x00), Some(0xF7), Some(0x24), None),
+        ),
+        // MAME without one
+        ("FF59: cld", (0xFF59, None, None, None, None, None, None)),
+        // AppleWin
+        (
+            "FF59:D8        CLD   A=00 X=00 Y=00 P=24 S=F7",
+            (0xFF59, Some(0x00), Some(0x00), Some(0x00), Some(0xF7), Some(0x24), None),
+        ),
+        // nestest
+        (
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+            (0xC000, Some(0x00), Some(0x00), Some(0x00), Some(0xFD), Some(0x24), Some(7)),
+        ),
+        // PC and cycles given as fields
+        ("PC=$0300 A=$41 TC=12", (0x0300, Some(0x41), None, None, None, None, Some(12))),
+    ];
+    for (line, expected) in cases {
+        assert_eq!(fields(&parse(line)), *expected, "{}", line);
+    }
+
+    for line in ["", "; comment", "Trace started", "A=00 X=00"] {
+        assert!(parse_line(line).unwrap().is_none(), "{}", line);
+    }
+    assert!(parse_line("   (loops for 12 instructions)").is_err());
+}
+
+#[test]
+fn tracediff_reads_its_own_text_traces() {
+    let record = TraceRecord {
+        pc: 0xC600,
+        bank: NO_BANK,
+        length: 3,
+        bytes: [0xAD, 0x30, 0xC0],
+        a: 0x12,
+        x: 0x34,
+        y: 0x56,
+        sp: 0xF0,
+        p: 0xB1,
+        cycles: 123456,
+    };
+    let step = parse(&record.text(InstructionSet::Cmos));
+    assert_eq!(fields(&step), (0xC600, Some(0x12), Some(0x34), Some(0x56), Some(0xF0), Some(0xB1), Some(123456)));
+}
+
+#[test]
+fn tracediff_parses_p() {
+    assert_eq!(parse_p("24"), Some(0x24));
+    assert_eq!(parse_p("$B1"), Some(0xB1));
+    assert_eq!(parse_p("NV-BDIZC"), Some(0xFF));
+    assert_eq!(parse_p("nv-bdizc"), Some(0x20));
+    assert_eq!(parse_p("Nv-bdIzC"), Some(0xA5));
+    assert_eq!(parse_p("xyz"), None);
+}
+
+#[test]
+fn tracediff_differences() {
+    let compare = Compare { p_mask: 0xCF, ignore: Vec::new() };
+    let ours = parse("FF59:D8 CLD A=00 X=01 Y=02 P=24 S=F7 CYC:10");
+
+    // B and the unused bit are masked off; fields one side leaves out are skipped
+    let theirs = parse("FF59:D8 CLD A=00 X=01 P=34 CYC:20");
+    assert!(compare.differences(&ours, &theirs, (Some(0), Some(10))).is_empty());
+
+    let theirs = parse("FF5A:D8 CLD A=00 X=02 Y=02 P=A5 S=F7 CYC:12");
+    assert_eq!(
+        compare.differences(&ours, &theirs, (Some(0), Some(0))),
+        [
+            "PC: emulator $FF59, reference $FF5A",
+            "X: emulator $01, reference $02",
+            "P: emulator $24, reference $A5",
+            "cycles: emulator +10, reference +12 from the first instruction; the one before took 2 cycles fewer in the emulator",
+        ]
+    );
+
+    let compare = Compare { p_mask: 0xCF, ignore: vec!["x".to_string(), "p".to_string(), "cycles".to_string()] };
+    assert_eq!(compare.differences(&ours, &theirs, (Some(0), Some(0))), ["PC: emulator $FF59, reference $FF5A"]);
+}
+
+// Whether tracediff finds the two logs agree
+fn diff(name: &str, emulator: &str, reference: &str, options: &[&str]) -> bool {
+    let path = |side: &str| env::temp_dir().join(format!("rust-iic-tracediff-{}-{}-{}.log", name, side, std::process::id()));
+    let (emulator_path, reference_path): (PathBuf, PathBuf) = (path("emu"), path("ref"));
+    fs::write(&emulator_path, emulator).unwrap();
+    fs::write(&reference_path, reference).unwrap();
+    let mut args = vec!["tracediff", emulator_path.to_str().unwrap(), reference_path.to_str().unwrap()];
+    args.extend_from_slice(options);
+    let result = run(&Cli::parse_from(args));
+    fs::remove_file(&emulator_path).unwrap();
+    fs::remove_file(&reference_path).unwrap();
+    result.unwrap()
+}
+
+const EMULATOR: &str = "\
+     100  rom1    $FA62  D8        CLD             A=00 X=00 Y=00 SP=FD P=nv-bdIzc
+     102  rom1    $FA63  20 84 FE  JSR  $FE84      A=00 X=00 Y=00 SP=FD P=nv-bdIzc
+     108  rom1    $FE84  A0 FF     LDY  #$FF       A=00 X=00 Y=00 SP=FB P=nv-bdIzc
+     110  rom1    $FE86  84 32     STY  $32        A=00 X=00 Y=FF SP=FB P=Nv-bdIzc
+     113  rom1    $FE88  60        RTS             A=00 X=00 Y=FF SP=FB P=Nv-bdIzc
+";
+
+// The same run as AppleWin logs it, from its own cycle count
+const REFERENCE: &str = "\
+FA62:D8        CLD           A=00 X=00 Y=00 P=24 S=FD CYC:5000
+FA63:20 84 FE  JSR $FE84     A=00 X=00 Y=00 P=24 S=FD CYC:5002
+FE84:A0 FF     LDY #$FF      A=00 X=00 Y=00 P=24 S=FB CYC:5008
+FE86:84 32     STY $32       A=00 X=00 Y=FF P=A4 S=FB CYC:5010
+FE88:60        RTS           A=00 X=00 Y=FF P=A4 S=FB CYC:5013
+";
+
+#[test]
+fn tracediff_agrees_and_diverges() {
+    assert!(diff("same", EMULATOR, REFERENCE, &[]));
+
+    // A wrong register and a slow instruction each diverge
+    assert!(!diff("y", EMULATOR, &REFERENCE.replace("Y=FF P=A4 S=FB CYC:5013", "Y=FE P=A4 S=FB CYC:5013"), &[]));
+    assert!(diff("y-ignored", EMULATOR, &REFERENCE.replace("Y=FF P=A4 S=FB CYC:5013", "Y=FE P=A4 S=FB CYC:5013"), &["--ignore", "y"]));
+    assert!(!diff("cycles", EMULATOR, &REFERENCE.replace("CYC:5008", "CYC:5009"), &[]));
+    assert!(!diff("pc", &EMULATOR.replace("$FE88", "$FE89"), REFERENCE, &[]));
+
+    // Extra emulator instructions before the reference's first are skipped,
+    // unless alignment is turned off
+    let late = format!("      90  rom1    $FA60  EA        NOP             A=00 X=00 Y=00 SP=FD P=nv-bdIzc\n{}", EMULATOR);
+    assert!(diff("align", &late, REFERENCE, &[]));
+    assert!(!diff("no-align", &late, REFERENCE, &["--no-align"]));
+    assert!(diff("skip", &late, REFERENCE, &["--no-align", "--skip-emulator", "1"]));
+}
+
+#[test]
+fn tracediff_length_mismatch_is_not_agreement() {
+    let short = |text: &str| text.lines().take(3).map(|line| format!("{}\n", line)).collect::<String>();
+    assert!(!diff("emu-short", &short(EMULATOR), REFERENCE, &[]));
+    assert!(!diff("ref-short", EMULATOR, &short(REFERENCE), &[]));
+    assert!(diff("both-short", &short(EMULATOR), &short(REFERENCE), &[]));
+}
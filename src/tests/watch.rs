@@ -0,0 +1,146 @@
+// Memory watches: value conditions, the bank an access resolves to under
+// the //c soft switches, and which CPU accesses fire.
+
+use std::sync::{Arc, Mutex};
+
+use super::iic_cpu;
+use crate::bus::Bus;
+use crate::hooks::{HookResult, MemoryWatch, WatchCondition, WatchHit, WatchKind, WatchList};
+use crate::mmu::{Bank, MemoryView};
+
+type Hits = Arc<Mutex<Vec<WatchHit>>>;
+
+// A condition, the (value, old) accesses fired at it and how many match
+type ConditionCase = (WatchCondition, &'static [(u8, Option<u8>)], usize);
+
+// A watch that records its hits
+fn recording(start: u16, end: u16, kind: WatchKind) -> (MemoryWatch, Hits) {
+    let hits = Hits::default();
+    let sink = hits.clone();
+    let watch = MemoryWatch::new("test", start, end, kind, move |hit| {
+        sink.lock().unwrap().push(*hit);
+        HookResult::Continue
+    });
+    (watch, hits)
+}
+
+fn hit(kind: WatchKind, value: u8, old: Option<u8>) -> WatchHit {
+    WatchHit { kind, addr: 0x0300, value, old, bank: Bank::Main, pc: 0 }
+}
+
+#[test]
+fn watch_conditions() {
+    let cases: &[ConditionCase] = &[
+        (WatchCondition::Any, &[(0x00, None), (0xFF, None)], 2),
+        (WatchCondition::Equal(0x42), &[(0x42, None), (0x43, None)], 1),
+        (WatchCondition::NotEqual(0x42), &[(0x42, None), (0x43, None), (0x00, None)], 2),
+        (WatchCondition::Masked { mask: 0x80, value: 0x80 }, &[(0x80, None), (0xFF, None), (0x7F, None)], 2),
+        (WatchCondition::Between(0x10, 0x1F), &[(0x0F, None), (0x10, None), (0x1F, None), (0x20, None)], 2),
+        // Only writes that reached memory and changed it
+        (WatchCondition::Changed, &[(0x01, Some(0x01)), (0x01, Some(0x02)), (0x01, None)], 1),
+    ];
+    for &(condition, values, expected) in cases {
+        let (watch, hits) = recording(0x0300, 0x0300, WatchKind::WRITE);
+        let mut list = WatchList::default();
+        list.add(watch.with_condition(condition));
+        for &(value, old) in values {
+            list.fire(&hit(WatchKind::WRITE, value, old));
+        }
+        assert_eq!(hits.lock().unwrap().len(), expected, "{:?}", condition);
+    }
+
+    // Kind, bank and range all have to match
+    let (watch, hits) = recording(0x0300, 0x0300, WatchKind::WRITE);
+    let mut list = WatchList::default();
+    list.add(watch.with_bank(Bank::Aux));
+    list.fire(&hit(WatchKind::WRITE, 0, None));
+    list.fire(&hit(WatchKind::READ, 0, None));
+    list.fire(&WatchHit { bank: Bank::Aux, ..hit(WatchKind::WRITE, 0, None) });
+    list.fire(&WatchHit { bank: Bank::Aux, addr: 0x0301, ..hit(WatchKind::WRITE, 0, None) });
+    assert_eq!(hits.lock().unwrap().len(), 1);
+}
+
+// Bank of every write the watch saw, with the byte it replaced
+fn written(bus: &mut Bus, addr: u16, value: u8) -> (Bank, Option<u8>) {
+    let (watch, hits) = recording(addr, addr, WatchKind::WRITE);
+    bus.watches.clear();
+    bus.watches.add(watch);
+    bus.write_byte(addr, value);
+    let hit = hits.lock().unwrap()[0];
+    (hit.bank, hit.old)
+}
+
+#[test]
+fn watch_bank_resolution() {
+    let mut cpu = iic_cpu();
+    let bus = &mut cpu.bus;
+    for switch in [0xC000, 0xC002, 0xC004, 0xC008] {
+        bus.write_byte(switch, 0); // 80STORE, RAMRD, RAMWRT, ALTZP off
+    }
+    bus.read_byte(0xC082); // LC off: ROM read, no write
+    assert_eq!(bus.read_bank(0x0000), Bank::Main);
+    assert_eq!(bus.read_bank(0x0800), Bank::Main);
+    assert_eq!(bus.read_bank(0xC030), Bank::Io);
+    assert_eq!(bus.read_bank(0xC100), Bank::Rom);
+    assert_eq!(bus.read_bank(0xD000), Bank::Rom);
+    assert_eq!(written(bus, 0xD000, 0x55), (Bank::Rom, None));
+
+    // RAMRD moves reads of $0200-$BFFF but not writes or zero page
+    bus.poke_view(MemoryView::Main, 0x0800, 0x99);
+    bus.write_byte(0xC003, 0);
+    assert_eq!(bus.read_bank(0x0800), Bank::Aux);
+    assert_eq!(bus.read_bank(0x00FF), Bank::Main);
+    assert_eq!(written(bus, 0x0800, 0x11), (Bank::Main, Some(0x99)));
+    assert_eq!(bus.peek_view(MemoryView::Main, 0x0800), Some(0x11));
+
+    // ALTZP moves zero page and the LC; two reads of $C08B enable LC bank 1
+    // for reading and writing
+    bus.write_byte(0xC009, 0);
+    bus.read_byte(0xC08B);
+    bus.read_byte(0xC08B);
+    assert_eq!(bus.read_bank(0x0000), Bank::Aux);
+    assert_eq!(bus.read_bank(0xD000), Bank::LcAux);
+    assert_eq!(written(bus, 0xD000, 0x22).0, Bank::LcAux);
+    assert_eq!(written(bus, 0xE000, 0x33).0, Bank::LcAux);
+    assert_eq!(bus.peek_view(MemoryView::LcAux1, 0xD000), Some(0x22));
+    assert_eq!(bus.peek_view(MemoryView::LcAux1, 0xE000), Some(0x33));
+    assert_eq!(bus.read_byte(0xD000), 0x22);
+
+    // 80STORE with PAGE2 takes the text page to aux, and the hi-res page
+    // too once HIRES is on
+    bus.write_byte(0xC002, 0);
+    bus.write_byte(0xC001, 0);
+    bus.write_byte(0xC055, 0);
+    assert_eq!(bus.read_bank(0x0400), Bank::Aux);
+    assert_eq!(bus.read_bank(0x0800), Bank::Main);
+    assert_eq!(bus.read_bank(0x2000), Bank::Main);
+    bus.write_byte(0xC057, 0);
+    assert_eq!(bus.read_bank(0x2000), Bank::Aux);
+    assert_eq!(written(bus, 0x2000, 0x44).0, Bank::Aux);
+    assert_eq!(bus.peek_view(MemoryView::Aux, 0x2000), Some(0x44));
+}
+
+#[test]
+fn watch_sees_data_accesses_not_instruction_bytes() {
+    let mut cpu = iic_cpu();
+    for switch in [0xC000, 0xC002, 0xC004, 0xC008] {
+        cpu.bus.write_byte(switch, 0);
+    }
+    // LDA #$10; LDA $0380; STA $0380,X
+    cpu.bus.write_bytes(0x0300, &[0xA9, 0x10, 0xAD, 0x80, 0x03, 0x9D, 0x80, 0x03]);
+    cpu.pc = 0x0300;
+    cpu.regs.x = 0x01;
+
+    let (read, reads) = recording(0x0300, 0x03FF, WatchKind::READ);
+    let (exec, execs) = recording(0x0300, 0x03FF, WatchKind::EXECUTE);
+    cpu.bus.watches.add(read);
+    cpu.bus.watches.add(exec);
+    for _ in 0..3 {
+        cpu.step();
+    }
+
+    let addrs = |hits: &Hits| hits.lock().unwrap().iter().map(|h| h.addr).collect::<Vec<_>>();
+    // The data read, then STA abs,X's dummy read of its target
+    assert_eq!(addrs(&reads), [0x0380, 0x0381]);
+    assert_eq!(addrs(&execs), [0x0300, 0x0302, 0x0305]);
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+
+use crate::{iou::IOU, mmu::MMU, util::apple_iic_font_index, video::{is_flashing, TextLayout, TEXT_MODE_BASE_ADDRESSES}};
+
+// Text-screen scraper.
+//
+// Decodes the 40/80-column text page into Unicode the same way
+// `Video::render_text_rows` draws it: the page/aux selection comes from the
+// shared `TextLayout`, glyphs from the same `apple_iic_font_index` mapping
+// and ALTCHAR/MouseText handling. Used by
+// the headless script runner, the monitor and anything else that wants to
+// read the screen without going through pixels.
+
+pub const TEXT_ROWS: usize = 24;
+
+// Closest Unicode equivalents for the 32 MouseText glyphs (font 0x00-0x1F),
+// checked against the glyphs in assets/font.bin. Several have no exact
+// match: the closed apple uses the private-use codepoint Apple's own fonts
+// draw the logo with, and the open apple the command key it became.
+const MOUSETEXT: [char; 32] = [
+    '\u{F8FF}', // closed apple
+    '\u{2318}', // open apple
+    '\u{2196}', // mouse pointer
+    '\u{231B}', // hourglass
+    '\u{2713}', // checkmark
+    '\u{2714}', // inverse checkmark
+    '\u{1FBB2}', // running man, left half
+    '\u{1FBB3}', // running man, right half
+    '\u{2190}', // left arrow
+    '\u{2026}', // ellipsis
+    '\u{2193}', // down arrow
+    '\u{2191}', // up arrow
+    '\u{2594}', // top line
+    '\u{21B5}', // return
+    '\u{2588}', // solid block
+    '\u{21E6}', // scroll left
+    '\u{21E8}', // scroll right
+    '\u{21E9}', // scroll down
+    '\u{21E7}', // scroll up
+    '\u{2500}', // middle line
+    '\u{2514}', // lower-left corner
+    '\u{2192}', // right arrow
+    '\u{2592}', // checkerboard
+    '\u{2591}', // checkerboard (offset)
+    '\u{231C}', // folder, left half
+    '\u{231D}', // folder, right half
+    '\u{2595}', // right vertical bar
+    '\u{25C6}', // diamond
+    '\u{2550}', // top and bottom lines
+    '\u{253C}', // cross
+    '\u{1FBBC}', // open box with a dot
+    '\u{258F}', // left vertical bar
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TextCell {
+    pub ch: char,
+    pub inverse: bool,
+    pub flash: bool,
+    pub mousetext: bool,
+}
+
+impl Default for TextCell {
+    fn default() -> Self {
+        Self { ch: ' ', inverse: false, flash: false, mousetext: false }
+    }
+}
+
+// Decoded text screen: TEXT_ROWS rows of `columns` (40 or 80) cells.
+pub struct TextScreen {
+    pub columns: usize,
+    pub cells: Vec<TextCell>,
+}
+
+impl TextScreen {
+    pub fn row(&self, row: usize) -> &[TextCell] {
+        &self.cells[row * self.columns..(row + 1) * self.columns]
+    }
+
+    pub fn row_string(&self, row: usize) -> String {
+        self.row(row).iter().map(|c| c.ch).collect()
+    }
+
+    pub fn contains(&self, needle: &str) -> bool {
+        (0..TEXT_ROWS).any(|row| self.row_string(row).contains(needle))
+    }
+
+    // One line per row with inverse and flashing runs wrapped in ANSI
+    // reverse-video / blink sequences, for dumping to a terminal.
+    pub fn to_ansi_string(&self) -> String {
+        let mut out = String::with_capacity(TEXT_ROWS * (self.columns + 16));
+        for row in 0..TEXT_ROWS {
+            let mut attr = (false, false);
+            for cell in self.row(row) {
+                if (cell.inverse, cell.flash) != attr {
+                    out.push_str("\x1b[0m");
+                    if cell.inverse || cell.flash {
+                        out.push_str("\x1b[7m");
+                    }
+                    if cell.flash {
+                        out.push_str("\x1b[5m");
+                    }
+                    attr = (cell.inverse, cell.flash);
+                }
+                out.push(cell.ch);
+            }
+            if attr != (false, false) {
+                out.push_str("\x1b[0m");
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// Plain text, one line per row, attributes dropped
+impl fmt::Display for TextScreen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in 0..TEXT_ROWS {
+            writeln!(f, "{}", self.row_string(row))?;
+        }
+        Ok(())
+    }
+}
+
+// Decode one screen byte into a cell using the active character set.
+pub fn decode_char(vram_code: u8, is_altchar: bool) -> TextCell {
+    let (font_offset, invert) = apple_iic_font_index(vram_code, is_altchar);
+    let index = font_offset / 8;
+
+    let flash = is_flashing(vram_code, is_altchar);
+
+    if index < 0x20 {
+        TextCell { ch: MOUSETEXT[index], inverse: invert, flash: false, mousetext: true }
+    } else {
+        TextCell { ch: index as u8 as char, inverse: invert && !flash, flash, mousetext: false }
+    }
+}
+
+// Decode the text screen as currently displayed, following the IOU video
+// mode exactly like Video::render_text_rows.
+pub fn read_text_screen(iou: &IOU, mmu: &MMU) -> TextScreen {
+    let layout = TextLayout::current(iou);
+    let columns = layout.columns() as usize;
+    let mut cells = Vec::with_capacity(TEXT_ROWS * columns);
+
+    for row in 0..TEXT_ROWS {
+        for col in 0..layout.columns() {
+            cells.push(decode_char(layout.code_at(mmu, row, col), layout.altchar));
+        }
+    }
+
+    TextScreen { columns, cells }
+}
+
+// Decode an explicit text page regardless of the current soft switches.
+// Page 2 lives at $0800; 80-column mode interleaves aux and main.
+pub fn read_text_page(mmu: &MMU, page2: bool, col80: bool, altchar: bool) -> TextScreen {
+    let offset = if page2 { 0x0400 } else { 0 };
+    let columns = if col80 { 80 } else { 40 };
+    let mut cells = Vec::with_capacity(TEXT_ROWS * columns);
+
+    for row_base in TEXT_MODE_BASE_ADDRESSES {
+        let base = row_base + offset;
+        for col in 0..40 {
+            if col80 {
+                cells.push(decode_char(mmu.read_aux_byte(base + col), altchar));
+            }
+            cells.push(decode_char(mmu.read_main_byte(base + col), altchar));
+        }
+    }
+
+    TextScreen { columns, cells }
+}
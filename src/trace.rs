@@ -0,0 +1,429 @@
+// CPU execution traces (--trace, monitor `trace`)
+//
+// A trace has one record per instruction: where it ran (PC and bank), its
+// bytes, the registers before it ran and the cycle it started on. The
+// binary format is a 16-byte header and 16-byte records, little endian:
+//
+//   header  "IICTRACE", version, instruction set (0: 65C02, 1: with the
+//           Rockwell bit instructions, 2: NMOS 6502, 3: WDC 65C02S), CPU
+//           name NUL-padded to 6 bytes
+//   record  PC (2), bank (1), length (1), instruction bytes (3),
+//           A, X, Y, SP, P (5), cycles since the previous record (4)
+//
+// The bank is the MemoryView index, or NO_BANK on the Generic system. A
+// record with bank SYNC carries instead, in bytes 4-11, the cycle count
+// the next record counts from; the writer puts one in before a gap too
+// long for 32 bits. Text traces have one line per record, as
+// TraceRecord::text formats it.
+//
+// Apart from the opcode table this module stands alone, so the offline
+// tools in src/bin can read traces with it.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::opcodes::{self, InstructionSet};
+
+pub const MAGIC: &[u8; 8] = b"IICTRACE";
+pub const VERSION: u8 = 1;
+const RECORD_LEN: usize = 16;
+
+// Bank of a record made on the Generic system
+pub const NO_BANK: u8 = 0xFF;
+// Bank byte marking a cycle count resync
+const SYNC: u8 = 0xFE;
+
+// Bank names by MemoryView index, as MemoryView::name gives them
+pub const BANK_NAMES: [&str; 8] = ["main", "aux", "lcmain1", "lcmain2", "lcaux1", "lcaux2", "rom1", "rom2"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub bank: u8,
+    pub length: u8,
+    pub bytes: [u8; 3],
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub p: u8,
+    pub cycles: u64,
+}
+
+impl TraceRecord {
+    pub fn bank_name(&self) -> &'static str {
+        BANK_NAMES.get(self.bank as usize).copied().unwrap_or("-")
+    }
+
+    // The instruction bytes as hex
+    pub fn dump(&self) -> String {
+        let length = (self.length as usize).clamp(1, 3);
+        let bytes: Vec<String> = self.bytes[..length].iter().map(|b| format!("{:02X}", b)).collect();
+        bytes.join(" ")
+    }
+
+    // One line of a text trace
+    pub fn text(&self, set: InstructionSet) -> String {
+        let (mnemonic, operand, _) = opcodes::decode(self.pc, self.bytes, set);
+        format!(
+            "{:>12}  {:<7} ${:04X}  {:<8}  {:<4} {:<10} A={:02X} X={:02X} Y={:02X} SP={:02X} P={}",
+            self.cycles,
+            self.bank_name(),
+            self.pc,
+            self.dump(),
+            mnemonic,
+            operand,
+            self.a,
+            self.x,
+            self.y,
+            self.sp,
+            opcodes::format_flags(self.p),
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceHeader {
+    pub cpu: String,
+    pub set: InstructionSet,
+}
+
+impl TraceHeader {
+    fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(MAGIC);
+        bytes[8] = VERSION;
+        bytes[9] = match self.set {
+            InstructionSet::Cmos => 0,
+            InstructionSet::Rockwell => 1,
+            InstructionSet::Nmos => 2,
+            InstructionSet::Wdc => 3,
+        };
+        let name = self.cpu.as_bytes();
+        let len = name.len().min(6);
+        bytes[10..10 + len].copy_from_slice(&name[..len]);
+        bytes
+    }
+
+    #[allow(dead_code)]
+    fn from_bytes(bytes: &[u8; 16]) -> io::Result<Self> {
+        if &bytes[..8] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a trace file"));
+        }
+        if bytes[8] != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("trace version {} not supported", bytes[8])));
+        }
+        let set = match bytes[9] {
+            0 => InstructionSet::Cmos,
+            1 => InstructionSet::Rockwell,
+            2 => InstructionSet::Nmos,
+            3 => InstructionSet::Wdc,
+            other => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown instruction set {}", other))),
+        };
+        let name: Vec<u8> = bytes[10..].iter().copied().take_while(|&b| b != 0).collect();
+        Ok(Self { cpu: String::from_utf8_lossy(&name).into_owned(), set })
+    }
+}
+
+pub struct TraceWriter<W: Write> {
+    out: W,
+    last_cycles: u64,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut out: W, header: &TraceHeader) -> io::Result<Self> {
+        out.write_all(&header.to_bytes())?;
+        Ok(Self { out, last_cycles: 0 })
+    }
+
+    pub fn write(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let delta = match record.cycles.checked_sub(self.last_cycles).and_then(|delta| u32::try_from(delta).ok()) {
+            Some(delta) => delta,
+            None => {
+                let mut sync = [0; RECORD_LEN];
+                sync[2] = SYNC;
+                sync[4..12].copy_from_slice(&record.cycles.to_le_bytes());
+                self.out.write_all(&sync)?;
+                0
+            }
+        };
+        self.last_cycles = record.cycles;
+
+        let mut bytes = [0; RECORD_LEN];
+        bytes[..2].copy_from_slice(&record.pc.to_le_bytes());
+        bytes[2] = record.bank;
+        bytes[3] = record.length;
+        bytes[4..7].copy_from_slice(&record.bytes);
+        bytes[7..12].copy_from_slice(&[record.a, record.x, record.y, record.sp, record.p]);
+        bytes[12..].copy_from_slice(&delta.to_le_bytes());
+        self.out.write_all(&bytes)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// Read by the tools in src/bin
+#[allow(dead_code)]
+pub struct TraceReader<R: Read> {
+    input: R,
+    pub header: TraceHeader,
+    last_cycles: u64,
+}
+
+#[allow(dead_code)]
+impl TraceReader<BufReader<File>> {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+#[allow(dead_code)]
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut bytes = [0; 16];
+        input.read_exact(&mut bytes)?;
+        let header = TraceHeader::from_bytes(&bytes)?;
+        Ok(Self { input, header, last_cycles: 0 })
+    }
+
+    // The next record, or None at the end of the file
+    fn read_record(&mut self) -> io::Result<Option<TraceRecord>> {
+        loop {
+            let mut bytes = [0; RECORD_LEN];
+            let mut filled = 0;
+            while filled < RECORD_LEN {
+                match self.input.read(&mut bytes[filled..])? {
+                    0 if filled == 0 => return Ok(None),
+                    0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "trace ends inside a record")),
+                    n => filled += n,
+                }
+            }
+            if bytes[2] == SYNC {
+                self.last_cycles = u64::from_le_bytes(bytes[4..12].try_into().unwrap());
+                continue;
+            }
+            let delta = u32::from_le_bytes(bytes[12..].try_into().unwrap());
+            self.last_cycles += delta as u64;
+            return Ok(Some(TraceRecord {
+                pc: u16::from_le_bytes([bytes[0], bytes[1]]),
+                bank: bytes[2],
+                length: bytes[3],
+                bytes: [bytes[4], bytes[5], bytes[6]],
+                a: bytes[7],
+                x: bytes[8],
+                y: bytes[9],
+                sp: bytes[10],
+                p: bytes[11],
+                cycles: self.last_cycles,
+            }));
+        }
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+// Which records a trace keeps; an empty filter keeps them all
+#[derive(Clone, Debug, Default)]
+pub struct TraceFilter {
+    // PC range, inclusive
+    pub range: Option<(u16, u16)>,
+    // Banks by MemoryView index; empty for any
+    pub banks: Vec<u8>,
+    // Cycle window, inclusive
+    pub cycles: Option<(u64, u64)>,
+}
+
+impl TraceFilter {
+    #[inline]
+    pub fn matches(&self, record: &TraceRecord) -> bool {
+        self.range.is_none_or(|(lo, hi)| (lo..=hi).contains(&record.pc))
+            && (self.banks.is_empty() || self.banks.contains(&record.bank))
+            && self.cycles.is_none_or(|(from, to)| (from..=to).contains(&record.cycles))
+    }
+
+    // "0800-0FFF" (hex, $ optional)
+    pub fn parse_range(text: &str) -> Result<(u16, u16)> {
+        let (lo, hi) = text.split_once('-').ok_or_else(|| anyhow!("range '{}' is not LO-HI", text))?;
+        let hex = |s: &str| u16::from_str_radix(s.trim().trim_start_matches('$'), 16);
+        match (hex(lo), hex(hi)) {
+            (Ok(lo), Ok(hi)) if lo <= hi => Ok((lo, hi)),
+            _ => Err(anyhow!("bad address range '{}'", text)),
+        }
+    }
+
+    // "main,aux,lcmain1"
+    pub fn parse_banks(text: &str) -> Result<Vec<u8>> {
+        text.split(',')
+            .map(|name| {
+                let name = name.trim().to_ascii_lowercase();
+                BANK_NAMES
+                    .iter()
+                    .position(|&bank| bank == name)
+                    .map(|index| index as u8)
+                    .ok_or_else(|| anyhow!("unknown bank '{}' (use {})", name, BANK_NAMES.join(", ")))
+            })
+            .collect()
+    }
+
+    // "1000000-2000000", or "1000000-" for everything from there on
+    pub fn parse_cycles(text: &str) -> Result<(u64, u64)> {
+        let (from, to) = text.split_once('-').ok_or_else(|| anyhow!("cycle window '{}' is not FROM-TO", text))?;
+        let from = from.trim().parse::<u64>().map_err(|_| anyhow!("bad cycle count '{}'", from))?;
+        let to = match to.trim() {
+            "" => u64::MAX,
+            to => to.parse::<u64>().map_err(|_| anyhow!("bad cycle count '{}'", to))?,
+        };
+        if from > to {
+            return Err(anyhow!("cycle window '{}' ends before it starts", text));
+        }
+        Ok((from, to))
+    }
+}
+
+// Where a trace starts or pauses: PC reaching an address, and optionally
+// a test of the registers there, as a monitor breakpoint condition makes
+pub struct Trigger {
+    pub addr: u16,
+    condition: Option<(String, TriggerTest)>,
+}
+
+type TriggerTest = Box<dyn Fn(&TraceRecord) -> bool + Send>;
+
+impl Trigger {
+    pub fn at(addr: u16) -> Self {
+        Self { addr, condition: None }
+    }
+
+    // `text` is how the test was written, for display
+    pub fn when(addr: u16, text: String, test: impl Fn(&TraceRecord) -> bool + Send + 'static) -> Self {
+        Self { addr, condition: Some((text, Box::new(test))) }
+    }
+
+    #[inline]
+    pub fn fires(&self, record: &TraceRecord) -> bool {
+        record.pc == self.addr && self.condition.as_ref().is_none_or(|(_, test)| test(record))
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "${:04X}", self.addr)?;
+        match &self.condition {
+            Some((text, _)) => write!(f, " if {}", text),
+            None => Ok(()),
+        }
+    }
+}
+
+enum TraceOutput {
+    Binary(TraceWriter<BufWriter<File>>),
+    Text(BufWriter<File>, InstructionSet),
+}
+
+// Streams the instructions the CPU runs to a trace file
+pub struct Tracer {
+    out: TraceOutput,
+    pub filter: TraceFilter,
+    // Tracing begins when start fires, and pauses after the instruction
+    // stop fires on; without a start it begins at once
+    pub start: Option<Trigger>,
+    pub stop: Option<Trigger>,
+    active: bool,
+    // With a ring size, only the last records are kept, and written when
+    // the trace is finished
+    ring: Option<(usize, VecDeque<TraceRecord>)>,
+    pub path: PathBuf,
+    pub records: u64,
+}
+
+impl Tracer {
+    // A path ending in .txt gets a text trace, any other the binary format
+    pub fn create(path: &Path, header: &TraceHeader, ring: Option<usize>) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        let out = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("txt")) {
+            TraceOutput::Text(file, header.set)
+        } else {
+            TraceOutput::Binary(TraceWriter::new(file, header)?)
+        };
+        Ok(Self {
+            out,
+            filter: TraceFilter::default(),
+            start: None,
+            stop: None,
+            active: true,
+            ring: ring.map(|size| (size.max(1), VecDeque::with_capacity(size.clamp(1, 1 << 20)))),
+            path: path.to_path_buf(),
+            records: 0,
+        })
+    }
+
+    pub fn set_start(&mut self, start: Option<Trigger>) {
+        self.active = start.is_none();
+        self.start = start;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    #[inline]
+    pub fn record(&mut self, record: TraceRecord) -> io::Result<()> {
+        if self.start.as_ref().is_some_and(|start| start.fires(&record)) {
+            self.active = true;
+        }
+        if !self.active {
+            return Ok(());
+        }
+        if self.stop.as_ref().is_some_and(|stop| stop.fires(&record)) {
+            self.active = false;
+        }
+        if !self.filter.matches(&record) {
+            return Ok(());
+        }
+        match self.ring.as_mut() {
+            Some((size, ring)) => {
+                if ring.len() >= *size {
+                    ring.pop_front();
+                }
+                ring.push_back(record);
+                Ok(())
+            }
+            None => self.write(&record),
+        }
+    }
+
+    fn write(&mut self, record: &TraceRecord) -> io::Result<()> {
+        self.records += 1;
+        match &mut self.out {
+            TraceOutput::Binary(writer) => writer.write(record),
+            TraceOutput::Text(out, set) => writeln!(out, "{}", record.text(*set)),
+        }
+    }
+
+    // Write what the ring holds and flush; returns the records written
+    pub fn finish(mut self) -> io::Result<u64> {
+        if let Some((_, ring)) = self.ring.take() {
+            for record in &ring {
+                self.write(record)?;
+            }
+        }
+        match &mut self.out {
+            TraceOutput::Binary(writer) => writer.flush()?,
+            TraceOutput::Text(out, _) => out.flush()?,
+        }
+        Ok(self.records)
+    }
+}
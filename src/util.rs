@@ -1,4 +1,5 @@
 use crate::mmu::MemStateMask;
+use crate::video::VideoModeMask;
 
 pub fn hexdump(data: &[u8], start: Option<u16>, length: Option<usize>) {
     let start = start.unwrap_or(0x0000) as usize;
@@ -55,6 +56,22 @@ pub fn mem_state_to_string(mem_state: u8) -> String {
     )
 }
 
+#[rustfmt::skip]
+pub fn video_mode_to_string(video_mode: u8) -> String {
+    let value = video_mode;
+    format!(
+        "[{}{}{}{}{}{}{}{}]",
+        if value & VideoModeMask::TEXT != 0 { 'T' } else { '.' },    // T: Text
+        if value & VideoModeMask::LORES != 0 { 'l' } else { '.' },   // l: Lo-Res
+        if value & VideoModeMask::HIRES != 0 { 'H' } else { '.' },   // H: Hi-Res
+        if value & VideoModeMask::DHIRES != 0 { 'D' } else { '.' },  // D: Double Hi-Res
+        if value & VideoModeMask::MIXED != 0 { 'M' } else { '.' },   // M: Mixed text and graphics
+        if value & VideoModeMask::PAGE2 != 0 { '2' } else { '.' },   // 2: Page 2
+        if value & VideoModeMask::COL80 != 0 { '8' } else { '.' },   // 8: 80 columns
+        if value & VideoModeMask::ALTCHAR != 0 { 'a' } else { '.' }  // a: Alternate character set
+    )
+}
+
 pub fn apple_iic_font_index(vram_code: u8, is_altchar: bool) -> (usize, bool) {
     // Returns (clean_font_index, invert_flag)
     // Clean Font Layout (0-127):
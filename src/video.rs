@@ -1,4 +1,4 @@
-use crate::{iou::IOU, mmu::MMU, util::apple_iic_font_index};
+use crate::{iou::IOU, mmu::MMU, savestate::{SaveState, StateReader, StateWriter}, util::apple_iic_font_index};
 
 const CHAR_ROM: &[u8; 1024] = include_bytes!("../assets/font.bin");
 
@@ -70,6 +70,55 @@ impl VideoModeMask {
     pub const ALTCHAR: u8  = 0b1000_0000; // Alternate Character Set
 }
 
+// Where the displayed text page comes from under the current soft switches.
+// The renderer and the text-screen scraper both read the screen through
+// this, so they always agree on which byte is shown in which column.
+#[derive(Clone, Copy, Debug)]
+pub struct TextLayout {
+    pub col80: bool,
+    pub altchar: bool,
+    page2: bool,
+    store80: bool,
+}
+
+impl TextLayout {
+    pub fn current(iou: &IOU) -> Self {
+        let video_mode = iou.video_mode.get();
+        Self {
+            col80: check_bits_u8!(video_mode, VideoModeMask::COL80),
+            altchar: check_bits_u8!(video_mode, VideoModeMask::ALTCHAR),
+            page2: check_bits_u8!(video_mode, VideoModeMask::PAGE2),
+            store80: iou.is_80store.get(),
+        }
+    }
+
+    pub fn columns(&self) -> u16 {
+        if self.col80 { 80 } else { 40 }
+    }
+
+    // Screen code displayed at `col` of text row `row`
+    pub fn code_at(&self, mmu: &MMU, row: usize, col: u16) -> u8 {
+        let row_base = TEXT_MODE_BASE_ADDRESSES[row];
+        if self.col80 {
+            // Even columns (0, 2, 4...) come from aux memory, odd from main
+            let addr = row_base + col / 2;
+            if col & 1 == 0 { mmu.read_aux_byte(addr) } else { mmu.read_main_byte(addr) }
+        } else if self.page2 && self.store80 {
+            // 80STORE turns PAGE2 into an aux/main select for page 1
+            mmu.read_aux_byte(row_base + col)
+        } else if self.page2 {
+            mmu.read_main_byte(row_base + 0x0400 + col)
+        } else {
+            mmu.read_main_byte(row_base + col)
+        }
+    }
+}
+
+// Screen codes $40-$7F flash in the primary character set
+pub fn is_flashing(char_code: u8, is_altchar: bool) -> bool {
+    !is_altchar && (0x40..=0x7F).contains(&char_code)
+}
+
 
 pub struct Video {
     framebuffer: Vec<u8>,
@@ -376,47 +425,13 @@ impl Video {
     }
    
     fn render_text_rows(&mut self, iou: &IOU, mmu: &MMU, rows: std::ops::Range<u16>) {
-        let video_mode = iou.video_mode.get();
-        let is_80col = check_bits_u8!(video_mode, VideoModeMask::COL80);
-        let is_altchar = check_bits_u8!(video_mode, VideoModeMask::ALTCHAR);
-        let is_page2 = check_bits_u8!(video_mode, VideoModeMask::PAGE2);
-        let is_80store = iou.is_80store.get();
-
-        let double_width = !is_80col;
+        let layout = TextLayout::current(iou);
+        let double_width = !layout.col80;
 
         for row in rows {
-            let row_base = TEXT_MODE_BASE_ADDRESSES[row as usize];
-
-            if is_80col {
-                for col_pair in 0..40_u16 {
-                    let addr = row_base + col_pair;
-                    
-                    // Even column (0, 2, 4...) -> AUX Memory
-                    let char_even = mmu.read_aux_byte(addr);
-                    self.draw_char(row, col_pair * 2, char_even, is_altchar, double_width);
-
-                    // Odd column (1, 3, 5...) -> MAIN Memory
-                    let char_odd = mmu.read_main_byte(addr);
-                    self.draw_char(row, col_pair * 2 + 1, char_odd, is_altchar, double_width);
-                }
-            } else {
-                for col in 0..40_u16 {
-                    // Handle Page 2 offset if 80STORE is OFF
-                    let (effective_addr, use_aux) = if !is_80store && is_page2 {
-                        (row_base + 0x0400 + col, false)
-                    } else if is_80store && is_page2 {
-                        (row_base + col, true)
-                    } else {
-                        (row_base + col, false)
-                    };
-
-                    let vram_code = if use_aux {
-                        mmu.read_aux_byte(effective_addr)
-                    } else {
-                        mmu.read_main_byte(effective_addr)
-                    };
-                    self.draw_char(row, col, vram_code, is_altchar, double_width);
-                }
+            for col in 0..layout.columns() {
+                let vram_code = layout.code_at(mmu, row as usize, col);
+                self.draw_char(row, col, vram_code, layout.altchar, double_width);
             }
         }
     }
@@ -424,10 +439,9 @@ impl Video {
     fn draw_char(&mut self, row: u16, col: u16, char_code: u8, is_altchar: bool, double_width: bool) {
         let (font_offset, mut invert) = apple_iic_font_index(char_code, is_altchar);
 
-       // Flashing range in VRAM: 0x40-0x7F (when not in AltChar/MouseText mode)
-        if !is_altchar && (char_code >= 0x40 && char_code <= 0x7F) {
+        if is_flashing(char_code, is_altchar) {
             // Flash rate: approx 2Hz. 60fps / 32 = ~1.8Hz
-            let flash_on = (self.frame_count / 16) % 2 == 0;
+            let flash_on = (self.frame_count / 16).is_multiple_of(2);
             if !flash_on {
                 invert = false;
             }
@@ -579,24 +593,24 @@ impl Video {
                 NTSC_PALETTE[15]
             } else {
                 if palette {
-                    if phase_column % 2 == 0 { NTSC_PALETTE[6] } else { NTSC_PALETTE[9] }
+                    if phase_column.is_multiple_of(2) { NTSC_PALETTE[6] } else { NTSC_PALETTE[9] }
                 } else {
-                    if phase_column % 2 == 0 { NTSC_PALETTE[3] } else { NTSC_PALETTE[12] }
+                    if phase_column.is_multiple_of(2) { NTSC_PALETTE[3] } else { NTSC_PALETTE[12] }
                 }
             }
         } else if prev && next {
             // between two ON pixels
             if palette {
-                if phase_column % 2 == 0 { NTSC_PALETTE[9] } else { NTSC_PALETTE[6] }
+                if phase_column.is_multiple_of(2) { NTSC_PALETTE[9] } else { NTSC_PALETTE[6] }
             } else {
-                if phase_column % 2 == 0 { NTSC_PALETTE[12] } else { NTSC_PALETTE[3] }
+                if phase_column.is_multiple_of(2) { NTSC_PALETTE[12] } else { NTSC_PALETTE[3] }
             }
         } else if prev || next {
             // single neighbor edge
             let base = if palette {
-                if phase_column % 2 == 0 { NTSC_PALETTE[9] } else { NTSC_PALETTE[6] }
+                if phase_column.is_multiple_of(2) { NTSC_PALETTE[9] } else { NTSC_PALETTE[6] }
             } else {
-                if phase_column % 2 == 0 { NTSC_PALETTE[12] } else { NTSC_PALETTE[3] }
+                if phase_column.is_multiple_of(2) { NTSC_PALETTE[12] } else { NTSC_PALETTE[3] }
             };
             [
                 (base[0] as f32 * 0.56) as u8,
@@ -684,7 +698,7 @@ impl Video {
                     
                     for dy in 0..8 {
                         for dx in 0..7 {
-                            let index = self.fb_index(x as usize + dx as usize, y as usize + dy as usize);
+                            let index = self.fb_index(x as usize + dx as usize, y + dy as usize);
                             if index + 4 <= self.framebuffer.len() {
                                 self.framebuffer[index..index + 4].copy_from_slice(&color);
                             }
@@ -715,7 +729,7 @@ impl Video {
                     
                     for dy in 0..8 {
                         for dx in 0..14 {
-                            let index = self.fb_index(x as usize + dx as usize, y as usize + dy as usize);
+                            let index = self.fb_index(x as usize + dx as usize, y + dy as usize);
                             if index + 4 <= self.framebuffer.len() {
                                 self.framebuffer[index..index + 4].copy_from_slice(&color);
                             }
@@ -787,7 +801,7 @@ impl Video {
                             (byte >> (bit - 1)) & 1 != 0
                         };
                         let next = if bit == 6 {
-                            (next_byte >> 0) & 1 != 0
+                            next_byte & 1 != 0
                         } else {
                             (byte >> (bit + 1)) & 1 != 0
                         };
@@ -988,7 +1002,7 @@ impl Video {
                             let color = if pixel_on { MONO_GREEN_RGBA } else { MONO_BLACK_RGBA };
                             let x = col as usize * 14 + bit as usize;
                             for dy in 0..2 {
-                                let index = self.fb_index(x, y as usize + dy);
+                                let index = self.fb_index(x, y + dy);
                                 if index + 4 <= self.framebuffer.len() {
                                     self.framebuffer[index..index + 4].copy_from_slice(&color);
                                 }
@@ -999,7 +1013,7 @@ impl Video {
                             let color = if pixel_on { MONO_GREEN_RGBA } else { MONO_BLACK_RGBA };
                             let x = col as usize * 14 + 7 + bit as usize;
                             for dy in 0..2 {
-                                let index = self.fb_index(x, y as usize + dy);
+                                let index = self.fb_index(x, y + dy);
                                 if index + 4 <= self.framebuffer.len() {
                                     self.framebuffer[index..index + 4].copy_from_slice(&color);
                                 }
@@ -1043,7 +1057,7 @@ impl Video {
                         let rgba = DHIRES_PALETTE[nibble as usize];
 
                         for dy in 0..2 {
-                            let index = self.fb_index(i, y as usize + dy);
+                            let index = self.fb_index(i, y + dy);
                             if index + 4 <= self.framebuffer.len() {
                                 self.framebuffer[index..index + 4].copy_from_slice(&rgba);
                             }
@@ -1070,6 +1084,17 @@ impl Video {
         &self.framebuffer
     }
 
+    // Active display area only (border cropped), RGBA, active_width x active_height
+    pub fn get_active_pixels(&self) -> Vec<u8> {
+        let row_bytes = self.active_width * 4;
+        let mut out = Vec::with_capacity(row_bytes * self.active_height);
+        for y in 0..self.active_height {
+            let start = self.fb_index(0, y);
+            out.extend_from_slice(&self.framebuffer[start..start + row_bytes]);
+        }
+        out
+    }
+
     fn lores_color_lookup(&self, color: u8) -> [u8; 4] {
         let rgba = NTSC_PALETTE[(color & 0x0F) as usize];
 
@@ -1085,3 +1110,29 @@ impl Video {
         }
     }
 }
+
+// Only the state that affects what the next frame looks like is saved
+// (flash phase and the per-scanline mode snapshots); display preferences
+// such as monochrome stay with the host.
+impl SaveState for Video {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.section(b"VID ");
+        w.usize(self.frame_count);
+        w.array(&self.scanline_modes);
+        for &store80 in &self.scanline_80store {
+            w.bool(store80);
+        }
+        w.usize(self.scanline_count);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> anyhow::Result<()> {
+        r.section(b"VID ")?;
+        self.frame_count = r.usize()?;
+        r.array(&mut self.scanline_modes)?;
+        for store80 in self.scanline_80store.iter_mut() {
+            *store80 = r.bool()?;
+        }
+        self.scanline_count = r.usize()?.min(192);
+        Ok(())
+    }
+}